@@ -38,10 +38,43 @@ impl Display for ExprKind {
             Self::Pow(a, b) => write!(f, "{} ^ {}", a, b)?,
             Self::Neg(expr) => write!(f, "-{}", expr)?,
 
+            Self::BitAnd(a, b) => write!(f, "{} & {}", a, b)?,
+            Self::BitOr(a, b) => write!(f, "{} | {}", a, b)?,
+            Self::BitXor(a, b) => write!(f, "{} ~ {}", a, b)?,
+            Self::Shl(a, b) => write!(f, "{} << {}", a, b)?,
+            Self::Shr(a, b) => write!(f, "{} >> {}", a, b)?,
+            Self::BitNot(expr) => write!(f, "~{}", expr)?,
+
             // assignment
             Self::Assignment(lhs, rhs) => write!(f, "{} = {}", lhs.ident, rhs)?,
 
+            Self::Call(callee, args) => write!(f, "call {} ({})", callee, comma_list(args))?,
+            Self::MethodCall(receiver, method, args) => write!(
+                f,
+                "call {}.{} ({})",
+                receiver,
+                method.ident,
+                comma_list(args)
+            )?,
+            Self::Field(expr, field) => write!(f, "{}.{}", expr, field.ident)?,
+            Self::Cast(expr, ty) => write!(f, "({} as {})", expr, ty)?,
+            Self::Variant(variant, args) if args.is_empty() => write!(f, "{}", variant.ident)?,
+            Self::Variant(variant, args) => write!(f, "{}({})", variant.ident, comma_list(args))?,
+            Self::Try(expr) => write!(f, "{}?", expr)?,
+            Self::StructLiteral(literal) => write!(
+                f,
+                "{} {{ {} }}",
+                literal.ident,
+                literal
+                    .fields
+                    .iter()
+                    .map(|field| format!("{}: {}", field.ident, field.value))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )?,
+
             Self::DebugPrint(expr) => write!(f, "print {}", expr)?,
+            Self::Panic(expr) => write!(f, "panic {}", expr)?,
 
             Self::DummyExpr => write!(f, "{{err}}")?,
         }
@@ -56,8 +89,20 @@ impl Display for Statement {
             Self::Expr(expr) => write!(f, "expr {}", expr)?,
             Self::Block(block) => write!(f, "block {}", block)?,
             Self::VarDecl(var_decl) => write!(f, "var_decl {}", var_decl)?,
+            Self::FnDecl(fn_decl) => write!(f, "fn_decl {}", fn_decl)?,
+            Self::StructDecl(struct_decl) => write!(f, "struct_decl {}", struct_decl)?,
+            Self::EnumDecl(enum_decl) => write!(f, "enum_decl {}", enum_decl)?,
+            Self::NewtypeDecl(newtype_decl) => write!(f, "newtype_decl {}", newtype_decl)?,
+            Self::TraitDecl(trait_decl) => write!(f, "trait_decl {}", trait_decl)?,
+            Self::ImplDecl(impl_decl) => write!(f, "impl_decl {}", impl_decl)?,
             Self::If(if_statement) => write!(f, "if {}", if_statement)?,
             Self::While(while_loop) => write!(f, "while {}", while_loop)?,
+            Self::Match(match_statement) => write!(f, "match {}", match_statement)?,
+            Self::Try(try_statement) => write!(f, "try {}", try_statement)?,
+            Self::Return(return_statement) => match &return_statement.value {
+                Some(value) => write!(f, "return {}", value)?,
+                None => write!(f, "return")?,
+            },
         }
         write!(f, "]")
     }
@@ -79,7 +124,122 @@ impl Display for Block {
 
 impl Display for VarDecl {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result {
-        write!(f, "[let {} = {}]", self.ident, self.value)
+        write!(f, "[let {}", self.ident)?;
+        if let Some(ty) = &self.ty {
+            write!(f, ": {}", ty)?;
+        }
+        write!(f, " = {}]", self.value)
+    }
+}
+
+impl Display for FnDecl {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write!(f, "[{} {}]", self.sig, self.body)
+    }
+}
+
+impl Display for FnSig {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write!(f, "fn {}{}(", self.ident, generics(&self.generics))?;
+        for (i, param) in self.params.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", param.ident)?;
+            if let Some(ty) = &param.ty {
+                write!(f, ": {}", ty)?;
+            }
+        }
+        write!(f, ")")?;
+        if let Some(ty) = &self.return_ty {
+            write!(f, " -> {}", ty)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for GenericParam {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write!(f, "{}", self.ident)?;
+        for (i, (bound, _)) in self.bounds.iter().enumerate() {
+            let sep = if i == 0 { ": " } else { " + " };
+            write!(f, "{}{}", sep, bound)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for TraitDecl {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write!(f, "[trait {} {{ ", self.ident)?;
+        for method in &self.methods {
+            write!(f, "{}; ", method)?;
+        }
+        write!(f, "}}]")
+    }
+}
+
+impl Display for ImplDecl {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write!(f, "[impl{} ", generics(&self.generics))?;
+        if let Some((trait_, _)) = &self.trait_ {
+            write!(f, "{} for ", trait_)?;
+        }
+        write!(f, "{} {{ {} }}]", self.ty, comma_list(&self.methods))
+    }
+}
+
+impl Display for StructDecl {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write!(
+            f,
+            "[struct {}{} {{ {} }}]",
+            self.ident,
+            generics(&self.generics),
+            self.fields
+                .iter()
+                .map(|field| format!("{}: {}", field.ident, field.ty))
+                .collect::<Vec<_>>()
+                .join(", ")
+        )
+    }
+}
+
+impl Display for EnumDecl {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write!(
+            f,
+            "[enum {}{} {{ {} }}]",
+            self.ident,
+            generics(&self.generics),
+            self.variants
+                .iter()
+                .map(|variant| match &variant.payload {
+                    Some(payload) => format!("{}({})", variant.ident, payload),
+                    None => variant.ident.to_string(),
+                })
+                .collect::<Vec<_>>()
+                .join(", ")
+        )
+    }
+}
+
+impl Display for NewtypeDecl {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write!(f, "[type {} = new {}]", self.ident, self.base)
+    }
+}
+
+impl Display for TypeExpr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        match &self.kind {
+            TypeExprKind::Named(name, args) if args.is_empty() => write!(f, "{}", name),
+            TypeExprKind::Named(name, args) => write!(f, "{}<{}>", name, comma_list(args)),
+            TypeExprKind::Function(params, ret) => {
+                write!(f, "fn({}) -> {}", comma_list(params), ret)
+            }
+            TypeExprKind::Unit => write!(f, "()"),
+        }
     }
 }
 
@@ -93,8 +253,62 @@ impl Display for IfStatement {
     }
 }
 
+impl Display for MatchStatement {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write!(f, "[match {} {{ ", self.scrutinee)?;
+        for arm in &self.arms {
+            write!(f, "{} => {} ", arm.pattern, arm.body)?;
+        }
+        write!(f, "}}]")
+    }
+}
+
+impl Display for Pattern {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        match &self.kind {
+            PatternKind::Wildcard => write!(f, "_"),
+            PatternKind::Variant {
+                ident,
+                binding: Some((binding, _)),
+                ..
+            } => write!(f, "{}({})", ident, binding),
+            PatternKind::Variant {
+                ident,
+                binding: None,
+                ..
+            } => write!(f, "{}", ident),
+        }
+    }
+}
+
+impl Display for TryStatement {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write!(
+            f,
+            "[try {} recover {} {}]",
+            self.body, self.binding, self.recover
+        )
+    }
+}
+
 impl Display for WhileLoop {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result {
         write!(f, "[while {} {}]", self.condition, self.body)
     }
 }
+
+fn comma_list<T: Display>(items: &[T]) -> String {
+    items
+        .iter()
+        .map(|item| item.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn generics(generics: &[GenericParam]) -> String {
+    if generics.is_empty() {
+        String::new()
+    } else {
+        format!("<{}>", comma_list(generics))
+    }
+}
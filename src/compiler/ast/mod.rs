@@ -1,6 +1,7 @@
 use internment::Intern;
 
 use crate::compiler::passes::resolve::VarResolved;
+use crate::compiler::passes::typecheck::{ImplResolved, MethodResolved, VariantResolved};
 use crate::runtime::value::Value;
 
 pub use self::types::*;
@@ -10,12 +11,31 @@ mod types;
 
 pub struct Module {
     pub statements: Vec<Statement>,
+
+    /// The spans of the comments, which the other passes ignore but the
+    /// formatter keeps.
+    pub comments: Vec<Span>,
+
+    /// The comments that are attributes, in source order.
+    pub attributes: Vec<Attribute>,
+}
+
+/// A comment of the form `#[name(arg, ...)]`, which applies to the
+/// statement after it, or `#![name(arg, ...)]`, which applies to the whole
+/// module. They're used to set the levels of lints.
+#[derive(Debug, Clone)]
+pub struct Attribute {
+    pub name: Intern<String>,
+    pub args: Vec<(Intern<String>, Span)>,
+    pub inner: bool,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone)]
 pub struct Block {
     pub statements: Vec<Statement>,
     pub num_vars: Option<usize>,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone)]
@@ -23,21 +43,150 @@ pub enum Statement {
     Expr(Expr),
     Block(Block),
     VarDecl(VarDecl),
+    FnDecl(FnDecl),
+    StructDecl(StructDecl),
+    EnumDecl(EnumDecl),
+    NewtypeDecl(NewtypeDecl),
+    TraitDecl(TraitDecl),
+    ImplDecl(ImplDecl),
     If(IfStatement),
     While(WhileLoop),
+    Match(MatchStatement),
+    Try(TryStatement),
+    Return(ReturnStatement),
+}
+
+impl Statement {
+    pub fn span(&self) -> Span {
+        match self {
+            Self::Expr(expr) => expr.span,
+            Self::Block(block) => block.span,
+            Self::VarDecl(var_decl) => var_decl.span,
+            Self::FnDecl(fn_decl) => fn_decl.sig.span.join(fn_decl.body.span),
+            Self::StructDecl(struct_decl) => struct_decl.span,
+            Self::EnumDecl(enum_decl) => enum_decl.span,
+            Self::NewtypeDecl(newtype_decl) => newtype_decl.span,
+            Self::TraitDecl(trait_decl) => trait_decl.span,
+            Self::ImplDecl(impl_decl) => impl_decl.span,
+            Self::If(if_statement) => if_statement.span,
+            Self::While(while_loop) => while_loop.span,
+            Self::Match(match_statement) => match_statement.span,
+            Self::Try(try_statement) => try_statement.span,
+            Self::Return(return_statement) => return_statement.span,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct VarDecl {
     pub ident: Intern<String>,
+    pub ident_span: Span,
+    pub ty: Option<TypeExpr>,
     pub value: Box<Expr>,
     pub span: Span,
 }
 
+#[derive(Debug, Clone)]
+pub struct FnDecl {
+    pub sig: FnSig,
+    pub body: Block,
+    pub resolved: Option<VarResolved>,
+}
+
+#[derive(Debug, Clone)]
+pub struct FnSig {
+    pub ident: Intern<String>,
+    pub generics: Vec<GenericParam>,
+    pub params: Vec<Param>,
+    pub return_ty: Option<TypeExpr>,
+    pub span: Span,
+}
+
+impl FnSig {
+    /// The span of the function's name, which the signature's span ends with.
+    pub fn ident_span(&self) -> Span {
+        Span {
+            start: self.span.end - self.ident.len(),
+            end: self.span.end,
+        }
+    }
+}
+
+/// A type parameter, such as the `T: Show` in `fn show<T: Show>(x: T)`.
+#[derive(Debug, Clone)]
+pub struct GenericParam {
+    pub ident: Intern<String>,
+    pub bounds: Vec<(Intern<String>, Span)>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Param {
+    pub ident: Intern<String>,
+    pub ty: Option<TypeExpr>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub struct StructDecl {
+    pub ident: Intern<String>,
+    pub generics: Vec<GenericParam>,
+    pub fields: Vec<FieldDecl>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub struct FieldDecl {
+    pub ident: Intern<String>,
+    pub ty: TypeExpr,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub struct EnumDecl {
+    pub ident: Intern<String>,
+    pub generics: Vec<GenericParam>,
+    pub variants: Vec<VariantDecl>,
+    pub span: Span,
+}
+
+/// A variant of an enum, and the type of the value it holds, if any.
+#[derive(Debug, Clone)]
+pub struct VariantDecl {
+    pub ident: Intern<String>,
+    pub payload: Option<TypeExpr>,
+    pub span: Span,
+}
+
+/// A type that's represented like `base`, but distinct from it.
+#[derive(Debug, Clone)]
+pub struct NewtypeDecl {
+    pub ident: Intern<String>,
+    pub base: TypeExpr,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub struct TraitDecl {
+    pub ident: Intern<String>,
+    pub methods: Vec<FnSig>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub struct ImplDecl {
+    pub generics: Vec<GenericParam>,
+    pub trait_: Option<(Intern<String>, Span)>,
+    pub ty: TypeExpr,
+    pub methods: Vec<FnDecl>,
+    pub resolved: Option<ImplResolved>,
+    pub span: Span,
+}
+
 #[derive(Debug, Clone)]
 pub struct Var {
     pub ident: Intern<String>,
     pub resolved: Option<VarResolved>,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone)]
@@ -45,12 +194,124 @@ pub struct IfStatement {
     pub condition: Expr,
     pub then: Block,
     pub else_: Option<Box<Statement>>,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone)]
 pub struct WhileLoop {
     pub condition: Expr,
     pub body: Block,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub struct MatchStatement {
+    pub scrutinee: Expr,
+    pub arms: Vec<MatchArm>,
+
+    /// The local slot the scrutinee is kept in while matching.
+    pub slot: Option<usize>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub struct MatchArm {
+    pub pattern: Pattern,
+    pub body: Block,
+}
+
+#[derive(Debug, Clone)]
+pub struct Pattern {
+    pub kind: PatternKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub enum PatternKind {
+    Wildcard,
+
+    /// A variant, binding the value it holds to a variable if one is given.
+    Variant {
+        ident: Intern<String>,
+        binding: Option<(Intern<String>, Span)>,
+
+        /// The index of the variant in its enum, found by the type checker.
+        tag: Option<usize>,
+    },
+}
+
+/// Runs `body`, and if it panics, unwinds back here and runs `recover`
+/// with the panic bound to `binding`.
+#[derive(Debug, Clone)]
+pub struct TryStatement {
+    pub body: Block,
+    pub binding: Intern<String>,
+    pub recover: Block,
+    pub span: Span,
+}
+
+impl TryStatement {
+    /// The span of the binding, which the statement's span ends with.
+    pub fn binding_span(&self) -> Span {
+        Span {
+            start: self.span.end - self.binding.len(),
+            end: self.span.end,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ReturnStatement {
+    pub value: Option<Expr>,
+    pub span: Span,
+}
+
+/// A type as written in the source, such as `Pair<Int, T>`.
+#[derive(Debug, Clone)]
+pub struct TypeExpr {
+    pub kind: TypeExprKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub enum TypeExprKind {
+    Named(Intern<String>, Vec<TypeExpr>),
+    Function(Vec<TypeExpr>, Box<TypeExpr>),
+    Unit,
+}
+
+#[derive(Debug, Clone)]
+pub struct Field {
+    pub ident: Intern<String>,
+    pub index: Option<usize>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Method {
+    pub ident: Intern<String>,
+    pub span: Span,
+    pub resolved: Option<MethodResolved>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Variant {
+    pub ident: Intern<String>,
+    pub resolved: Option<VariantResolved>,
+}
+
+#[derive(Debug, Clone)]
+pub struct StructLiteral {
+    pub ident: Intern<String>,
+    /// Sorted into declaration order by the type checker, which is the
+    /// order they're evaluated in.
+    pub fields: Vec<FieldInit>,
+}
+
+#[derive(Debug, Clone)]
+pub struct FieldInit {
+    pub ident: Intern<String>,
+    pub value: Expr,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone)]
@@ -80,10 +341,37 @@ pub enum ExprKind {
     Pow(Box<Expr>, Box<Expr>),
     Neg(Box<Expr>),
 
+    // bitwise
+    BitAnd(Box<Expr>, Box<Expr>),
+    BitOr(Box<Expr>, Box<Expr>),
+    BitXor(Box<Expr>, Box<Expr>),
+    Shl(Box<Expr>, Box<Expr>),
+    Shr(Box<Expr>, Box<Expr>),
+    BitNot(Box<Expr>),
+
     // assignment
     Assignment(Var, Box<Expr>),
 
+    Call(Box<Expr>, Vec<Expr>),
+    MethodCall(Box<Expr>, Method, Vec<Expr>),
+    Field(Box<Expr>, Field),
+    StructLiteral(StructLiteral),
+
+    /// Wrapping a value in a newtype, or unwrapping it.
+    Cast(Box<Expr>, TypeExpr),
+
+    /// A variant of an enum, such as `Some(1)` or `None`.
+    ///
+    /// The parser only knows the builtin variants, and the resolver turns
+    /// the variables and calls that name other variants into these.
+    Variant(Variant, Vec<Expr>),
+
+    /// Unwrap an `Option` or `Result`, or return early if it's `None` or
+    /// `Err`.
+    Try(Box<Expr>),
+
     DebugPrint(Box<Expr>),
+    Panic(Box<Expr>),
 
     DummyExpr,
 }
@@ -1,6 +1,29 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Display, Formatter};
+
+use internment::Intern;
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Type {
     Builtin(BuiltinType),
+
+    /// An inference variable, to be solved by unification.
+    Var(TypeVar),
+
+    /// A named type parameter, such as the `T` in `fn id<T>(x: T) -> T`.
+    ///
+    /// Parameters only unify with themselves.
+    Param(TypeParam),
+
+    /// A user-defined, possibly generic, type such as `Pair<Int, String>`.
+    Constructor(Intern<String>, Vec<Type>),
+
+    Function(Vec<Type>, Box<Type>),
+
+    /// A type declared with `type Name = new Base;`, which never unifies
+    /// with its base type.
+    Newtype(Intern<String>),
+
     Unknown,
 }
 
@@ -8,18 +31,251 @@ pub enum Type {
 pub enum BuiltinType {
     String,
     Integer,
+    BigInt,
     Float,
     Bool,
     Unit,
 }
 
-pub fn unify_types(a: Type, b: Type) -> Result<Type, (Type, Type)> {
-    match (a, b) {
-        (a, Type::Unknown) => Ok(a),
-        (Type::Unknown, b) => Ok(b),
+impl BuiltinType {
+    pub fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "String" => Self::String,
+            "Int" => Self::Integer,
+            "BigInt" => Self::BigInt,
+            "Float" => Self::Float,
+            "Bool" => Self::Bool,
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TypeVar(usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TypeParam {
+    pub name: Intern<String>,
+    id: usize,
+}
+
+/// A polymorphic type, quantified over some type parameters.
+#[derive(Debug, Clone)]
+pub struct Scheme {
+    pub params: Vec<TypeParam>,
+    pub ty: Type,
+}
+
+impl Type {
+    /// Replace type parameters according to `mapping`.
+    pub fn replace_params(&self, mapping: &HashMap<TypeParam, Type>) -> Type {
+        match self {
+            Self::Param(param) => mapping.get(param).cloned().unwrap_or(Self::Param(*param)),
+            Self::Constructor(name, args) => Self::Constructor(
+                *name,
+                args.iter().map(|arg| arg.replace_params(mapping)).collect(),
+            ),
+            Self::Function(params, ret) => Self::Function(
+                params.iter().map(|p| p.replace_params(mapping)).collect(),
+                Box::new(ret.replace_params(mapping)),
+            ),
+            other => other.clone(),
+        }
+    }
+}
+
+/// The solutions to inference variables found so far.
+#[derive(Default, Clone)]
+pub struct Substitution {
+    vars: Vec<Option<Type>>,
+    num_params: usize,
+}
+
+impl Substitution {
+    pub fn fresh_var(&mut self) -> Type {
+        self.vars.push(None);
+        Type::Var(TypeVar(self.vars.len() - 1))
+    }
+
+    pub fn fresh_param(&mut self, name: Intern<String>) -> TypeParam {
+        self.num_params += 1;
+        TypeParam {
+            name,
+            id: self.num_params - 1,
+        }
+    }
+
+    /// Apply the substitution to a type, replacing all solved variables.
+    pub fn resolve(&self, ty: &Type) -> Type {
+        match ty {
+            Type::Var(var) => match &self.vars[var.0] {
+                Some(ty) => self.resolve(ty),
+                None => Type::Var(*var),
+            },
+            Type::Constructor(name, args) => {
+                Type::Constructor(*name, args.iter().map(|arg| self.resolve(arg)).collect())
+            }
+            Type::Function(params, ret) => Type::Function(
+                params.iter().map(|p| self.resolve(p)).collect(),
+                Box::new(self.resolve(ret)),
+            ),
+            other => other.clone(),
+        }
+    }
+
+    /// Unify two types, returning the unified type or the pair of types
+    /// that couldn't be unified.
+    pub fn unify_types(&mut self, a: &Type, b: &Type) -> Result<Type, (Type, Type)> {
+        match (self.resolve(a), self.resolve(b)) {
+            (a, Type::Unknown) => Ok(a),
+            (Type::Unknown, b) => Ok(b),
+
+            (Type::Var(a), Type::Var(b)) if a == b => Ok(Type::Var(a)),
+            (Type::Var(var), ty) | (ty, Type::Var(var)) => {
+                if self.occurs(var, &ty) {
+                    return Err((Type::Var(var), ty));
+                }
+                self.vars[var.0] = Some(ty.clone());
+                Ok(ty)
+            }
+
+            (Type::Builtin(a), Type::Builtin(b)) if a == b => Ok(Type::Builtin(a)),
+            (Type::Param(a), Type::Param(b)) if a == b => Ok(Type::Param(a)),
+            (Type::Newtype(a), Type::Newtype(b)) if a == b => Ok(Type::Newtype(a)),
+
+            (Type::Constructor(a, a_args), Type::Constructor(b, b_args))
+                if a == b && a_args.len() == b_args.len() =>
+            {
+                let args = a_args
+                    .iter()
+                    .zip(&b_args)
+                    .map(|(a, b)| self.unify_types(a, b))
+                    .collect::<Result<_, _>>()?;
+                Ok(Type::Constructor(a, args))
+            }
+
+            (Type::Function(a_params, a_ret), Type::Function(b_params, b_ret))
+                if a_params.len() == b_params.len() =>
+            {
+                let params = a_params
+                    .iter()
+                    .zip(&b_params)
+                    .map(|(a, b)| self.unify_types(a, b))
+                    .collect::<Result<_, _>>()?;
+                let ret = self.unify_types(&a_ret, &b_ret)?;
+                Ok(Type::Function(params, Box::new(ret)))
+            }
+
+            (a, b) => Err((a, b)),
+        }
+    }
+
+    /// Replace the parameters of a scheme with fresh inference variables,
+    /// also returning the variable chosen for each parameter.
+    pub fn instantiate(&mut self, scheme: &Scheme) -> (Type, HashMap<TypeParam, Type>) {
+        let mapping = scheme
+            .params
+            .iter()
+            .map(|param| (*param, self.fresh_var()))
+            .collect();
+        let ty = self.resolve(&scheme.ty).replace_params(&mapping);
+        (ty, mapping)
+    }
+
+    /// Quantify over the variables in `scheme` that don't appear in `env`,
+    /// turning them into type parameters.
+    pub fn generalise(&mut self, scheme: &Scheme, env: &HashSet<TypeVar>) -> Scheme {
+        let ty = self.resolve(&scheme.ty);
+
+        let mut vars = vec![];
+        self.free_vars(&ty, &mut vars);
+
+        let mut params = scheme.params.clone();
+        for var in vars {
+            if env.contains(&var) {
+                continue;
+            }
+
+            let name = Intern::new(param_name(params.len()));
+            let param = self.fresh_param(name);
+            self.vars[var.0] = Some(Type::Param(param));
+            params.push(param);
+        }
+
+        Scheme {
+            params,
+            ty: self.resolve(&ty),
+        }
+    }
+
+    /// Collect the unsolved variables in a type, in order of appearance.
+    pub fn free_vars(&self, ty: &Type, vars: &mut Vec<TypeVar>) {
+        match self.resolve(ty) {
+            Type::Var(var) if !vars.contains(&var) => vars.push(var),
+            Type::Constructor(_, args) => {
+                for arg in &args {
+                    self.free_vars(arg, vars);
+                }
+            }
+            Type::Function(params, ret) => {
+                for param in &params {
+                    self.free_vars(param, vars);
+                }
+                self.free_vars(&ret, vars);
+            }
+            _ => {}
+        }
+    }
+
+    fn occurs(&self, var: TypeVar, ty: &Type) -> bool {
+        let mut vars = vec![];
+        self.free_vars(ty, &mut vars);
+        vars.contains(&var)
+    }
+}
+
+/// Name an inferred type parameter `a`, `b`, ..., `z`, `a1`, ...
+fn param_name(n: usize) -> String {
+    let letter = (b'a' + (n % 26) as u8) as char;
+    match n / 26 {
+        0 => letter.to_string(),
+        suffix => format!("{}{}", letter, suffix),
+    }
+}
+
+impl Display for Type {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        fn comma_list(types: &[Type]) -> String {
+            types
+                .iter()
+                .map(|ty| ty.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        }
 
-        (Type::Builtin(a), Type::Builtin(b)) if a == b => Ok(Type::Builtin(a)),
+        match self {
+            Self::Builtin(builtin) => write!(f, "{}", builtin),
+            Self::Var(_) => write!(f, "_"),
+            Self::Param(param) => write!(f, "{}", param.name),
+            Self::Constructor(name, args) if args.is_empty() => write!(f, "{}", name),
+            Self::Constructor(name, args) => write!(f, "{}<{}>", name, comma_list(args)),
+            Self::Function(params, ret) => write!(f, "fn({}) -> {}", comma_list(params), ret),
+            Self::Newtype(name) => write!(f, "{}", name),
+            Self::Unknown => write!(f, "{{unknown}}"),
+        }
+    }
+}
 
-        (a, b) => Err((a, b)),
+impl Display for BuiltinType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::String => "String",
+            Self::Integer => "Int",
+            Self::BigInt => "BigInt",
+            Self::Float => "Float",
+            Self::Bool => "Bool",
+            Self::Unit => "()",
+        };
+        write!(f, "{}", name)
     }
 }
@@ -0,0 +1,891 @@
+/// The longer description of a diagnostic code, as shown by
+/// `jellyfish explain`.
+pub struct Explanation {
+    pub code: &'static str,
+    pub title: &'static str,
+    pub description: &'static str,
+
+    /// A program with the problem, and the same program without it. Both
+    /// are empty if the problem can't be written in source.
+    pub wrong: &'static str,
+    pub fixed: &'static str,
+}
+
+impl Explanation {
+    /// The explanation of a code, ignoring case, such as `J0009` or the
+    /// name of a lint.
+    pub fn find(code: &str) -> Option<&'static Explanation> {
+        EXPLANATIONS
+            .iter()
+            .find(|explanation| explanation.code.eq_ignore_ascii_case(code))
+    }
+
+    /// The explanation as text to print.
+    pub fn render(&self) -> String {
+        let indent = |code: &str| {
+            code.lines()
+                .map(|line| match line {
+                    "" => "\n".to_string(),
+                    line => format!("    {}\n", line),
+                })
+                .collect::<String>()
+        };
+
+        let mut text = format!("{}: {}\n\n{}\n", self.code, self.title, self.description);
+        if !self.wrong.is_empty() {
+            text += &format!("\nFor example, this is wrong:\n\n{}", indent(self.wrong));
+            text += &format!("\nThis is how it could be fixed:\n\n{}", indent(self.fixed));
+        }
+        text
+    }
+}
+
+pub const EXPLANATIONS: &[Explanation] = &[
+    Explanation {
+        code: "J0001",
+        title: "unexpected token",
+        description: "\
+The parser found something other than what the grammar allows at that point,
+often because of a missing bracket, comma or semicolon just before it.",
+        wrong: "print((1 + 2);",
+        fixed: "print((1 + 2));",
+    },
+    Explanation {
+        code: "J0002",
+        title: "invalid assignment target",
+        description: "\
+Only a variable can be assigned to. The left-hand side of `=` was some other
+expression.",
+        wrong: "\
+let a = 1;
+a + 1 = 2;",
+        fixed: "\
+let a = 1;
+a = a + 1;
+print(a);",
+    },
+    Explanation {
+        code: "J0003",
+        title: "unresolved variable",
+        description: "\
+A name was used that no variable or function in scope has. It may be
+misspelt, declared later on, declared in a block that has already ended, or
+be a variable of the top level used inside a function, which functions can't
+see.",
+        wrong: "\
+let count = 1;
+print(cuont);",
+        fixed: "\
+let count = 1;
+print(count);",
+    },
+    Explanation {
+        code: "J0004",
+        title: "too many local variables",
+        description: "\
+A function, or the top level, may have at most 256 local variables in scope
+at once, since they're numbered with a single byte. Move some of the work
+into other functions, or group related values into a struct.",
+        wrong: "",
+        fixed: "",
+    },
+    Explanation {
+        code: "J0005",
+        title: "too many functions",
+        description: "\
+A program may have at most 256 functions and methods, counting the builtin
+ones, since they're numbered with a single byte.",
+        wrong: "",
+        fixed: "",
+    },
+    Explanation {
+        code: "J0006",
+        title: "duplicate definition",
+        description: "\
+Two functions, or two types, were declared with the same name. Each function
+and type of a program needs a name of its own.",
+        wrong: "\
+fn area() -> Int { return 1; }
+fn area() -> Int { return 2; }",
+        fixed: "\
+fn area() -> Int { return 1; }
+fn double_area() -> Int { return 2; }",
+    },
+    Explanation {
+        code: "J0007",
+        title: "nested item",
+        description: "\
+Functions, structs, newtypes, traits and impl blocks may only be declared at
+the top level of a program, not inside a function or block.",
+        wrong: "\
+fn outer() -> Int {
+    fn inner() -> Int { return 1; }
+    return inner();
+}",
+        fixed: "\
+fn inner() -> Int { return 1; }
+
+fn outer() -> Int {
+    return inner();
+}",
+    },
+    Explanation {
+        code: "J0008",
+        title: "invalid assignment target",
+        description: "\
+A function was assigned to, as if it were a variable. Functions can't be
+changed once they're declared; use a variable to hold a value that changes.",
+        wrong: "\
+fn limit() -> Int { return 1; }
+limit = 2;",
+        fixed: "\
+let limit = 1;
+limit = 2;
+print(limit);",
+    },
+    Explanation {
+        code: "J0009",
+        title: "mismatched types",
+        description: "\
+A value has a different type to the one it's required to have, such as the
+annotated type of a variable, the type of a parameter, or the type a
+function returns.",
+        wrong: "\
+let a: Int = \"one\";
+print(a);",
+        fixed: "\
+let a: Int = 1;
+print(a);",
+    },
+    Explanation {
+        code: "J0010",
+        title: "missing return",
+        description: "\
+A function with a return type can reach the end of its body without
+returning a value. Every path through it must end with `return`.",
+        wrong: "\
+fn sign(n: Int) -> Int {
+    if n < 0 {
+        return -1;
+    }
+}",
+        fixed: "\
+fn sign(n: Int) -> Int {
+    if n < 0 {
+        return -1;
+    }
+    return 1;
+}",
+    },
+    Explanation {
+        code: "J0011",
+        title: "wrong number of arguments",
+        description: "A function was called with more or fewer arguments than it has parameters.",
+        wrong: "\
+fn add(a: Int, b: Int) -> Int {
+    return a + b;
+}
+print(add(1));",
+        fixed: "\
+fn add(a: Int, b: Int) -> Int {
+    return a + b;
+}
+print(add(1, 2));",
+    },
+    Explanation {
+        code: "J0012",
+        title: "wrong number of type arguments",
+        description: "\
+A generic type was given more or fewer type arguments than it has type
+parameters.",
+        wrong: "\
+struct Pair<A, B> {
+    first: A,
+    second: B,
+}
+fn first(pair: Pair<Int>) -> Int {
+    return pair.first;
+}",
+        fixed: "\
+struct Pair<A, B> {
+    first: A,
+    second: B,
+}
+fn first(pair: Pair<Int, Int>) -> Int {
+    return pair.first;
+}",
+    },
+    Explanation {
+        code: "J0013",
+        title: "unknown type",
+        description: "A type was named that isn't builtin and isn't declared anywhere in the program.",
+        wrong: "\
+let a: Integer = 1;
+print(a);",
+        fixed: "\
+let a: Int = 1;
+print(a);",
+    },
+    Explanation {
+        code: "J0014",
+        title: "unknown field",
+        description: "A field was accessed that the struct doesn't have, or on a value that isn't a struct.",
+        wrong: "\
+struct Point {
+    x: Int,
+    y: Int,
+}
+let p = Point { x: 1, y: 2 };
+print(p.z);",
+        fixed: "\
+struct Point {
+    x: Int,
+    y: Int,
+}
+let p = Point { x: 1, y: 2 };
+print(p.y);",
+    },
+    Explanation {
+        code: "J0015",
+        title: "missing field",
+        description: "A struct literal doesn't give a value to every field of the struct.",
+        wrong: "\
+struct Point {
+    x: Int,
+    y: Int,
+}
+let p = Point { x: 1 };
+print(p.x);",
+        fixed: "\
+struct Point {
+    x: Int,
+    y: Int,
+}
+let p = Point { x: 1, y: 0 };
+print(p.x);",
+    },
+    Explanation {
+        code: "J0016",
+        title: "duplicate field",
+        description: "\
+A struct declares the same field twice, or a struct literal gives the same
+field a value twice.",
+        wrong: "\
+struct Point {
+    x: Int,
+    y: Int,
+}
+let p = Point { x: 1, x: 2, y: 3 };
+print(p.x);",
+        fixed: "\
+struct Point {
+    x: Int,
+    y: Int,
+}
+let p = Point { x: 1, y: 3 };
+print(p.x);",
+    },
+    Explanation {
+        code: "J0017",
+        title: "type annotation needed",
+        description: "\
+The type of a value has to be known where it's used, such as to look up a
+field or method on it, but nothing before that point says what it is.
+Annotating the type of the variable or parameter fixes it.",
+        wrong: "\
+struct Point {
+    x: Int,
+    y: Int,
+}
+fn get_x(p) -> Int {
+    return p.x;
+}",
+        fixed: "\
+struct Point {
+    x: Int,
+    y: Int,
+}
+fn get_x(p: Point) -> Int {
+    return p.x;
+}",
+    },
+    Explanation {
+        code: "J0018",
+        title: "return outside function",
+        description: "`return` was used at the top level, where there's no function to return from.",
+        wrong: "\
+let a = 1;
+return a;",
+        fixed: "\
+fn one() -> Int {
+    let a = 1;
+    return a;
+}
+print(one());",
+    },
+    Explanation {
+        code: "J0019",
+        title: "unknown trait",
+        description: "A trait was named, in a bound or an impl block, that isn't declared anywhere in the program.",
+        wrong: "\
+fn show<T: Describe>(value: T) {
+    print(value);
+}",
+        fixed: "\
+trait Describe {
+    fn describe(self) -> String;
+}
+fn show<T: Describe>(value: T) {
+    print(value.describe());
+}",
+    },
+    Explanation {
+        code: "J0020",
+        title: "expected a trait",
+        description: "\
+In `impl Trait for Type`, the name before `for` must be a plain trait name,
+without type arguments.",
+        wrong: "\
+trait Describe {
+    fn describe(self) -> String;
+}
+impl Describe<Int> for Int {
+    fn describe(self) -> String { return \"an integer\"; }
+}",
+        fixed: "\
+trait Describe {
+    fn describe(self) -> String;
+}
+impl Describe for Int {
+    fn describe(self) -> String { return \"an integer\"; }
+}",
+    },
+    Explanation {
+        code: "J0021",
+        title: "unknown method",
+        description: "\
+A method was called that the type of the receiver doesn't have, either in
+an impl block of its own or in an implementation of a trait.",
+        wrong: "\
+struct Point {
+    x: Int,
+    y: Int,
+}
+let p = Point { x: 1, y: 2 };
+print(p.sum());",
+        fixed: "\
+struct Point {
+    x: Int,
+    y: Int,
+}
+impl Point {
+    fn sum(self) -> Int { return self.x + self.y; }
+}
+let p = Point { x: 1, y: 2 };
+print(p.sum());",
+    },
+    Explanation {
+        code: "J0022",
+        title: "unsatisfied bound",
+        description: "\
+A generic function was called with a type that doesn't implement a trait its
+type parameter is bound by.",
+        wrong: "\
+trait Describe {
+    fn describe(self) -> String;
+}
+fn show<T: Describe>(value: T) {
+    print(value.describe());
+}
+show(1);",
+        fixed: "\
+trait Describe {
+    fn describe(self) -> String;
+}
+impl Describe for Int {
+    fn describe(self) -> String { return \"an integer\"; }
+}
+fn show<T: Describe>(value: T) {
+    print(value.describe());
+}
+show(1);",
+    },
+    Explanation {
+        code: "J0023",
+        title: "missing `self`",
+        description: "\
+Every method in an impl block or trait must take `self` as its first
+parameter. Functions that don't need a receiver belong at the top level.",
+        wrong: "\
+struct Point {
+    x: Int,
+    y: Int,
+}
+impl Point {
+    fn origin() -> Point { return Point { x: 0, y: 0 }; }
+}",
+        fixed: "\
+struct Point {
+    x: Int,
+    y: Int,
+}
+fn origin() -> Point {
+    return Point { x: 0, y: 0 };
+}",
+    },
+    Explanation {
+        code: "J0024",
+        title: "missing trait method",
+        description: "An implementation of a trait doesn't implement every method of the trait.",
+        wrong: "\
+trait Shape {
+    fn area(self) -> Int;
+    fn sides(self) -> Int;
+}
+impl Shape for Int {
+    fn area(self) -> Int { return self * self; }
+}",
+        fixed: "\
+trait Shape {
+    fn area(self) -> Int;
+    fn sides(self) -> Int;
+}
+impl Shape for Int {
+    fn area(self) -> Int { return self * self; }
+    fn sides(self) -> Int { return 4; }
+}",
+    },
+    Explanation {
+        code: "J0025",
+        title: "method not in trait",
+        description: "\
+An implementation of a trait has a method the trait doesn't declare. Methods
+of the type's own go in an impl block without a trait.",
+        wrong: "\
+trait Shape {
+    fn area(self) -> Int;
+}
+impl Shape for Int {
+    fn area(self) -> Int { return self * self; }
+    fn sides(self) -> Int { return 4; }
+}",
+        fixed: "\
+trait Shape {
+    fn area(self) -> Int;
+}
+impl Shape for Int {
+    fn area(self) -> Int { return self * self; }
+}
+impl Int {
+    fn sides(self) -> Int { return 4; }
+}",
+    },
+    Explanation {
+        code: "J0026",
+        title: "invalid impl target",
+        description: "\
+Methods can only be implemented for builtin types, structs and newtypes. A
+generic struct must be implemented for all of its type arguments at once,
+with a distinct type parameter for each.",
+        wrong: "\
+struct Wrapper<T> {
+    inner: T,
+}
+impl Wrapper<Int> {
+    fn get(self) -> Int { return self.inner; }
+}",
+        fixed: "\
+struct Wrapper<T> {
+    inner: T,
+}
+impl<T> Wrapper<T> {
+    fn get(self) -> T { return self.inner; }
+}",
+    },
+    Explanation {
+        code: "J0027",
+        title: "invalid cast",
+        description: "\
+`as` only converts between a newtype and the type it's based on. Other
+conversions are done by builtin functions.",
+        wrong: "\
+type UserId = new Int;
+let id = \"7\" as UserId;
+print(id as Int);",
+        fixed: "\
+type UserId = new Int;
+let id = 7 as UserId;
+print(id as Int);",
+    },
+    Explanation {
+        code: "J0028",
+        title: "`?` outside function",
+        description: "\
+`?` returns early from the function it's in when a value is `None` or an
+`Err`, so it can't be used at the top level. Use `match` there instead.",
+        wrong: "\
+let a = Some(1)?;
+print(a);",
+        fixed: "\
+match Some(1) {
+    Some(a) => { print(a); }
+    None => {}
+}",
+    },
+    Explanation {
+        code: "J0029",
+        title: "invalid use of `?`",
+        description: "\
+`?` was applied to a value that isn't an `Option` or a `Result`. Other enums
+have no variant that's known to be the value, so they have to be matched.",
+        wrong: "\
+fn increment(n: Int) -> Option<Int> {
+    return Some(n? + 1);
+}",
+        fixed: "\
+fn increment(n: Option<Int>) -> Option<Int> {
+    return Some(n? + 1);
+}",
+    },
+    Explanation {
+        code: "J0030",
+        title: "cannot match",
+        description: "\
+`match` was used on a value that isn't an enum, like `Option`, `Result` or one
+declared with `enum`, which are the only types with variants to match. Use
+`if` to compare other values.",
+        wrong: "\
+match 1 {
+    _ => { print(\"one\"); }
+}",
+        fixed: "\
+if 1 == 1 {
+    print(\"one\");
+}",
+    },
+    Explanation {
+        code: "J0031",
+        title: "unknown variant",
+        description: "\
+A pattern names a variant the matched type doesn't have. An `Option` has
+`Some` and `None`, a `Result` has `Ok` and `Err`, and other enums have the
+variants they're declared with.",
+        wrong: "\
+match Some(1) {
+    Some(n) => { print(n); }
+    Nothing => {}
+}",
+        fixed: "\
+match Some(1) {
+    Some(n) => { print(n); }
+    None => {}
+}",
+    },
+    Explanation {
+        code: "J0032",
+        title: "non-exhaustive match",
+        description: "\
+A `match` doesn't have an arm for every variant of the matched type. Add the
+missing arms, or a `_` arm for the rest.",
+        wrong: "\
+match Some(1) {
+    Some(n) => { print(n); }
+}",
+        fixed: "\
+match Some(1) {
+    Some(n) => { print(n); }
+    _ => {}
+}",
+    },
+    Explanation {
+        code: "J0033",
+        title: "error in constant expression",
+        description: "\
+An expression made only of literals always fails when it's evaluated, so the
+program would always panic there. Errors inside a `try` body are left to
+happen at runtime, where they can be recovered from. So are errors inside a
+function, since it might only be called inside a `try`, and inside a branch or
+loop that might never run, but they're warned about. Branches that can never
+run, like `if false { ... }`, aren't checked at all.",
+        wrong: "\
+let a = 1 / 0;
+print(a);",
+        fixed: "\
+try {
+    let a = 1 / 0;
+    print(a);
+} recover err {
+    print(err.message);
+}",
+    },
+    Explanation {
+        code: "J0034",
+        title: "unsatisfied bound",
+        description: "\
+A newtype was passed where a type parameter is bound by a trait that the
+newtype implements. Generic code finds methods by the type of a value at
+runtime, and at runtime a newtype is just its base type, so the newtype's own
+methods can't be found there. Call the method on the value directly instead.",
+        wrong: "\
+trait Describe {
+    fn describe(self) -> String;
+}
+type UserId = new Int;
+impl Describe for UserId {
+    fn describe(self) -> String { return \"a user\"; }
+}
+fn show<T: Describe>(value: T) {
+    print(value.describe());
+}
+show(7 as UserId);",
+        fixed: "\
+trait Describe {
+    fn describe(self) -> String;
+}
+type UserId = new Int;
+impl Describe for UserId {
+    fn describe(self) -> String { return \"a user\"; }
+}
+let id = 7 as UserId;
+print(id.describe());",
+    },
+    Explanation {
+        code: "J0035",
+        title: "too many traits",
+        description: "\
+A program may have at most 256 traits, counting the builtin ones, since
+they're numbered with a single byte. Merge traits that are always implemented
+together.",
+        wrong: "",
+        fixed: "",
+    },
+    Explanation {
+        code: "J0036",
+        title: "too many fields",
+        description: "\
+A struct may have at most 256 fields, since they're numbered with a single
+byte. Group related fields into structs of their own.",
+        wrong: "",
+        fixed: "",
+    },
+    Explanation {
+        code: "J0037",
+        title: "too many variants",
+        description: "\
+An enum may have at most 256 variants, since they're numbered with a single
+byte. Group related variants into enums of their own, held by one variant.",
+        wrong: "",
+        fixed: "",
+    },
+    Explanation {
+        code: "J1001",
+        title: "integer overflow",
+        description: "\
+An operation on `Int`s gave a result too large or small to fit in 64 bits.
+The `wrapping_` and `saturating_` builtins give a result that fits anyway,
+and `BigInt` has no limit.",
+        wrong: "\
+let max = 9223372036854775807;
+let one = 1;
+print(max + one);",
+        fixed: "\
+let max = 9223372036854775807;
+let one = 1;
+print(saturating_add(max, one));",
+    },
+    Explanation {
+        code: "J1002",
+        title: "division by zero",
+        description: "An `Int` was divided by zero, or the remainder of dividing by zero was taken.",
+        wrong: "\
+fn ratio(a: Int, b: Int) -> Int {
+    return a / b;
+}
+print(ratio(1, 0));",
+        fixed: "\
+fn ratio(a: Int, b: Int) -> Int {
+    if b == 0 {
+        return 0;
+    }
+    return a / b;
+}
+print(ratio(1, 0));",
+    },
+    Explanation {
+        code: "J1003",
+        title: "negative exponent",
+        description: "An `Int` was raised to a negative power, which wouldn't give a whole number.",
+        wrong: "\
+let exponent = -1;
+print(2 ^ exponent);",
+        fixed: "\
+let exponent = 1;
+print(2 ^ exponent);",
+    },
+    Explanation {
+        code: "J1004",
+        title: "shift amount out of range",
+        description: "A shift with `<<` or `>>` was by a negative amount, or by 64 bits or more.",
+        wrong: "\
+let amount = 64;
+print(1 << amount);",
+        fixed: "\
+let amount = 3;
+print(1 << amount);",
+    },
+    Explanation {
+        code: "J1005",
+        title: "type error",
+        description: "\
+An instruction was given a value of the wrong type. The type checker rules
+this out for compiled programs, so it only happens when running bytecode that
+was damaged or not made by the compiler.",
+        wrong: "",
+        fixed: "",
+    },
+    Explanation {
+        code: "J1006",
+        title: "panic",
+        description: "\
+The program panicked, with `panic` or a builtin that failed, and no `try`
+statement recovered from it.",
+        wrong: "panic(\"something went wrong\");",
+        fixed: "\
+try {
+    panic(\"something went wrong\");
+} recover err {
+    print(err.message);
+}",
+    },
+    Explanation {
+        code: "J1007",
+        title: "invalid bytecode",
+        description: "\
+The bytecode did something the verifier can't rule out by itself, like calling
+a function with the wrong number of arguments or reading a field a struct
+doesn't have. Like J1005, this only happens when running bytecode that was
+damaged or not made by the compiler.",
+        wrong: "",
+        fixed: "",
+    },
+    Explanation {
+        code: "J1008",
+        title: "output closed",
+        description: "\
+Standard output was closed while the program was printing, usually because it
+was piped into a program that stopped reading, like `head`. The program stops,
+since nothing it prints can be read, but `jellyfish` exits successfully and
+doesn't report it.",
+        wrong: "",
+        fixed: "",
+    },
+    Explanation {
+        code: "J1009",
+        title: "stack overflow",
+        description: "\
+Functions called each other too deeply, usually because a recursive function
+never reaches the case that stops it. At most 65536 calls can be running at
+once. It can be recovered from with `try`, like any other runtime error.",
+        wrong: "\
+fn count(n: Int) -> Int {
+    return count(n - 1) + 1;
+}
+let _c = count(3);",
+        fixed: "\
+fn count(n: Int) -> Int {
+    if n == 0 {
+        return 0;
+    }
+    return count(n - 1) + 1;
+}
+let _c = count(3);",
+    },
+    Explanation {
+        code: "unused_variable",
+        title: "unused variable",
+        description: "\
+A variable or parameter is never read. It may be left over from an earlier
+version of the code, or a different variable may have been used by mistake.
+Names starting with `_` are never reported.",
+        wrong: "\
+let width = 2;
+let height = 3;
+print(width * width);",
+        fixed: "\
+let width = 2;
+let height = 3;
+print(width * height);",
+    },
+    Explanation {
+        code: "unused_assignment",
+        title: "unused assignment",
+        description: "\
+A value is assigned to a variable, but always assigned again or goes out of
+scope before it's read, so the assignment has no effect.",
+        wrong: "\
+let total = 0;
+total = 1;
+total = 2;
+print(total);",
+        fixed: "\
+let total = 0;
+total = total + 1;
+total = total + 2;
+print(total);",
+    },
+    Explanation {
+        code: "shadowed_binding",
+        title: "shadowed binding",
+        description: "\
+A variable is declared with the same name as one already in scope, which
+hides it until the end of the block. Using a different name avoids
+confusing the two.",
+        wrong: "\
+let total = 1;
+{
+    let total = 2;
+    print(total);
+}
+print(total);",
+        fixed: "\
+let total = 1;
+{
+    let subtotal = 2;
+    print(subtotal);
+}
+print(total);",
+    },
+    Explanation {
+        code: "unreachable_code",
+        title: "unreachable code",
+        description: "Statements follow one that always returns, so they never run.",
+        wrong: "\
+fn one() -> Int {
+    return 1;
+    print(\"done\");
+}",
+        fixed: "\
+fn one() -> Int {
+    print(\"done\");
+    return 1;
+}",
+    },
+    Explanation {
+        code: "constant_condition",
+        title: "constant condition",
+        description: "\
+The condition of a `while` loop is always false, so the loop never runs, or
+always true with nothing in the loop returning or panicking, so only a
+runtime error can end it.",
+        wrong: "\
+while 1 > 2 {
+    print(\"never\");
+}",
+        fixed: "\
+let i = 0;
+while i < 2 {
+    print(i);
+    i = i + 1;
+}",
+    },
+];
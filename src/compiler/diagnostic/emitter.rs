@@ -0,0 +1,319 @@
+use std::io::{self, IsTerminal, Write};
+
+use ansi_term::{Colour, Style};
+
+use crate::compiler::diagnostic::{Diagnostic, ErrorReport, Severity};
+use crate::source::{display_width, expand_tabs, Source, Span};
+
+/// Renders reports somewhere: a terminal, a file, or a host's own UI.
+pub trait Emitter {
+    fn emit(&mut self, report: &ErrorReport, source: &Source) -> io::Result<()>;
+
+    /// Render every report from compiling a source.
+    fn emit_all(&mut self, reports: &[ErrorReport], source: &Source) -> io::Result<()> {
+        reports
+            .iter()
+            .try_for_each(|report| self.emit(report, source))
+    }
+}
+
+/// Whether to render reports with ANSI colours.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorChoice {
+    /// Colour when writing to a terminal, unless `NO_COLOR` is set.
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    /// Whether to colour what's written to a stream.
+    pub fn enabled(self, stream: &impl IsTerminal) -> bool {
+        match self {
+            Self::Auto => std::env::var_os("NO_COLOR").is_none() && stream.is_terminal(),
+            Self::Always => true,
+            Self::Never => false,
+        }
+    }
+}
+
+/// Renders reports as text with the source lines they point at, for
+/// people.
+pub struct HumanEmitter<W> {
+    out: W,
+    color: bool,
+}
+
+impl<W: Write> HumanEmitter<W> {
+    pub fn new(out: W, color: bool) -> Self {
+        Self { out, color }
+    }
+}
+
+impl<W: Write> Emitter for HumanEmitter<W> {
+    fn emit(&mut self, report: &ErrorReport, source: &Source) -> io::Result<()> {
+        let out = &mut self.out;
+        let color = self.color;
+        let style = |style: Style| if color { style } else { Style::new() };
+
+        let severity_style = style(Style::new().fg(report.severity.colour()).bold());
+
+        let label_style = style(Style::new().fg(Colour::Blue).bold());
+        let note_style = style(Style::new().fg(Colour::Cyan).bold());
+        let hint_style = style(Style::new().fg(Colour::Green).bold());
+
+        let line_num_style = style(Style::new().fg(Colour::White).dimmed());
+        let underline_style = style(Style::new().fg(report.severity.colour()));
+
+        writeln!(
+            out,
+            "{}: {}",
+            severity_style.paint(heading(report)),
+            report.title
+        )?;
+
+        for label in &report.labels {
+            if let Some(span) = label.span {
+                let span = span.overlap(source.file_span());
+                let loc = source.line_col(span.start);
+                let header = format!("in `{}`, line {}, col {}:", source.name, loc.line, loc.col);
+                writeln!(out, "   {}", label_style.paint(header))?;
+
+                let rows = snippet(source, span);
+                let number_width = rows
+                    .iter()
+                    .filter_map(|row| row.line)
+                    .map(|line| line.to_string().len())
+                    .max()
+                    .unwrap_or(0);
+
+                for row in rows {
+                    let gutter = match (row.line, row.underline) {
+                        (Some(line), _) => format!("{:>1$} | ", line, number_width),
+                        (None, true) => " ".repeat(number_width + 3),
+                        (None, false) => format!("{:<1$}", "...", number_width + 3),
+                    };
+                    let text = match row.underline {
+                        true => underline_style.paint(row.text),
+                        false => Style::new().paint(row.text),
+                    };
+                    writeln!(
+                        out,
+                        "      {}{}{}",
+                        line_num_style.paint(gutter),
+                        underline_style.paint(row.marker),
+                        text
+                    )?;
+                }
+            }
+
+            writeln!(out, "   {}: {}", label_style.paint("msg"), label.msg)?;
+        }
+
+        for note in &report.notes {
+            writeln!(out, "   {}: {}", note_style.paint("note"), note)?;
+        }
+
+        for hint in &report.hints {
+            writeln!(out, "   {}: {}", hint_style.paint("hint"), hint)?;
+        }
+
+        writeln!(out)
+    }
+
+    fn emit_all(&mut self, reports: &[ErrorReport], source: &Source) -> io::Result<()> {
+        if reports
+            .iter()
+            .any(|report| report.severity == Severity::Error)
+        {
+            writeln!(self.out, "ENCOUNTERED ERROR(S) WHILE COMPILING:\n")?;
+        }
+
+        reports
+            .iter()
+            .try_for_each(|report| self.emit(report, source))
+    }
+}
+
+/// Renders each report as a line of JSON, for tools.
+pub struct JsonEmitter<W> {
+    out: W,
+}
+
+impl<W: Write> JsonEmitter<W> {
+    pub fn new(out: W) -> Self {
+        Self { out }
+    }
+}
+
+impl<W: Write> Emitter for JsonEmitter<W> {
+    fn emit(&mut self, report: &ErrorReport, source: &Source) -> io::Result<()> {
+        writeln!(self.out, "{}", Diagnostic::new(report, source).to_json())
+    }
+}
+
+/// Renders each report on one line, as `file:line:col: error[code]: msg`,
+/// the form editors know how to jump to.
+pub struct ShortEmitter<W> {
+    out: W,
+    color: bool,
+}
+
+impl<W: Write> ShortEmitter<W> {
+    pub fn new(out: W, color: bool) -> Self {
+        Self { out, color }
+    }
+}
+
+impl<W: Write> Emitter for ShortEmitter<W> {
+    fn emit(&mut self, report: &ErrorReport, source: &Source) -> io::Result<()> {
+        let severity_style = match self.color {
+            true => Style::new().fg(report.severity.colour()).bold(),
+            false => Style::new(),
+        };
+
+        // the first label that points somewhere says where the report is
+        let primary = report.labels.iter().find(|label| label.span.is_some());
+        let location = match primary.and_then(|label| label.span) {
+            Some(span) => {
+                let loc = source.line_col(span.overlap(source.file_span()).start);
+                format!("{}:{}:{}", source.name, loc.line, loc.col)
+            }
+            None => source.name.clone(),
+        };
+
+        let message = match primary.or(report.labels.first()) {
+            Some(label) => format!("{}: {}", report.title, label.msg),
+            None => report.title.to_string(),
+        };
+
+        writeln!(
+            self.out,
+            "{}: {}: {}",
+            location,
+            severity_style.paint(heading(report)),
+            message
+        )
+    }
+}
+
+/// The severity and code a report starts with, such as `error[J0009]`.
+fn heading(report: &ErrorReport) -> String {
+    match report.code {
+        Some(code) => format!("{}[{}]", report.severity.name(), code),
+        None => report.severity.name().to_string(),
+    }
+}
+
+/// How many lines around a span are shown with it.
+const CONTEXT_LINES: usize = 1;
+
+/// The most lines of a span that are shown before the middle is left out.
+const MAX_SPAN_LINES: usize = 6;
+
+/// A line of a source snippet, under a gutter with the line number.
+struct SnippetRow {
+    /// The line of source shown, or `None` for an underline or for lines
+    /// that were left out.
+    line: Option<usize>,
+
+    /// What's drawn between the gutter and the text to mark the lines a
+    /// span covers. Empty unless the span covers more than one line.
+    marker: String,
+    text: String,
+    underline: bool,
+}
+
+/// The lines to show for a span: its own lines with the span underlined,
+/// and a line of context on either side. A span over several lines is
+/// drawn as a bracket down the left of them, joined to where it starts
+/// and ends.
+fn snippet(source: &Source, span: Span) -> Vec<SnippetRow> {
+    // where the last character of the span starts
+    let end = match source.source[..span.end].chars().next_back() {
+        Some(c) if span.len() > 0 => span.end - c.len_utf8(),
+        _ => span.start,
+    };
+
+    let first = source.line_index(span.start);
+    let last = source.line_index(end);
+    let last_line = source.line_index(source.source.len());
+
+    let line_text = |index: usize| {
+        let start = source.line_start(index).unwrap_or_default();
+        expand_tabs(source.span_str(source.line_span(start)).trim_end())
+    };
+    let source_row = |index: usize, marker: &str| SnippetRow {
+        line: Some(index + 1),
+        marker: marker.to_string(),
+        text: line_text(index),
+        underline: false,
+    };
+    let underline_row = |marker: &str, text: String| SnippetRow {
+        line: None,
+        marker: marker.to_string(),
+        text,
+        underline: true,
+    };
+    let is_blank = |index: usize| line_text(index).is_empty();
+
+    let multiline = first != last;
+    let blank_marker = if multiline { "  " } else { "" };
+
+    let mut rows = vec![];
+    for index in first.saturating_sub(CONTEXT_LINES)..first {
+        if !is_blank(index) {
+            rows.push(source_row(index, blank_marker));
+        }
+    }
+
+    let start_col = source.display_col(span.start);
+    if !multiline {
+        let end = span.end.min(source.line_span(span.start).end);
+        let width = display_width(source.span_str(Span {
+            start: span.start,
+            end,
+        }));
+        rows.push(source_row(first, ""));
+        rows.push(underline_row(
+            "",
+            " ".repeat(start_col) + &"^".repeat(width.max(1)),
+        ));
+    } else {
+        let indent =
+            display_width(&line_text(first)) - display_width(line_text(first).trim_start());
+        if start_col <= indent {
+            rows.push(source_row(first, "/ "));
+        } else {
+            rows.push(source_row(first, "  "));
+            rows.push(underline_row(" _", "_".repeat(start_col) + "^"));
+        }
+
+        for index in first + 1..last {
+            let elided = last - first + 1 > MAX_SPAN_LINES;
+            if elided && index == first + MAX_SPAN_LINES / 2 {
+                rows.push(SnippetRow {
+                    line: None,
+                    marker: "| ".to_string(),
+                    text: String::new(),
+                    underline: false,
+                });
+            }
+            if !elided || index < first + MAX_SPAN_LINES / 2 || index + MAX_SPAN_LINES / 2 > last {
+                rows.push(source_row(index, "| "));
+            }
+        }
+
+        rows.push(source_row(last, "| "));
+        let end_col = source.display_col(end);
+        rows.push(underline_row("|_", "_".repeat(end_col) + "^"));
+    }
+
+    for index in last + 1..=(last + CONTEXT_LINES).min(last_line) {
+        if !is_blank(index) {
+            rows.push(source_row(index, blank_marker));
+        }
+    }
+
+    rows
+}
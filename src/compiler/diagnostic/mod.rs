@@ -1,43 +1,235 @@
-use ansi_term::{Colour, Style};
+use std::io::{self, Write};
+
+use ansi_term::Colour;
 use internment::Intern;
 
-use crate::compiler::ast::Expr;
+use crate::compiler::ast::Type;
 use crate::compiler::lexer::token::{Token, TokenKind};
+use crate::runtime::vm::RuntimeError;
 use crate::source::{Source, Span};
 
+pub use self::codes::{Explanation, EXPLANATIONS};
+pub use self::emitter::{ColorChoice, Emitter, HumanEmitter, JsonEmitter, ShortEmitter};
+pub use self::structured::{Diagnostic, DiagnosticLabel, Position};
+
+mod codes;
+mod emitter;
+mod structured;
+
 pub type JlyResult<T> = Result<T, Error>;
 
+#[derive(Clone)]
 pub enum Error {
-    UnexpectedToken { expected: TokenKind, found: Token },
+    UnexpectedToken {
+        expected: TokenKind,
+        found: Token,
+    },
     Expected(&'static str, Token),
-    InvalidAssignmentTarget(Expr),
+    InvalidAssignmentTarget(Span),
+
+    UnresolvedVariable {
+        ident: Intern<String>,
+        span: Span,
+
+        /// The name in scope that's closest to it, if any is close enough.
+        similar: Option<Intern<String>>,
 
-    UnresolvedVariable(Intern<String>),
+        /// Where it's declared, if that's after it's used.
+        declared: Option<Span>,
+    },
     TooManyLocals(Span),
+    TooManyFunctions(Span),
+    DuplicateDefinition(Intern<String>, Span),
+    NestedItem(Span),
+    AssignToFunction(Intern<String>, Span),
+
+    TypeMismatch {
+        expected: Type,
+        found: Type,
+        span: Span,
+    },
+    MissingReturn {
+        expected: Type,
+        span: Span,
+    },
+    WrongArgCount {
+        expected: usize,
+        found: usize,
+        span: Span,
+    },
+    WrongTypeArgCount {
+        expected: usize,
+        found: usize,
+        span: Span,
+    },
+    UnknownType(Intern<String>, Span),
+    UnknownField {
+        ty: Type,
+        field: Intern<String>,
+        span: Span,
+    },
+    MissingField {
+        ty: Intern<String>,
+        field: Intern<String>,
+        span: Span,
+    },
+    DuplicateField(Intern<String>, Span),
+    TypeAnnotationNeeded(Span),
+    ReturnOutsideFunction(Span),
+
+    UnknownTrait(Intern<String>, Span),
+    TooManyTraits(Span),
+    TooManyFields(Span),
+    TooManyVariants(Span),
+    NotATrait(Span),
+    UnknownMethod {
+        ty: Type,
+        method: Intern<String>,
+        span: Span,
+    },
+    UnsatisfiedBound {
+        ty: Type,
+        trait_: Intern<String>,
+        span: Span,
+    },
+    /// A newtype whose impl of a trait can't be used by generic code,
+    /// which sees only its base type at runtime.
+    NewtypeBound {
+        ty: Intern<String>,
+        base: Type,
+        trait_: Intern<String>,
+        span: Span,
+    },
+    MissingSelf(Span),
+    MissingTraitMethod {
+        trait_: Intern<String>,
+        method: Intern<String>,
+        span: Span,
+    },
+    NotInTrait {
+        trait_: Intern<String>,
+        method: Intern<String>,
+        span: Span,
+    },
+    InvalidImplTarget(Span),
+
+    InvalidCast {
+        from: Type,
+        to: Type,
+        span: Span,
+    },
+
+    TryOutsideFunction(Span),
+    InvalidTry {
+        ty: Type,
+        span: Span,
+    },
+    CannotMatch {
+        ty: Type,
+        span: Span,
+    },
+    UnknownVariant {
+        ty: Type,
+        variant: Intern<String>,
+        span: Span,
+    },
+    NonExhaustiveMatch {
+        missing: Intern<String>,
+        span: Span,
+    },
+
+    /// A constant expression that fails when it's folded.
+    ConstantEvaluation {
+        error: RuntimeError,
+        span: Span,
+    },
 }
 
 impl Error {
-    pub fn report(&self) -> ErrorReport {
+    /// The stable code of the error, which `jellyfish explain` describes.
+    pub fn code(&self) -> &'static str {
         match self {
+            Self::UnexpectedToken { .. } | Self::Expected(..) => "J0001",
+            Self::InvalidAssignmentTarget(_) => "J0002",
+            Self::UnresolvedVariable { .. } => "J0003",
+            Self::TooManyLocals(_) => "J0004",
+            Self::TooManyFunctions(_) => "J0005",
+            Self::DuplicateDefinition(..) => "J0006",
+            Self::NestedItem(_) => "J0007",
+            Self::AssignToFunction(..) => "J0008",
+            Self::TypeMismatch { .. } => "J0009",
+            Self::MissingReturn { .. } => "J0010",
+            Self::WrongArgCount { .. } => "J0011",
+            Self::WrongTypeArgCount { .. } => "J0012",
+            Self::UnknownType(..) => "J0013",
+            Self::UnknownField { .. } => "J0014",
+            Self::MissingField { .. } => "J0015",
+            Self::DuplicateField(..) => "J0016",
+            Self::TypeAnnotationNeeded(_) => "J0017",
+            Self::ReturnOutsideFunction(_) => "J0018",
+            Self::TooManyTraits(_) => "J0035",
+            Self::TooManyFields(_) => "J0036",
+            Self::TooManyVariants(_) => "J0037",
+            Self::UnknownTrait(..) => "J0019",
+            Self::NotATrait(_) => "J0020",
+            Self::UnknownMethod { .. } => "J0021",
+            Self::UnsatisfiedBound { .. } => "J0022",
+            Self::MissingSelf(_) => "J0023",
+            Self::MissingTraitMethod { .. } => "J0024",
+            Self::NotInTrait { .. } => "J0025",
+            Self::InvalidImplTarget(_) => "J0026",
+            Self::InvalidCast { .. } => "J0027",
+            Self::TryOutsideFunction(_) => "J0028",
+            Self::InvalidTry { .. } => "J0029",
+            Self::CannotMatch { .. } => "J0030",
+            Self::UnknownVariant { .. } => "J0031",
+            Self::NonExhaustiveMatch { .. } => "J0032",
+            Self::ConstantEvaluation { .. } => "J0033",
+            Self::NewtypeBound { .. } => "J0034",
+        }
+    }
+
+    pub fn report(&self) -> ErrorReport {
+        let report = match self {
             Self::UnexpectedToken { expected, found } => ErrorReport::new("unexpected token")
                 .with_labelled_source(
-                    format!("expected {:?} but found {:?}", expected, found.kind),
+                    format!("expected {} but found {}", expected, found.kind),
                     found.span,
                 ),
 
             Self::Expected(s, found) => ErrorReport::new("unexpected token").with_labelled_source(
-                format!("expected {} but found {:?}", s, found.kind),
+                format!("expected {} but found {}", s, found.kind),
                 found.span,
             ),
 
-            Self::InvalidAssignmentTarget(lhs) => ErrorReport::new("invalid assignment target")
+            Self::InvalidAssignmentTarget(span) => ErrorReport::new("invalid assignment target")
                 .with_labelled_source(
                     "expected a variable, found an expression".to_string(),
-                    lhs.span,
+                    *span,
                 ),
 
-            Self::UnresolvedVariable(ident) => ErrorReport::new("unresolved variable")
-                .with_label(format!("unresolved variable `{}`", ident)),
+            Self::UnresolvedVariable {
+                ident,
+                span,
+                similar,
+                declared,
+            } => {
+                let mut report = ErrorReport::new("unresolved variable")
+                    .with_labelled_source(format!("no variable `{}` in scope", ident), *span);
+                if let Some(declared) = declared {
+                    report = report.with_labelled_source(
+                        format!(
+                            "`{}` is declared here, and can only be used after it",
+                            ident
+                        ),
+                        *declared,
+                    );
+                }
+                if let Some(similar) = similar {
+                    report = report.with_hint(format!("did you mean `{}`?", similar));
+                }
+                report
+            }
 
             Self::TooManyLocals(span) => ErrorReport::new("too many local variables")
                 .with_labelled_source(
@@ -45,7 +237,197 @@ impl Error {
                     *span,
                 )
                 .with_note("why do you even have that many variables?".to_string()),
-        }
+
+            Self::TooManyFunctions(span) => ErrorReport::new("too many functions")
+                .with_labelled_source(
+                    "a maximum of 256 functions is allowed per program".to_string(),
+                    *span,
+                ),
+
+            Self::TooManyTraits(span) => ErrorReport::new("too many traits")
+                .with_labelled_source(
+                    "a maximum of 256 traits is allowed per program".to_string(),
+                    *span,
+                )
+                .with_note("the builtin traits count towards it".to_string()),
+
+            Self::TooManyFields(span) => ErrorReport::new("too many fields").with_labelled_source(
+                "a maximum of 256 fields is allowed per struct".to_string(),
+                *span,
+            ),
+
+            Self::TooManyVariants(span) => ErrorReport::new("too many variants")
+                .with_labelled_source(
+                    "a maximum of 256 variants is allowed per enum".to_string(),
+                    *span,
+                ),
+
+            Self::DuplicateDefinition(ident, span) => ErrorReport::new("duplicate definition")
+                .with_labelled_source(format!("`{}` is defined more than once", ident), *span),
+
+            Self::NestedItem(span) => ErrorReport::new("nested item").with_labelled_source(
+                "functions and structs may only be declared at the top level".to_string(),
+                *span,
+            ),
+
+            Self::AssignToFunction(ident, span) => ErrorReport::new("invalid assignment target")
+                .with_labelled_source(format!("cannot assign to function `{}`", ident), *span),
+
+            Self::TypeMismatch {
+                expected,
+                found,
+                span,
+            } => ErrorReport::new("mismatched types").with_labelled_source(
+                format!("expected `{}` but found `{}`", expected, found),
+                *span,
+            ),
+
+            Self::MissingReturn { expected, span } => ErrorReport::new("missing return")
+                .with_labelled_source(
+                    format!(
+                        "this function may finish without returning a value of type `{}`",
+                        expected
+                    ),
+                    *span,
+                ),
+
+            Self::WrongArgCount {
+                expected,
+                found,
+                span,
+            } => ErrorReport::new("wrong number of arguments").with_labelled_source(
+                format!("expected {} argument(s) but found {}", expected, found),
+                *span,
+            ),
+
+            Self::WrongTypeArgCount {
+                expected,
+                found,
+                span,
+            } => ErrorReport::new("wrong number of type arguments").with_labelled_source(
+                format!("expected {} type argument(s) but found {}", expected, found),
+                *span,
+            ),
+
+            Self::UnknownType(ident, span) => ErrorReport::new("unknown type")
+                .with_labelled_source(format!("no type named `{}`", ident), *span),
+
+            Self::UnknownField { ty, field, span } => ErrorReport::new("unknown field")
+                .with_labelled_source(format!("`{}` has no field `{}`", ty, field), *span),
+
+            Self::MissingField { ty, field, span } => ErrorReport::new("missing field")
+                .with_labelled_source(format!("field `{}` of `{}` isn't set", field, ty), *span),
+
+            Self::DuplicateField(field, span) => ErrorReport::new("duplicate field")
+                .with_labelled_source(format!("field `{}` appears more than once", field), *span),
+
+            Self::TypeAnnotationNeeded(span) => ErrorReport::new("type annotation needed")
+                .with_labelled_source("the type of this must be known here".to_string(), *span)
+                .with_hint("try annotating the type of the variable or parameter".to_string()),
+
+            Self::ReturnOutsideFunction(span) => ErrorReport::new("return outside function")
+                .with_labelled_source(
+                    "`return` may only be used inside a function".to_string(),
+                    *span,
+                ),
+
+            Self::UnknownTrait(ident, span) => ErrorReport::new("unknown trait")
+                .with_labelled_source(format!("no trait named `{}`", ident), *span),
+
+            Self::NotATrait(span) => ErrorReport::new("expected a trait")
+                .with_labelled_source("this isn't the name of a trait".to_string(), *span),
+
+            Self::UnknownMethod { ty, method, span } => ErrorReport::new("unknown method")
+                .with_labelled_source(format!("`{}` has no method `{}`", ty, method), *span),
+
+            Self::UnsatisfiedBound { ty, trait_, span } => ErrorReport::new("unsatisfied bound")
+                .with_labelled_source(format!("`{}` doesn't implement `{}`", ty, trait_), *span),
+
+            Self::NewtypeBound {
+                ty,
+                base,
+                trait_,
+                span,
+            } => ErrorReport::new("unsatisfied bound")
+                .with_labelled_source(
+                    format!(
+                        "`{}` implements `{}`, but generic code can't use a newtype's impls",
+                        ty, trait_
+                    ),
+                    *span,
+                )
+                .with_note(format!(
+                    "at runtime a `{}` is just its base type, `{}`",
+                    ty, base
+                ))
+                .with_hint("call the method on the value directly instead".to_string()),
+
+            Self::MissingSelf(span) => ErrorReport::new("missing `self`").with_labelled_source(
+                "methods must take `self` as their first parameter".to_string(),
+                *span,
+            ),
+
+            Self::MissingTraitMethod {
+                trait_,
+                method,
+                span,
+            } => ErrorReport::new("missing trait method").with_labelled_source(
+                format!("method `{}` of `{}` isn't implemented", method, trait_),
+                *span,
+            ),
+
+            Self::NotInTrait {
+                trait_,
+                method,
+                span,
+            } => ErrorReport::new("method not in trait").with_labelled_source(
+                format!("`{}` isn't a method of `{}`", method, trait_),
+                *span,
+            ),
+
+            Self::InvalidImplTarget(span) => ErrorReport::new("invalid impl target")
+                .with_labelled_source(
+                    "can only implement methods for builtin types and structs".to_string(),
+                    *span,
+                )
+                .with_note(
+                    "generic structs must be implemented for all of their type arguments"
+                        .to_string(),
+                ),
+
+            Self::InvalidCast { from, to, span } => ErrorReport::new("invalid cast")
+                .with_labelled_source(format!("can't convert `{}` to `{}`", from, to), *span)
+                .with_note(
+                    "`as` only converts between a newtype and its underlying type".to_string(),
+                ),
+
+            Self::TryOutsideFunction(span) => ErrorReport::new("`?` outside function")
+                .with_labelled_source("`?` may only be used inside a function".to_string(), *span),
+
+            Self::InvalidTry { ty, span } => ErrorReport::new("invalid use of `?`")
+                .with_labelled_source(format!("`?` can't be applied to `{}`", ty), *span)
+                .with_note("`?` only works on `Option` and `Result` values".to_string()),
+
+            Self::CannotMatch { ty, span } => ErrorReport::new("cannot match")
+                .with_labelled_source(format!("`{}` can't be matched on", ty), *span)
+                .with_note("only enums can be matched".to_string()),
+
+            Self::UnknownVariant { ty, variant, span } => ErrorReport::new("unknown variant")
+                .with_labelled_source(format!("`{}` has no variant `{}`", ty, variant), *span),
+
+            Self::NonExhaustiveMatch { missing, span } => ErrorReport::new("non-exhaustive match")
+                .with_labelled_source(format!("variant `{}` isn't covered", missing), *span),
+
+            Self::ConstantEvaluation { error, span } => {
+                ErrorReport::new("error in constant expression")
+                    .with_labelled_source(format!("this always fails with: {}", error), *span)
+                    .with_hint(
+                        "wrap it in a `try` statement to recover from it at runtime".to_string(),
+                    )
+            }
+        };
+
+        report.with_code(self.code())
     }
 }
 
@@ -55,8 +437,38 @@ pub struct Label {
     // TODO: Add Source IDs. (SourceSpan type?)
 }
 
+/// How serious a report is. Only errors stop a program from compiling.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+}
+
+impl Severity {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Error => "error",
+            Self::Warning => "warning",
+            Self::Note => "note",
+        }
+    }
+
+    fn colour(self) -> Colour {
+        match self {
+            Self::Error => Colour::Red,
+            Self::Warning => Colour::Yellow,
+            Self::Note => Colour::Cyan,
+        }
+    }
+}
+
 pub struct ErrorReport {
     pub title: &'static str,
+    pub severity: Severity,
+
+    /// What identifies the kind of problem, such as the name of a lint.
+    pub code: Option<&'static str>,
     pub labels: Vec<Label>,
 
     pub notes: Vec<String>,
@@ -67,6 +479,8 @@ impl ErrorReport {
     pub fn new(title: &'static str) -> Self {
         Self {
             title,
+            severity: Severity::Error,
+            code: None,
             labels: vec![],
 
             notes: vec![],
@@ -74,6 +488,20 @@ impl ErrorReport {
         }
     }
 
+    pub fn warning(title: &'static str) -> Self {
+        Self::new(title).with_severity(Severity::Warning)
+    }
+
+    pub fn with_severity(mut self, severity: Severity) -> Self {
+        self.severity = severity;
+        self
+    }
+
+    pub fn with_code(mut self, code: &'static str) -> Self {
+        self.code = Some(code);
+        self
+    }
+
     pub fn with_label(mut self, msg: String) -> Self {
         self.labels.push(Label { msg, span: None });
         self
@@ -96,87 +524,107 @@ impl ErrorReport {
         self.hints.push(hint);
         self
     }
+}
 
-    fn print(self, source: &Source) {
-        let err_style = Style::new().fg(Colour::Red).bold();
-
-        let label_style = Style::new().fg(Colour::Blue).bold();
-        let note_style = Style::new().fg(Colour::Cyan).bold();
-        let hint_style = Style::new().fg(Colour::Green).bold();
-
-        let line_num_style = Style::new().fg(Colour::White).dimmed();
-        let underline_style = Style::new().fg(Colour::Red);
-
-        eprintln!("{}: {}", err_style.paint("error"), self.title);
-
-        for label in self.labels {
-            if let Some(span) = label.span {
-                let loc = source.line_col(span.start);
-                let line_span = source.line_span(span.start);
-
-                let header = format!("in `{}`, line {}, col {}:", source.name, loc.line, loc.col);
+/// How reports are printed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorFormat {
+    /// Text with the source lines, for people.
+    Human,
 
-                let line_num = format!("{} | ", loc.line);
-                let line = source.span_str(line_span).trim_end();
+    /// A JSON object per line, for tools.
+    Json,
 
-                let mut underline = span.overlap(line_span).relative_to(line_span.start);
-                if underline.len() == 0 {
-                    underline.end += 1;
-                }
-
-                eprintln!("   {}", label_style.paint(header));
-                eprintln!("      {}{}", line_num_style.paint(&line_num), line);
-                eprintln!(
-                    "      {}{}",
-                    " ".repeat(line_num.len() + underline.start),
-                    underline_style.paint("^".repeat(underline.len()))
-                );
-            }
+    /// A line per report, starting with where it is, for editors.
+    Short,
+}
 
-            eprintln!("   {}: {}", label_style.paint("msg"), label.msg);
-        }
+pub struct ErrorReporter {
+    reports: Vec<ErrorReport>,
+    color: ColorChoice,
+    format: ErrorFormat,
+}
 
-        for note in self.notes {
-            eprintln!("   {}: {}", note_style.paint("note"), note);
+impl Default for ErrorReporter {
+    fn default() -> Self {
+        Self {
+            reports: vec![],
+            color: ColorChoice::Auto,
+            format: ErrorFormat::Human,
         }
+    }
+}
 
-        for hint in self.hints {
-            eprintln!("   {}: {}", hint_style.paint("hint"), hint);
-        }
+impl ErrorReporter {
+    pub fn set_format(&mut self, format: ErrorFormat) {
+        self.format = format;
+    }
 
-        eprintln!();
+    /// The reports so far, with their positions in the source worked out.
+    pub fn diagnostics(&self, source: &Source) -> Vec<Diagnostic> {
+        self.reports
+            .iter()
+            .map(|report| Diagnostic::new(report, source))
+            .collect()
     }
-}
 
-#[derive(Default)]
-pub struct ErrorReporter {
-    reports: Vec<ErrorReport>,
-}
+    /// Whether to render reports with ANSI colours.
+    pub fn set_color(&mut self, color: ColorChoice) {
+        self.color = color;
+    }
 
-impl ErrorReporter {
     pub fn report(&mut self, err: ErrorReport) {
         self.reports.push(err);
     }
 
+    pub fn reports(&self) -> &[ErrorReport] {
+        &self.reports
+    }
+
+    /// Whether any of the reports is an error, rather than a warning or
+    /// a note.
     pub fn had_errors(&self) -> bool {
-        !self.reports.is_empty()
+        self.reports
+            .iter()
+            .any(|report| report.severity == Severity::Error)
     }
 
     #[allow(clippy::result_unit_err)]
     pub fn assert_ok(&self) -> Result<(), ()> {
-        match self.reports.is_empty() {
-            true => Ok(()),
-            false => Err(()),
+        match self.had_errors() {
+            false => Ok(()),
+            true => Err(()),
         }
     }
 
-    pub fn print(self, source: &Source) {
-        if self.had_errors() {
-            eprintln!("ENCOUNTERED ERROR(S) WHILE COMPILING:\n");
-
-            for report in self.reports {
-                report.print(source);
-            }
+    /// An emitter writing to `out` in the chosen format.
+    pub fn emitter<'a>(&self, out: impl Write + 'a, color: bool) -> Box<dyn Emitter + 'a> {
+        match self.format {
+            ErrorFormat::Human => Box::new(HumanEmitter::new(out, color)),
+            ErrorFormat::Json => Box::new(JsonEmitter::new(out)),
+            ErrorFormat::Short => Box::new(ShortEmitter::new(out, color)),
         }
     }
+
+    /// Render the reports with an emitter.
+    pub fn emit(&self, source: &Source, emitter: &mut dyn Emitter) -> io::Result<()> {
+        emitter.emit_all(&self.reports, source)
+    }
+
+    /// The reports rendered in the chosen format. They're only coloured if
+    /// colour is always on, since there's no terminal to detect.
+    pub fn render(&self, source: &Source) -> String {
+        let mut out = vec![];
+        let color = self.color == ColorChoice::Always;
+        // writing to a `Vec` can't fail
+        let _ = self.emit(source, &mut *self.emitter(&mut out, color));
+        String::from_utf8_lossy(&out).into_owned()
+    }
+
+    /// Print the reports to standard error.
+    pub fn print(self, source: &Source) {
+        let stderr = io::stderr();
+        let color = self.color.enabled(&stderr);
+        let _ = self.emit(source, &mut *self.emitter(stderr.lock(), color));
+    }
 }
@@ -0,0 +1,105 @@
+use crate::compiler::diagnostic::{ErrorReport, Severity};
+use crate::json::Json;
+use crate::source::{Source, Span};
+
+/// A report with its positions worked out, for tools that read
+/// diagnostics rather than people.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: Option<&'static str>,
+    pub message: &'static str,
+    pub file: String,
+    pub labels: Vec<DiagnosticLabel>,
+    pub notes: Vec<String>,
+    pub hints: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiagnosticLabel {
+    pub message: String,
+
+    /// The byte offsets of the source it points at, if it points at any.
+    pub span: Option<Span>,
+    pub start: Option<Position>,
+    pub end: Option<Position>,
+}
+
+/// A position in a file, counting lines and characters from 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Position {
+    fn new(source: &Source, offset: usize) -> Self {
+        let loc = source.line_col(offset);
+        Self {
+            line: loc.line,
+            column: loc.col,
+        }
+    }
+
+    fn to_json(self) -> Json {
+        Json::object([("line", self.line.into()), ("column", self.column.into())])
+    }
+}
+
+impl Diagnostic {
+    pub fn new(report: &ErrorReport, source: &Source) -> Self {
+        let labels = report
+            .labels
+            .iter()
+            .map(|label| {
+                let span = label.span.map(|span| span.overlap(source.file_span()));
+                DiagnosticLabel {
+                    message: label.msg.clone(),
+                    span,
+                    start: span.map(|span| Position::new(source, span.start)),
+                    end: span.map(|span| Position::new(source, span.end)),
+                }
+            })
+            .collect();
+
+        Self {
+            severity: report.severity,
+            code: report.code,
+            message: report.title,
+            file: source.name.clone(),
+            labels,
+            notes: report.notes.clone(),
+            hints: report.hints.clone(),
+        }
+    }
+
+    /// The diagnostic as a single line of JSON.
+    pub fn to_json(&self) -> String {
+        let labels: Vec<Json> = self
+            .labels
+            .iter()
+            .map(|label| {
+                let span = label.span.map(|span| {
+                    Json::object([("start", span.start.into()), ("end", span.end.into())])
+                });
+                Json::object([
+                    ("message", label.message.as_str().into()),
+                    ("span", span.into()),
+                    ("start", label.start.map(Position::to_json).into()),
+                    ("end", label.end.map(Position::to_json).into()),
+                ])
+            })
+            .collect();
+
+        Json::object([
+            ("severity", self.severity.name().into()),
+            ("code", self.code.into()),
+            ("message", self.message.into()),
+            ("file", self.file.as_str().into()),
+            ("labels", labels.into()),
+            ("notes", self.notes.clone().into()),
+            ("hints", self.hints.clone().into()),
+        ])
+        .to_string()
+    }
+}
@@ -0,0 +1,689 @@
+//! Prints a module back out as source code in the canonical style, keeping
+//! its comments.
+//!
+//! Comments aren't part of the tree, so they're placed by position: each
+//! one is printed before the first statement, field, method or arm that
+//! comes after it, or before the closing brace around it. Comments that
+//! follow code on the same line stay at the end of the line.
+
+use std::collections::BTreeMap;
+
+use crate::compiler::ast::*;
+use crate::compiler::lexer::token::{punct, Punctuation, TokenKind};
+use crate::compiler::lexer::Lexer;
+use crate::source::{Source, Span};
+
+/// Lists and operator chains longer than this are split over several lines.
+const MAX_WIDTH: usize = 100;
+
+const INDENT: usize = 4;
+
+/// How tightly an expression binds, which decides where parentheses are
+/// needed. Must be kept in sync with the parser.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Prec {
+    Assignment,
+    LogicalOr,
+    LogicalAnd,
+    LogicalNot,
+    Comparison,
+    BitOr,
+    BitXor,
+    BitAnd,
+    Shift,
+    Term,
+    Factor,
+    Exponent,
+    Cast,
+    Negative,
+    Call,
+    Atom,
+}
+
+impl Prec {
+    fn of(kind: &ExprKind) -> Self {
+        match kind {
+            ExprKind::Assignment(..) => Self::Assignment,
+            ExprKind::LogicalOr(..) => Self::LogicalOr,
+            ExprKind::LogicalAnd(..) => Self::LogicalAnd,
+            ExprKind::LogicalNot(..) => Self::LogicalNot,
+            ExprKind::Equal(..)
+            | ExprKind::NotEqual(..)
+            | ExprKind::LT(..)
+            | ExprKind::GT(..)
+            | ExprKind::LTEqual(..)
+            | ExprKind::GTEqual(..) => Self::Comparison,
+            ExprKind::BitOr(..) => Self::BitOr,
+            ExprKind::BitXor(..) => Self::BitXor,
+            ExprKind::BitAnd(..) => Self::BitAnd,
+            ExprKind::Shl(..) | ExprKind::Shr(..) => Self::Shift,
+            ExprKind::Add(..) | ExprKind::Sub(..) => Self::Term,
+            ExprKind::Mul(..) | ExprKind::Div(..) | ExprKind::Mod(..) => Self::Factor,
+            ExprKind::Pow(..) => Self::Exponent,
+            ExprKind::Cast(..) => Self::Cast,
+            ExprKind::Neg(..) | ExprKind::BitNot(..) => Self::Negative,
+            ExprKind::Call(..)
+            | ExprKind::MethodCall(..)
+            | ExprKind::Field(..)
+            | ExprKind::Try(..) => Self::Call,
+            ExprKind::Var(_)
+            | ExprKind::Value(_)
+            | ExprKind::StructLiteral(_)
+            | ExprKind::Variant(..)
+            | ExprKind::DebugPrint(_)
+            | ExprKind::Panic(_)
+            | ExprKind::DummyExpr => Self::Atom,
+        }
+    }
+}
+
+/// An infix operator's operands, symbol and precedence.
+fn binary(kind: &ExprKind) -> Option<(&Expr, &'static str, &Expr)> {
+    let (lhs, op, rhs) = match kind {
+        ExprKind::LogicalOr(lhs, rhs) => (lhs, "||", rhs),
+        ExprKind::LogicalAnd(lhs, rhs) => (lhs, "&&", rhs),
+        ExprKind::Equal(lhs, rhs) => (lhs, "==", rhs),
+        ExprKind::NotEqual(lhs, rhs) => (lhs, "!=", rhs),
+        ExprKind::LT(lhs, rhs) => (lhs, "<", rhs),
+        ExprKind::GT(lhs, rhs) => (lhs, ">", rhs),
+        ExprKind::LTEqual(lhs, rhs) => (lhs, "<=", rhs),
+        ExprKind::GTEqual(lhs, rhs) => (lhs, ">=", rhs),
+        ExprKind::Add(lhs, rhs) => (lhs, "+", rhs),
+        ExprKind::Sub(lhs, rhs) => (lhs, "-", rhs),
+        ExprKind::Mul(lhs, rhs) => (lhs, "*", rhs),
+        ExprKind::Div(lhs, rhs) => (lhs, "/", rhs),
+        ExprKind::Mod(lhs, rhs) => (lhs, "%", rhs),
+        ExprKind::Pow(lhs, rhs) => (lhs, "^", rhs),
+        ExprKind::BitAnd(lhs, rhs) => (lhs, "&", rhs),
+        ExprKind::BitOr(lhs, rhs) => (lhs, "|", rhs),
+        ExprKind::BitXor(lhs, rhs) => (lhs, "~", rhs),
+        ExprKind::Shl(lhs, rhs) => (lhs, "<<", rhs),
+        ExprKind::Shr(lhs, rhs) => (lhs, ">>", rhs),
+        _ => return None,
+    };
+    Some((lhs, op, rhs))
+}
+
+/// Where an expression starts, and what it may contain.
+#[derive(Clone, Copy)]
+struct Context {
+    col: usize,
+    indent: usize,
+
+    /// Whether a struct literal would be ambiguous here, as in an `if`
+    /// condition, so it needs parentheses.
+    no_struct_literals: bool,
+}
+
+impl Context {
+    /// The context of what follows `text`, if it's printed here.
+    fn after(self, text: &str) -> Self {
+        let col = match text.rfind('\n') {
+            Some(newline) => text[newline + 1..].chars().count(),
+            None => self.col + text.chars().count(),
+        };
+        Self { col, ..self }
+    }
+}
+
+pub struct Formatter<'a> {
+    source: &'a Source,
+    out: String,
+    indent: usize,
+
+    /// Whether the next line continues the last one, as in `} else {`.
+    joining: bool,
+
+    comments: Vec<Span>,
+    next_comment: usize,
+
+    /// The position of each opening brace's closing brace, for finding
+    /// the end of declarations.
+    braces: BTreeMap<usize, usize>,
+}
+
+impl<'a> Formatter<'a> {
+    pub fn new(source: &'a Source, comments: Vec<Span>) -> Self {
+        let mut braces = BTreeMap::new();
+        let mut open = vec![];
+        for token in Lexer::new(source.cursor()) {
+            match token.kind {
+                punct!(LBrace) => open.push(token.span.start),
+                punct!(RBrace) => {
+                    if let Some(start) = open.pop() {
+                        braces.insert(start, token.span.start);
+                    }
+                }
+                TokenKind::Eof => break,
+                _ => {}
+            }
+        }
+
+        Self {
+            source,
+            out: String::new(),
+            indent: 0,
+            joining: false,
+            comments,
+            next_comment: 0,
+            braces,
+        }
+    }
+
+    pub fn format(mut self, module: &Module) -> String {
+        self.statements(&module.statements);
+        self.comments_before(usize::MAX);
+        self.out
+    }
+
+    fn line(&mut self, text: &str) {
+        if std::mem::take(&mut self.joining) {
+            self.out.pop();
+        } else {
+            self.out += &" ".repeat(self.indent * INDENT);
+        }
+        self.out += text;
+        self.out.push('\n');
+    }
+
+    fn context(&self, prefix: &str) -> Context {
+        Context {
+            col: self.indent * INDENT,
+            indent: self.indent,
+            no_struct_literals: false,
+        }
+        .after(prefix)
+    }
+
+    /// Print the comments before `pos` that haven't been printed yet.
+    fn comments_before(&mut self, pos: usize) {
+        while let Some(&comment) = self
+            .comments
+            .get(self.next_comment)
+            .filter(|comment| comment.start < pos)
+        {
+            self.next_comment += 1;
+            let text = self.source.span_str(comment).trim_end().to_string();
+
+            let line_start = self.source.line_span(comment.start).start;
+            let after_code = !self.source.source[line_start..comment.start]
+                .trim()
+                .is_empty();
+
+            if after_code && !self.out.is_empty() {
+                self.out.pop();
+                self.out += " ";
+                self.out += &text;
+                self.out.push('\n');
+            } else {
+                self.blank_line_before(comment.start);
+                self.line(&text);
+            }
+        }
+    }
+
+    /// Keep one blank line where the source has any before `pos`, except
+    /// at the start of the file or of a block.
+    fn blank_line_before(&mut self, pos: usize) {
+        let before = self.source.source[..pos].trim_end_matches([' ', '\t', '\r', '\n']);
+        let gap = &self.source.source[before.len()..pos];
+
+        if gap.matches('\n').count() >= 2 && !self.out.is_empty() && !self.out.ends_with("{\n") {
+            self.out.push('\n');
+        }
+    }
+
+    /// Get ready to print something that starts at `pos` in the source.
+    fn start_item(&mut self, pos: usize) {
+        self.comments_before(pos);
+        self.blank_line_before(pos);
+    }
+
+    /// The closing brace of the first braces after `pos`.
+    fn closing_brace(&self, pos: usize) -> usize {
+        self.braces
+            .range(pos..)
+            .next()
+            .map_or(self.source.source.len(), |(_, close)| *close)
+    }
+
+    fn has_comments_before(&self, pos: usize) -> bool {
+        self.comments
+            .get(self.next_comment)
+            .is_some_and(|comment| comment.start < pos)
+    }
+
+    /// Print `header {`, then what `body` prints indented, then `}`, or
+    /// `header {}` if there's nothing between the braces.
+    fn braced(&mut self, header: &str, close: usize, empty: bool, body: impl FnOnce(&mut Self)) {
+        let open = match header {
+            "" => "{".to_string(),
+            header => format!("{} {{", header),
+        };
+
+        if empty && !self.has_comments_before(close) {
+            self.line(&format!("{}}}", open));
+            return;
+        }
+
+        self.line(&open);
+        self.indent += 1;
+        body(self);
+        self.comments_before(close);
+        self.indent -= 1;
+        self.line("}");
+    }
+
+    fn block(&mut self, header: &str, block: &Block) {
+        let close = block.span.end.saturating_sub(1);
+        self.braced(header, close, block.statements.is_empty(), |s| {
+            s.statements(&block.statements)
+        });
+    }
+
+    fn statements(&mut self, statements: &[Statement]) {
+        for statement in statements {
+            self.statement(statement);
+        }
+    }
+
+    fn statement(&mut self, statement: &Statement) {
+        self.start_item(statement.span().start);
+
+        match statement {
+            Statement::Expr(expr) => {
+                let expr = self.expr(expr, self.context(""));
+                self.line(&format!("{};", expr));
+            }
+            Statement::Block(block) => self.block("", block),
+            Statement::VarDecl(var_decl) => {
+                let mut prefix = format!("let {}", var_decl.ident);
+                if let Some(ty) = &var_decl.ty {
+                    prefix += &format!(": {}", ty);
+                }
+                prefix += " = ";
+
+                let value = self.expr(&var_decl.value, self.context(&prefix));
+                self.line(&format!("{}{};", prefix, value));
+            }
+            Statement::FnDecl(fn_decl) => self.fn_decl(fn_decl),
+            Statement::StructDecl(struct_decl) => self.struct_decl(struct_decl),
+            Statement::EnumDecl(enum_decl) => self.enum_decl(enum_decl),
+            Statement::NewtypeDecl(newtype_decl) => self.line(&format!(
+                "type {} = new {};",
+                newtype_decl.ident, newtype_decl.base
+            )),
+            Statement::TraitDecl(trait_decl) => {
+                let close = self.closing_brace(trait_decl.span.end);
+                let header = format!("trait {}", trait_decl.ident);
+                self.braced(&header, close, trait_decl.methods.is_empty(), |s| {
+                    for sig in &trait_decl.methods {
+                        s.start_item(sig.span.start);
+                        let sig = s.fn_sig(sig);
+                        s.line(&format!("{};", sig));
+                    }
+                });
+            }
+            Statement::ImplDecl(impl_decl) => {
+                let mut header = format!("impl{} ", generics(&impl_decl.generics));
+                if let Some((trait_, _)) = impl_decl.trait_ {
+                    header += &format!("{} for ", trait_);
+                }
+                header += &impl_decl.ty.to_string();
+
+                let close = self.closing_brace(impl_decl.span.end);
+                self.braced(&header, close, impl_decl.methods.is_empty(), |s| {
+                    for method in &impl_decl.methods {
+                        s.start_item(method.sig.span.start);
+                        s.fn_decl(method);
+                    }
+                });
+            }
+            Statement::If(if_statement) => self.if_statement("", if_statement),
+            Statement::While(while_loop) => {
+                let condition = self.condition("while ", &while_loop.condition);
+                self.block(&format!("while {}", condition), &while_loop.body);
+            }
+            Statement::Match(match_statement) => {
+                let scrutinee = self.condition("match ", &match_statement.scrutinee);
+                let header = format!("match {}", scrutinee);
+                let close = self.closing_brace(match_statement.span.end);
+                self.braced(&header, close, match_statement.arms.is_empty(), |s| {
+                    for arm in &match_statement.arms {
+                        s.start_item(arm.pattern.span.start);
+                        s.block(&format!("{} =>", arm.pattern), &arm.body);
+                    }
+                });
+            }
+            Statement::Try(try_statement) => {
+                self.block("try", &try_statement.body);
+                self.joining = true;
+                self.block(
+                    &format!(" recover {}", try_statement.binding),
+                    &try_statement.recover,
+                );
+            }
+            Statement::Return(return_statement) => match &return_statement.value {
+                Some(value) => {
+                    let value = self.expr(value, self.context("return "));
+                    self.line(&format!("return {};", value));
+                }
+                None => self.line("return;"),
+            },
+        }
+    }
+
+    /// Print an `if` statement, after `prefix` if it's an `else if`.
+    fn if_statement(&mut self, prefix: &str, if_statement: &IfStatement) {
+        let header = format!("{}if ", prefix);
+        let condition = self.condition(&header, &if_statement.condition);
+        self.block(&format!("{}{}", header, condition), &if_statement.then);
+
+        match if_statement.else_.as_deref() {
+            Some(Statement::If(else_if)) => {
+                self.joining = true;
+                self.if_statement(" else ", else_if);
+            }
+            Some(Statement::Block(else_)) => {
+                self.joining = true;
+                self.block(" else", else_);
+            }
+            Some(_) => unreachable!("the parser only allows blocks and `if`s after `else`"),
+            None => {}
+        }
+    }
+
+    /// An `if`, `while` or `match` condition, where struct literals need
+    /// parentheses.
+    fn condition(&self, prefix: &str, expr: &Expr) -> String {
+        let context = Context {
+            no_struct_literals: true,
+            ..self.context(prefix)
+        };
+        self.expr(expr, context)
+    }
+
+    fn fn_decl(&mut self, fn_decl: &FnDecl) {
+        let sig = self.fn_sig(&fn_decl.sig);
+        self.block(&sig, &fn_decl.body);
+    }
+
+    fn fn_sig(&self, sig: &FnSig) -> String {
+        let name = format!("fn {}{}", sig.ident, generics(&sig.generics));
+        let params = self.list(
+            ("(", ")"),
+            &sig.params,
+            self.context(&name),
+            |_, param, _| match &param.ty {
+                Some(ty) => format!("{}: {}", param.ident, ty),
+                None => param.ident.to_string(),
+            },
+        );
+
+        match &sig.return_ty {
+            Some(ty) => format!("{}{} -> {}", name, params, ty),
+            None => format!("{}{}", name, params),
+        }
+    }
+
+    fn struct_decl(&mut self, struct_decl: &StructDecl) {
+        let header = format!(
+            "struct {}{}",
+            struct_decl.ident,
+            generics(&struct_decl.generics)
+        );
+        let close = self.closing_brace(struct_decl.span.end);
+        self.braced(&header, close, struct_decl.fields.is_empty(), |s| {
+            for field in &struct_decl.fields {
+                s.start_item(field.span.start);
+                s.line(&format!("{}: {},", field.ident, field.ty));
+            }
+        });
+    }
+
+    fn enum_decl(&mut self, enum_decl: &EnumDecl) {
+        let header = format!("enum {}{}", enum_decl.ident, generics(&enum_decl.generics));
+        let close = self.closing_brace(enum_decl.span.end);
+        self.braced(&header, close, enum_decl.variants.is_empty(), |s| {
+            for variant in &enum_decl.variants {
+                s.start_item(variant.span.start);
+                match &variant.payload {
+                    Some(payload) => s.line(&format!("{}({}),", variant.ident, payload)),
+                    None => s.line(&format!("{},", variant.ident)),
+                }
+            }
+        });
+    }
+
+    fn expr(&self, expr: &Expr, context: Context) -> String {
+        if binary(&expr.kind).is_some() {
+            return self.binary(expr, context);
+        }
+
+        // the operand of a method call or field access
+        let callee =
+            |callee: &Expr| self.operand(callee, Prec::of(&callee.kind) < Prec::Call, context);
+
+        match &expr.kind {
+            ExprKind::Var(var) => var.ident.to_string(),
+
+            // literals are printed as they were written
+            ExprKind::Value(_) => self.source.span_str(expr.span).to_string(),
+
+            ExprKind::LogicalNot(operand) => {
+                let paren = Prec::of(&operand.kind) < Prec::LogicalNot;
+                format!("!{}", self.operand(operand, paren, context.after("!")))
+            }
+            ExprKind::Neg(operand) | ExprKind::BitNot(operand) => {
+                let op = match expr.kind {
+                    ExprKind::Neg(_) => "-",
+                    _ => "~",
+                };
+
+                // prefix operators can be nested without parentheses
+                let paren = Prec::of(&operand.kind) < Prec::Call
+                    && !matches!(operand.kind, ExprKind::Neg(_) | ExprKind::BitNot(_));
+                format!("{}{}", op, self.operand(operand, paren, context.after(op)))
+            }
+
+            ExprKind::Assignment(var, value) => {
+                let prefix = format!("{} = ", var.ident);
+                let value = self.expr(value, context.after(&prefix));
+                format!("{}{}", prefix, value)
+            }
+
+            ExprKind::Call(function, args) => {
+                let function = callee(function);
+                let args = self.args(args, context.after(&function));
+                format!("{}{}", function, args)
+            }
+            ExprKind::MethodCall(receiver, method, args) => {
+                let receiver = format!("{}.{}", callee(receiver), method.ident);
+                let args = self.args(args, context.after(&receiver));
+                format!("{}{}", receiver, args)
+            }
+            ExprKind::Field(operand, field) => format!("{}.{}", callee(operand), field.ident),
+            ExprKind::Try(operand) => format!("{}?", callee(operand)),
+
+            ExprKind::Cast(operand, ty) => {
+                let paren = Prec::of(&operand.kind) < Prec::Cast;
+                format!("{} as {}", self.operand(operand, paren, context), ty)
+            }
+
+            ExprKind::StructLiteral(literal) => {
+                if context.no_struct_literals {
+                    let inner = Context {
+                        no_struct_literals: false,
+                        ..context
+                    };
+                    return self.operand(expr, true, inner);
+                }
+
+                let prefix = format!("{} ", literal.ident);
+                let fields = self.list(
+                    ("{", "}"),
+                    &literal.fields,
+                    context.after(&prefix),
+                    |s, field, context| {
+                        let prefix = format!("{}: ", field.ident);
+                        let value = s.expr(&field.value, context.after(&prefix));
+                        format!("{}{}", prefix, value)
+                    },
+                );
+                format!("{}{}", prefix, fields)
+            }
+
+            ExprKind::Variant(variant, args) if args.is_empty() => variant.ident.to_string(),
+            ExprKind::Variant(variant, args) => {
+                let args = self.args(args, context.after(&variant.ident));
+                format!("{}{}", variant.ident, args)
+            }
+
+            ExprKind::DebugPrint(operand) => {
+                let operand = self.expr(operand, inner(context.after("print(")));
+                format!("print({})", operand)
+            }
+            ExprKind::Panic(operand) => {
+                let operand = self.expr(operand, inner(context.after("panic(")));
+                format!("panic({})", operand)
+            }
+
+            ExprKind::DummyExpr => unreachable!("the module has no errors"),
+
+            _ => unreachable!("binary operators are handled above"),
+        }
+    }
+
+    /// An infix operator, on one line if it fits and otherwise with each
+    /// operand of the chain of operators at its precedence on its own
+    /// line, after its operator.
+    fn binary(&self, expr: &Expr, context: Context) -> String {
+        let prec = Prec::of(&expr.kind);
+
+        // `a - b + c` is `(a - b) + c`, so the chain runs down the left,
+        // except for `^`, which is right-associative and isn't chained
+        let mut first = expr;
+        let mut rest = vec![];
+        while let Some((lhs, op, rhs)) = binary(&first.kind) {
+            if Prec::of(&first.kind) != prec || (prec == Prec::Exponent && !rest.is_empty()) {
+                break;
+            }
+            rest.push((op, rhs));
+            first = lhs;
+        }
+        rest.reverse();
+
+        // an operand on the side the operator doesn't associate towards
+        // needs parentheses at the same precedence too
+        let paren = |operand: &Expr, is_lhs: bool| match (prec == Prec::Exponent) == is_lhs {
+            true => Prec::of(&operand.kind) <= prec,
+            false => Prec::of(&operand.kind) < prec,
+        };
+
+        let first = self.operand(first, paren(first, true), context);
+
+        let mut flat = first.clone();
+        for (op, operand) in &rest {
+            flat += &format!(" {} ", op);
+            flat += &self.operand(operand, paren(operand, false), context.after(&flat));
+        }
+
+        if !flat.contains('\n') && context.col + flat.chars().count() <= MAX_WIDTH {
+            return flat;
+        }
+
+        let operand_context = Context {
+            col: (context.indent + 1) * INDENT,
+            indent: context.indent + 1,
+            ..context
+        };
+        let indent = " ".repeat(operand_context.col);
+
+        let mut broken = first;
+        for (op, operand) in &rest {
+            let prefix = format!("{} ", op);
+            let operand_context = operand_context.after(&prefix);
+            let operand = self.operand(operand, paren(operand, false), operand_context);
+            broken += &format!("\n{}{}{}", indent, prefix, operand);
+        }
+        broken
+    }
+
+    /// An operand of an operator, in parentheses if it wouldn't be parsed
+    /// back as one otherwise.
+    fn operand(&self, expr: &Expr, paren: bool, context: Context) -> String {
+        match paren {
+            true => format!("({})", self.expr(expr, inner(context.after("(")))),
+            false => self.expr(expr, context),
+        }
+    }
+
+    fn args(&self, args: &[Expr], context: Context) -> String {
+        self.list(("(", ")"), args, context, |s, arg, context| {
+            s.expr(arg, context)
+        })
+    }
+
+    /// A comma-separated list, on one line if it fits and otherwise with
+    /// each item on its own line.
+    fn list<T>(
+        &self,
+        (open, close): (&str, &str),
+        items: &[T],
+        context: Context,
+        item: impl Fn(&Self, &T, Context) -> String,
+    ) -> String {
+        if items.is_empty() {
+            return format!("{}{}", open, close);
+        }
+
+        // braces get spaces inside them, as in `Point { x: 1, y: 2 }`
+        let pad = if open == "{" { " " } else { "" };
+
+        let mut flat = format!("{}{}", open, pad);
+        for (i, it) in items.iter().enumerate() {
+            if i > 0 {
+                flat += ", ";
+            }
+            flat += &item(self, it, inner(context.after(&flat)));
+        }
+        flat += pad;
+        flat += close;
+
+        if !flat.contains('\n') && context.col + flat.chars().count() <= MAX_WIDTH {
+            return flat;
+        }
+
+        let indent = " ".repeat((context.indent + 1) * INDENT);
+        let item_context = Context {
+            col: (context.indent + 1) * INDENT,
+            indent: context.indent + 1,
+            no_struct_literals: false,
+        };
+
+        let mut broken = format!("{}\n", open);
+        for it in items {
+            broken += &format!("{}{},\n", indent, item(self, it, item_context));
+        }
+        broken += &" ".repeat(context.indent * INDENT);
+        broken += close;
+        broken
+    }
+}
+
+/// The context inside brackets, where struct literals are allowed again.
+fn inner(context: Context) -> Context {
+    Context {
+        no_struct_literals: false,
+        ..context
+    }
+}
+
+fn generics(generics: &[GenericParam]) -> String {
+    if generics.is_empty() {
+        return String::new();
+    }
+
+    let params: Vec<_> = generics.iter().map(|param| param.to_string()).collect();
+    format!("<{}>", params.join(", "))
+}
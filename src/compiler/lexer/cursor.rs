@@ -1,4 +1,5 @@
-use crate::compiler::lexer::token::{Token, TokenKind};
+use crate::compiler::ast::Attribute;
+use crate::compiler::lexer::token::{punct, Punctuation, Token, TokenKind};
 use crate::compiler::lexer::Lexer;
 use crate::source::Span;
 
@@ -38,6 +39,25 @@ impl<'sess> Cursor<'sess> {
         }
     }
 
+    /// Split a `>>` into two `>`s and consume the first, so that nested
+    /// type arguments like `Option<Option<Int>>` can be closed.
+    pub fn split_shr(&mut self) -> Option<Token> {
+        let current = self.current.as_mut()?;
+        if current.kind != punct!(Shr) {
+            return None;
+        }
+
+        let mut first = *current;
+        first.kind = punct!(GT);
+        first.span.end = first.span.start + 1;
+
+        current.kind = punct!(GT);
+        current.span.start += 1;
+
+        self.prev = Some(first);
+        Some(first)
+    }
+
     pub fn eat(&mut self, kind: TokenKind) -> bool {
         if self.matches(kind) {
             self.next();
@@ -61,6 +81,16 @@ impl<'sess> Cursor<'sess> {
         self.peek().kind == TokenKind::Eof
     }
 
+    /// The spans of the comments skipped so far.
+    pub fn take_comments(&mut self) -> Vec<Span> {
+        std::mem::take(&mut self.lexer.comments)
+    }
+
+    /// The comments skipped so far that are attributes.
+    pub fn take_attributes(&mut self) -> Vec<Attribute> {
+        std::mem::take(&mut self.lexer.attributes)
+    }
+
     pub fn prev_span(&self) -> Span {
         self.prev.map(|token| token.span).unwrap_or_default()
     }
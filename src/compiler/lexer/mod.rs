@@ -1,6 +1,8 @@
 use internment::Intern;
 
-use crate::source::Cursor;
+use crate::compiler::ast::Attribute;
+use crate::runtime::value::bigint::BigInt;
+use crate::source::{Cursor, Span};
 
 use self::cursor::Cursor as TokenCursor;
 use self::token::*;
@@ -10,13 +12,26 @@ pub mod token;
 
 const NUMBER_SEP: char = '_';
 
+const OVERFLOW_MSG: &str = "integer literal overflowed (an `n` suffix makes it a `BigInt`)";
+
 pub struct Lexer<'sess> {
     cursor: Cursor<'sess>,
+
+    /// Comments aren't tokens, but their spans are kept as trivia for the
+    /// formatter.
+    pub comments: Vec<Span>,
+
+    /// The comments that are also attributes.
+    pub attributes: Vec<Attribute>,
 }
 
 impl<'sess> Lexer<'sess> {
     pub fn new(cursor: Cursor<'sess>) -> Self {
-        Self { cursor }
+        Self {
+            cursor,
+            comments: vec![],
+            attributes: vec![],
+        }
     }
 
     pub fn cursor(self) -> TokenCursor<'sess> {
@@ -38,6 +53,11 @@ impl<'sess> Lexer<'sess> {
 
                 '#' => {
                     self.cursor.eat_while(|c| c != '\n');
+                    let span = self.cursor.span();
+                    self.comments.push(span);
+                    if let Some(attribute) = parse_attribute(self.cursor.lexeme(), span.start) {
+                        self.attributes.push(attribute);
+                    }
                     continue;
                 }
 
@@ -52,6 +72,7 @@ impl<'sess> Lexer<'sess> {
                 ',' => punct!(Comma),
                 ':' => punct!(Colon),
                 ';' => punct!(Semicolon),
+                '?' => punct!(Question),
 
                 '-' if self.cursor.eat('>') => punct!(Arrow),
                 '=' if self.cursor.eat('>') => punct!(FatArrow),
@@ -70,6 +91,9 @@ impl<'sess> Lexer<'sess> {
                 '%' => punct!(Mod),
                 '^' => punct!(Pow),
 
+                '<' if self.cursor.eat('<') => punct!(Shl),
+                '>' if self.cursor.eat('>') => punct!(Shr),
+
                 '>' if self.cursor.eat('=') => punct!(GTEqual),
                 '<' if self.cursor.eat('=') => punct!(LTEqual),
                 '>' => punct!(GT),
@@ -83,6 +107,10 @@ impl<'sess> Lexer<'sess> {
                 '|' if self.cursor.eat('|') => punct!(LogicalOr),
                 '!' => punct!(Bang),
 
+                '&' => punct!(BitAnd),
+                '|' => punct!(BitOr),
+                '~' => punct!(Tilde),
+
                 'a'..='z' | 'A'..='Z' | '_' => self.lex_alpha(),
                 c @ '0'..='9' => self.lex_number(c),
 
@@ -112,6 +140,20 @@ impl<'sess> Lexer<'sess> {
             "while" => kwd!(While),
 
             "let" => kwd!(Let),
+            "fn" => kwd!(Fn),
+            "return" => kwd!(Return),
+            "struct" => kwd!(Struct),
+            "enum" => kwd!(Enum),
+            "trait" => kwd!(Trait),
+            "impl" => kwd!(Impl),
+            "for" => kwd!(For),
+            "type" => kwd!(Type),
+            "new" => kwd!(New),
+            "as" => kwd!(As),
+            "match" => kwd!(Match),
+            "try" => kwd!(Try),
+            "recover" => kwd!(Recover),
+            "panic" => kwd!(Panic),
 
             "true" => TokenKind::Bool(true),
             "false" => TokenKind::Bool(false),
@@ -137,7 +179,7 @@ impl<'sess> Lexer<'sess> {
                     Err(msg) => return TokenKind::Error(msg),
                 },
                 '"' => break,
-                d => d as char,
+                d => d,
             };
 
             s.push(d);
@@ -159,7 +201,7 @@ impl<'sess> Lexer<'sess> {
             't' => '\t',
             'r' => '\r',
             'n' => '\n',
-            c => c as char,
+            c => c,
         })
     }
 
@@ -172,7 +214,7 @@ impl<'sess> Lexer<'sess> {
             '0' if self.cursor.eat('o') => self.lex_integer_with_radix(8),
 
             // `0_` is illegal, but we should lex it anyway.
-            '0' if second.is_digit(10) || second == NUMBER_SEP => {
+            '0' if second.is_ascii_digit() || second == NUMBER_SEP => {
                 self.collect_digits(10);
                 self.eat_fractional_part(10);
                 TokenKind::Error("leading zeroes")
@@ -218,10 +260,12 @@ impl<'sess> Lexer<'sess> {
             );
 
             TokenKind::Float(f.to_bits())
+        } else if self.cursor.eat('n') {
+            lex_big_integer(&integer, 10)
         } else {
             let n = match parse_digits(integer, 10) {
                 Some(n) => n,
-                None => return TokenKind::Error("integer literal overflowed"),
+                None => return TokenKind::Error(OVERFLOW_MSG),
             };
             TokenKind::Integer(n)
         }
@@ -238,9 +282,13 @@ impl<'sess> Lexer<'sess> {
             return TokenKind::Error("only decimal numbers may have a fractional part");
         }
 
+        if self.cursor.eat('n') {
+            return lex_big_integer(&integer, radix);
+        }
+
         let n = match parse_digits(integer, radix) {
             Some(n) => n,
-            None => return TokenKind::Error("integer literal overflowed"),
+            None => return TokenKind::Error(OVERFLOW_MSG),
         };
 
         TokenKind::Integer(n)
@@ -284,6 +332,11 @@ impl<'sess> Iterator for Lexer<'sess> {
     }
 }
 
+fn lex_big_integer(digits: &str, radix: u32) -> TokenKind {
+    let n = BigInt::parse(digits, radix).unwrap();
+    TokenKind::BigInteger(Intern::new(n.to_string()))
+}
+
 fn parse_digits(digits: String, radix: u32) -> Option<u64> {
     let mut n: u64 = 0;
     for digit in digits.chars() {
@@ -301,3 +354,55 @@ fn parse_digits(digits: String, radix: u32) -> Option<u64> {
 
     Some(n)
 }
+
+fn is_ident(s: &str) -> bool {
+    s.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// The attribute a comment is, if it's of the form `#[name(arg, ...)]` or
+/// `#![name(arg, ...)]`. `start` is where the comment starts.
+fn parse_attribute(comment: &str, start: usize) -> Option<Attribute> {
+    let (inner, prefix) = match comment.starts_with("#!") {
+        true => (true, 2),
+        false => (false, 1),
+    };
+
+    let body = comment[prefix..]
+        .trim_end()
+        .strip_prefix('[')?
+        .strip_suffix(']')?;
+    let (name, args) = body.split_once('(')?;
+    let args = args.strip_suffix(')')?;
+    if !is_ident(name) {
+        return None;
+    }
+
+    let mut offset = start + prefix + 1 + name.len() + 1;
+    let mut parsed = vec![];
+    for arg in args.split(',') {
+        let ident = arg.trim();
+        if !ident.is_empty() {
+            if !is_ident(ident) {
+                return None;
+            }
+            let arg_start = offset + arg.len() - arg.trim_start().len();
+            let span = Span {
+                start: arg_start,
+                end: arg_start + ident.len(),
+            };
+            parsed.push((Intern::new(ident.to_string()), span));
+        }
+        offset += arg.len() + 1;
+    }
+
+    Some(Attribute {
+        name: Intern::new(name.to_string()),
+        args: parsed,
+        inner,
+        span: Span {
+            start,
+            end: start + comment.len(),
+        },
+    })
+}
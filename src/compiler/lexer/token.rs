@@ -12,6 +12,9 @@ pub enum TokenKind {
 
     String(Intern<String>),
     Integer(u64),
+
+    /// The decimal digits of an integer literal with an `n` suffix.
+    BigInteger(Intern<String>),
     Float(u64),
     Bool(bool),
 
@@ -35,6 +38,7 @@ pub enum Punctuation {
 
     Arrow,
     FatArrow,
+    Question,
 
     Add,
     Sub,
@@ -54,6 +58,12 @@ pub enum Punctuation {
     LogicalOr,
     Bang,
 
+    BitAnd,
+    BitOr,
+    Tilde,
+    Shl,
+    Shr,
+
     LT,
     GT,
     LTEqual,
@@ -71,6 +81,120 @@ pub enum Keyword {
     Else,
     While,
     Let,
+    Fn,
+    Return,
+    Struct,
+    Enum,
+    Trait,
+    Impl,
+    For,
+    Type,
+    New,
+    As,
+    Match,
+    Try,
+    Recover,
+    Panic,
+}
+
+impl Punctuation {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::LParen => "(",
+            Self::RParen => ")",
+            Self::LBrace => "{",
+            Self::RBrace => "}",
+            Self::LBracket => "[",
+            Self::RBracket => "]",
+
+            Self::Dot => ".",
+            Self::Comma => ",",
+            Self::Colon => ":",
+            Self::Semicolon => ";",
+
+            Self::Arrow => "->",
+            Self::FatArrow => "=>",
+            Self::Question => "?",
+
+            Self::Add => "+",
+            Self::Sub => "-",
+            Self::Mul => "*",
+            Self::Div => "/",
+            Self::Mod => "%",
+            Self::Pow => "^",
+
+            Self::AddEqual => "+=",
+            Self::SubEqual => "-=",
+            Self::MulEqual => "*=",
+            Self::DivEqual => "/=",
+            Self::ModEqual => "%=",
+            Self::PowEqual => "^=",
+
+            Self::LogicalAnd => "&&",
+            Self::LogicalOr => "||",
+            Self::Bang => "!",
+
+            Self::BitAnd => "&",
+            Self::BitOr => "|",
+            Self::Tilde => "~",
+            Self::Shl => "<<",
+            Self::Shr => ">>",
+
+            Self::LT => "<",
+            Self::GT => ">",
+            Self::LTEqual => "<=",
+            Self::GTEqual => ">=",
+
+            Self::Equal => "=",
+            Self::EqualEqual => "==",
+            Self::BangEqual => "!=",
+        }
+    }
+}
+
+impl Keyword {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::DebugPrint => "print",
+            Self::If => "if",
+            Self::Else => "else",
+            Self::While => "while",
+            Self::Let => "let",
+            Self::Fn => "fn",
+            Self::Return => "return",
+            Self::Struct => "struct",
+            Self::Enum => "enum",
+            Self::Trait => "trait",
+            Self::Impl => "impl",
+            Self::For => "for",
+            Self::Type => "type",
+            Self::New => "new",
+            Self::As => "as",
+            Self::Match => "match",
+            Self::Try => "try",
+            Self::Recover => "recover",
+            Self::Panic => "panic",
+        }
+    }
+}
+
+/// How a kind of token is described in messages, such as "`)`" or
+/// "identifier `x`".
+impl std::fmt::Display for TokenKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Punctuation(punctuation) => write!(f, "`{}`", punctuation.as_str()),
+            Self::Keyword(keyword) => write!(f, "keyword `{}`", keyword.as_str()),
+            Self::Ident(ident) => write!(f, "identifier `{}`", ident),
+            Self::String(_) => write!(f, "a string"),
+            Self::Integer(n) => write!(f, "integer `{}`", n),
+            Self::BigInteger(digits) => write!(f, "integer `{}n`", digits),
+            Self::Float(bits) => write!(f, "float `{}`", f64::from_bits(*bits)),
+            Self::Bool(b) => write!(f, "`{}`", b),
+            Self::Error(msg) => write!(f, "an invalid token ({})", msg),
+            Self::Eof => write!(f, "the end of the file"),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
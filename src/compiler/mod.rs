@@ -1,22 +1,38 @@
-use crate::compiler::passes::run_passes;
+use crate::compiler::passes::{run_passes, PassOptions};
+use crate::fmt::DisplayWithSource;
 use crate::runtime::CompiledProgram;
 use crate::Source;
 
 use self::diagnostic::ErrorReporter;
+use self::format::Formatter;
+use self::lexer::token::TokenKind;
 use self::lexer::Lexer;
 use self::parser::Parser;
 
 pub mod ast;
 pub mod diagnostic;
+pub mod format;
 pub mod lexer;
 pub mod parser;
 pub mod passes;
 
+#[cfg(test)]
+pub mod tests;
+
 impl<'sess> CompiledProgram<'sess> {
     #[allow(clippy::result_unit_err)]
     pub fn compile(
         source: &'sess Source,
         diagnostics: &mut ErrorReporter,
+    ) -> Result<CompiledProgram<'sess>, ()> {
+        Self::compile_with(source, diagnostics, PassOptions::default())
+    }
+
+    #[allow(clippy::result_unit_err)]
+    pub fn compile_with(
+        source: &'sess Source,
+        diagnostics: &mut ErrorReporter,
+        options: PassOptions,
     ) -> Result<CompiledProgram<'sess>, ()> {
         let lexer = Lexer::new(source.cursor());
         let parser = Parser::new(lexer, diagnostics);
@@ -24,14 +40,51 @@ impl<'sess> CompiledProgram<'sess> {
         let mut module = parser.parse();
         diagnostics.assert_ok()?;
 
-        let chunk = match run_passes(&mut module) {
-            Ok(chunk) => chunk,
-            Err(err) => {
-                diagnostics.report(err.report());
-                return Err(());
-            }
-        };
+        let chunk = run_passes(&mut module, options, diagnostics)?;
+
+        Ok(Self {
+            source: Some(source),
+            chunk,
+            lines: None,
+        })
+    }
+}
 
-        Ok(Self { source, chunk })
+/// Each token of the source on its own line, for debugging the lexer.
+pub fn dump_tokens(source: &Source) -> String {
+    let mut lexer = Lexer::new(source.cursor());
+    let mut out = String::new();
+    loop {
+        let token = lexer.lex_token();
+        out += &format!("{}\n", token.display(source));
+        if token.kind == TokenKind::Eof {
+            return out;
+        }
     }
 }
+
+/// Each top-level statement of the parsed source on its own line, for
+/// debugging the parser.
+#[allow(clippy::result_unit_err)]
+pub fn dump_ast(source: &Source, diagnostics: &mut ErrorReporter) -> Result<String, ()> {
+    let lexer = Lexer::new(source.cursor());
+    let module = Parser::new(lexer, diagnostics).parse();
+    diagnostics.assert_ok()?;
+
+    Ok(module
+        .statements
+        .iter()
+        .map(|statement| format!("{}\n", statement))
+        .collect())
+}
+
+/// The source reformatted in the canonical style, with its comments kept.
+#[allow(clippy::result_unit_err)]
+pub fn format_source(source: &Source, diagnostics: &mut ErrorReporter) -> Result<String, ()> {
+    let lexer = Lexer::new(source.cursor());
+    let mut module = Parser::new(lexer, diagnostics).parse();
+    diagnostics.assert_ok()?;
+
+    let comments = std::mem::take(&mut module.comments);
+    Ok(Formatter::new(source, comments).format(&module))
+}
@@ -1,3 +1,4 @@
+use crate::runtime::builtins::builtin_variant;
 use crate::runtime::value::*;
 
 use super::*;
@@ -13,10 +14,17 @@ enum Prec {
 
     Comparison,
 
+    BitOr,
+    BitXor,
+    BitAnd,
+    Shift,
+
     Term,
     Factor,
     Exponent,
+    Cast,
     Negative,
+    Call,
 }
 
 /// Associativity of an infix operator.
@@ -33,13 +41,15 @@ impl<'sess> PrefixFunction<'sess> {
         Some(match kind {
             TokenKind::Ident(_) => Self(Parser::parse_var),
             TokenKind::String(_) => Self(Parser::parse_string),
-            TokenKind::Integer(_) => Self(Parser::parse_integer),
+            TokenKind::Integer(_) | TokenKind::BigInteger(_) => Self(Parser::parse_integer),
             TokenKind::Float(_) => Self(Parser::parse_float),
             TokenKind::Bool(_) => Self(Parser::parse_bool),
             punct!(Bang) => Self(Parser::parse_logical_not),
             punct!(Sub) => Self(Parser::parse_negative),
+            punct!(Tilde) => Self(Parser::parse_bit_not),
             punct!(LParen) => Self(Parser::parse_grouping),
             kwd!(DebugPrint) => Self(Parser::parse_print),
+            kwd!(Panic) => Self(Parser::parse_panic),
             _ => return None,
         })
     }
@@ -86,14 +96,25 @@ impl<'sess> InfixRule<'sess> {
             | punct!(LTEqual)
             | punct!(GTEqual) => rule!(Basic, Comparison),
 
+            // bitwise, where a binary `~` is xor
+            punct!(BitOr) => rule!(Basic, BitOr),
+            punct!(Tilde) => rule!(Basic, BitXor),
+            punct!(BitAnd) => rule!(Basic, BitAnd),
+            punct!(Shl) | punct!(Shr) => rule!(Basic, Shift),
+
             // arithmetic
             punct!(Add) | punct!(Sub) => rule!(Basic, Term),
             punct!(Mul) | punct!(Div) | punct!(Mod) => rule!(Basic, Factor),
             punct!(Pow) => rule!(Basic, Exponent, Right),
+            kwd!(As) => rule!(Func(Parser::parse_cast), Cast),
 
             // assignment
             punct!(Equal) => rule!(Func(Parser::parse_assignment), Assignment),
 
+            punct!(LParen) => rule!(Func(Parser::parse_call), Call),
+            punct!(Dot) => rule!(Func(Parser::parse_field), Call),
+            punct!(Question) => rule!(Func(Parser::parse_try), Call),
+
             _ => return None,
         })
     }
@@ -107,8 +128,8 @@ impl<'sess> Parser<'sess> {
     fn parse_prec(&mut self, min_prec: usize) -> JlyResult<Expr> {
         let lhs_token = self.cursor.next();
 
-        let prefix_fn =
-            PrefixFunction::from(lhs_token.kind).ok_or(Error::Expected("an expression", lhs_token))?;
+        let prefix_fn = PrefixFunction::from(lhs_token.kind)
+            .ok_or(Error::Expected("an expression", lhs_token))?;
 
         // Parse the prefix, which is either a prefix operator or a value.
         let mut expr = prefix_fn.0(self, lhs_token)?;
@@ -176,6 +197,13 @@ impl<'sess> Parser<'sess> {
             punct!(Mod) => expr!(boxed Mod(lhs, rhs), span),
             punct!(Pow) => expr!(boxed Pow(lhs, rhs), span),
 
+            // bitwise
+            punct!(BitAnd) => expr!(boxed BitAnd(lhs, rhs), span),
+            punct!(BitOr) => expr!(boxed BitOr(lhs, rhs), span),
+            punct!(Tilde) => expr!(boxed BitXor(lhs, rhs), span),
+            punct!(Shl) => expr!(boxed Shl(lhs, rhs), span),
+            punct!(Shr) => expr!(boxed Shr(lhs, rhs), span),
+
             _ => unreachable!(),
         })
     }
@@ -191,12 +219,64 @@ impl<'sess> Parser<'sess> {
 
         let lhs = match lhs.kind {
             ExprKind::Var(var) => var,
-            _ => return Err(Error::InvalidAssignmentTarget(lhs)),
+            _ => return Err(Error::InvalidAssignmentTarget(lhs.span)),
         };
 
         Ok(expr!(Assignment(lhs, Box::new(rhs)), span))
     }
 
+    fn parse_cast(&mut self, expr: Expr) -> JlyResult<Expr> {
+        self.expect(kwd!(As))?;
+        let ty = self.parse_type()?;
+
+        let span = expr.span.join(ty.span);
+
+        Ok(expr!(Cast(Box::new(expr), ty), span))
+    }
+
+    fn parse_call(&mut self, callee: Expr) -> JlyResult<Expr> {
+        let args = self.with_struct_literals(true, |s| {
+            s.parse_comma_list(Self::parse_expr, punct!(LParen), punct!(RParen))
+        })?;
+
+        let span = callee.span.join(self.cursor.prev_span());
+
+        Ok(expr!(Call(Box::new(callee), args), span))
+    }
+
+    fn parse_field(&mut self, lhs: Expr) -> JlyResult<Expr> {
+        self.expect(punct!(Dot))?;
+        let (ident, ident_span) = self.parse_ident()?;
+
+        if self.cursor.matches(punct!(LParen)) {
+            let args = self.with_struct_literals(true, |s| {
+                s.parse_comma_list(Self::parse_expr, punct!(LParen), punct!(RParen))
+            })?;
+
+            let method = Method {
+                ident,
+                span: ident_span,
+                resolved: None,
+            };
+            let span = lhs.span.join(self.cursor.prev_span());
+
+            return Ok(expr!(MethodCall(Box::new(lhs), method, args), span));
+        }
+
+        let span = lhs.span.join(ident_span);
+
+        Ok(expr!(
+            Field(Box::new(lhs), Field { ident, index: None }),
+            span
+        ))
+    }
+
+    fn parse_try(&mut self, expr: Expr) -> JlyResult<Expr> {
+        let question = self.expect(punct!(Question))?;
+        let span = expr.span.join(question.span);
+        Ok(expr!(Try(Box::new(expr)), span))
+    }
+
     fn parse_print(&mut self, print_token: Token) -> JlyResult<Expr> {
         let lparen = self.expect(punct!(LParen))?;
         let expr = self.parse_grouping(lparen)?;
@@ -206,17 +286,69 @@ impl<'sess> Parser<'sess> {
         Ok(expr!(boxed DebugPrint(expr), span))
     }
 
+    fn parse_panic(&mut self, panic_token: Token) -> JlyResult<Expr> {
+        let lparen = self.expect(punct!(LParen))?;
+        let expr = self.parse_grouping(lparen)?;
+
+        let span = panic_token.span.join(expr.span);
+
+        Ok(expr!(boxed Panic(expr), span))
+    }
+
     fn parse_var(&mut self, token: Token) -> JlyResult<Expr> {
-        match token.kind {
-            TokenKind::Ident(ident) => Ok(expr!(
-                Var(Var {
-                    ident,
-                    resolved: None
-                }),
-                token.span
-            )),
+        let ident = match token.kind {
+            TokenKind::Ident(ident) => ident,
             _ => unreachable!(),
+        };
+
+        if self.struct_literals && self.cursor.matches(punct!(LBrace)) {
+            return self.parse_struct_literal(ident, token);
         }
+
+        if builtin_variant(&ident).is_some() {
+            let args = if self.cursor.matches(punct!(LParen)) {
+                self.with_struct_literals(true, |s| {
+                    s.parse_comma_list(Self::parse_expr, punct!(LParen), punct!(RParen))
+                })?
+            } else {
+                vec![]
+            };
+
+            let span = token.span.join(self.cursor.prev_span());
+            let variant = Variant {
+                ident,
+                resolved: None,
+            };
+            return Ok(expr!(Variant(variant, args), span));
+        }
+
+        Ok(expr!(
+            Var(Var {
+                ident,
+                resolved: None,
+                span: token.span,
+            }),
+            token.span
+        ))
+    }
+
+    fn parse_struct_literal(&mut self, ident: Intern<String>, token: Token) -> JlyResult<Expr> {
+        let fields =
+            self.parse_comma_list(Self::parse_field_init, punct!(LBrace), punct!(RBrace))?;
+
+        let span = token.span.join(self.cursor.prev_span());
+
+        Ok(expr!(StructLiteral(StructLiteral { ident, fields }), span))
+    }
+
+    fn parse_field_init(&mut self) -> JlyResult<FieldInit> {
+        let (ident, ident_span) = self.parse_ident()?;
+        self.expect(punct!(Colon))?;
+        let value = self.parse_expr()?;
+
+        let span = ident_span.join(value.span);
+
+        Ok(FieldInit { ident, value, span })
     }
 
     fn parse_string(&mut self, token: Token) -> JlyResult<Expr> {
@@ -229,6 +361,10 @@ impl<'sess> Parser<'sess> {
     fn parse_integer(&mut self, token: Token) -> JlyResult<Expr> {
         match token.kind {
             TokenKind::Integer(n) => Ok(expr!(Value(Value::Integer(n as i64)), token.span)),
+            TokenKind::BigInteger(digits) => {
+                let n = bigint::BigInt::parse(&digits, 10).unwrap();
+                Ok(expr!(Value(Value::from(n)), token.span))
+            }
             _ => unreachable!(),
         }
     }
@@ -259,10 +395,18 @@ impl<'sess> Parser<'sess> {
         Ok(expr!(Neg(Box::new(expr)), span))
     }
 
+    fn parse_bit_not(&mut self, token: Token) -> JlyResult<Expr> {
+        let expr = self.parse_prec(Prec::Negative as usize + 1)?;
+        let span = token.span.join(expr.span);
+        Ok(expr!(BitNot(Box::new(expr)), span))
+    }
+
     fn parse_grouping(&mut self, _token: Token) -> JlyResult<Expr> {
-        let expr = self.parse_or_recover(Self::parse_expr, |s, span| {
-            s.recover_to(punct!(RParen));
-            expr!(DummyExpr, span)
+        let expr = self.with_struct_literals(true, |s| {
+            s.parse_or_recover(Self::parse_expr, |s, span| {
+                s.recover_to(punct!(RParen));
+                expr!(DummyExpr, span)
+            })
         });
         self.expect(punct!(RParen))?;
         Ok(expr)
@@ -1,3 +1,5 @@
+use internment::Intern;
+
 use crate::compiler::ast::*;
 use crate::compiler::diagnostic::*;
 use crate::compiler::lexer::cursor::Cursor;
@@ -7,10 +9,19 @@ use crate::source::Span;
 
 mod expr;
 mod stmt;
+mod types;
 
 pub struct Parser<'sess> {
     cursor: Cursor<'sess>,
     diagnostics: &'sess mut ErrorReporter,
+
+    /// Whether `Ident { ... }` may be parsed as a struct literal, which
+    /// isn't the case in `if` and `while` conditions.
+    struct_literals: bool,
+
+    /// Whether the last statement may be an expression without a
+    /// semicolon, as the inputs of the REPL can be.
+    trailing_expr: bool,
 }
 
 impl<'sess> Parser<'sess> {
@@ -18,16 +29,27 @@ impl<'sess> Parser<'sess> {
         Self {
             cursor: lexer.cursor(),
             diagnostics,
+            struct_literals: true,
+            trailing_expr: false,
         }
     }
 
+    pub fn allow_trailing_expr(mut self) -> Self {
+        self.trailing_expr = true;
+        self
+    }
+
     pub fn parse(mut self) -> Module {
         let mut statements = vec![];
         while !self.cursor.eof() {
             statements.push(self.parse_statement());
         }
 
-        Module { statements }
+        Module {
+            statements,
+            comments: self.cursor.take_comments(),
+            attributes: self.cursor.take_attributes(),
+        }
     }
 
     fn parse_or_recover<T, F: FnMut(&mut Self) -> JlyResult<T>, R: FnMut(&mut Self, Span) -> T>(
@@ -66,6 +88,10 @@ impl<'sess> Parser<'sess> {
     ) -> JlyResult<Vec<T>> {
         self.expect(start)?;
 
+        if self.cursor.eat(end) {
+            return Ok(vec![]);
+        }
+
         let mut items = vec![f(self)?];
         while self.cursor.eat(punct!(Comma)) && !self.cursor.eof() && self.cursor.peek().kind != end
         {
@@ -81,7 +107,28 @@ impl<'sess> Parser<'sess> {
         Ok(items)
     }
 
+    fn with_struct_literals<T>(&mut self, allowed: bool, f: impl FnOnce(&mut Self) -> T) -> T {
+        let prev = std::mem::replace(&mut self.struct_literals, allowed);
+        let result = f(self);
+        self.struct_literals = prev;
+        result
+    }
+
+    fn parse_ident(&mut self) -> JlyResult<(Intern<String>, Span)> {
+        let token = self.cursor.next();
+        match token.kind {
+            TokenKind::Ident(ident) => Ok((ident, token.span)),
+            _ => Err(Error::Expected("an identifier", token)),
+        }
+    }
+
     fn expect(&mut self, kind: TokenKind) -> JlyResult<Token> {
+        if kind == punct!(GT) {
+            if let Some(token) = self.cursor.split_shr() {
+                return Ok(token);
+            }
+        }
+
         let token = self.cursor.next();
         if token.kind == kind {
             Ok(token)
@@ -4,7 +4,7 @@ impl<'sess> Parser<'sess> {
     pub fn parse_block(&mut self) -> JlyResult<Block> {
         let mut statements = vec![];
 
-        self.expect(punct!(LBrace))?;
+        let lbrace = self.expect(punct!(LBrace))?;
 
         while !(self.cursor.eof() || self.cursor.matches(punct!(RBrace))) {
             statements.push(self.parse_statement());
@@ -19,6 +19,7 @@ impl<'sess> Parser<'sess> {
         Ok(Block {
             statements,
             num_vars: None,
+            span: lbrace.span.join(self.cursor.prev_span()),
         })
     }
 
@@ -32,15 +33,26 @@ impl<'sess> Parser<'sess> {
     fn parse_statement_inner(&mut self) -> JlyResult<Statement> {
         match self.cursor.peek().kind {
             kwd!(Let) => Ok(Statement::VarDecl(self.parse_var_decl()?)),
+            kwd!(Fn) => Ok(Statement::FnDecl(self.parse_fn_decl()?)),
+            kwd!(Struct) => Ok(Statement::StructDecl(self.parse_struct_decl()?)),
+            kwd!(Enum) => Ok(Statement::EnumDecl(self.parse_enum_decl()?)),
+            kwd!(Type) => Ok(Statement::NewtypeDecl(self.parse_newtype_decl()?)),
+            kwd!(Trait) => Ok(Statement::TraitDecl(self.parse_trait_decl()?)),
+            kwd!(Impl) => Ok(Statement::ImplDecl(self.parse_impl_decl()?)),
             kwd!(If) => Ok(Statement::If(self.parse_if_statement()?)),
             kwd!(While) => Ok(Statement::While(self.parse_while_loop()?)),
+            kwd!(Return) => Ok(Statement::Return(self.parse_return()?)),
+            kwd!(Match) => Ok(Statement::Match(self.parse_match()?)),
+            kwd!(Try) => Ok(Statement::Try(self.parse_try_statement()?)),
             punct!(LBrace) => {
                 let block = self.parse_block()?;
                 Ok(Statement::Block(block))
             }
             _ => {
                 let expr = self.parse_expr()?;
-                self.expect(punct!(Semicolon))?;
+                if !(self.trailing_expr && self.cursor.eof()) {
+                    self.expect(punct!(Semicolon))?;
+                }
 
                 Ok(Statement::Expr(expr))
             }
@@ -50,10 +62,12 @@ impl<'sess> Parser<'sess> {
     fn parse_var_decl(&mut self) -> JlyResult<VarDecl> {
         let let_token = self.expect(kwd!(Let))?;
 
-        let ident_token = self.cursor.next();
-        let ident = match ident_token.kind {
-            TokenKind::Ident(ident) => ident,
-            _ => return Err(Error::Expected("an identifier", ident_token)),
+        let (ident, ident_span) = self.parse_ident()?;
+
+        let ty = if self.cursor.eat(punct!(Colon)) {
+            Some(self.parse_type()?)
+        } else {
+            None
         };
 
         self.expect(punct!(Equal))?;
@@ -64,18 +78,223 @@ impl<'sess> Parser<'sess> {
 
         let span = let_token.span.join(semicolon_token.span);
 
-        Ok(VarDecl { ident, value, span })
+        Ok(VarDecl {
+            ident,
+            ident_span,
+            ty,
+            value,
+            span,
+        })
+    }
+
+    fn parse_fn_decl(&mut self) -> JlyResult<FnDecl> {
+        let sig = self.parse_fn_sig()?;
+        let body = self.parse_block()?;
+
+        Ok(FnDecl {
+            sig,
+            body,
+            resolved: None,
+        })
+    }
+
+    fn parse_fn_sig(&mut self) -> JlyResult<FnSig> {
+        let fn_token = self.expect(kwd!(Fn))?;
+        let (ident, ident_span) = self.parse_ident()?;
+        let generics = self.parse_generics()?;
+
+        let params = self.parse_comma_list(Self::parse_param, punct!(LParen), punct!(RParen))?;
+
+        let return_ty = if self.cursor.eat(punct!(Arrow)) {
+            Some(self.parse_type()?)
+        } else {
+            None
+        };
+
+        Ok(FnSig {
+            ident,
+            generics,
+            params,
+            return_ty,
+            span: fn_token.span.join(ident_span),
+        })
+    }
+
+    fn parse_param(&mut self) -> JlyResult<Param> {
+        let (ident, span) = self.parse_ident()?;
+
+        let ty = if self.cursor.eat(punct!(Colon)) {
+            Some(self.parse_type()?)
+        } else {
+            None
+        };
+
+        Ok(Param { ident, ty, span })
+    }
+
+    fn parse_struct_decl(&mut self) -> JlyResult<StructDecl> {
+        let struct_token = self.expect(kwd!(Struct))?;
+        let (ident, ident_span) = self.parse_ident()?;
+        let generics = self.parse_generics()?;
+
+        let fields =
+            self.parse_comma_list(Self::parse_field_decl, punct!(LBrace), punct!(RBrace))?;
+
+        Ok(StructDecl {
+            ident,
+            generics,
+            fields,
+            span: struct_token.span.join(ident_span),
+        })
+    }
+
+    fn parse_field_decl(&mut self) -> JlyResult<FieldDecl> {
+        let (ident, span) = self.parse_ident()?;
+        self.expect(punct!(Colon))?;
+        let ty = self.parse_type()?;
+        Ok(FieldDecl { ident, ty, span })
+    }
+
+    fn parse_enum_decl(&mut self) -> JlyResult<EnumDecl> {
+        let enum_token = self.expect(kwd!(Enum))?;
+        let (ident, ident_span) = self.parse_ident()?;
+        let generics = self.parse_generics()?;
+
+        let variants =
+            self.parse_comma_list(Self::parse_variant_decl, punct!(LBrace), punct!(RBrace))?;
+
+        Ok(EnumDecl {
+            ident,
+            generics,
+            variants,
+            span: enum_token.span.join(ident_span),
+        })
+    }
+
+    fn parse_variant_decl(&mut self) -> JlyResult<VariantDecl> {
+        let (ident, span) = self.parse_ident()?;
+
+        let payload = if self.cursor.eat(punct!(LParen)) {
+            let ty = self.parse_type()?;
+            self.expect(punct!(RParen))?;
+            Some(ty)
+        } else {
+            None
+        };
+
+        Ok(VariantDecl {
+            ident,
+            payload,
+            span,
+        })
+    }
+
+    fn parse_newtype_decl(&mut self) -> JlyResult<NewtypeDecl> {
+        let type_token = self.expect(kwd!(Type))?;
+        let (ident, ident_span) = self.parse_ident()?;
+
+        self.expect(punct!(Equal))?;
+        self.expect(kwd!(New))?;
+        let base = self.parse_type()?;
+        self.expect(punct!(Semicolon))?;
+
+        Ok(NewtypeDecl {
+            ident,
+            base,
+            span: type_token.span.join(ident_span),
+        })
+    }
+
+    fn parse_trait_decl(&mut self) -> JlyResult<TraitDecl> {
+        let trait_token = self.expect(kwd!(Trait))?;
+        let (ident, ident_span) = self.parse_ident()?;
+
+        self.expect(punct!(LBrace))?;
+
+        let mut methods = vec![];
+        while !(self.cursor.eof() || self.cursor.matches(punct!(RBrace))) {
+            methods.push(self.parse_fn_sig()?);
+            self.expect(punct!(Semicolon))?;
+        }
+
+        self.expect(punct!(RBrace))?;
+
+        Ok(TraitDecl {
+            ident,
+            methods,
+            span: trait_token.span.join(ident_span),
+        })
+    }
+
+    fn parse_impl_decl(&mut self) -> JlyResult<ImplDecl> {
+        let impl_token = self.expect(kwd!(Impl))?;
+        let generics = self.parse_generics()?;
+
+        let mut ty = self.parse_type()?;
+        let mut trait_ = None;
+
+        if self.cursor.eat(kwd!(For)) {
+            trait_ = match ty.kind {
+                TypeExprKind::Named(ident, args) if args.is_empty() => Some((ident, ty.span)),
+                _ => return Err(Error::NotATrait(ty.span)),
+            };
+            ty = self.parse_type()?;
+        }
+
+        self.expect(punct!(LBrace))?;
+
+        let mut methods = vec![];
+        while !(self.cursor.eof() || self.cursor.matches(punct!(RBrace))) {
+            methods.push(self.parse_fn_decl()?);
+        }
+
+        self.expect(punct!(RBrace))?;
+
+        Ok(ImplDecl {
+            generics,
+            trait_,
+            span: impl_token.span.join(ty.span),
+            ty,
+            methods,
+            resolved: None,
+        })
+    }
+
+    /// Parse the (optional) list of type parameters of a function or type.
+    fn parse_generics(&mut self) -> JlyResult<Vec<GenericParam>> {
+        if !self.cursor.matches(punct!(LT)) {
+            return Ok(vec![]);
+        }
+
+        self.parse_comma_list(Self::parse_generic_param, punct!(LT), punct!(GT))
+    }
+
+    fn parse_generic_param(&mut self) -> JlyResult<GenericParam> {
+        let (ident, _) = self.parse_ident()?;
+
+        let mut bounds = vec![];
+        if self.cursor.eat(punct!(Colon)) {
+            loop {
+                bounds.push(self.parse_ident()?);
+                if !self.cursor.eat(punct!(Add)) {
+                    break;
+                }
+            }
+        }
+
+        Ok(GenericParam { ident, bounds })
     }
 
     fn parse_if_statement(&mut self) -> JlyResult<IfStatement> {
-        self.expect(kwd!(If))?;
+        let if_token = self.expect(kwd!(If))?;
 
-        let condition = self.parse_expr()?;
+        let condition = self.with_struct_literals(false, Self::parse_expr)?;
+        let span = if_token.span.join(condition.span);
 
         let then = self.parse_block()?;
 
         let else_ = if self.cursor.eat(kwd!(Else)) {
-            let stmt = if self.cursor.eat(kwd!(If)) {
+            let stmt = if self.cursor.matches(kwd!(If)) {
                 Statement::If(self.parse_if_statement()?)
             } else {
                 Statement::Block(self.parse_block()?)
@@ -89,13 +308,107 @@ impl<'sess> Parser<'sess> {
             condition,
             then,
             else_,
+            span,
         })
     }
 
     fn parse_while_loop(&mut self) -> JlyResult<WhileLoop> {
-        self.expect(kwd!(While))?;
-        let condition = self.parse_expr()?;
+        let while_token = self.expect(kwd!(While))?;
+        let condition = self.with_struct_literals(false, Self::parse_expr)?;
+        let span = while_token.span.join(condition.span);
+        let body = self.parse_block()?;
+        Ok(WhileLoop {
+            condition,
+            body,
+            span,
+        })
+    }
+
+    fn parse_match(&mut self) -> JlyResult<MatchStatement> {
+        let match_token = self.expect(kwd!(Match))?;
+        let scrutinee = self.with_struct_literals(false, Self::parse_expr)?;
+
+        self.expect(punct!(LBrace))?;
+
+        let mut arms = vec![];
+        while !(self.cursor.eof() || self.cursor.matches(punct!(RBrace))) {
+            let pattern = self.parse_pattern()?;
+            self.expect(punct!(FatArrow))?;
+            let body = self.parse_block()?;
+            self.cursor.eat(punct!(Comma));
+
+            arms.push(MatchArm { pattern, body });
+        }
+
+        self.expect(punct!(RBrace))?;
+
+        Ok(MatchStatement {
+            span: match_token.span.join(scrutinee.span),
+            scrutinee,
+            arms,
+            slot: None,
+        })
+    }
+
+    fn parse_pattern(&mut self) -> JlyResult<Pattern> {
+        let (ident, ident_span) = self.parse_ident()?;
+
+        if *ident == "_" {
+            return Ok(Pattern {
+                kind: PatternKind::Wildcard,
+                span: ident_span,
+            });
+        }
+
+        let mut binding = None;
+        if self.cursor.eat(punct!(LParen)) {
+            let (ident, span) = self.parse_ident()?;
+            if *ident != "_" {
+                binding = Some((ident, span));
+            }
+            self.expect(punct!(RParen))?;
+        }
+
+        Ok(Pattern {
+            kind: PatternKind::Variant {
+                ident,
+                binding,
+                tag: None,
+            },
+            span: ident_span.join(self.cursor.prev_span()),
+        })
+    }
+
+    fn parse_try_statement(&mut self) -> JlyResult<TryStatement> {
+        let try_token = self.expect(kwd!(Try))?;
         let body = self.parse_block()?;
-        Ok(WhileLoop { condition, body })
+
+        self.expect(kwd!(Recover))?;
+        let (binding, binding_span) = self.parse_ident()?;
+        let recover = self.parse_block()?;
+
+        Ok(TryStatement {
+            body,
+            binding,
+            recover,
+            span: try_token.span.join(binding_span),
+        })
+    }
+
+    fn parse_return(&mut self) -> JlyResult<ReturnStatement> {
+        let return_token = self.expect(kwd!(Return))?;
+
+        let value = if self.cursor.matches(punct!(Semicolon)) {
+            None
+        } else {
+            Some(self.parse_expr()?)
+        };
+
+        let semicolon_token = self.expect(punct!(Semicolon))?;
+
+        Ok(ReturnStatement {
+            value,
+            span: return_token.span.join(semicolon_token.span),
+        })
     }
 }
@@ -0,0 +1,47 @@
+use super::*;
+
+impl<'sess> Parser<'sess> {
+    pub fn parse_type(&mut self) -> JlyResult<TypeExpr> {
+        let token = self.cursor.next();
+
+        let kind = match token.kind {
+            punct!(LParen) => {
+                self.expect(punct!(RParen))?;
+                TypeExprKind::Unit
+            }
+
+            kwd!(Fn) => {
+                let params =
+                    self.parse_comma_list(Self::parse_type, punct!(LParen), punct!(RParen))?;
+
+                let ret = if self.cursor.eat(punct!(Arrow)) {
+                    self.parse_type()?
+                } else {
+                    TypeExpr {
+                        kind: TypeExprKind::Unit,
+                        span: self.cursor.prev_span(),
+                    }
+                };
+
+                TypeExprKind::Function(params, Box::new(ret))
+            }
+
+            TokenKind::Ident(ident) => {
+                let args = if self.cursor.matches(punct!(LT)) {
+                    self.parse_comma_list(Self::parse_type, punct!(LT), punct!(GT))?
+                } else {
+                    vec![]
+                };
+
+                TypeExprKind::Named(ident, args)
+            }
+
+            _ => return Err(Error::Expected("a type", token)),
+        };
+
+        Ok(TypeExpr {
+            kind,
+            span: token.span.join(self.cursor.prev_span()),
+        })
+    }
+}
@@ -1,13 +1,26 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use internment::Intern;
+
 use crate::compiler::ast::*;
 use crate::compiler::diagnostic::JlyResult;
+use crate::compiler::passes::resolve::VarResolved;
+use crate::compiler::passes::typecheck::MethodResolved;
 use crate::runtime::chunk::{Chunk, Instr};
-use crate::runtime::value::Value;
+use crate::runtime::value::bigint::BigInt;
+use crate::runtime::value::{JellyFunction, Object, StructLayout, Value};
+use crate::source::Span;
 
 use super::visit::Visitor;
 
 enum JumpKind {
     Jump,
     JumpNot,
+
+    /// Not a jump as such, but a handler's destination is patched the
+    /// same way.
+    PushHandler,
 }
 
 impl JumpKind {
@@ -15,6 +28,7 @@ impl JumpKind {
         match self {
             Self::Jump => Instr::JumpU32,
             Self::JumpNot => Instr::JumpNotU32,
+            Self::PushHandler => Instr::PushHandlerU32,
         }
     }
 }
@@ -29,38 +43,33 @@ impl Chunk {
         self.code.push(instr.into());
     }
 
-    pub fn emit_constant(&mut self, value: Value) {
-        let idx = self.constants.len();
-        self.constants.push(value);
-
-        if idx <= 0xff {
-            self.emit_instr(Instr::LoadConstantU8);
-            self.emit_u8(idx as u8);
-        } else {
-            self.emit_instr(Instr::LoadConstantU32);
-            self.emit_u32(idx as u32);
+    /// Attribute the code emitted from now on to `span`.
+    fn set_span(&mut self, span: Span) {
+        let offset = self.code.len();
+        match self.spans.last_mut() {
+            Some((_, last)) if *last == span => {}
+            Some((start, last)) if *start == offset => *last = span,
+            _ => self.spans.push((offset, span)),
         }
     }
 
     #[inline]
     fn emit_u8(&mut self, n: u8) {
-        self.code.push((n as u8).into());
+        self.code.push(n.into());
     }
 
     #[inline]
     fn emit_u32(&mut self, n: u32) {
-        self.emit_u8((n & 0xff000000) as u8);
-        self.emit_u8((n & 0x00ff0000) as u8);
-        self.emit_u8((n & 0x0000ff00) as u8);
-        self.emit_u8((n & 0x000000ff) as u8);
+        for byte in n.to_be_bytes() {
+            self.emit_u8(byte);
+        }
     }
 
     #[inline]
     fn set_u32(&mut self, idx: usize, n: u32) {
-        self.code[idx].set_byte((n & 0xff000000) as u8);
-        self.code[idx + 1].set_byte((n & 0x00ff0000) as u8);
-        self.code[idx + 2].set_byte((n & 0x0000ff00) as u8);
-        self.code[idx + 3].set_byte((n & 0x000000ff) as u8);
+        for (i, byte) in n.to_be_bytes().into_iter().enumerate() {
+            self.code[idx + i].set_byte(byte);
+        }
     }
 
     fn new_jump_source(&mut self, kind: JumpKind) -> JumpSource {
@@ -82,26 +91,185 @@ impl Chunk {
 
     fn jump_depart(&mut self, dest: JumpDest, kind: JumpKind) {
         self.emit_instr(kind.instr());
-        self.emit_u32(dest.0 as u32);
+        self.emit_u32(dest.0);
+    }
+}
+
+/// A constant that can be shared. Objects other than `BigInt`s compare by
+/// identity, so functions and layouts never are.
+#[derive(Clone, PartialEq, Eq, Hash)]
+enum ConstantKey {
+    String(Intern<String>),
+    Integer(i64),
+    Float(u64),
+    BigInt(BigInt),
+}
+
+impl ConstantKey {
+    fn of(value: &Value) -> Option<Self> {
+        match value {
+            Value::Object(object) => match &**object {
+                Object::BigInt(n) => Some(Self::BigInt(n.clone())),
+                _ => None,
+            },
+            Value::String(s) => Some(Self::String(*s)),
+            Value::Integer(n) => Some(Self::Integer(*n)),
+            Value::Float(bits) => Some(Self::Float(*bits)),
+            Value::Bool(_) | Value::Unit => None,
+        }
     }
 }
 
 #[derive(Default)]
 pub struct CodeGenerator {
     chunk: Chunk,
+
+    /// Where each shareable constant is in this chunk's pool.
+    constant_indices: HashMap<ConstantKey, usize>,
+
+    /// The constants of the whole program, so that every function uses the
+    /// same object for equal `BigInt`s.
+    shared_constants: HashMap<ConstantKey, Value>,
+
+    /// Whether the top level returns the value of its last statement,
+    /// if that's an expression, rather than `()`.
+    return_last_expr: bool,
+
+    /// The spans of the statements and expressions being generated, so
+    /// code emitted after a child is attributed to its parent again.
+    enclosing_spans: Vec<Span>,
 }
 
 impl CodeGenerator {
+    pub fn returning_last_expr() -> Self {
+        Self {
+            return_last_expr: true,
+            ..Self::default()
+        }
+    }
+
     pub fn chunk(self) -> Chunk {
         self.chunk
     }
+
+    /// Attribute the code emitted from now on to `span`, until `exit_span`.
+    fn enter_span(&mut self, span: Span) {
+        self.enclosing_spans.push(span);
+        self.chunk.set_span(span);
+    }
+
+    fn exit_span(&mut self) {
+        self.enclosing_spans.pop();
+        if let Some(span) = self.enclosing_spans.last() {
+            self.chunk.set_span(*span);
+        }
+    }
+
+    fn emit_constant(&mut self, value: Value) {
+        match value {
+            Value::Bool(true) => return self.chunk.emit_instr(Instr::LoadTrue),
+            Value::Bool(false) => return self.chunk.emit_instr(Instr::LoadFalse),
+            Value::Unit => return self.chunk.emit_instr(Instr::LoadUnit),
+            Value::Integer(n) => {
+                if let Ok(n) = i8::try_from(n) {
+                    self.chunk.emit_instr(Instr::LoadSmallInt);
+                    return self.chunk.emit_u8(n as u8);
+                }
+            }
+            _ => {}
+        }
+
+        let idx = self.add_constant(value);
+
+        if idx <= 0xff {
+            self.chunk.emit_instr(Instr::LoadConstantU8);
+            self.chunk.emit_u8(idx as u8);
+        } else {
+            self.chunk.emit_instr(Instr::LoadConstantU32);
+            self.chunk.emit_u32(idx as u32);
+        }
+    }
+
+    /// Add a constant to the pool, reusing an equal one if there is one.
+    fn add_constant(&mut self, value: Value) -> usize {
+        let key = match ConstantKey::of(&value) {
+            Some(key) => key,
+            None => {
+                self.chunk.constants.push(value);
+                return self.chunk.constants.len() - 1;
+            }
+        };
+
+        if let Some(idx) = self.constant_indices.get(&key) {
+            return *idx;
+        }
+
+        let value = self
+            .shared_constants
+            .entry(key.clone())
+            .or_insert(value)
+            .clone();
+        self.chunk.constants.push(value);
+        self.constant_indices
+            .insert(key, self.chunk.constants.len() - 1);
+        self.chunk.constants.len() - 1
+    }
 }
 
 impl Visitor for CodeGenerator {
     fn visit_module(&mut self, module: &mut Module) -> JlyResult<()> {
+        // define all functions up front, in the order they were resolved
+        for statement in &mut module.statements {
+            match statement {
+                Statement::FnDecl(fn_decl) => self.visit_fn_decl(fn_decl)?,
+                Statement::ImplDecl(impl_decl) => {
+                    for method in &mut impl_decl.methods {
+                        self.visit_fn_decl(method)?;
+                    }
+                }
+                _ => {}
+            }
+        }
+
         for statement in &mut module.statements {
+            if let Statement::ImplDecl(impl_decl) = statement {
+                self.visit_impl_decl(impl_decl)?;
+            }
+        }
+
+        let mut statements: Vec<_> = module
+            .statements
+            .iter_mut()
+            .filter(|statement| {
+                !matches!(
+                    statement,
+                    Statement::FnDecl(_)
+                        | Statement::StructDecl(_)
+                        | Statement::EnumDecl(_)
+                        | Statement::NewtypeDecl(_)
+                        | Statement::TraitDecl(_)
+                        | Statement::ImplDecl(_)
+                )
+            })
+            .collect();
+
+        let last_expr = match statements.last() {
+            Some(Statement::Expr(expr))
+                if self.return_last_expr && !matches!(expr.kind, ExprKind::Assignment(..)) =>
+            {
+                statements.pop()
+            }
+            _ => None,
+        };
+
+        for statement in statements {
             self.visit_statement(statement)?;
         }
+
+        match last_expr {
+            Some(Statement::Expr(expr)) => self.visit_expr(expr)?,
+            _ => self.chunk.emit_instr(Instr::LoadUnit),
+        }
         self.chunk.emit_instr(Instr::Return);
         Ok(())
     }
@@ -117,6 +285,7 @@ impl Visitor for CodeGenerator {
     }
 
     fn visit_statement(&mut self, statement: &mut Statement) -> JlyResult<()> {
+        self.enter_span(statement.span());
         match statement {
             Statement::Expr(expr) => {
                 self.visit_expr(expr)?;
@@ -124,9 +293,19 @@ impl Visitor for CodeGenerator {
             }
             Statement::Block(block) => self.visit_block(block)?,
             Statement::VarDecl(var_decl) => self.visit_var_decl(var_decl)?,
+            Statement::FnDecl(fn_decl) => self.visit_fn_decl(fn_decl)?,
+            Statement::StructDecl(struct_decl) => self.visit_struct_decl(struct_decl)?,
+            Statement::EnumDecl(enum_decl) => self.visit_enum_decl(enum_decl)?,
+            Statement::NewtypeDecl(newtype_decl) => self.visit_newtype_decl(newtype_decl)?,
+            Statement::TraitDecl(trait_decl) => self.visit_trait_decl(trait_decl)?,
+            Statement::ImplDecl(impl_decl) => self.visit_impl_decl(impl_decl)?,
             Statement::If(if_statement) => self.visit_if_statement(if_statement)?,
             Statement::While(while_loop) => self.visit_while_loop(while_loop)?,
+            Statement::Match(match_statement) => self.visit_match(match_statement)?,
+            Statement::Try(try_statement) => self.visit_try_statement(try_statement)?,
+            Statement::Return(return_statement) => self.visit_return(return_statement)?,
         }
+        self.exit_span();
         Ok(())
     }
 
@@ -139,11 +318,13 @@ impl Visitor for CodeGenerator {
             }};
         }
 
+        self.enter_span(expr.span);
+
         match &mut expr.kind {
             ExprKind::Var(var) => self.visit_var(var)?,
 
             ExprKind::Value(value) => {
-                self.chunk.emit_constant(value.clone());
+                self.emit_constant(value.clone());
             }
 
             ExprKind::LogicalOr(a, b) => binary_op!(a OrBool b),
@@ -174,27 +355,124 @@ impl Visitor for CodeGenerator {
                 self.chunk.emit_instr(Instr::NegInt);
             }
 
+            ExprKind::BitAnd(a, b) => binary_op!(a BitAndInt b),
+            ExprKind::BitOr(a, b) => binary_op!(a BitOrInt b),
+            ExprKind::BitXor(a, b) => binary_op!(a BitXorInt b),
+            ExprKind::Shl(a, b) => binary_op!(a ShlInt b),
+            ExprKind::Shr(a, b) => binary_op!(a ShrInt b),
+            ExprKind::BitNot(expr) => {
+                self.visit_expr(expr)?;
+                self.chunk.emit_instr(Instr::BitNotInt);
+            }
+
             ExprKind::Assignment(lhs, rhs) => {
                 self.visit_expr(rhs)?;
                 self.chunk.emit_instr(Instr::StoreLocal);
                 self.chunk.emit_u8(lhs.resolved.unwrap().byte());
             }
 
+            ExprKind::Call(callee, args) => {
+                self.visit_expr(callee)?;
+                for arg in args.iter_mut() {
+                    self.visit_expr(arg)?;
+                }
+                self.chunk.emit_instr(Instr::Call);
+                self.chunk.emit_u8(args.len() as u8);
+            }
+
+            ExprKind::MethodCall(receiver, method, args) => match method.resolved.unwrap() {
+                MethodResolved::Static(global) => {
+                    self.chunk.emit_instr(Instr::LoadGlobal);
+                    self.chunk.emit_u8(global as u8);
+                    self.visit_expr(receiver)?;
+                    for arg in args.iter_mut() {
+                        self.visit_expr(arg)?;
+                    }
+                    self.chunk.emit_instr(Instr::Call);
+                    self.chunk.emit_u8(args.len() as u8 + 1);
+                }
+                MethodResolved::Dynamic {
+                    trait_index,
+                    method,
+                } => {
+                    self.visit_expr(receiver)?;
+                    for arg in args.iter_mut() {
+                        self.visit_expr(arg)?;
+                    }
+                    self.chunk.emit_instr(Instr::Invoke);
+                    self.chunk.emit_u8(trait_index as u8);
+                    self.chunk.emit_u8(method as u8);
+                    self.chunk.emit_u8(args.len() as u8 + 1);
+                }
+            },
+
+            ExprKind::Variant(variant, args) => {
+                match args.first_mut() {
+                    Some(value) => self.visit_expr(value)?,
+                    None => self.chunk.emit_instr(Instr::LoadUnit),
+                }
+
+                let resolved = variant.resolved.as_ref().unwrap();
+                let layout = resolved.layout.clone();
+                self.emit_constant(Value::Object(Rc::new(Object::EnumLayout(layout))));
+                self.chunk.emit_instr(Instr::MakeVariant);
+                self.chunk.emit_u8(resolved.tag as u8);
+            }
+
+            ExprKind::Try(expr) => {
+                self.visit_expr(expr)?;
+                self.chunk.emit_instr(Instr::Try);
+            }
+
+            // newtypes are represented by their base type
+            ExprKind::Cast(expr, _) => self.visit_expr(expr)?,
+
+            ExprKind::Field(expr, field) => {
+                self.visit_expr(expr)?;
+                self.chunk.emit_instr(Instr::GetField);
+                self.chunk.emit_u8(field.index.unwrap() as u8);
+            }
+
+            ExprKind::StructLiteral(literal) => {
+                for field in &mut literal.fields {
+                    self.visit_expr(&mut field.value)?;
+                }
+
+                let layout = StructLayout {
+                    name: literal.ident,
+                    fields: literal.fields.iter().map(|field| field.ident).collect(),
+                };
+                self.emit_constant(Value::Object(Rc::new(Object::StructLayout(Rc::new(
+                    layout,
+                )))));
+                self.chunk.emit_instr(Instr::MakeStruct);
+            }
+
             ExprKind::DebugPrint(expr) => {
                 self.visit_expr(expr)?;
                 self.chunk.emit_instr(Instr::DebugPrint);
                 self.chunk.emit_instr(Instr::LoadUnit);
             }
 
+            ExprKind::Panic(expr) => {
+                self.visit_expr(expr)?;
+                self.chunk.emit_instr(Instr::Panic);
+            }
+
             ExprKind::DummyExpr => unreachable!(),
         }
 
+        self.exit_span();
         Ok(())
     }
 
     fn visit_var(&mut self, var: &mut Var) -> JlyResult<()> {
-        self.chunk.emit_instr(Instr::LoadLocal);
-        self.chunk.emit_u8(var.resolved.unwrap().byte());
+        let resolved = var.resolved.unwrap();
+        match resolved {
+            VarResolved::Local(_) => self.chunk.emit_instr(Instr::LoadLocal),
+            VarResolved::Global(_) => self.chunk.emit_instr(Instr::LoadGlobal),
+        }
+        self.chunk.emit_u8(resolved.byte());
         Ok(())
     }
 
@@ -202,6 +480,67 @@ impl Visitor for CodeGenerator {
         self.visit_expr(&mut var_decl.value)
     }
 
+    fn visit_fn_decl(&mut self, fn_decl: &mut FnDecl) -> JlyResult<()> {
+        let mut codegen = CodeGenerator {
+            shared_constants: std::mem::take(&mut self.shared_constants),
+            ..CodeGenerator::default()
+        };
+        codegen.visit_block(&mut fn_decl.body)?;
+        self.shared_constants = std::mem::take(&mut codegen.shared_constants);
+        codegen.chunk.emit_instr(Instr::LoadUnit);
+        codegen.chunk.emit_instr(Instr::Return);
+
+        let function = JellyFunction {
+            name: fn_decl.sig.ident,
+            chunk: Rc::new(codegen.chunk()),
+            arity: fn_decl.sig.params.len(),
+        };
+
+        self.emit_constant(Value::Object(Rc::new(Object::JellyFunction(Box::new(
+            function,
+        )))));
+        self.chunk.emit_instr(Instr::DefineGlobal);
+
+        Ok(())
+    }
+
+    fn visit_struct_decl(&mut self, _struct_decl: &mut StructDecl) -> JlyResult<()> {
+        Ok(())
+    }
+
+    fn visit_enum_decl(&mut self, _enum_decl: &mut EnumDecl) -> JlyResult<()> {
+        Ok(())
+    }
+
+    fn visit_newtype_decl(&mut self, _newtype_decl: &mut NewtypeDecl) -> JlyResult<()> {
+        Ok(())
+    }
+
+    fn visit_trait_decl(&mut self, _trait_decl: &mut TraitDecl) -> JlyResult<()> {
+        Ok(())
+    }
+
+    /// Register the vtable of a trait impl. The methods themselves have
+    /// already been defined as globals.
+    fn visit_impl_decl(&mut self, impl_decl: &mut ImplDecl) -> JlyResult<()> {
+        let resolved = match &impl_decl.resolved {
+            Some(resolved) => resolved,
+            None => return Ok(()),
+        };
+
+        self.emit_constant(Value::String(resolved.ty));
+        for global in &resolved.methods {
+            self.chunk.emit_instr(Instr::LoadGlobal);
+            self.chunk.emit_u8(*global as u8);
+        }
+
+        self.chunk.emit_instr(Instr::DefineImpl);
+        self.chunk.emit_u8(resolved.trait_index as u8);
+        self.chunk.emit_u8(resolved.methods.len() as u8);
+
+        Ok(())
+    }
+
     fn visit_if_statement(&mut self, if_statement: &mut IfStatement) -> JlyResult<()> {
         self.visit_expr(&mut if_statement.condition)?;
 
@@ -237,4 +576,80 @@ impl Visitor for CodeGenerator {
 
         Ok(())
     }
+
+    fn visit_match(&mut self, match_statement: &mut MatchStatement) -> JlyResult<()> {
+        let slot = match_statement.slot.unwrap() as u8;
+        self.visit_expr(&mut match_statement.scrutinee)?;
+
+        let mut end_jumps = vec![];
+
+        for arm in &mut match_statement.arms {
+            let (tag, binding) = match &arm.pattern.kind {
+                PatternKind::Wildcard => (None, false),
+                PatternKind::Variant { binding, tag, .. } => {
+                    (Some(tag.unwrap()), binding.is_some())
+                }
+            };
+
+            let next_arm = tag.map(|tag| {
+                self.chunk.emit_instr(Instr::LoadLocal);
+                self.chunk.emit_u8(slot);
+                self.chunk.emit_instr(Instr::IsVariant);
+                self.chunk.emit_u8(tag as u8);
+                self.chunk.new_jump_source(JumpKind::JumpNot)
+            });
+
+            if binding {
+                self.chunk.emit_instr(Instr::LoadLocal);
+                self.chunk.emit_u8(slot);
+                self.chunk.emit_instr(Instr::GetPayload);
+            }
+
+            self.visit_block(&mut arm.body)?;
+
+            if binding {
+                self.chunk.emit_instr(Instr::Pop);
+            }
+
+            end_jumps.push(self.chunk.new_jump_source(JumpKind::Jump));
+
+            if let Some(next_arm) = next_arm {
+                self.chunk.jump_arrive(next_arm);
+            }
+        }
+
+        for jump in end_jumps {
+            self.chunk.jump_arrive(jump);
+        }
+
+        // pop the scrutinee
+        self.chunk.emit_instr(Instr::Pop);
+
+        Ok(())
+    }
+
+    fn visit_try_statement(&mut self, try_statement: &mut TryStatement) -> JlyResult<()> {
+        let handler = self.chunk.new_jump_source(JumpKind::PushHandler);
+        self.visit_block(&mut try_statement.body)?;
+        self.chunk.emit_instr(Instr::PopHandler);
+        let end_jump = self.chunk.new_jump_source(JumpKind::Jump);
+
+        // the VM arrives here with the panic pushed as the binding
+        self.chunk.jump_arrive(handler);
+        self.visit_block(&mut try_statement.recover)?;
+        self.chunk.emit_instr(Instr::Pop);
+
+        self.chunk.jump_arrive(end_jump);
+
+        Ok(())
+    }
+
+    fn visit_return(&mut self, return_statement: &mut ReturnStatement) -> JlyResult<()> {
+        match &mut return_statement.value {
+            Some(value) => self.visit_expr(value)?,
+            None => self.chunk.emit_instr(Instr::LoadUnit),
+        }
+        self.chunk.emit_instr(Instr::Return);
+        Ok(())
+    }
 }
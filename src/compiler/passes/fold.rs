@@ -0,0 +1,441 @@
+use std::cmp::Ordering;
+
+use crate::compiler::ast::*;
+use crate::compiler::diagnostic::{Error, ErrorReport, JlyResult};
+use crate::compiler::passes::visit::Visitor;
+use crate::runtime::builtins::{pow_int, shift_amount};
+use crate::runtime::value::bigint::BigInt;
+use crate::runtime::value::Value;
+use crate::runtime::vm::RuntimeError;
+use crate::source::Span;
+
+/// Evaluates operators whose operands are all literals, and removes the
+/// branches of `if` and `while` statements that can never be taken.
+///
+/// Runs after type checking, so any operator left in the tree works on
+/// builtin types, and is evaluated exactly as the VM would.
+#[derive(Default)]
+pub struct ConstantFolder {
+    /// How many `try` bodies we're in. Errors there are left for the VM,
+    /// since they might be recovered from.
+    try_depth: usize,
+
+    /// Whether we're in a function, which might only be called inside a
+    /// `try`. Errors there are left for the VM too, but warned about.
+    in_fn: bool,
+
+    /// How many branches or loop bodies we're in, which might never run.
+    /// Errors there are also only warned about.
+    conditional_depth: usize,
+    errors: Vec<Error>,
+    warnings: Vec<ErrorReport>,
+}
+
+impl ConstantFolder {
+    pub fn take_errors(&mut self) -> Vec<Error> {
+        std::mem::take(&mut self.errors)
+    }
+
+    pub fn take_warnings(&mut self) -> Vec<ErrorReport> {
+        std::mem::take(&mut self.warnings)
+    }
+
+    /// Visit code that only runs under some condition.
+    fn visit_conditional(
+        &mut self,
+        visit: impl FnOnce(&mut Self) -> JlyResult<()>,
+    ) -> JlyResult<()> {
+        self.conditional_depth += 1;
+        let result = visit(self);
+        self.conditional_depth -= 1;
+        result
+    }
+
+    fn visit_else(&mut self, else_: &mut Option<Box<Statement>>) -> JlyResult<()> {
+        if let Some(else_) = else_ {
+            self.visit_statement(else_)?;
+            Self::eliminate_dead_branches(else_);
+        }
+        Ok(())
+    }
+
+    fn fold_children(&mut self, kind: &mut ExprKind) -> JlyResult<()> {
+        match kind {
+            ExprKind::LogicalOr(a, b)
+            | ExprKind::LogicalAnd(a, b)
+            | ExprKind::Equal(a, b)
+            | ExprKind::NotEqual(a, b)
+            | ExprKind::LT(a, b)
+            | ExprKind::GT(a, b)
+            | ExprKind::LTEqual(a, b)
+            | ExprKind::GTEqual(a, b)
+            | ExprKind::Add(a, b)
+            | ExprKind::Sub(a, b)
+            | ExprKind::Mul(a, b)
+            | ExprKind::Div(a, b)
+            | ExprKind::Mod(a, b)
+            | ExprKind::Pow(a, b)
+            | ExprKind::BitAnd(a, b)
+            | ExprKind::BitOr(a, b)
+            | ExprKind::BitXor(a, b)
+            | ExprKind::Shl(a, b)
+            | ExprKind::Shr(a, b) => {
+                self.visit_expr(a)?;
+                self.visit_expr(b)?;
+            }
+
+            ExprKind::LogicalNot(expr)
+            | ExprKind::Neg(expr)
+            | ExprKind::BitNot(expr)
+            | ExprKind::Assignment(_, expr)
+            | ExprKind::Field(expr, _)
+            | ExprKind::Cast(expr, _)
+            | ExprKind::Try(expr)
+            | ExprKind::DebugPrint(expr)
+            | ExprKind::Panic(expr) => self.visit_expr(expr)?,
+
+            ExprKind::Call(callee, args) | ExprKind::MethodCall(callee, _, args) => {
+                self.visit_expr(callee)?;
+                for arg in args {
+                    self.visit_expr(arg)?;
+                }
+            }
+            ExprKind::Variant(_, args) => {
+                for arg in args {
+                    self.visit_expr(arg)?;
+                }
+            }
+            ExprKind::StructLiteral(literal) => {
+                for field in &mut literal.fields {
+                    self.visit_expr(&mut field.value)?;
+                }
+            }
+
+            ExprKind::Var(_) | ExprKind::Value(_) | ExprKind::DummyExpr => {}
+        }
+        Ok(())
+    }
+
+    /// Replace a statement whose condition is known with the code that
+    /// would run.
+    fn eliminate_dead_branches(statement: &mut Statement) {
+        let taken = match statement {
+            Statement::If(if_statement) => match literal(&if_statement.condition) {
+                Some(Value::Bool(true)) => Some(Statement::Block(if_statement.then.clone())),
+                Some(Value::Bool(false)) => Some(match if_statement.else_.take() {
+                    Some(else_) => *else_,
+                    None => empty_block(if_statement.span),
+                }),
+                _ => None,
+            },
+            Statement::While(while_loop) => match literal(&while_loop.condition) {
+                Some(Value::Bool(false)) => Some(empty_block(while_loop.span)),
+                _ => None,
+            },
+            _ => None,
+        };
+
+        if let Some(taken) = taken {
+            *statement = taken;
+        }
+    }
+}
+
+impl Visitor for ConstantFolder {
+    fn visit_module(&mut self, module: &mut Module) -> JlyResult<()> {
+        for statement in &mut module.statements {
+            self.visit_statement(statement)?;
+            Self::eliminate_dead_branches(statement);
+        }
+        Ok(())
+    }
+
+    fn visit_block(&mut self, block: &mut Block) -> JlyResult<()> {
+        for statement in &mut block.statements {
+            self.visit_statement(statement)?;
+            Self::eliminate_dead_branches(statement);
+        }
+        Ok(())
+    }
+
+    fn visit_expr(&mut self, expr: &mut Expr) -> JlyResult<()> {
+        self.fold_children(&mut expr.kind)?;
+
+        match evaluate(&expr.kind) {
+            Ok(Some(value)) => expr.kind = ExprKind::Value(value),
+            Ok(None) => {}
+            Err(_) if self.try_depth > 0 => {}
+            Err(error) if self.in_fn => self.warnings.push(
+                ErrorReport::warning("error in constant expression")
+                    .with_code("J0033")
+                    .with_labelled_source(format!("this always fails with: {}", error), expr.span)
+                    .with_note(
+                        "the function panics here whenever it gets this far, which is only \
+                         recovered from if it's called inside a `try`"
+                            .to_string(),
+                    ),
+            ),
+            Err(error) if self.conditional_depth > 0 => self.warnings.push(
+                ErrorReport::warning("error in constant expression")
+                    .with_code("J0033")
+                    .with_labelled_source(format!("this always fails with: {}", error), expr.span)
+                    .with_note(
+                        "the program panics here if this code is ever reached, which depends \
+                         on the values it runs with"
+                            .to_string(),
+                    ),
+            ),
+            Err(error) => self.errors.push(Error::ConstantEvaluation {
+                error,
+                span: expr.span,
+            }),
+        }
+
+        Ok(())
+    }
+
+    fn visit_var(&mut self, _var: &mut Var) -> JlyResult<()> {
+        Ok(())
+    }
+
+    fn visit_var_decl(&mut self, var_decl: &mut VarDecl) -> JlyResult<()> {
+        self.visit_expr(&mut var_decl.value)
+    }
+
+    fn visit_fn_decl(&mut self, fn_decl: &mut FnDecl) -> JlyResult<()> {
+        self.in_fn = true;
+        let result = self.visit_block(&mut fn_decl.body);
+        self.in_fn = false;
+        result
+    }
+
+    fn visit_struct_decl(&mut self, _struct_decl: &mut StructDecl) -> JlyResult<()> {
+        Ok(())
+    }
+
+    fn visit_enum_decl(&mut self, _enum_decl: &mut EnumDecl) -> JlyResult<()> {
+        Ok(())
+    }
+
+    fn visit_newtype_decl(&mut self, _newtype_decl: &mut NewtypeDecl) -> JlyResult<()> {
+        Ok(())
+    }
+
+    fn visit_trait_decl(&mut self, _trait_decl: &mut TraitDecl) -> JlyResult<()> {
+        Ok(())
+    }
+
+    fn visit_impl_decl(&mut self, impl_decl: &mut ImplDecl) -> JlyResult<()> {
+        for method in &mut impl_decl.methods {
+            self.visit_fn_decl(method)?;
+        }
+        Ok(())
+    }
+
+    // the branch that's never taken is removed by the enclosing block, and
+    // isn't looked into, since errors in it can't happen
+    fn visit_if_statement(&mut self, if_statement: &mut IfStatement) -> JlyResult<()> {
+        self.visit_expr(&mut if_statement.condition)?;
+        let IfStatement { then, else_, .. } = if_statement;
+        match literal(&if_statement.condition) {
+            Some(Value::Bool(true)) => self.visit_block(then),
+            Some(Value::Bool(false)) => self.visit_else(else_),
+            _ => self.visit_conditional(|folder| {
+                folder.visit_block(then)?;
+                folder.visit_else(else_)
+            }),
+        }
+    }
+
+    fn visit_while_loop(&mut self, while_loop: &mut WhileLoop) -> JlyResult<()> {
+        self.visit_expr(&mut while_loop.condition)?;
+        match literal(&while_loop.condition) {
+            Some(Value::Bool(false)) => Ok(()),
+            _ => self.visit_conditional(|folder| folder.visit_block(&mut while_loop.body)),
+        }
+    }
+
+    fn visit_match(&mut self, match_statement: &mut MatchStatement) -> JlyResult<()> {
+        self.visit_expr(&mut match_statement.scrutinee)?;
+        self.visit_conditional(|folder| {
+            for arm in &mut match_statement.arms {
+                folder.visit_block(&mut arm.body)?;
+            }
+            Ok(())
+        })
+    }
+
+    fn visit_try_statement(&mut self, try_statement: &mut TryStatement) -> JlyResult<()> {
+        self.try_depth += 1;
+        let result = self.visit_block(&mut try_statement.body);
+        self.try_depth -= 1;
+        result?;
+
+        self.visit_conditional(|folder| folder.visit_block(&mut try_statement.recover))
+    }
+
+    fn visit_return(&mut self, return_statement: &mut ReturnStatement) -> JlyResult<()> {
+        match &mut return_statement.value {
+            Some(value) => self.visit_expr(value),
+            None => Ok(()),
+        }
+    }
+}
+
+/// The value an expression always has, if it's made of literals alone.
+pub(crate) fn constant_value(expr: &Expr) -> Option<Value> {
+    let mut expr = expr.clone();
+
+    // an operation that always fails isn't a constant, but is left for the
+    // VM to report
+    let mut folder = ConstantFolder {
+        try_depth: 1,
+        ..ConstantFolder::default()
+    };
+    folder.visit_expr(&mut expr).ok()?;
+    literal(&expr).cloned()
+}
+
+fn literal(expr: &Expr) -> Option<&Value> {
+    match &expr.kind {
+        ExprKind::Value(value) => Some(value),
+        _ => None,
+    }
+}
+
+fn empty_block(span: Span) -> Statement {
+    Statement::Block(Block {
+        statements: vec![],
+        num_vars: Some(0),
+        span,
+    })
+}
+
+/// The value of an operator applied to literals, or `None` if it isn't
+/// applied to literals.
+fn evaluate(kind: &ExprKind) -> Result<Option<Value>, RuntimeError> {
+    use ExprKind::{
+        Add, BitAnd, BitNot, BitOr, BitXor, Div, Equal, GTEqual, LTEqual, LogicalAnd, LogicalNot,
+        LogicalOr, Mod, Mul, Neg, NotEqual, Pow, Shl, Shr, Sub, GT, LT,
+    };
+
+    let (a, b) = match kind {
+        LogicalOr(a, b)
+        | LogicalAnd(a, b)
+        | Equal(a, b)
+        | NotEqual(a, b)
+        | LT(a, b)
+        | GT(a, b)
+        | LTEqual(a, b)
+        | GTEqual(a, b)
+        | Add(a, b)
+        | Sub(a, b)
+        | Mul(a, b)
+        | Div(a, b)
+        | Mod(a, b)
+        | Pow(a, b)
+        | BitAnd(a, b)
+        | BitOr(a, b)
+        | BitXor(a, b)
+        | Shl(a, b)
+        | Shr(a, b) => match (literal(a), literal(b)) {
+            (Some(a), Some(b)) => (a, Some(b)),
+            _ => return Ok(None),
+        },
+        LogicalNot(a) | Neg(a) | BitNot(a) => match literal(a) {
+            Some(a) => (a, None),
+            None => return Ok(None),
+        },
+        _ => return Ok(None),
+    };
+
+    let value = match (kind, a, b) {
+        (LogicalOr(..), Value::Bool(a), Some(Value::Bool(b))) => Value::Bool(*a || *b),
+        (LogicalAnd(..), Value::Bool(a), Some(Value::Bool(b))) => Value::Bool(*a && *b),
+        (LogicalNot(_), Value::Bool(a), None) => Value::Bool(!a),
+
+        (Equal(..), a, Some(b)) => Value::Bool(a == b),
+        (NotEqual(..), a, Some(b)) => Value::Bool(a != b),
+        (LT(..), a, Some(b)) => return Ok(compare(a, b).map(|o| Value::Bool(o.is_lt()))),
+        (GT(..), a, Some(b)) => return Ok(compare(a, b).map(|o| Value::Bool(o.is_gt()))),
+        (LTEqual(..), a, Some(b)) => return Ok(compare(a, b).map(|o| Value::Bool(o.is_le()))),
+        (GTEqual(..), a, Some(b)) => return Ok(compare(a, b).map(|o| Value::Bool(o.is_ge()))),
+
+        (Add(..), a, Some(b)) => return integer_op(a, b, i64::checked_add, BigInt::checked_add),
+        (Sub(..), a, Some(b)) => return integer_op(a, b, i64::checked_sub, BigInt::checked_sub),
+        (Mul(..), a, Some(b)) => return integer_op(a, b, i64::checked_mul, BigInt::checked_mul),
+        (Div(..), a, Some(b)) => {
+            check_divisor(b)?;
+            return integer_op(a, b, i64::checked_div, BigInt::checked_div);
+        }
+        (Mod(..), a, Some(b)) => {
+            check_divisor(b)?;
+            return integer_op(a, b, i64::checked_rem, BigInt::checked_rem);
+        }
+        (Pow(..), Value::Integer(a), Some(Value::Integer(b))) => {
+            Value::Integer(pow_int(*a, *b, i64::checked_mul)?)
+        }
+        (Pow(..), a, Some(Value::Integer(b))) => match a.bigint() {
+            Ok(a) => Value::from(a.pow(*b)?),
+            Err(_) => return Ok(None),
+        },
+        (Neg(_), Value::Integer(a), None) => {
+            Value::Integer(a.checked_neg().ok_or(RuntimeError::IntegerOverflow)?)
+        }
+        (Neg(_), a, None) => match a.bigint() {
+            Ok(a) => Value::from(a.neg()),
+            Err(_) => return Ok(None),
+        },
+
+        (BitAnd(..), Value::Integer(a), Some(Value::Integer(b))) => Value::Integer(a & b),
+        (BitOr(..), Value::Integer(a), Some(Value::Integer(b))) => Value::Integer(a | b),
+        (BitXor(..), Value::Integer(a), Some(Value::Integer(b))) => Value::Integer(a ^ b),
+        (Shl(..), Value::Integer(a), Some(Value::Integer(b))) => {
+            Value::Integer(a << shift_amount(*b)?)
+        }
+        (Shr(..), Value::Integer(a), Some(Value::Integer(b))) => {
+            Value::Integer(a >> shift_amount(*b)?)
+        }
+        (BitNot(_), Value::Integer(a), None) => Value::Integer(!a),
+
+        _ => return Ok(None),
+    };
+
+    Ok(Some(value))
+}
+
+fn integer_op(
+    a: &Value,
+    b: &Value,
+    int: fn(i64, i64) -> Option<i64>,
+    big: fn(&BigInt, &BigInt) -> Option<BigInt>,
+) -> Result<Option<Value>, RuntimeError> {
+    let value = match (a, b) {
+        (Value::Integer(a), Value::Integer(b)) => {
+            Value::Integer(int(*a, *b).ok_or(RuntimeError::IntegerOverflow)?)
+        }
+        _ => match (a.bigint(), b.bigint()) {
+            (Ok(a), Ok(b)) => Value::from(big(a, b).ok_or(RuntimeError::IntegerOverflow)?),
+            _ => return Ok(None),
+        },
+    };
+    Ok(Some(value))
+}
+
+fn compare(a: &Value, b: &Value) -> Option<Ordering> {
+    match (a, b) {
+        (Value::Integer(a), Value::Integer(b)) => Some(a.cmp(b)),
+        _ => Some(a.bigint().ok()?.cmp(b.bigint().ok()?)),
+    }
+}
+
+fn check_divisor(divisor: &Value) -> Result<(), RuntimeError> {
+    let is_zero = match divisor {
+        Value::Integer(n) => *n == 0,
+        divisor => divisor.bigint().is_ok_and(BigInt::is_zero),
+    };
+    match is_zero {
+        true => Err(RuntimeError::DivisionByZero),
+        false => Ok(()),
+    }
+}
@@ -0,0 +1,743 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+use internment::Intern;
+
+use crate::compiler::ast::*;
+use crate::compiler::diagnostic::{ErrorReport, JlyResult, Severity};
+use crate::compiler::passes::fold::constant_value;
+use crate::compiler::passes::resolve::{Bindings, DefinitionKind};
+use crate::compiler::passes::typecheck::statement_returns;
+use crate::compiler::passes::visit::Visitor;
+use crate::runtime::value::Value;
+use crate::source::Span;
+
+/// A check for code that's allowed, but probably isn't what was meant.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Lint {
+    UnusedVariable,
+    UnusedAssignment,
+    ShadowedBinding,
+    UnreachableCode,
+    ConstantCondition,
+}
+
+impl Lint {
+    pub const ALL: [Lint; 5] = [
+        Lint::UnusedVariable,
+        Lint::UnusedAssignment,
+        Lint::ShadowedBinding,
+        Lint::UnreachableCode,
+        Lint::ConstantCondition,
+    ];
+
+    /// The name it's allowed or denied by.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::UnusedVariable => "unused_variable",
+            Self::UnusedAssignment => "unused_assignment",
+            Self::ShadowedBinding => "shadowed_binding",
+            Self::UnreachableCode => "unreachable_code",
+            Self::ConstantCondition => "constant_condition",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|lint| lint.name() == name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LintLevel {
+    Allow,
+    Warn,
+    Deny,
+}
+
+impl LintLevel {
+    pub fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "allow" => Self::Allow,
+            "warn" => Self::Warn,
+            "deny" => Self::Deny,
+            _ => return None,
+        })
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Allow => "allow",
+            Self::Warn => "warn",
+            Self::Deny => "deny",
+        }
+    }
+}
+
+/// The level of each lint, as set on the command line. Attributes in the
+/// source take precedence over these.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LintLevels {
+    levels: [Option<LintLevel>; Lint::ALL.len()],
+}
+
+impl LintLevels {
+    pub fn set(&mut self, lint: Lint, level: LintLevel) {
+        self.levels[lint as usize] = Some(level);
+    }
+
+    /// The level set for a lint, if it isn't left at the default.
+    fn get(&self, lint: Lint) -> Option<LintLevel> {
+        self.levels[lint as usize]
+    }
+}
+
+/// Check a module that's been resolved and type checked for each lint,
+/// giving the reports for those that aren't allowed.
+///
+/// In an interactive session the variables of the top level can still be
+/// used by later inputs, so they're never reported as unused.
+pub fn lint(
+    module: &mut Module,
+    bindings: &Bindings,
+    levels: LintLevels,
+    session: bool,
+) -> Vec<ErrorReport> {
+    let mut linter = Linter::new(bindings, session);
+    if linter.visit_module(module).is_err() {
+        return vec![];
+    }
+
+    let targets = attribute_targets(&module.attributes, &linter.statements);
+    let mut reports = vec![];
+
+    for attribute in &module.attributes {
+        if LintLevel::from_name(&attribute.name).is_none() {
+            continue;
+        }
+        for (name, span) in &attribute.args {
+            if Lint::from_name(name).is_none() {
+                let lints: Vec<_> = Lint::ALL.iter().map(|lint| lint.name()).collect();
+                reports.push(
+                    ErrorReport::warning("unknown lint")
+                        .with_labelled_source(format!("there's no lint named `{}`", name), *span)
+                        .with_note(format!("the lints are {}", lints.join(", "))),
+                );
+            }
+        }
+    }
+
+    for warning in linter.warnings {
+        // the attributes on the innermost statement win, then the ones on
+        // the whole module, then the command line
+        let mut level = levels.get(warning.lint).map(|level| (level, None));
+        let mut innermost = None;
+        for (attribute, target) in module.attributes.iter().zip(&targets) {
+            let applies = match target {
+                _ if attribute.inner => true,
+                Some(target) => {
+                    target.start <= warning.span.start && warning.span.end <= target.end
+                }
+                None => false,
+            };
+            let names_lint = attribute
+                .args
+                .iter()
+                .any(|(name, _)| **name == warning.lint.name());
+            let attr_level = match LintLevel::from_name(&attribute.name) {
+                Some(attr_level) if applies && names_lint => attr_level,
+                _ => continue,
+            };
+
+            let size = match (attribute.inner, target) {
+                (false, Some(target)) => target.len(),
+                _ => usize::MAX,
+            };
+            if innermost.is_none_or(|innermost| size <= innermost) {
+                innermost = Some(size);
+                level = Some((attr_level, Some(attribute)));
+            }
+        }
+
+        let report = warning.report.with_code(warning.lint.name());
+        let report = match level {
+            None => report.with_note(format!(
+                "`#[warn({})]` is on by default",
+                warning.lint.name()
+            )),
+            Some((LintLevel::Allow, _)) => continue,
+            Some((level, None)) => report.with_note(format!(
+                "`--{} {}` was given",
+                level.name(),
+                warning.lint.name()
+            )),
+            Some((level, Some(attribute))) => report.with_labelled_source(
+                format!("the level is set to `{}` here", level.name()),
+                attribute.span,
+            ),
+        };
+
+        reports.push(match level {
+            Some((LintLevel::Deny, _)) => report.with_severity(Severity::Error),
+            _ => report,
+        });
+    }
+
+    reports
+}
+
+/// The statement each outer attribute applies to, which is the first one
+/// after it.
+fn attribute_targets(attributes: &[Attribute], statements: &[Span]) -> Vec<Option<Span>> {
+    attributes
+        .iter()
+        .map(|attribute| {
+            statements
+                .iter()
+                .filter(|span| span.start >= attribute.span.end)
+                .min_by_key(|span| (span.start, usize::MAX - span.len()))
+                .copied()
+        })
+        .collect()
+}
+
+struct Warning {
+    lint: Lint,
+    span: Span,
+    report: ErrorReport,
+}
+
+struct Local {
+    ident: Intern<String>,
+    span: Span,
+    read: bool,
+
+    /// Whether it's declared at the top level, outside of any block.
+    top_level: bool,
+}
+
+/// An assignment to a local.
+struct Write {
+    definition: usize,
+    span: Span,
+    read: bool,
+}
+
+/// The assignments that may have been the last to each local, at some
+/// point in the code.
+#[derive(Clone, Default)]
+struct Flow {
+    reaching: HashMap<usize, BTreeSet<usize>>,
+
+    /// Whether the point can't be reached, having returned before it.
+    dead: bool,
+}
+
+impl Flow {
+    /// The flow at a point that can be reached from either of two others.
+    fn merge(self, other: Flow) -> Flow {
+        if self.dead {
+            return other;
+        }
+        if other.dead {
+            return self;
+        }
+
+        let mut merged = self;
+        for (definition, writes) in other.reaching {
+            merged
+                .reaching
+                .entry(definition)
+                .or_default()
+                .extend(writes);
+        }
+        merged
+    }
+}
+
+struct Linter {
+    definitions: HashMap<usize, usize>,
+    references: HashMap<usize, usize>,
+    session: bool,
+
+    /// The locals declared so far, by the index of their definition.
+    locals: BTreeMap<usize, Local>,
+
+    /// The definitions of the locals in scope, innermost last.
+    visible: Vec<usize>,
+    depth: usize,
+    in_fn: bool,
+
+    writes: Vec<Write>,
+    write_ids: HashMap<usize, usize>,
+    flow: Flow,
+
+    /// The assignments made in each `try` body we're in, any of which its
+    /// `recover` block may see.
+    try_writes: Vec<Vec<(usize, usize)>>,
+
+    /// Whether the code visited so far can leave the function it's in.
+    exits: bool,
+
+    /// The span of every statement, for finding what attributes apply to.
+    statements: Vec<Span>,
+
+    warnings: Vec<Warning>,
+    warned: HashSet<(Lint, usize, usize)>,
+}
+
+impl Linter {
+    fn new(bindings: &Bindings, session: bool) -> Self {
+        Self {
+            // definitions and uses are found by where their names start
+            definitions: bindings
+                .definitions
+                .iter()
+                .enumerate()
+                .filter(|(_, definition)| definition.kind == DefinitionKind::Variable)
+                .map(|(i, definition)| (definition.span.start, i))
+                .collect(),
+            references: bindings
+                .references
+                .iter()
+                .map(|reference| (reference.span.start, reference.definition))
+                .collect(),
+            session,
+            locals: BTreeMap::new(),
+            visible: vec![],
+            depth: 0,
+            in_fn: false,
+            writes: vec![],
+            write_ids: HashMap::new(),
+            flow: Flow::default(),
+            try_writes: vec![],
+            exits: false,
+            statements: vec![],
+            warnings: vec![],
+            warned: HashSet::new(),
+        }
+    }
+
+    /// Record a warning. Loop bodies are visited twice, so a warning that
+    /// has already been given is skipped.
+    fn warn(&mut self, lint: Lint, span: Span, report: ErrorReport) {
+        if self.warned.insert((lint, span.start, span.end)) {
+            self.warnings.push(Warning { lint, span, report });
+        }
+    }
+
+    fn declare(&mut self, ident: Intern<String>, span: Span) {
+        let definition = match self.definitions.get(&span.start) {
+            Some(definition) => *definition,
+            None => return,
+        };
+
+        let shadowed = self
+            .visible
+            .iter()
+            .rev()
+            .map(|definition| &self.locals[definition])
+            .find(|local| local.ident == ident)
+            .map(|local| local.span);
+        if let Some(shadowed) = shadowed {
+            self.warn(
+                Lint::ShadowedBinding,
+                span,
+                ErrorReport::warning("shadowed binding")
+                    .with_labelled_source(format!("this shadows an earlier `{}`", ident), span)
+                    .with_labelled_source("which is declared here".to_string(), shadowed),
+            );
+        }
+
+        let top_level = !self.in_fn && self.depth == 0;
+        self.locals.entry(definition).or_insert(Local {
+            ident,
+            span,
+            read: false,
+            top_level,
+        });
+        self.visible.push(definition);
+        self.flow.reaching.remove(&definition);
+    }
+
+    fn read(&mut self, var: &Var) {
+        let definition = match self.references.get(&var.span.start) {
+            Some(definition) => *definition,
+            None => return,
+        };
+        if let Some(local) = self.locals.get_mut(&definition) {
+            local.read = true;
+        }
+        for &write in self.flow.reaching.get(&definition).into_iter().flatten() {
+            self.writes[write].read = true;
+        }
+    }
+
+    fn write(&mut self, var: &Var, span: Span) {
+        let definition = match self.references.get(&var.span.start) {
+            Some(definition) if self.locals.contains_key(definition) => *definition,
+            _ => return,
+        };
+
+        let writes = &mut self.writes;
+        let write = *self.write_ids.entry(span.start).or_insert_with(|| {
+            writes.push(Write {
+                definition,
+                span,
+                read: false,
+            });
+            writes.len() - 1
+        });
+
+        self.flow
+            .reaching
+            .insert(definition, BTreeSet::from([write]));
+        for writes in &mut self.try_writes {
+            writes.push((definition, write));
+        }
+    }
+
+    /// Visit the statements of a block or module, warning about any that
+    /// can't be reached.
+    fn visit_statements(&mut self, statements: &mut [Statement]) -> JlyResult<()> {
+        let returns = statements.iter().position(statement_returns);
+        if let Some(returns) = returns.filter(|&i| i + 1 < statements.len()) {
+            let span = statements[returns + 1]
+                .span()
+                .join(statements[statements.len() - 1].span());
+            self.warn(
+                Lint::UnreachableCode,
+                span,
+                ErrorReport::warning("unreachable code")
+                    .with_labelled_source("this code is never run".to_string(), span)
+                    .with_labelled_source(
+                        "because this always returns first".to_string(),
+                        statements[returns].span(),
+                    ),
+            );
+        }
+
+        for statement in statements {
+            self.statements.push(statement.span());
+            self.visit_statement(statement)?;
+        }
+        Ok(())
+    }
+
+    /// Give the warnings about the locals that were never read, and the
+    /// assignments that never were.
+    fn finish(&mut self) {
+        let unused = |local: &Local, session: bool| {
+            local.ident.starts_with('_') || *local.ident == "self" || (session && local.top_level)
+        };
+
+        let mut warnings = vec![];
+        for local in self.locals.values() {
+            if !local.read && !unused(local, self.session) {
+                warnings.push((
+                    Lint::UnusedVariable,
+                    local.span,
+                    ErrorReport::warning("unused variable")
+                        .with_labelled_source(
+                            format!("`{}` is never used", local.ident),
+                            local.span,
+                        )
+                        .with_hint(format!(
+                            "if that's on purpose, name it `_{}` instead",
+                            local.ident
+                        )),
+                ));
+            }
+        }
+
+        for write in &self.writes {
+            let local = &self.locals[&write.definition];
+            if !write.read && local.read && !unused(local, self.session) {
+                warnings.push((
+                    Lint::UnusedAssignment,
+                    write.span,
+                    ErrorReport::warning("unused assignment")
+                        .with_labelled_source(
+                            format!("the value assigned to `{}` is never read", local.ident),
+                            write.span,
+                        )
+                        .with_note(
+                            "it's always assigned again, or goes out of scope, first".to_string(),
+                        ),
+                ));
+            }
+        }
+
+        for (lint, span, report) in warnings {
+            self.warn(lint, span, report);
+        }
+    }
+}
+
+impl Visitor for Linter {
+    fn visit_module(&mut self, module: &mut Module) -> JlyResult<()> {
+        self.visit_statements(&mut module.statements)?;
+        self.finish();
+        Ok(())
+    }
+
+    fn visit_block(&mut self, block: &mut Block) -> JlyResult<()> {
+        let num_visible = self.visible.len();
+        self.depth += 1;
+
+        self.visit_statements(&mut block.statements)?;
+
+        self.depth -= 1;
+        self.visible.truncate(num_visible);
+        Ok(())
+    }
+
+    fn visit_expr(&mut self, expr: &mut Expr) -> JlyResult<()> {
+        match &mut expr.kind {
+            ExprKind::Var(var) => self.visit_var(var)?,
+            ExprKind::Value(_) | ExprKind::DummyExpr => {}
+
+            ExprKind::LogicalOr(lhs, rhs)
+            | ExprKind::LogicalAnd(lhs, rhs)
+            | ExprKind::Equal(lhs, rhs)
+            | ExprKind::NotEqual(lhs, rhs)
+            | ExprKind::LT(lhs, rhs)
+            | ExprKind::GT(lhs, rhs)
+            | ExprKind::LTEqual(lhs, rhs)
+            | ExprKind::GTEqual(lhs, rhs)
+            | ExprKind::Add(lhs, rhs)
+            | ExprKind::Sub(lhs, rhs)
+            | ExprKind::Mul(lhs, rhs)
+            | ExprKind::Div(lhs, rhs)
+            | ExprKind::Mod(lhs, rhs)
+            | ExprKind::Pow(lhs, rhs)
+            | ExprKind::BitAnd(lhs, rhs)
+            | ExprKind::BitOr(lhs, rhs)
+            | ExprKind::BitXor(lhs, rhs)
+            | ExprKind::Shl(lhs, rhs)
+            | ExprKind::Shr(lhs, rhs) => {
+                self.visit_expr(lhs)?;
+                self.visit_expr(rhs)?;
+            }
+
+            ExprKind::Assignment(lhs, rhs) => {
+                self.visit_expr(rhs)?;
+                self.write(lhs, expr.span);
+            }
+
+            ExprKind::Call(callee, args) | ExprKind::MethodCall(callee, _, args) => {
+                self.visit_expr(callee)?;
+                for arg in args {
+                    self.visit_expr(arg)?;
+                }
+            }
+
+            ExprKind::Variant(_, args) => {
+                for arg in args {
+                    self.visit_expr(arg)?;
+                }
+            }
+
+            ExprKind::StructLiteral(literal) => {
+                for field in &mut literal.fields {
+                    self.visit_expr(&mut field.value)?;
+                }
+            }
+
+            ExprKind::Try(expr) => {
+                self.visit_expr(expr)?;
+                self.exits = true;
+            }
+
+            ExprKind::Neg(expr)
+            | ExprKind::BitNot(expr)
+            | ExprKind::LogicalNot(expr)
+            | ExprKind::Field(expr, _)
+            | ExprKind::Cast(expr, _)
+            | ExprKind::DebugPrint(expr) => self.visit_expr(expr)?,
+
+            ExprKind::Panic(expr) => {
+                self.visit_expr(expr)?;
+                self.exits = true;
+            }
+        }
+        Ok(())
+    }
+
+    fn visit_var(&mut self, var: &mut Var) -> JlyResult<()> {
+        self.read(var);
+        Ok(())
+    }
+
+    fn visit_var_decl(&mut self, var_decl: &mut VarDecl) -> JlyResult<()> {
+        self.visit_expr(&mut var_decl.value)?;
+        self.declare(var_decl.ident, var_decl.ident_span);
+        Ok(())
+    }
+
+    fn visit_fn_decl(&mut self, fn_decl: &mut FnDecl) -> JlyResult<()> {
+        // functions can't see the locals of the top level
+        let outer_visible = std::mem::take(&mut self.visible);
+        let outer_flow = std::mem::take(&mut self.flow);
+        self.in_fn = true;
+
+        for param in &fn_decl.sig.params {
+            self.declare(param.ident, param.span);
+        }
+        self.visit_block(&mut fn_decl.body)?;
+
+        self.in_fn = false;
+        self.visible = outer_visible;
+        self.flow = outer_flow;
+        self.exits = false;
+        Ok(())
+    }
+
+    fn visit_struct_decl(&mut self, _struct_decl: &mut StructDecl) -> JlyResult<()> {
+        Ok(())
+    }
+
+    fn visit_enum_decl(&mut self, _enum_decl: &mut EnumDecl) -> JlyResult<()> {
+        Ok(())
+    }
+
+    fn visit_newtype_decl(&mut self, _newtype_decl: &mut NewtypeDecl) -> JlyResult<()> {
+        Ok(())
+    }
+
+    fn visit_trait_decl(&mut self, _trait_decl: &mut TraitDecl) -> JlyResult<()> {
+        Ok(())
+    }
+
+    fn visit_impl_decl(&mut self, impl_decl: &mut ImplDecl) -> JlyResult<()> {
+        for method in &mut impl_decl.methods {
+            self.statements.push(method.sig.span.join(method.body.span));
+            self.visit_fn_decl(method)?;
+        }
+        Ok(())
+    }
+
+    fn visit_if_statement(&mut self, if_statement: &mut IfStatement) -> JlyResult<()> {
+        self.visit_expr(&mut if_statement.condition)?;
+
+        let before = self.flow.clone();
+        self.visit_block(&mut if_statement.then)?;
+        let then = std::mem::replace(&mut self.flow, before);
+
+        if let Some(else_) = &mut if_statement.else_ {
+            self.statements.push(else_.span());
+            self.visit_statement(else_)?;
+        }
+
+        let else_ = std::mem::take(&mut self.flow);
+        self.flow = then.merge(else_);
+        Ok(())
+    }
+
+    fn visit_while_loop(&mut self, while_loop: &mut WhileLoop) -> JlyResult<()> {
+        let outer_exits = std::mem::replace(&mut self.exits, false);
+
+        // the body is visited twice, so that what the end of one iteration
+        // assigns is seen by the start of the next
+        self.visit_expr(&mut while_loop.condition)?;
+        let mut entry = self.flow.clone();
+        for _ in 0..2 {
+            self.visit_block(&mut while_loop.body)?;
+            entry = entry.merge(std::mem::take(&mut self.flow));
+            self.flow = entry.clone();
+            self.visit_expr(&mut while_loop.condition)?;
+        }
+
+        let span = while_loop.condition.span;
+        match constant_value(&while_loop.condition) {
+            Some(Value::Bool(false)) => self.warn(
+                Lint::ConstantCondition,
+                span,
+                ErrorReport::warning("constant condition").with_labelled_source(
+                    "this is always false, so the loop never runs".to_string(),
+                    span,
+                ),
+            ),
+            Some(Value::Bool(true)) if !self.exits => self.warn(
+                Lint::ConstantCondition,
+                span,
+                ErrorReport::warning("constant condition")
+                    .with_labelled_source(
+                        "this is always true, so only a runtime error can end the loop".to_string(),
+                        span,
+                    )
+                    .with_note("nothing in the loop returns or panics".to_string()),
+            ),
+            _ => {}
+        }
+
+        self.exits |= outer_exits;
+        Ok(())
+    }
+
+    fn visit_match(&mut self, match_statement: &mut MatchStatement) -> JlyResult<()> {
+        self.visit_expr(&mut match_statement.scrutinee)?;
+
+        let before = self.flow.clone();
+        let mut after = Flow {
+            dead: !match_statement.arms.is_empty(),
+            ..before.clone()
+        };
+        for arm in &mut match_statement.arms {
+            self.flow = before.clone();
+
+            let num_visible = self.visible.len();
+            if let PatternKind::Variant {
+                binding: Some((binding, span)),
+                ..
+            } = arm.pattern.kind
+            {
+                self.declare(binding, span);
+            }
+            self.visit_block(&mut arm.body)?;
+            self.visible.truncate(num_visible);
+
+            after = after.merge(std::mem::take(&mut self.flow));
+        }
+
+        self.flow = after;
+        Ok(())
+    }
+
+    fn visit_try_statement(&mut self, try_statement: &mut TryStatement) -> JlyResult<()> {
+        let before = self.flow.clone();
+        self.try_writes.push(vec![]);
+        self.visit_block(&mut try_statement.body)?;
+        let writes = self.try_writes.pop().unwrap_or_default();
+
+        // the body may panic anywhere, so the handler may see any of what
+        // it assigned
+        let body = std::mem::take(&mut self.flow);
+        let mut recover = before.merge(body.clone());
+        recover.dead = false;
+        for (definition, write) in writes {
+            recover
+                .reaching
+                .entry(definition)
+                .or_default()
+                .insert(write);
+        }
+        self.flow = recover;
+
+        let num_visible = self.visible.len();
+        self.declare(try_statement.binding, try_statement.binding_span());
+        self.visit_block(&mut try_statement.recover)?;
+        self.visible.truncate(num_visible);
+
+        let recover = std::mem::take(&mut self.flow);
+        self.flow = body.merge(recover);
+        Ok(())
+    }
+
+    fn visit_return(&mut self, return_statement: &mut ReturnStatement) -> JlyResult<()> {
+        if let Some(value) = &mut return_statement.value {
+            self.visit_expr(value)?;
+        }
+        self.flow.dead = true;
+        self.exits = true;
+        Ok(())
+    }
+}
@@ -1,20 +1,166 @@
 use crate::compiler::ast::Module;
-use crate::compiler::diagnostic::JlyResult;
+use crate::compiler::diagnostic::{Error, ErrorReporter, JlyResult};
 use crate::compiler::passes::codegen::CodeGenerator;
+use crate::compiler::passes::fold::ConstantFolder;
+use crate::compiler::passes::lint::{lint, LintLevels};
 use crate::compiler::passes::resolve::Resolver;
+use crate::compiler::passes::typecheck::TypeChecker;
 use crate::compiler::passes::visit::Visitor;
 use crate::runtime::chunk::Chunk;
 
 pub mod codegen;
+pub mod fold;
+pub mod lint;
+pub mod peephole;
 pub mod resolve;
+pub mod typecheck;
 pub mod visit;
 
-pub fn run_passes(module: &mut Module) -> JlyResult<Chunk> {
+/// Which optional passes to run.
+#[derive(Debug, Clone, Copy)]
+pub struct PassOptions {
+    /// Evaluate constant expressions and remove dead branches.
+    pub fold_constants: bool,
+
+    /// Fuse common instruction sequences in the generated bytecode.
+    pub peephole: bool,
+
+    /// The level of each lint, unless the source sets it.
+    pub lints: LintLevels,
+}
+
+impl Default for PassOptions {
+    fn default() -> Self {
+        Self {
+            fold_constants: true,
+            peephole: true,
+            lints: LintLevels::default(),
+        }
+    }
+}
+
+/// Compile a parsed module, reporting every error the semantic passes find.
+/// Code is only generated if there are none.
+#[allow(clippy::result_unit_err)]
+pub fn run_passes(
+    module: &mut Module,
+    options: PassOptions,
+    diagnostics: &mut ErrorReporter,
+) -> Result<Chunk, ()> {
     let mut resolver = Resolver::new();
-    resolver.visit_module(module)?;
+    let mut typechecker = TypeChecker::new();
+    let codegen = CodeGenerator::default();
+    run_passes_with(
+        &mut resolver,
+        &mut typechecker,
+        codegen,
+        module,
+        options,
+        diagnostics,
+        false,
+    )
+}
 
-    let mut codegen = CodeGenerator::default();
-    codegen.visit_module(module)?;
+/// The names and types declared by the inputs of an interactive session
+/// so far, so each input can use what the ones before it declared.
+#[derive(Clone)]
+pub struct Incremental {
+    resolver: Resolver,
+    typechecker: TypeChecker,
+}
 
+impl Default for Incremental {
+    fn default() -> Self {
+        Self {
+            resolver: Resolver::new(),
+            typechecker: TypeChecker::new(),
+        }
+    }
+}
+
+impl Incremental {
+    /// Compile one input, which returns the value of its last statement if
+    /// that's an expression.
+    ///
+    /// The declarations are kept even if the input fails to compile, so
+    /// callers should keep a copy to roll back to.
+    #[allow(clippy::result_unit_err)]
+    pub fn run_passes(
+        &mut self,
+        module: &mut Module,
+        options: PassOptions,
+        diagnostics: &mut ErrorReporter,
+    ) -> Result<Chunk, ()> {
+        let codegen = CodeGenerator::returning_last_expr();
+        run_passes_with(
+            &mut self.resolver,
+            &mut self.typechecker,
+            codegen,
+            module,
+            options,
+            diagnostics,
+            true,
+        )
+    }
+}
+
+fn run_passes_with(
+    resolver: &mut Resolver,
+    typechecker: &mut TypeChecker,
+    codegen: CodeGenerator,
+    module: &mut Module,
+    options: PassOptions,
+    diagnostics: &mut ErrorReporter,
+    session: bool,
+) -> Result<Chunk, ()> {
+    // both passes recover from their errors, and the type checker treats
+    // whatever the resolver couldn't resolve as having any type
+    let result = resolver.visit_module(module);
+    report_errors(diagnostics, result, resolver.take_errors());
+    let result = typechecker.visit_module(module);
+    report_errors(diagnostics, result, typechecker.take_errors());
+    diagnostics.assert_ok()?;
+
+    // lints that are denied are errors too
+    for report in lint(module, resolver.bindings(), options.lints, session) {
+        diagnostics.report(report);
+    }
+    diagnostics.assert_ok()?;
+
+    let mut chunk = generate(codegen, module, options, diagnostics)?;
+
+    if options.peephole {
+        peephole::optimise(&mut chunk);
+    }
+
+    Ok(chunk)
+}
+
+fn generate(
+    mut codegen: CodeGenerator,
+    module: &mut Module,
+    options: PassOptions,
+    diagnostics: &mut ErrorReporter,
+) -> Result<Chunk, ()> {
+    if options.fold_constants {
+        let mut folder = ConstantFolder::default();
+        let result = folder.visit_module(module);
+        for warning in folder.take_warnings() {
+            diagnostics.report(warning);
+        }
+        report_errors(diagnostics, result, folder.take_errors());
+        diagnostics.assert_ok()?;
+    }
+
+    if let Err(err) = codegen.visit_module(module) {
+        diagnostics.report(err.report());
+        return Err(());
+    }
     Ok(codegen.chunk())
 }
+
+fn report_errors(diagnostics: &mut ErrorReporter, result: JlyResult<()>, errors: Vec<Error>) {
+    for err in errors.into_iter().chain(result.err()) {
+        diagnostics.report(err.report());
+    }
+}
@@ -0,0 +1,249 @@
+use std::rc::Rc;
+
+use crate::runtime::chunk::{Chunk, Instr, Opcode};
+use crate::runtime::value::{Object, Value};
+use crate::source::Span;
+
+/// A decoded instruction.
+struct Op {
+    instr: Instr,
+    operands: Vec<u8>,
+
+    /// For jumps, the index of the instruction jumped to, which may be
+    /// one past the last.
+    target: usize,
+
+    span: Option<Span>,
+    removed: bool,
+}
+
+/// Rewrite common instruction sequences into cheaper ones, in `chunk` and
+/// the chunks of the functions it defines.
+pub fn optimise(chunk: &mut Chunk) {
+    for constant in &mut chunk.constants {
+        if let Value::Object(obj) = constant {
+            if let Some(Object::JellyFunction(function)) = Rc::get_mut(obj) {
+                if let Some(chunk) = Rc::get_mut(&mut function.chunk) {
+                    optimise(chunk);
+                }
+            }
+        }
+    }
+
+    let mut ops = decode(chunk);
+
+    // a rewrite can make way for another, so keep going until none apply
+    loop {
+        let fused = fuse(&mut ops);
+        let threaded = thread_jumps(&mut ops);
+        if !(fused || threaded) {
+            break;
+        }
+        ops = compact(ops);
+    }
+
+    encode(chunk, ops);
+}
+
+fn decode(chunk: &Chunk) -> Vec<Op> {
+    let mut ops = vec![];
+    let mut index_of = vec![0; chunk.code.len() + 1];
+
+    let mut offset = 0;
+    while offset < chunk.code.len() {
+        let instr = chunk.code[offset].instr();
+        let len = instr.operand_len();
+
+        index_of[offset] = ops.len();
+        ops.push(Op {
+            instr,
+            operands: chunk.code[offset + 1..offset + 1 + len]
+                .iter()
+                .map(Opcode::byte)
+                .collect(),
+            target: 0,
+            span: chunk.span_at(offset),
+            removed: false,
+        });
+
+        offset += 1 + len;
+    }
+    index_of[chunk.code.len()] = ops.len();
+
+    for op in &mut ops {
+        if op.instr.is_jump() {
+            let offset = u32::from_be_bytes(op.operands[..].try_into().unwrap());
+            op.target = index_of[offset as usize];
+        }
+    }
+
+    ops
+}
+
+fn encode(chunk: &mut Chunk, ops: Vec<Op>) {
+    let mut offsets = Vec::with_capacity(ops.len() + 1);
+    let mut offset = 0;
+    for op in &ops {
+        offsets.push(offset as u32);
+        offset += 1 + op.instr.operand_len();
+    }
+    offsets.push(offset as u32);
+
+    chunk.code.clear();
+    chunk.spans.clear();
+
+    for op in ops {
+        if let Some(span) = op.span {
+            if chunk.spans.last().map(|(_, last)| *last) != Some(span) {
+                chunk.spans.push((chunk.code.len(), span));
+            }
+        }
+
+        chunk.code.push(op.instr.into());
+
+        let operands = match op.instr.is_jump() {
+            true => offsets[op.target].to_be_bytes().to_vec(),
+            false => op.operands,
+        };
+        chunk.code.extend(operands.into_iter().map(Opcode::from));
+    }
+}
+
+/// Drop removed instructions. Jumps to them land on the instruction
+/// after instead.
+fn compact(ops: Vec<Op>) -> Vec<Op> {
+    let mut new_index = Vec::with_capacity(ops.len() + 1);
+    let mut kept = 0;
+    for op in &ops {
+        new_index.push(kept);
+        if !op.removed {
+            kept += 1;
+        }
+    }
+    new_index.push(kept);
+
+    ops.into_iter()
+        .filter(|op| !op.removed)
+        .map(|mut op| {
+            op.target = new_index[op.target];
+            op
+        })
+        .collect()
+}
+
+/// Whether something jumps to each instruction. A sequence can only be
+/// fused if nothing jumps into the middle of it.
+fn jump_targets(ops: &[Op]) -> Vec<bool> {
+    let mut targets = vec![false; ops.len() + 1];
+    for op in ops.iter().filter(|op| op.instr.is_jump()) {
+        targets[op.target] = true;
+    }
+    targets
+}
+
+fn fuse(ops: &mut [Op]) -> bool {
+    let targets = jump_targets(ops);
+    let mut changed = false;
+
+    let mut i = 0;
+    while i < ops.len() {
+        let len = fuse_at(ops, i, &targets);
+        changed |= len > 0;
+        i += len.max(1);
+    }
+
+    changed
+}
+
+/// Try to fuse the sequence starting at `i`, returning its length.
+fn fuse_at(ops: &mut [Op], i: usize, targets: &[bool]) -> usize {
+    let window = &ops[i..ops.len().min(i + 4)];
+    let instrs: Vec<Instr> = window.iter().map(|op| op.instr).collect();
+    let free = |len: usize| len <= window.len() && !targets[i + 1..i + len].contains(&true);
+
+    let len = match instrs[..] {
+        // `!=`
+        [Instr::Equal, Instr::NotBool, ..] if free(2) => {
+            ops[i].instr = Instr::NotEqual;
+            2
+        }
+
+        // the result of a `print` statement
+        [Instr::LoadUnit, Instr::Pop, ..] if free(2) => {
+            ops[i].removed = true;
+            2
+        }
+
+        // an assignment statement
+        [Instr::StoreLocal, Instr::Pop, ..] if free(2) => {
+            ops[i].instr = Instr::StoreLocalPop;
+            2
+        }
+
+        // `x = x + 1;`
+        [Instr::LoadLocal, Instr::LoadSmallInt, op @ (Instr::AddInt | Instr::SubInt), Instr::StoreLocalPop]
+            if free(4) && ops[i].operands == ops[i + 3].operands =>
+        {
+            let n = ops[i + 1].operands[0] as i8;
+            let delta = match op {
+                Instr::SubInt => n.checked_neg(),
+                _ => Some(n),
+            };
+            match delta {
+                Some(delta) => {
+                    ops[i].instr = Instr::IncLocal;
+                    ops[i].operands.push(delta as u8);
+                    ops[i].span = ops[i + 2].span;
+                    4
+                }
+                None => return 0,
+            }
+        }
+
+        _ => return 0,
+    };
+
+    for op in &mut ops[i + 1..i + len] {
+        op.removed = true;
+    }
+    len
+}
+
+/// Make jumps to unconditional jumps go straight to where they lead, and
+/// remove jumps to the next instruction.
+fn thread_jumps(ops: &mut [Op]) -> bool {
+    let mut changed = false;
+
+    for i in 0..ops.len() {
+        if !matches!(ops[i].instr, Instr::JumpU32 | Instr::JumpNotU32) {
+            continue;
+        }
+
+        // jumps that only lead to each other are left alone
+        let mut target = ops[i].target;
+        for _ in 0..ops.len() {
+            match ops.get(target) {
+                Some(next) if next.instr == Instr::JumpU32 => target = next.target,
+                _ => break,
+            }
+        }
+        if ops
+            .get(target)
+            .is_some_and(|next| next.instr == Instr::JumpU32)
+        {
+            continue;
+        }
+
+        if target != ops[i].target {
+            ops[i].target = target;
+            changed = true;
+        }
+
+        if ops[i].instr == Instr::JumpU32 && target == i + 1 {
+            ops[i].removed = true;
+            changed = true;
+        }
+    }
+
+    changed
+}
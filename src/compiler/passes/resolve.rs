@@ -1,26 +1,116 @@
+use std::collections::HashSet;
+
 use crate::compiler::ast::*;
 use crate::compiler::diagnostic::{Error, JlyResult};
 use crate::compiler::passes::visit::Visitor;
+use crate::runtime::builtins::BUILTIN_FNS;
 use crate::source::Span;
 use internment::Intern;
 
+#[derive(Clone)]
 pub struct Binding {
     ident: Intern<String>,
     defined: bool,
+    definition: Option<usize>,
+}
+
+#[derive(Clone)]
+struct Global {
+    ident: Intern<String>,
+    definition: Option<usize>,
+}
+
+/// A variable that couldn't be resolved, which may still be declared later
+/// on in a scope it's used in.
+#[derive(Clone)]
+struct Unresolved {
+    ident: Intern<String>,
+
+    /// The index of its error.
+    error: usize,
+
+    /// The depth of the innermost scope it's used in that's still open.
+    depth: usize,
+}
+
+/// Where each name is declared and used, for editor tooling.
+#[derive(Debug, Default, Clone)]
+pub struct Bindings {
+    pub definitions: Vec<Definition>,
+    pub references: Vec<Reference>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DefinitionKind {
+    Function,
+    Variable,
+}
+
+#[derive(Debug, Clone)]
+pub struct Definition {
+    pub ident: Intern<String>,
+    pub kind: DefinitionKind,
+
+    /// The name where it's declared.
+    pub span: Span,
+
+    /// The block the name can be used in, or `None` if that's the rest of
+    /// the module.
+    pub scope: Option<Span>,
 }
 
 #[derive(Debug, Clone, Copy)]
-pub struct VarResolved(usize);
+pub struct Reference {
+    pub span: Span,
+
+    /// The index of the definition it refers to.
+    pub definition: usize,
+}
+
+impl Bindings {
+    fn define(&mut self, ident: Intern<String>, kind: DefinitionKind, span: Span) -> usize {
+        self.definitions.push(Definition {
+            ident,
+            kind,
+            span,
+            scope: None,
+        });
+        self.definitions.len() - 1
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum VarResolved {
+    /// A stack slot in the current function.
+    Local(usize),
+
+    /// A top-level function.
+    Global(usize),
+}
 
 impl VarResolved {
     pub fn byte(&self) -> u8 {
-        self.0 as u8
+        match self {
+            Self::Local(n) | Self::Global(n) => *n as u8,
+        }
     }
 }
 
+#[derive(Clone)]
 pub struct Resolver {
     vars: Vec<Binding>,
-    scopes: Vec<usize>,
+    scopes: Vec<(usize, Span)>,
+    globals: Vec<Global>,
+    bindings: Bindings,
+
+    /// The variants of the enums declared so far, which the parser takes
+    /// for variables.
+    variants: HashSet<Intern<String>>,
+
+    /// The errors found so far, which don't stop the rest of the module
+    /// from being resolved.
+    errors: Vec<Error>,
+    unresolved: Vec<Unresolved>,
 }
 
 impl Resolver {
@@ -28,56 +118,344 @@ impl Resolver {
         Self {
             vars: vec![],
             scopes: vec![],
+            globals: BUILTIN_FNS
+                .iter()
+                .map(|func| Global {
+                    ident: Intern::from_ref(func.name),
+                    definition: None,
+                })
+                .collect(),
+            bindings: Bindings::default(),
+            variants: HashSet::new(),
+            errors: vec![],
+            unresolved: vec![],
         }
     }
 
-    fn start_scope(&mut self) {
-        self.scopes.push(self.vars.len());
+    /// The errors found since this was last called.
+    pub fn take_errors(&mut self) -> Vec<Error> {
+        std::mem::take(&mut self.errors)
+    }
+
+    /// Where the names in the modules resolved so far are declared and used.
+    pub fn bindings(&self) -> &Bindings {
+        &self.bindings
+    }
+
+    fn start_scope(&mut self, span: Span) {
+        self.scopes.push((self.vars.len(), span));
     }
 
     fn end_scope(&mut self) -> usize {
-        let prev_len = self.scopes.pop().unwrap();
+        let (prev_len, span) = self.scopes.pop().unwrap();
         let scope_size = self.vars.len() - prev_len;
 
-        self.vars.truncate(prev_len);
+        self.close_vars(prev_len, span);
+
+        // a declaration after this scope still encloses the uses in it
+        let depth = self.scopes.len();
+        for unresolved in &mut self.unresolved {
+            unresolved.depth = unresolved.depth.min(depth);
+        }
 
         scope_size
     }
 
-    fn declare_var(&mut self, ident: Intern<String>, span: Span) -> JlyResult<VarResolved> {
+    /// Remove the variables from `start` on, which were visible in `scope`.
+    fn close_vars(&mut self, start: usize, scope: Span) {
+        for binding in self.vars.drain(start..) {
+            if let Some(definition) = binding.definition {
+                self.bindings.definitions[definition].scope = Some(scope);
+            }
+        }
+    }
+
+    fn declare_var(&mut self, ident: Intern<String>, span: Span) -> VarResolved {
+        // point the uses of it earlier in the scope at the declaration
+        let depth = self.scopes.len();
+        let errors = &mut self.errors;
+        self.unresolved.retain(|unresolved| {
+            if unresolved.ident != ident || unresolved.depth != depth {
+                return true;
+            }
+            if let Error::UnresolvedVariable {
+                similar, declared, ..
+            } = &mut errors[unresolved.error]
+            {
+                *similar = None;
+                *declared = Some(span);
+            }
+            false
+        });
+
+        let definition = self.bindings.define(ident, DefinitionKind::Variable, span);
+        self.push_var(ident, Some(definition), span)
+    }
+
+    fn push_var(
+        &mut self,
+        ident: Intern<String>,
+        definition: Option<usize>,
+        span: Span,
+    ) -> VarResolved {
         let n = self.vars.len();
 
         self.vars.push(Binding {
             ident,
             defined: false,
+            definition,
         });
 
-        if n > 0xff {
-            return Err(Error::TooManyLocals(span));
+        if n == 0x100 {
+            self.errors.push(Error::TooManyLocals(span));
         }
 
-        Ok(VarResolved(n))
+        VarResolved::Local(n)
     }
 
     fn define_var(&mut self, var: VarResolved) {
-        self.vars[var.0].defined = true;
+        if let VarResolved::Local(n) = var {
+            self.vars[n].defined = true;
+        }
     }
 
-    fn resolve_var(&mut self, ident: Intern<String>) -> JlyResult<VarResolved> {
-        self.vars
+    /// Declare a global. A duplicate is still given a global of its own,
+    /// so the type checker numbers the rest of them the same way.
+    fn declare_global(
+        &mut self,
+        ident: Intern<String>,
+        definition: Option<usize>,
+        span: Span,
+    ) -> VarResolved {
+        if self.resolve_global(ident).is_some() {
+            self.errors.push(Error::DuplicateDefinition(ident, span));
+        }
+
+        if self.globals.len() == 0x100 {
+            self.errors.push(Error::TooManyFunctions(span));
+        }
+
+        self.globals.push(Global { ident, definition });
+
+        VarResolved::Global(self.globals.len() - 1)
+    }
+
+    fn resolve_global(&self, ident: Intern<String>) -> Option<VarResolved> {
+        self.globals
+            .iter()
+            .position(|global| global.ident == ident)
+            .map(VarResolved::Global)
+    }
+
+    fn resolve_var(&mut self, ident: Intern<String>) -> Option<VarResolved> {
+        if let Some(n) = self
+            .vars
             .iter()
             .rposition(|binding| binding.ident == ident && binding.defined)
-            .map(VarResolved)
-            .ok_or(Error::UnresolvedVariable(ident))
+        {
+            return Some(VarResolved::Local(n));
+        }
+
+        self.resolve_global(ident)
+    }
+
+    /// The name in scope that's most like an unresolved one, if any is
+    /// close enough to be a likely typo.
+    fn similar_name(&self, ident: Intern<String>) -> Option<Intern<String>> {
+        let vars = self
+            .vars
+            .iter()
+            .rev()
+            .filter(|binding| binding.defined)
+            .map(|binding| binding.ident);
+
+        // methods are stored under names that can't be written
+        let globals = self
+            .globals
+            .iter()
+            .map(|global| global.ident)
+            .filter(|ident| ident.chars().all(|c| c.is_alphanumeric() || c == '_'));
+
+        // a candidate that takes as many edits as the name has characters
+        // has nothing in common with it
+        let len = ident.chars().count();
+        let max_distance = (len / 3).max(1);
+        vars.chain(globals)
+            .map(|candidate| (edit_distance(&ident, &candidate), candidate))
+            .filter(|(distance, _)| *distance <= max_distance && *distance < len)
+            .min_by_key(|(distance, _)| *distance)
+            .map(|(_, candidate)| candidate)
+    }
+
+    fn resolve_fn(&mut self, fn_decl: &mut FnDecl) -> JlyResult<()> {
+        // functions can't see the locals of the top level
+        let outer_vars = std::mem::take(&mut self.vars);
+        let outer_unresolved = std::mem::take(&mut self.unresolved);
+
+        for param in &fn_decl.sig.params {
+            let var = self.declare_var(param.ident, param.span);
+            self.define_var(var);
+        }
+
+        self.visit_block(&mut fn_decl.body)?;
+
+        self.close_vars(0, fn_decl.body.span);
+        self.vars = outer_vars;
+        self.unresolved = outer_unresolved;
+
+        Ok(())
+    }
+
+    fn unresolve(&mut self, var: &Var) {
+        // a variable used in its own initialiser is declared, but not yet
+        // defined
+        let declared = self
+            .vars
+            .iter()
+            .rev()
+            .find(|binding| binding.ident == var.ident)
+            .and_then(|binding| binding.definition)
+            .map(|definition| self.bindings.definitions[definition].span);
+
+        let similar = match declared {
+            Some(_) => None,
+            None => self.similar_name(var.ident),
+        };
+
+        if declared.is_none() {
+            self.unresolved.push(Unresolved {
+                ident: var.ident,
+                error: self.errors.len(),
+                depth: self.scopes.len(),
+            });
+        }
+
+        self.errors.push(Error::UnresolvedVariable {
+            ident: var.ident,
+            span: var.span,
+            similar,
+            declared,
+        });
+    }
+
+    /// The variant that a variable, or a call of one, really is, if it's
+    /// named after a user-defined variant.
+    fn as_variant(&self, kind: &mut ExprKind) -> Option<ExprKind> {
+        let ident = match kind {
+            ExprKind::Var(var) => var.ident,
+            ExprKind::Call(callee, _) => match &callee.kind {
+                ExprKind::Var(var) => var.ident,
+                _ => return None,
+            },
+            _ => return None,
+        };
+        if !self.variants.contains(&ident) {
+            return None;
+        }
+
+        let args = match kind {
+            ExprKind::Call(_, args) => std::mem::take(args),
+            _ => vec![],
+        };
+        let variant = Variant {
+            ident,
+            resolved: None,
+        };
+        Some(ExprKind::Variant(variant, args))
+    }
+
+    /// Resolve a statement. One that can't be resolved at all is reported
+    /// and replaced with an empty block, so the later passes skip it.
+    fn resolve_statement(&mut self, statement: &mut Statement) {
+        if let Err(err) = self.visit_statement(statement) {
+            self.errors.push(err);
+            *statement = Statement::Block(Block {
+                statements: vec![],
+                num_vars: Some(0),
+                span: Span::default(),
+            });
+        }
     }
 }
 
+/// The number of characters to insert, remove or replace to turn one string
+/// into the other.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, a) in a.chars().enumerate() {
+        let mut row = vec![i + 1];
+        for (j, b) in b.iter().enumerate() {
+            let cost = if a == *b { prev[j] } else { prev[j] + 1 };
+            row.push(cost.min(prev[j + 1] + 1).min(row[j] + 1));
+        }
+        prev = row;
+    }
+
+    prev[b.len()]
+}
+
+/// The name of the global that a method is stored in, which can't clash
+/// with any identifier.
+fn method_global(impl_decl: &ImplDecl, method: &FnDecl) -> Intern<String> {
+    let ty = match &impl_decl.ty.kind {
+        TypeExprKind::Named(ident, _) => ident.to_string(),
+        TypeExprKind::Function(_, _) => "fn".to_string(),
+        TypeExprKind::Unit => "()".to_string(),
+    };
+
+    Intern::new(match impl_decl.trait_ {
+        Some((trait_, _)) => format!("<{} as {}>::{}", ty, trait_, method.sig.ident),
+        None => format!("{}::{}", ty, method.sig.ident),
+    })
+}
+
 impl Visitor for Resolver {
+    fn visit_module(&mut self, module: &mut Module) -> JlyResult<()> {
+        // functions can be used before they're declared
+        for statement in &mut module.statements {
+            match statement {
+                Statement::FnDecl(fn_decl) => {
+                    let sig = &fn_decl.sig;
+                    let definition =
+                        self.bindings
+                            .define(sig.ident, DefinitionKind::Function, sig.ident_span());
+                    fn_decl.resolved =
+                        Some(self.declare_global(sig.ident, Some(definition), sig.span));
+                }
+                Statement::ImplDecl(impl_decl) => {
+                    let globals: Vec<_> = impl_decl
+                        .methods
+                        .iter()
+                        .map(|method| (method_global(impl_decl, method), method.sig.span))
+                        .collect();
+
+                    for (method, (global, span)) in impl_decl.methods.iter_mut().zip(globals) {
+                        method.resolved = Some(self.declare_global(global, None, span));
+                    }
+                }
+                Statement::EnumDecl(enum_decl) => {
+                    let variants = enum_decl.variants.iter().map(|variant| variant.ident);
+                    self.variants.extend(variants);
+                }
+                _ => {}
+            }
+        }
+
+        for statement in &mut module.statements {
+            self.resolve_statement(statement);
+        }
+        self.unresolved.clear();
+
+        Ok(())
+    }
+
     fn visit_block(&mut self, block: &mut Block) -> JlyResult<()> {
-        self.start_scope();
+        self.start_scope(block.span);
 
         for statement in &mut block.statements {
-            self.visit_statement(statement)?;
+            self.resolve_statement(statement);
         }
 
         block.num_vars = Some(self.end_scope());
@@ -86,6 +464,11 @@ impl Visitor for Resolver {
     }
 
     fn visit_expr(&mut self, expr: &mut Expr) -> JlyResult<()> {
+        let span = expr.span;
+        if let Some(kind) = self.as_variant(&mut expr.kind) {
+            expr.kind = kind;
+        }
+
         match &mut expr.kind {
             ExprKind::Var(var) => self.visit_var(var)?,
             ExprKind::Value(_) | ExprKind::DummyExpr => {}
@@ -103,35 +486,142 @@ impl Visitor for Resolver {
             | ExprKind::Mul(lhs, rhs)
             | ExprKind::Div(lhs, rhs)
             | ExprKind::Mod(lhs, rhs)
-            | ExprKind::Pow(lhs, rhs) => {
+            | ExprKind::Pow(lhs, rhs)
+            | ExprKind::BitAnd(lhs, rhs)
+            | ExprKind::BitOr(lhs, rhs)
+            | ExprKind::BitXor(lhs, rhs)
+            | ExprKind::Shl(lhs, rhs)
+            | ExprKind::Shr(lhs, rhs) => {
                 self.visit_expr(lhs)?;
                 self.visit_expr(rhs)?;
             }
 
-            ExprKind::Neg(expr) => self.visit_expr(expr)?,
+            ExprKind::Neg(expr) | ExprKind::BitNot(expr) => self.visit_expr(expr)?,
 
             ExprKind::Assignment(lhs, rhs) => {
                 self.visit_var(lhs)?;
+                if let Some(VarResolved::Global(_)) = lhs.resolved {
+                    self.errors.push(Error::AssignToFunction(lhs.ident, span));
+                }
                 self.visit_expr(rhs)?;
             }
 
-            ExprKind::LogicalNot(expr) | ExprKind::DebugPrint(expr) => self.visit_expr(expr)?,
+            ExprKind::Call(callee, args) => {
+                self.visit_expr(callee)?;
+                for arg in args {
+                    self.visit_expr(arg)?;
+                }
+            }
+
+            ExprKind::MethodCall(receiver, _, args) => {
+                self.visit_expr(receiver)?;
+                for arg in args {
+                    self.visit_expr(arg)?;
+                }
+            }
+
+            ExprKind::Field(expr, _) | ExprKind::Cast(expr, _) | ExprKind::Try(expr) => {
+                self.visit_expr(expr)?
+            }
+
+            ExprKind::Variant(_, args) => {
+                for arg in args {
+                    self.visit_expr(arg)?;
+                }
+            }
+
+            ExprKind::StructLiteral(literal) => {
+                for field in &mut literal.fields {
+                    self.visit_expr(&mut field.value)?;
+                }
+            }
+
+            ExprKind::LogicalNot(expr) | ExprKind::DebugPrint(expr) | ExprKind::Panic(expr) => {
+                self.visit_expr(expr)?
+            }
         }
         Ok(())
     }
 
     fn visit_var(&mut self, var: &mut Var) -> JlyResult<()> {
-        var.resolved = Some(self.resolve_var(var.ident)?);
+        // the type checker treats an unresolved variable as having any type
+        let resolved = match self.resolve_var(var.ident) {
+            Some(resolved) => resolved,
+            None => {
+                self.unresolve(var);
+                return Ok(());
+            }
+        };
+        let definition = match resolved {
+            VarResolved::Local(n) => self.vars[n].definition,
+            VarResolved::Global(n) => self.globals[n].definition,
+        };
+        if let Some(definition) = definition {
+            self.bindings.references.push(Reference {
+                span: var.span,
+                definition,
+            });
+        }
+
+        var.resolved = Some(resolved);
         Ok(())
     }
 
     fn visit_var_decl(&mut self, var_decl: &mut VarDecl) -> JlyResult<()> {
-        let var = self.declare_var(var_decl.ident, var_decl.span)?;
+        let var = self.declare_var(var_decl.ident, var_decl.ident_span);
         self.visit_expr(&mut var_decl.value)?;
         self.define_var(var);
         Ok(())
     }
 
+    fn visit_fn_decl(&mut self, fn_decl: &mut FnDecl) -> JlyResult<()> {
+        if !self.scopes.is_empty() {
+            return Err(Error::NestedItem(fn_decl.sig.span));
+        }
+
+        self.resolve_fn(fn_decl)
+    }
+
+    fn visit_struct_decl(&mut self, struct_decl: &mut StructDecl) -> JlyResult<()> {
+        if !self.scopes.is_empty() {
+            return Err(Error::NestedItem(struct_decl.span));
+        }
+        Ok(())
+    }
+
+    fn visit_enum_decl(&mut self, enum_decl: &mut EnumDecl) -> JlyResult<()> {
+        if !self.scopes.is_empty() {
+            return Err(Error::NestedItem(enum_decl.span));
+        }
+        Ok(())
+    }
+
+    fn visit_newtype_decl(&mut self, newtype_decl: &mut NewtypeDecl) -> JlyResult<()> {
+        if !self.scopes.is_empty() {
+            return Err(Error::NestedItem(newtype_decl.span));
+        }
+        Ok(())
+    }
+
+    fn visit_trait_decl(&mut self, trait_decl: &mut TraitDecl) -> JlyResult<()> {
+        if !self.scopes.is_empty() {
+            return Err(Error::NestedItem(trait_decl.span));
+        }
+        Ok(())
+    }
+
+    fn visit_impl_decl(&mut self, impl_decl: &mut ImplDecl) -> JlyResult<()> {
+        if !self.scopes.is_empty() {
+            return Err(Error::NestedItem(impl_decl.span));
+        }
+
+        for method in &mut impl_decl.methods {
+            self.resolve_fn(method)?;
+        }
+
+        Ok(())
+    }
+
     fn visit_if_statement(&mut self, if_statement: &mut IfStatement) -> JlyResult<()> {
         self.visit_expr(&mut if_statement.condition)?;
         self.visit_block(&mut if_statement.then)?;
@@ -145,4 +635,52 @@ impl Visitor for Resolver {
         self.visit_expr(&mut while_loop.condition)?;
         self.visit_block(&mut while_loop.body)
     }
+
+    fn visit_match(&mut self, match_statement: &mut MatchStatement) -> JlyResult<()> {
+        self.visit_expr(&mut match_statement.scrutinee)?;
+
+        // the scrutinee stays on the stack while the arms are tested
+        self.start_scope(match_statement.span);
+        let slot = self.push_var(Intern::from_ref("<match>"), None, match_statement.span);
+        self.define_var(slot);
+        match_statement.slot = Some(slot.byte() as usize);
+
+        for arm in &mut match_statement.arms {
+            self.start_scope(arm.body.span);
+            if let PatternKind::Variant {
+                binding: Some((binding, span)),
+                ..
+            } = arm.pattern.kind
+            {
+                let var = self.declare_var(binding, span);
+                self.define_var(var);
+            }
+            self.visit_block(&mut arm.body)?;
+            self.end_scope();
+        }
+
+        self.end_scope();
+
+        Ok(())
+    }
+
+    fn visit_try_statement(&mut self, try_statement: &mut TryStatement) -> JlyResult<()> {
+        self.visit_block(&mut try_statement.body)?;
+
+        // the VM pushes the panic just above the locals the handler saw
+        self.start_scope(try_statement.recover.span);
+        let var = self.declare_var(try_statement.binding, try_statement.binding_span());
+        self.define_var(var);
+        self.visit_block(&mut try_statement.recover)?;
+        self.end_scope();
+
+        Ok(())
+    }
+
+    fn visit_return(&mut self, return_statement: &mut ReturnStatement) -> JlyResult<()> {
+        if let Some(value) = &mut return_statement.value {
+            self.visit_expr(value)?;
+        }
+        Ok(())
+    }
 }
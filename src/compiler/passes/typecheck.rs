@@ -0,0 +1,2009 @@
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+use internment::Intern;
+
+use crate::compiler::ast::*;
+use crate::compiler::diagnostic::{Error, JlyResult};
+use crate::compiler::passes::fold::constant_value;
+use crate::compiler::passes::resolve::VarResolved;
+use crate::compiler::passes::visit::Visitor;
+use crate::runtime::builtins::{
+    native_impls, BuiltinTrait, BUILTIN_ENUMS, BUILTIN_FNS, PANIC_FIELDS, PANIC_STRUCT,
+};
+use crate::runtime::value::{EnumLayout, Object, Value};
+use crate::source::Span;
+
+const INT: Type = Type::Builtin(BuiltinType::Integer);
+const BIGINT: Type = Type::Builtin(BuiltinType::BigInt);
+const BOOL: Type = Type::Builtin(BuiltinType::Bool);
+const UNIT: Type = Type::Builtin(BuiltinType::Unit);
+const STRING: Type = Type::Builtin(BuiltinType::String);
+
+#[derive(Clone)]
+struct StructDef {
+    params: Vec<TypeParam>,
+    fields: Vec<(Intern<String>, Type)>,
+}
+
+#[derive(Clone)]
+struct EnumDef {
+    params: Vec<TypeParam>,
+    variants: Vec<(Intern<String>, Option<Type>)>,
+
+    /// The names the VM prints the enum's values with.
+    layout: Rc<EnumLayout>,
+}
+
+#[derive(Clone)]
+struct TraitDef {
+    ident: Intern<String>,
+
+    /// The parameter standing for `Self` in the method signatures.
+    self_param: TypeParam,
+    methods: Vec<(Intern<String>, Scheme)>,
+}
+
+#[derive(Clone)]
+struct ImplDef {
+    target: Type,
+    methods: Vec<usize>,
+
+    /// Whether this is a builtin type's impl of a builtin trait, whose
+    /// methods only exist in the VM's vtables.
+    native: bool,
+}
+
+impl ImplDef {
+    fn method(&self, trait_index: usize, method: usize) -> MethodResolved {
+        match self.native {
+            true => MethodResolved::Dynamic {
+                trait_index,
+                method,
+            },
+            false => MethodResolved::Static(self.methods[method]),
+        }
+    }
+}
+
+/// The names visible in a signature or body, besides variables.
+#[derive(Clone, Default)]
+struct Scope {
+    type_params: HashMap<Intern<String>, TypeParam>,
+    self_ty: Option<Type>,
+}
+
+#[derive(Clone)]
+struct Global {
+    scheme: Scheme,
+    checked: bool,
+    scope: Scope,
+}
+
+impl Global {
+    /// A stand-in for a function whose signature couldn't be checked.
+    fn unknown() -> Self {
+        Self {
+            scheme: Scheme {
+                params: vec![],
+                ty: Type::Unknown,
+            },
+            checked: true,
+            scope: Scope::default(),
+        }
+    }
+}
+
+/// A type that must implement a trait, once it's known.
+#[derive(Clone)]
+struct Constraint {
+    ty: Type,
+    trait_index: usize,
+    span: Span,
+}
+
+/// Where the methods of a trait impl are stored.
+#[derive(Debug, Clone)]
+pub struct ImplResolved {
+    pub trait_index: usize,
+
+    /// The name the VM uses for the implementing type.
+    pub ty: Intern<String>,
+
+    /// The globals holding the methods, in the order the trait declares them.
+    pub methods: Vec<usize>,
+}
+
+/// Which variant of which enum a variant expression makes.
+#[derive(Debug, Clone)]
+pub struct VariantResolved {
+    pub layout: Rc<EnumLayout>,
+    pub tag: usize,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum MethodResolved {
+    /// The receiver's type is known, so the method is a plain global.
+    Static(usize),
+
+    /// The receiver is a type parameter, so the method is looked up in the
+    /// vtable of its runtime type.
+    Dynamic { trait_index: usize, method: usize },
+}
+
+/// Infers the type of every expression, Hindley-Milner style.
+///
+/// Functions are generalised once their body has been checked, so a
+/// function without annotations can still be used at several types
+/// by the functions and statements that come after it.
+#[derive(Clone)]
+pub struct TypeChecker {
+    subst: Substitution,
+
+    structs: HashMap<Intern<String>, StructDef>,
+    newtypes: HashMap<Intern<String>, Type>,
+    enums: HashMap<Intern<String>, EnumDef>,
+
+    /// The enum and tag of every variant, since variants are written
+    /// without their enum's name.
+    variants: HashMap<Intern<String>, (Intern<String>, usize)>,
+    globals: Vec<Global>,
+    locals: Vec<Type>,
+
+    traits: Vec<TraitDef>,
+    trait_indices: HashMap<Intern<String>, usize>,
+    bounds: HashMap<TypeParam, Vec<usize>>,
+    constraints: Vec<Constraint>,
+
+    /// Methods from impl blocks without a trait, by type and method name.
+    inherent_methods: HashMap<(Intern<String>, Intern<String>), usize>,
+    trait_impls: HashMap<(usize, Intern<String>), ImplDef>,
+
+    scope: Scope,
+    return_ty: Option<Type>,
+
+    /// The types of the names declared so far, by the span of the name.
+    declarations: Vec<(Span, Type)>,
+
+    /// The errors found so far, which don't stop the rest of the module
+    /// from being checked.
+    errors: Vec<Error>,
+}
+
+impl Default for TypeChecker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TypeChecker {
+    pub fn new() -> Self {
+        let mut typechecker = Self {
+            subst: Substitution::default(),
+            structs: HashMap::new(),
+            newtypes: HashMap::new(),
+            enums: HashMap::new(),
+            variants: HashMap::new(),
+            globals: vec![],
+            locals: vec![],
+            traits: vec![],
+            trait_indices: HashMap::new(),
+            bounds: HashMap::new(),
+            constraints: vec![],
+            inherent_methods: HashMap::new(),
+            trait_impls: HashMap::new(),
+            scope: Scope::default(),
+            return_ty: None,
+            declarations: vec![],
+            errors: vec![],
+        };
+        typechecker.declare_builtin_traits();
+        typechecker.declare_builtin_enums();
+
+        // builtin functions take up the first globals, as in the resolver
+        for func in BUILTIN_FNS {
+            let lower = |ty| Type::Builtin(BuiltinType::from_name(ty).unwrap());
+            let params = func.params.iter().map(|param| lower(param)).collect();
+            let ty = Type::Function(params, Box::new(lower(func.ret)));
+            typechecker.globals.push(Global {
+                scheme: Scheme { params: vec![], ty },
+                checked: true,
+                scope: Scope::default(),
+            });
+        }
+
+        let fields = PANIC_FIELDS
+            .iter()
+            .map(|(field, ty)| {
+                let ty = Type::Builtin(BuiltinType::from_name(ty).unwrap());
+                (Intern::from_ref(*field), ty)
+            })
+            .collect();
+        typechecker.structs.insert(
+            Intern::from_ref(PANIC_STRUCT),
+            StructDef {
+                params: vec![],
+                fields,
+            },
+        );
+
+        typechecker
+    }
+
+    fn declare_builtin_enums(&mut self) {
+        for def in BUILTIN_ENUMS {
+            let params: Vec<TypeParam> = def
+                .params
+                .iter()
+                .map(|param| self.subst.fresh_param(Intern::from_ref(*param)))
+                .collect();
+
+            let name = Intern::from_ref(def.name);
+            let variants: Vec<_> = def
+                .variants
+                .iter()
+                .map(|(variant, payload)| {
+                    let payload = payload.map(|index| Type::Param(params[index]));
+                    (Intern::from_ref(*variant), payload)
+                })
+                .collect();
+
+            for (tag, (variant, _)) in variants.iter().enumerate() {
+                self.variants.insert(*variant, (name, tag));
+            }
+            let layout = Rc::new(EnumLayout {
+                name,
+                variants: variants.iter().map(|(variant, _)| *variant).collect(),
+            });
+            self.enums.insert(
+                name,
+                EnumDef {
+                    params,
+                    variants,
+                    layout,
+                },
+            );
+        }
+    }
+
+    fn declare_builtin_traits(&mut self) {
+        for trait_ in BuiltinTrait::ALL {
+            let ident = Intern::from_ref(trait_.name());
+            let self_param = self.subst.fresh_param(Intern::from_ref("Self"));
+            let self_ty = Type::Param(self_param);
+
+            let ty = match trait_ {
+                BuiltinTrait::Add | BuiltinTrait::Sub | BuiltinTrait::Mul => {
+                    Type::Function(vec![self_ty.clone(), self_ty.clone()], Box::new(self_ty))
+                }
+                BuiltinTrait::Eq => Type::Function(vec![self_ty.clone(), self_ty], Box::new(BOOL)),
+                BuiltinTrait::Ord => Type::Function(vec![self_ty.clone(), self_ty], Box::new(INT)),
+                BuiltinTrait::Display => Type::Function(vec![self_ty], Box::new(STRING)),
+            };
+
+            self.bounds.insert(self_param, vec![trait_ as usize]);
+            self.trait_indices.insert(ident, trait_ as usize);
+            self.traits.push(TraitDef {
+                ident,
+                self_param,
+                methods: vec![(
+                    Intern::from_ref(trait_.method()),
+                    Scheme { params: vec![], ty },
+                )],
+            });
+        }
+
+        for (trait_, ty) in native_impls() {
+            let target = Type::Builtin(BuiltinType::from_name(ty).unwrap());
+            self.trait_impls.insert(
+                (trait_ as usize, Intern::from_ref(ty)),
+                ImplDef {
+                    target,
+                    methods: vec![],
+                    native: true,
+                },
+            );
+        }
+    }
+
+    fn unify(&mut self, expected: &Type, found: &Type, span: Span) -> JlyResult<Type> {
+        self.subst
+            .unify_types(expected, found)
+            .map_err(|_| Error::TypeMismatch {
+                expected: self.subst.resolve(expected),
+                found: self.subst.resolve(found),
+                span,
+            })
+    }
+
+    /// The errors found since this was last called.
+    pub fn take_errors(&mut self) -> Vec<Error> {
+        std::mem::take(&mut self.errors)
+    }
+
+    /// Record the error of a top-level item, so the rest of the module can
+    /// still be checked. Returns whether there was one.
+    fn recover(&mut self, result: JlyResult<()>) -> bool {
+        match result {
+            Ok(()) => false,
+            Err(err) => {
+                self.errors.push(err);
+                self.scope = Scope::default();
+                true
+            }
+        }
+    }
+
+    /// Check a statement, recording its error so the ones after it can
+    /// still be checked.
+    fn check_statement(&mut self, statement: &mut Statement) {
+        let num_locals = self.locals.len();
+        if let Err(err) = self.visit_statement(statement) {
+            self.errors.push(err);
+            self.locals.truncate(num_locals);
+
+            // the variable is still declared, with a type that fits anything
+            if let Statement::VarDecl(_) = statement {
+                self.locals.push(Type::Unknown);
+            }
+        }
+    }
+
+    /// The type of a variable or function, given the span of its name where
+    /// it's declared.
+    pub fn declared_type(&self, span: Span) -> Option<Type> {
+        self.declarations
+            .iter()
+            .rfind(|(declared, _)| *declared == span)
+            .map(|(_, ty)| self.subst.resolve(ty))
+    }
+
+    /// A type with the inference variables solved so far filled in.
+    pub fn resolve_type(&self, ty: &Type) -> Type {
+        self.subst.resolve(ty)
+    }
+
+    fn check_expr(&mut self, expr: &mut Expr) -> JlyResult<Type> {
+        self.visit_expr(expr)?;
+        Ok(expr.ty.clone().unwrap())
+    }
+
+    fn expect_expr(&mut self, expr: &mut Expr, expected: &Type) -> JlyResult<Type> {
+        let found = self.check_expr(expr)?;
+        self.unify(expected, &found, expr.span)
+    }
+
+    /// Instantiate a scheme, remembering the bounds on its parameters.
+    fn instantiate(&mut self, scheme: &Scheme, span: Span) -> Type {
+        let (ty, mapping) = self.subst.instantiate(scheme);
+        self.constrain(&mapping, span);
+        ty
+    }
+
+    fn constrain(&mut self, mapping: &HashMap<TypeParam, Type>, span: Span) {
+        for (param, ty) in mapping {
+            for &trait_index in self.bounds.get(param).into_iter().flatten() {
+                self.constraints.push(Constraint {
+                    ty: ty.clone(),
+                    trait_index,
+                    span,
+                });
+            }
+        }
+    }
+
+    /// Check the constraints whose types are known by now. Those on
+    /// variables in `env` may still be solved later, so they're kept.
+    /// Check the constraints on types that are known by now, recording the
+    /// ones that aren't met.
+    fn check_constraints(&mut self, env: &HashSet<TypeVar>) {
+        let mut pending = vec![];
+
+        while let Some(constraint) = self.constraints.pop() {
+            let trait_index = constraint.trait_index;
+            let span = constraint.span;
+
+            let unsatisfied = |ty| Error::UnsatisfiedBound {
+                ty,
+                trait_: self.traits[trait_index].ident,
+                span,
+            };
+
+            match self.subst.resolve(&constraint.ty) {
+                Type::Unknown => {}
+
+                Type::Var(var) if env.contains(&var) => pending.push(constraint),
+                Type::Var(_) => self.errors.push(Error::TypeAnnotationNeeded(span)),
+
+                Type::Param(param) => {
+                    let bounds = self.bounds.get(&param).map_or(&[][..], |b| &b[..]);
+                    if !bounds.contains(&trait_index) {
+                        let err = unsatisfied(Type::Param(param));
+                        self.errors.push(err);
+                    }
+                }
+
+                // at runtime a newtype is its base type, so generic code
+                // would find the base type's methods instead of its own
+                Type::Newtype(name) => {
+                    let err = match self.trait_impls.contains_key(&(trait_index, name)) {
+                        true => Error::NewtypeBound {
+                            ty: name,
+                            base: self.newtypes[&name].clone(),
+                            trait_: self.traits[trait_index].ident,
+                            span,
+                        },
+                        false => unsatisfied(Type::Newtype(name)),
+                    };
+                    self.errors.push(err);
+                }
+
+                ty => {
+                    let impl_def =
+                        type_head(&ty).and_then(|head| self.trait_impls.get(&(trait_index, head)));
+
+                    let target = match impl_def {
+                        Some(impl_def) => impl_def.target.clone(),
+                        None => {
+                            let err = unsatisfied(ty);
+                            self.errors.push(err);
+                            continue;
+                        }
+                    };
+
+                    // the impl may in turn need its parameters to implement
+                    // some traits
+                    if let (Type::Constructor(_, params), Type::Constructor(_, args)) =
+                        (&target, &ty)
+                    {
+                        let mapping = params
+                            .iter()
+                            .zip(args)
+                            .filter_map(|(param, arg)| match param {
+                                Type::Param(param) => Some((*param, arg.clone())),
+                                _ => None,
+                            })
+                            .collect();
+                        self.constrain(&mapping, span);
+                    }
+                }
+            }
+        }
+
+        self.constraints = pending;
+    }
+
+    fn var_type(&mut self, var: &Var, span: Span) -> Type {
+        match var.resolved {
+            Some(VarResolved::Local(n)) => self.locals[n].clone(),
+            Some(VarResolved::Global(n)) => {
+                let scheme = self.globals[n].scheme.clone();
+                self.instantiate(&scheme, span)
+            }
+
+            // the resolver has already reported it
+            None => Type::Unknown,
+        }
+    }
+
+    /// Convert a type written in the source to a `Type`.
+    fn lower_type(&mut self, ty: &TypeExpr) -> JlyResult<Type> {
+        match &ty.kind {
+            TypeExprKind::Unit => Ok(UNIT),
+
+            TypeExprKind::Function(params, ret) => {
+                let params = params
+                    .iter()
+                    .map(|param| self.lower_type(param))
+                    .collect::<JlyResult<_>>()?;
+                let ret = self.lower_type(ret)?;
+                Ok(Type::Function(params, Box::new(ret)))
+            }
+
+            TypeExprKind::Named(name, args) => {
+                let expect_no_args = || match args.len() {
+                    0 => Ok(()),
+                    n => Err(Error::WrongTypeArgCount {
+                        expected: 0,
+                        found: n,
+                        span: ty.span,
+                    }),
+                };
+
+                if let Some(param) = self.scope.type_params.get(name) {
+                    expect_no_args()?;
+                    return Ok(Type::Param(*param));
+                }
+
+                if let (Some(self_ty), "Self") = (&self.scope.self_ty, name.as_str()) {
+                    expect_no_args()?;
+                    return Ok(self_ty.clone());
+                }
+
+                if let Some(builtin) = BuiltinType::from_name(name) {
+                    expect_no_args()?;
+                    return Ok(Type::Builtin(builtin));
+                }
+
+                if self.newtypes.contains_key(name) {
+                    expect_no_args()?;
+                    return Ok(Type::Newtype(*name));
+                }
+
+                let num_params = match (self.structs.get(name), self.enums.get(name)) {
+                    (Some(def), _) => def.params.len(),
+                    (_, Some(def)) => def.params.len(),
+                    _ => return Err(Error::UnknownType(*name, ty.span)),
+                };
+
+                if num_params != args.len() {
+                    return Err(Error::WrongTypeArgCount {
+                        expected: num_params,
+                        found: args.len(),
+                        span: ty.span,
+                    });
+                }
+
+                let args = args
+                    .iter()
+                    .map(|arg| self.lower_type(arg))
+                    .collect::<JlyResult<_>>()?;
+
+                Ok(Type::Constructor(*name, args))
+            }
+        }
+    }
+
+    fn lookup_trait(&self, ident: Intern<String>, span: Span) -> JlyResult<usize> {
+        self.trait_indices
+            .get(&ident)
+            .copied()
+            .ok_or(Error::UnknownTrait(ident, span))
+    }
+
+    /// Bring type parameters into scope, along with their bounds.
+    fn declare_type_params(&mut self, generics: &[GenericParam]) -> JlyResult<Vec<TypeParam>> {
+        let mut params = vec![];
+
+        for generic in generics {
+            let param = self.subst.fresh_param(generic.ident);
+
+            let bounds = generic
+                .bounds
+                .iter()
+                .map(|(bound, span)| self.lookup_trait(*bound, *span))
+                .collect::<JlyResult<_>>()?;
+
+            self.bounds.insert(param, bounds);
+            self.scope.type_params.insert(generic.ident, param);
+            params.push(param);
+        }
+
+        Ok(params)
+    }
+
+    fn type_exists(&self, ident: Intern<String>) -> bool {
+        self.structs.contains_key(&ident)
+            || self.newtypes.contains_key(&ident)
+            || self.enums.contains_key(&ident)
+            || BuiltinType::from_name(&ident).is_some()
+    }
+
+    fn declare_struct(&mut self, struct_decl: &StructDecl) -> JlyResult<()> {
+        if self.type_exists(struct_decl.ident) {
+            return Err(Error::DuplicateDefinition(
+                struct_decl.ident,
+                struct_decl.span,
+            ));
+        }
+
+        self.scope = Scope::default();
+        let params = self.declare_type_params(&struct_decl.generics)?;
+        self.scope = Scope::default();
+
+        self.structs.insert(
+            struct_decl.ident,
+            StructDef {
+                params,
+                fields: vec![],
+            },
+        );
+
+        Ok(())
+    }
+
+    /// Declare an enum and its variants. What the variants hold is only
+    /// known once every type is declared.
+    fn declare_enum(&mut self, enum_decl: &EnumDecl) -> JlyResult<()> {
+        if self.type_exists(enum_decl.ident) {
+            return Err(Error::DuplicateDefinition(enum_decl.ident, enum_decl.span));
+        }
+
+        let mut variants: Vec<(Intern<String>, Option<Type>)> = vec![];
+        for variant in &enum_decl.variants {
+            let declared = self.variants.contains_key(&variant.ident)
+                || variants.iter().any(|(ident, _)| *ident == variant.ident);
+            if declared {
+                return Err(Error::DuplicateDefinition(variant.ident, variant.span));
+            }
+            if variants.len() == 0x100 {
+                return Err(Error::TooManyVariants(variant.span));
+            }
+            variants.push((variant.ident, None));
+        }
+
+        self.scope = Scope::default();
+        let params = self.declare_type_params(&enum_decl.generics)?;
+        self.scope = Scope::default();
+
+        for (tag, (variant, _)) in variants.iter().enumerate() {
+            self.variants.insert(*variant, (enum_decl.ident, tag));
+        }
+        let layout = Rc::new(EnumLayout {
+            name: enum_decl.ident,
+            variants: variants.iter().map(|(variant, _)| *variant).collect(),
+        });
+        self.enums.insert(
+            enum_decl.ident,
+            EnumDef {
+                params,
+                variants,
+                layout,
+            },
+        );
+
+        Ok(())
+    }
+
+    /// The type of a function or method from its signature, with fresh
+    /// variables for anything not written out.
+    fn declare_sig(&mut self, sig: &FnSig, is_method: bool) -> JlyResult<Scheme> {
+        let params = self.declare_type_params(&sig.generics)?;
+
+        let takes_self = matches!(sig.params.first(), Some(param) if *param.ident == "self");
+        if is_method && !takes_self {
+            return Err(Error::MissingSelf(sig.span));
+        }
+
+        let mut param_tys = vec![];
+        for (i, param) in sig.params.iter().enumerate() {
+            let ty = match &param.ty {
+                Some(ty) => self.lower_type(ty)?,
+                None if is_method && i == 0 => self.scope.self_ty.clone().unwrap(),
+                None => self.subst.fresh_var(),
+            };
+            param_tys.push(ty);
+        }
+
+        let return_ty = match &sig.return_ty {
+            Some(ty) => self.lower_type(ty)?,
+            None => self.subst.fresh_var(),
+        };
+
+        Ok(Scheme {
+            params,
+            ty: Type::Function(param_tys, Box::new(return_ty)),
+        })
+    }
+
+    fn declare_fn(&mut self, fn_decl: &FnDecl) -> JlyResult<()> {
+        self.scope = Scope::default();
+        let scheme = self.declare_sig(&fn_decl.sig, false)?;
+
+        self.globals.push(Global {
+            scheme,
+            checked: false,
+            scope: std::mem::take(&mut self.scope),
+        });
+
+        Ok(())
+    }
+
+    fn declare_trait(&mut self, trait_decl: &TraitDecl) -> JlyResult<()> {
+        if self.trait_indices.contains_key(&trait_decl.ident) {
+            return Err(Error::DuplicateDefinition(
+                trait_decl.ident,
+                trait_decl.span,
+            ));
+        }
+
+        let index = self.traits.len();
+        if index == 0x100 {
+            self.errors.push(Error::TooManyTraits(trait_decl.span));
+        }
+
+        let self_param = self.subst.fresh_param(Intern::from_ref("Self"));
+        self.bounds.insert(self_param, vec![index]);
+
+        self.trait_indices.insert(trait_decl.ident, index);
+        self.traits.push(TraitDef {
+            ident: trait_decl.ident,
+            self_param,
+            methods: vec![],
+        });
+
+        Ok(())
+    }
+
+    /// Declare the methods of an impl block as globals, and check that a
+    /// trait impl matches its trait.
+    fn declare_impl(&mut self, impl_decl: &mut ImplDecl) -> JlyResult<()> {
+        self.scope = Scope::default();
+        let impl_params = self.declare_type_params(&impl_decl.generics)?;
+
+        let target = self.lower_type(&impl_decl.ty)?;
+        let head =
+            impl_head(&target, &impl_params).ok_or(Error::InvalidImplTarget(impl_decl.ty.span))?;
+
+        self.scope.self_ty = Some(target.clone());
+        let impl_scope = std::mem::take(&mut self.scope);
+
+        let mut methods = vec![];
+        for method in &impl_decl.methods {
+            self.scope = impl_scope.clone();
+
+            let mut scheme = self.declare_sig(&method.sig, true)?;
+            scheme.params.splice(0..0, impl_params.iter().copied());
+
+            methods.push((method.sig.ident, self.globals.len()));
+            self.globals.push(Global {
+                scheme,
+                checked: false,
+                scope: std::mem::take(&mut self.scope),
+            });
+        }
+
+        let (trait_, span) = match impl_decl.trait_ {
+            Some(trait_) => trait_,
+            None => {
+                for (ident, global) in methods {
+                    self.inherent_methods.insert((head, ident), global);
+                }
+                return Ok(());
+            }
+        };
+
+        let trait_index = self.lookup_trait(trait_, span)?;
+        if self.trait_impls.contains_key(&(trait_index, head)) {
+            let ident = Intern::new(format!("impl {} for {}", trait_, head));
+            return Err(Error::DuplicateDefinition(ident, impl_decl.span));
+        }
+
+        let trait_methods = self.traits[trait_index].methods.clone();
+        let self_param = self.traits[trait_index].self_param;
+
+        for (method, (ident, _)) in impl_decl.methods.iter().zip(&methods) {
+            if !trait_methods.iter().any(|(m, _)| m == ident) {
+                return Err(Error::NotInTrait {
+                    trait_,
+                    method: *ident,
+                    span: method.sig.span,
+                });
+            }
+        }
+
+        let mut globals = vec![];
+        for (ident, trait_scheme) in &trait_methods {
+            let i = match methods.iter().position(|(m, _)| m == ident) {
+                Some(i) => i,
+                None => {
+                    return Err(Error::MissingTraitMethod {
+                        trait_,
+                        method: *ident,
+                        span: impl_decl.span,
+                    })
+                }
+            };
+
+            let global = methods[i].1;
+            let sig_span = impl_decl.methods[i].sig.span;
+
+            let scheme = self.globals[global].scheme.clone();
+            let own_params = &scheme.params[impl_params.len()..];
+
+            if own_params.len() != trait_scheme.params.len() {
+                return Err(Error::WrongTypeArgCount {
+                    expected: trait_scheme.params.len(),
+                    found: own_params.len(),
+                    span: sig_span,
+                });
+            }
+
+            let mut mapping: HashMap<_, _> = trait_scheme
+                .params
+                .iter()
+                .copied()
+                .zip(own_params.iter().map(|param| Type::Param(*param)))
+                .collect();
+            mapping.insert(self_param, target.clone());
+
+            let expected = trait_scheme.ty.replace_params(&mapping);
+            self.unify(&expected, &scheme.ty, sig_span)?;
+
+            globals.push(global);
+        }
+
+        self.trait_impls.insert(
+            (trait_index, head),
+            ImplDef {
+                target,
+                methods: globals.clone(),
+                native: false,
+            },
+        );
+
+        impl_decl.resolved = Some(ImplResolved {
+            trait_index,
+            ty: head,
+            methods: globals,
+        });
+
+        Ok(())
+    }
+
+    /// The unsolved variables in the signatures of functions that haven't
+    /// been checked yet, which can't be generalised.
+    fn env_vars(&self) -> HashSet<TypeVar> {
+        let mut vars = vec![];
+        for global in self.globals.iter().filter(|global| !global.checked) {
+            self.subst.free_vars(&global.scheme.ty, &mut vars);
+        }
+        vars.into_iter().collect()
+    }
+
+    fn check_struct_literal(&mut self, literal: &mut StructLiteral, span: Span) -> JlyResult<Type> {
+        let (params, fields) = match self.structs.get(&literal.ident) {
+            Some(def) => (def.params.clone(), def.fields.clone()),
+            None => return Err(Error::UnknownType(literal.ident, span)),
+        };
+
+        let args: Vec<Type> = params.iter().map(|_| self.subst.fresh_var()).collect();
+        let mapping = params.into_iter().zip(args.iter().cloned()).collect();
+        self.constrain(&mapping, span);
+
+        let mut seen = vec![];
+        for init in &literal.fields {
+            if seen.contains(&init.ident) {
+                return Err(Error::DuplicateField(init.ident, init.span));
+            }
+            seen.push(init.ident);
+        }
+
+        for (field, _) in &fields {
+            if !seen.contains(field) {
+                return Err(Error::MissingField {
+                    ty: literal.ident,
+                    field: *field,
+                    span,
+                });
+            }
+        }
+
+        let ty = Type::Constructor(literal.ident, args);
+
+        for init in &mut literal.fields {
+            let field_ty = match fields.iter().find(|(field, _)| *field == init.ident) {
+                Some((_, field_ty)) => field_ty.replace_params(&mapping),
+                None => {
+                    return Err(Error::UnknownField {
+                        ty: self.subst.resolve(&ty),
+                        field: init.ident,
+                        span: init.span,
+                    })
+                }
+            };
+            self.expect_expr(&mut init.value, &field_ty)?;
+        }
+
+        literal.fields.sort_by_key(|init| {
+            fields
+                .iter()
+                .position(|(field, _)| *field == init.ident)
+                .unwrap()
+        });
+
+        Ok(ty)
+    }
+
+    fn check_field(&mut self, expr: &mut Expr, field: &mut Field, span: Span) -> JlyResult<Type> {
+        let ty = self.check_expr(expr)?;
+
+        let (name, args) = match self.subst.resolve(&ty) {
+            Type::Constructor(name, args) => (name, args),
+            Type::Unknown => return Ok(Type::Unknown),
+            Type::Var(_) => return Err(Error::TypeAnnotationNeeded(expr.span)),
+            ty => {
+                return Err(Error::UnknownField {
+                    ty,
+                    field: field.ident,
+                    span,
+                })
+            }
+        };
+
+        let def = match self.structs.get(&name) {
+            Some(def) => def,
+            None => {
+                return Err(Error::UnknownField {
+                    ty: Type::Constructor(name, args),
+                    field: field.ident,
+                    span,
+                })
+            }
+        };
+
+        let index = match def.fields.iter().position(|(f, _)| *f == field.ident) {
+            Some(index) => index,
+            None => {
+                return Err(Error::UnknownField {
+                    ty: Type::Constructor(name, args),
+                    field: field.ident,
+                    span,
+                })
+            }
+        };
+
+        let mapping = def.params.iter().copied().zip(args).collect();
+        field.index = Some(index);
+
+        Ok(def.fields[index].1.replace_params(&mapping))
+    }
+
+    /// Instantiate the parameters of an enum with fresh variables.
+    fn instantiate_enum(&mut self, name: Intern<String>) -> (Vec<Type>, HashMap<TypeParam, Type>) {
+        let params = self.enums[&name].params.clone();
+        let args: Vec<Type> = params.iter().map(|_| self.subst.fresh_var()).collect();
+        let mapping = params.into_iter().zip(args.iter().cloned()).collect();
+        (args, mapping)
+    }
+
+    fn check_variant(
+        &mut self,
+        variant: &mut Variant,
+        args: &mut [Expr],
+        span: Span,
+    ) -> JlyResult<Type> {
+        let (name, tag) = self.variants[&variant.ident];
+
+        let (type_args, mapping) = self.instantiate_enum(name);
+        let def = &self.enums[&name];
+        let payload = def.variants[tag].1.clone();
+        variant.resolved = Some(VariantResolved {
+            layout: def.layout.clone(),
+            tag,
+        });
+
+        let expected = payload.iter().count();
+        if args.len() != expected {
+            return Err(Error::WrongArgCount {
+                expected,
+                found: args.len(),
+                span,
+            });
+        }
+
+        if let (Some(payload), Some(arg)) = (payload, args.first_mut()) {
+            self.expect_expr(arg, &payload.replace_params(&mapping))?;
+        }
+
+        Ok(Type::Constructor(name, type_args))
+    }
+
+    /// Check `expr?`, which unwraps the first variant of an enum and
+    /// returns any other variant from the enclosing function.
+    fn check_try(&mut self, expr: &mut Expr, span: Span) -> JlyResult<Type> {
+        let ty = self.check_expr(expr)?;
+
+        let return_ty = match &self.return_ty {
+            Some(ty) => ty.clone(),
+            None => return Err(Error::TryOutsideFunction(span)),
+        };
+
+        // only the builtin enums have a variant to unwrap and the rest to
+        // return
+        let is_builtin = |name: &Intern<String>| BUILTIN_ENUMS.iter().any(|def| **name == def.name);
+        let (name, args) = match self.subst.resolve(&ty) {
+            Type::Constructor(name, args) if is_builtin(&name) => (name, args),
+            Type::Unknown => return Ok(Type::Unknown),
+            Type::Var(_) => return Err(Error::TypeAnnotationNeeded(expr.span)),
+            ty => return Err(Error::InvalidTry { ty, span }),
+        };
+
+        let def = &self.enums[&name];
+        let params = def.params.clone();
+        let unwrapped = match &def.variants[0].1 {
+            Some(Type::Param(param)) => *param,
+            _ => unreachable!("the first variant of an enum holds a value"),
+        };
+
+        // the function only has to agree on what the other variants hold
+        let returned = params
+            .iter()
+            .zip(&args)
+            .map(|(param, arg)| match *param == unwrapped {
+                true => self.subst.fresh_var(),
+                false => arg.clone(),
+            })
+            .collect();
+        self.unify(&return_ty, &Type::Constructor(name, returned), span)?;
+
+        let index = params.iter().position(|param| *param == unwrapped);
+        Ok(args[index.unwrap()].clone())
+    }
+
+    /// Find a method of a concrete type.
+    fn lookup_method(&self, head: Intern<String>, ident: Intern<String>) -> Option<MethodResolved> {
+        if let Some(global) = self.inherent_methods.get(&(head, ident)) {
+            return Some(MethodResolved::Static(*global));
+        }
+
+        self.traits
+            .iter()
+            .enumerate()
+            .find_map(|(trait_index, def)| {
+                let method = def.methods.iter().position(|(m, _)| *m == ident)?;
+                let impl_def = self.trait_impls.get(&(trait_index, head))?;
+                Some(impl_def.method(trait_index, method))
+            })
+    }
+
+    /// Find the method implementing a builtin trait for `ty`.
+    fn builtin_method(
+        &self,
+        ty: &Type,
+        trait_: BuiltinTrait,
+        span: Span,
+    ) -> JlyResult<MethodResolved> {
+        let trait_index = trait_ as usize;
+
+        let resolved = match ty {
+            Type::Param(param) => self
+                .bounds
+                .get(param)
+                .filter(|bounds| bounds.contains(&trait_index))
+                .map(|_| MethodResolved::Dynamic {
+                    trait_index,
+                    method: 0,
+                }),
+            ty => type_head(ty)
+                .and_then(|head| self.trait_impls.get(&(trait_index, head)))
+                .map(|impl_def| impl_def.method(trait_index, 0)),
+        };
+
+        resolved.ok_or(Error::UnsatisfiedBound {
+            ty: ty.clone(),
+            trait_: Intern::from_ref(trait_.name()),
+            span,
+        })
+    }
+
+    /// The type of a method, with `Self` replaced by the receiver's type.
+    fn method_type(&mut self, resolved: MethodResolved, receiver_ty: &Type, span: Span) -> Type {
+        match resolved {
+            MethodResolved::Static(global) => {
+                let scheme = self.globals[global].scheme.clone();
+                self.instantiate(&scheme, span)
+            }
+            MethodResolved::Dynamic {
+                trait_index,
+                method,
+            } => {
+                let def = &self.traits[trait_index];
+                let mapping = HashMap::from([(def.self_param, receiver_ty.clone())]);
+                let scheme = def.methods[method].1.clone();
+                self.instantiate(&scheme, span).replace_params(&mapping)
+            }
+        }
+    }
+
+    /// Check the receiver and arguments of a method against its type,
+    /// returning the type of the result.
+    fn apply_method(
+        &mut self,
+        fn_ty: Type,
+        receiver: (&Type, Span),
+        args: &[(Type, Span)],
+        span: Span,
+    ) -> JlyResult<Type> {
+        let (params, ret) = match fn_ty {
+            Type::Function(params, ret) => (params, ret),
+            _ => unreachable!("methods are functions"),
+        };
+
+        if params.len() != args.len() + 1 {
+            return Err(Error::WrongArgCount {
+                expected: params.len() - 1,
+                found: args.len(),
+                span,
+            });
+        }
+
+        self.unify(&params[0], receiver.0, receiver.1)?;
+        for (param, (arg_ty, arg_span)) in params[1..].iter().zip(args) {
+            self.unify(param, arg_ty, *arg_span)?;
+        }
+
+        Ok(*ret)
+    }
+
+    fn check_method_call(
+        &mut self,
+        receiver: &mut Expr,
+        method: &mut Method,
+        args: &mut [Expr],
+        span: Span,
+    ) -> JlyResult<Type> {
+        let receiver_ty = self.check_expr(receiver)?;
+
+        let mut arg_tys = vec![];
+        for arg in args.iter_mut() {
+            arg_tys.push((self.check_expr(arg)?, arg.span));
+        }
+
+        let unknown_method = |ty| Error::UnknownMethod {
+            ty,
+            method: method.ident,
+            span: method.span,
+        };
+
+        let ty = self.subst.resolve(&receiver_ty);
+        let resolved = match &ty {
+            Type::Unknown => return Ok(Type::Unknown),
+            Type::Var(_) => return Err(Error::TypeAnnotationNeeded(receiver.span)),
+
+            Type::Param(param) => self.bounds.get(param).and_then(|bounds| {
+                bounds.iter().find_map(|&trait_index| {
+                    self.traits[trait_index]
+                        .methods
+                        .iter()
+                        .position(|(m, _)| *m == method.ident)
+                        .map(|method| MethodResolved::Dynamic {
+                            trait_index,
+                            method,
+                        })
+                })
+            }),
+
+            ty => type_head(ty).and_then(|head| self.lookup_method(head, method.ident)),
+        };
+
+        let resolved = resolved.ok_or_else(|| unknown_method(ty.clone()))?;
+        method.resolved = Some(resolved);
+
+        let fn_ty = self.method_type(resolved, &ty, method.span);
+        self.apply_method(fn_ty, (&receiver_ty, receiver.span), &arg_tys, span)
+    }
+
+    /// Check the left operand of a builtin `==`, which works on any
+    /// builtin type except functions.
+    /// The type of an operand of builtin arithmetic, which is an `Int`
+    /// unless it's known to be a `BigInt`.
+    fn numeric_type(&mut self, operand: &Expr) -> JlyResult<Type> {
+        let ty = operand.ty.clone().unwrap();
+        match self.subst.resolve(&ty) == BIGINT {
+            true => Ok(BIGINT),
+            false => self.unify(&INT, &ty, operand.span),
+        }
+    }
+
+    fn check_builtin_eq(&mut self, lhs: &Expr) -> JlyResult<Type> {
+        let ty = self.subst.resolve(lhs.ty.as_ref().unwrap());
+        match ty {
+            Type::Var(_) => self.unify(&INT, &ty, lhs.span),
+            Type::Function(..) => Err(Error::UnsatisfiedBound {
+                ty,
+                trait_: Intern::from_ref(BuiltinTrait::Eq.name()),
+                span: lhs.span,
+            }),
+            ty => Ok(ty),
+        }
+    }
+
+    /// Check an operator whose left operand is a user type or type
+    /// parameter, returning the trait method that implements it. Other
+    /// operands are left for the caller to check as builtin types.
+    fn check_overloaded(
+        &mut self,
+        lhs: &mut Expr,
+        rhs: Option<&mut Expr>,
+        trait_: BuiltinTrait,
+        span: Span,
+    ) -> JlyResult<Option<(Method, Type)>> {
+        let lhs_ty = self.check_expr(lhs)?;
+
+        let ty = self.subst.resolve(&lhs_ty);
+        let resolved = match ty {
+            Type::Param(_) | Type::Constructor(..) => self.builtin_method(&ty, trait_, span)?,
+
+            // newtypes without an impl fall back to their base type's
+            // builtin operator, if it typechecks
+            Type::Newtype(_) => match self.builtin_method(&ty, trait_, span) {
+                Ok(resolved) => resolved,
+                Err(_) => return Ok(None),
+            },
+
+            _ => return Ok(None),
+        };
+
+        let fn_ty = self.method_type(resolved, &ty, span);
+
+        let mut args = vec![];
+        if let Some(rhs) = rhs {
+            args.push((self.check_expr(rhs)?, rhs.span));
+        }
+
+        let ty = self.apply_method(fn_ty, (&lhs_ty, lhs.span), &args, span)?;
+
+        let method = Method {
+            ident: Intern::from_ref(trait_.method()),
+            span,
+            resolved: Some(resolved),
+        };
+
+        Ok(Some((method, ty)))
+    }
+
+    /// Check `expr as ty`, which either wraps a value in a newtype or
+    /// unwraps one.
+    fn check_cast(&mut self, expr: &mut Expr, ty: &TypeExpr, span: Span) -> JlyResult<Type> {
+        let from = self.check_expr(expr)?;
+        let to = self.lower_type(ty)?;
+
+        let from = self.subst.resolve(&from);
+
+        // the error has already been reported
+        if from == Type::Unknown || to == Type::Unknown {
+            return Ok(to);
+        }
+
+        // a cast wraps or unwraps a single newtype, so a newtype of a
+        // newtype takes two casts to get to the bottom
+        let wraps = match &to {
+            Type::Newtype(name) if from != to => {
+                let base = self.newtypes[name].clone();
+                self.subst.unify_types(&base, &from).is_ok()
+            }
+            _ => false,
+        };
+        let unwraps = !wraps
+            && match &from {
+                Type::Newtype(name) => {
+                    let base = self.newtypes[name].clone();
+                    self.subst.unify_types(&base, &to).is_ok()
+                }
+                _ => false,
+            };
+
+        match wraps || unwraps {
+            true => Ok(to),
+            false => Err(Error::InvalidCast {
+                from: self.subst.resolve(&from),
+                to,
+                span,
+            }),
+        }
+    }
+
+    fn check_call(&mut self, callee: &mut Expr, args: &mut [Expr], span: Span) -> JlyResult<Type> {
+        let callee_ty = self.check_expr(callee)?;
+
+        let mut arg_tys = vec![];
+        for arg in args.iter_mut() {
+            arg_tys.push(self.check_expr(arg)?);
+        }
+
+        match self.subst.resolve(&callee_ty) {
+            Type::Function(params, ret) => {
+                if params.len() != args.len() {
+                    return Err(Error::WrongArgCount {
+                        expected: params.len(),
+                        found: args.len(),
+                        span,
+                    });
+                }
+
+                for ((param, arg_ty), arg) in params.iter().zip(&arg_tys).zip(args) {
+                    self.unify(param, arg_ty, arg.span)?;
+                }
+
+                Ok(*ret)
+            }
+
+            Type::Unknown => Ok(Type::Unknown),
+
+            other => {
+                let ret = self.subst.fresh_var();
+                let fn_ty = Type::Function(arg_tys, Box::new(ret.clone()));
+                self.unify(&fn_ty, &other, callee.span)?;
+                Ok(ret)
+            }
+        }
+    }
+}
+
+impl Visitor for TypeChecker {
+    fn visit_module(&mut self, module: &mut Module) -> JlyResult<()> {
+        // the items that couldn't be declared, which aren't checked further
+        let mut failed = HashSet::new();
+
+        for (i, statement) in module.statements.iter().enumerate() {
+            if let Statement::TraitDecl(trait_decl) = statement {
+                let result = self.declare_trait(trait_decl);
+                if self.recover(result) {
+                    failed.insert(i);
+                }
+            }
+        }
+
+        for (i, statement) in module.statements.iter().enumerate() {
+            let result = match statement {
+                Statement::StructDecl(struct_decl) => self.declare_struct(struct_decl),
+                Statement::EnumDecl(enum_decl) => self.declare_enum(enum_decl),
+                Statement::NewtypeDecl(newtype_decl) => {
+                    if self.type_exists(newtype_decl.ident) {
+                        Err(Error::DuplicateDefinition(
+                            newtype_decl.ident,
+                            newtype_decl.span,
+                        ))
+                    } else {
+                        self.newtypes.insert(newtype_decl.ident, Type::Unknown);
+                        Ok(())
+                    }
+                }
+                _ => continue,
+            };
+            if self.recover(result) {
+                failed.insert(i);
+            }
+        }
+
+        for (i, statement) in module.statements.iter_mut().enumerate() {
+            let result = match statement {
+                _ if failed.contains(&i) => continue,
+                Statement::StructDecl(struct_decl) => self.visit_struct_decl(struct_decl),
+                Statement::EnumDecl(enum_decl) => self.visit_enum_decl(enum_decl),
+                Statement::NewtypeDecl(newtype_decl) => self.visit_newtype_decl(newtype_decl),
+                _ => continue,
+            };
+            self.recover(result);
+        }
+
+        for (i, statement) in module.statements.iter_mut().enumerate() {
+            if let Statement::TraitDecl(trait_decl) = statement {
+                if !failed.contains(&i) {
+                    let result = self.visit_trait_decl(trait_decl);
+                    self.recover(result);
+                }
+            }
+        }
+
+        // globals are declared in the same order as the resolver did, and
+        // ones that can't be are filled in so the rest keep their numbers
+        for (i, statement) in module.statements.iter_mut().enumerate() {
+            let (result, end) = match statement {
+                Statement::FnDecl(fn_decl) => {
+                    let end = self.globals.len() + 1;
+                    (self.declare_fn(fn_decl), end)
+                }
+                Statement::ImplDecl(impl_decl) => {
+                    let end = self.globals.len() + impl_decl.methods.len();
+                    (self.declare_impl(impl_decl), end)
+                }
+                _ => continue,
+            };
+            if self.recover(result) && self.globals.len() < end {
+                self.globals.resize_with(end, Global::unknown);
+                failed.insert(i);
+            }
+        }
+
+        for (i, statement) in module.statements.iter_mut().enumerate() {
+            let result = match statement {
+                _ if failed.contains(&i) => continue,
+                Statement::FnDecl(fn_decl) => self.visit_fn_decl(fn_decl),
+                Statement::ImplDecl(impl_decl) => self.visit_impl_decl(impl_decl),
+                _ => continue,
+            };
+            self.recover(result);
+        }
+
+        for statement in &mut module.statements {
+            match statement {
+                Statement::FnDecl(_)
+                | Statement::StructDecl(_)
+                | Statement::EnumDecl(_)
+                | Statement::NewtypeDecl(_)
+                | Statement::TraitDecl(_)
+                | Statement::ImplDecl(_) => {}
+                statement => self.check_statement(statement),
+            }
+        }
+
+        self.check_constraints(&HashSet::new());
+
+        Ok(())
+    }
+
+    fn visit_block(&mut self, block: &mut Block) -> JlyResult<()> {
+        let num_locals = self.locals.len();
+
+        for statement in &mut block.statements {
+            self.check_statement(statement);
+        }
+
+        self.locals.truncate(num_locals);
+
+        Ok(())
+    }
+
+    fn visit_expr(&mut self, expr: &mut Expr) -> JlyResult<()> {
+        macro_rules! operands {
+            ($ty:expr, $($operand:ident),+) => {{
+                $(self.expect_expr($operand, &$ty)?;)+
+            }};
+        }
+
+        let span = expr.span;
+        let op_trait = operator_trait(&expr.kind);
+        let mut overloaded = None;
+
+        // checks an operator that might be overloaded, falling back to
+        // checking it as a builtin operator
+        macro_rules! operator {
+            ($a:ident, $b:expr, $builtin:expr) => {
+                match self.check_overloaded($a, $b, op_trait.unwrap(), span)? {
+                    Some((method, ty)) => {
+                        overloaded = Some(method);
+                        ty
+                    }
+                    None => $builtin,
+                }
+            };
+        }
+
+        let ty = match &mut expr.kind {
+            ExprKind::Var(var) => self.var_type(var, span),
+            ExprKind::Value(value) => Type::Builtin(match value {
+                Value::String(_) => BuiltinType::String,
+                Value::Integer(_) => BuiltinType::Integer,
+                Value::Float(_) => BuiltinType::Float,
+                Value::Bool(_) => BuiltinType::Bool,
+                Value::Unit => BuiltinType::Unit,
+                Value::Object(obj) => match &**obj {
+                    Object::BigInt(_) => BuiltinType::BigInt,
+                    _ => unreachable!("only big integers are literal objects"),
+                },
+            }),
+
+            ExprKind::LogicalOr(a, b) | ExprKind::LogicalAnd(a, b) => {
+                operands!(BOOL, a, b);
+                BOOL
+            }
+            ExprKind::LogicalNot(a) => {
+                operands!(BOOL, a);
+                BOOL
+            }
+
+            ExprKind::Equal(a, b) | ExprKind::NotEqual(a, b) => {
+                operator!(a, Some(b), {
+                    let a_ty = self.check_builtin_eq(a)?;
+                    self.expect_expr(b, &a_ty)?;
+                    BOOL
+                })
+            }
+
+            ExprKind::LT(a, b)
+            | ExprKind::GT(a, b)
+            | ExprKind::LTEqual(a, b)
+            | ExprKind::GTEqual(a, b) => {
+                // `cmp` gives an `Int` which is then compared with zero
+                operator!(a, Some(b), {
+                    let ty = self.numeric_type(a)?;
+                    operands!(ty, b);
+                    BOOL
+                });
+                BOOL
+            }
+
+            ExprKind::Add(a, b) | ExprKind::Sub(a, b) | ExprKind::Mul(a, b) => {
+                operator!(a, Some(b), {
+                    let ty = self.numeric_type(a)?;
+                    operands!(ty, b);
+                    ty
+                })
+            }
+
+            ExprKind::Div(a, b) | ExprKind::Mod(a, b) => {
+                self.check_expr(a)?;
+                let ty = self.numeric_type(a)?;
+                operands!(ty, b);
+                ty
+            }
+            ExprKind::Pow(a, b) => {
+                self.check_expr(a)?;
+                let ty = self.numeric_type(a)?;
+                operands!(INT, b);
+                ty
+            }
+            ExprKind::Neg(a) => {
+                self.check_expr(a)?;
+                self.numeric_type(a)?
+            }
+
+            ExprKind::BitAnd(a, b)
+            | ExprKind::BitOr(a, b)
+            | ExprKind::BitXor(a, b)
+            | ExprKind::Shl(a, b)
+            | ExprKind::Shr(a, b) => {
+                operands!(INT, a, b);
+                INT
+            }
+            ExprKind::BitNot(a) => {
+                operands!(INT, a);
+                INT
+            }
+
+            ExprKind::Assignment(lhs, rhs) => {
+                let var_ty = self.var_type(lhs, span);
+                self.expect_expr(rhs, &var_ty)?
+            }
+
+            ExprKind::Call(callee, args) => self.check_call(callee, args, span)?,
+            ExprKind::MethodCall(receiver, method, args) => {
+                self.check_method_call(receiver, method, args, span)?
+            }
+            ExprKind::Field(expr, field) => self.check_field(expr, field, span)?,
+            ExprKind::StructLiteral(literal) => self.check_struct_literal(literal, span)?,
+            ExprKind::Cast(expr, ty) => self.check_cast(expr, ty, span)?,
+            ExprKind::Variant(variant, args) => self.check_variant(variant, args, span)?,
+            ExprKind::Try(expr) => self.check_try(expr, span)?,
+
+            ExprKind::DebugPrint(a) => {
+                let ty = self.check_expr(a)?;
+                let ty = self.subst.resolve(&ty);
+
+                // values without a `Display` impl are printed as they are.
+                // the impl is picked here, from the static type, so it's only
+                // used for the value itself, and the values inside it, like
+                // the `x` in `Some(x)`, are always printed as they are
+                if matches!(
+                    ty,
+                    Type::Param(_) | Type::Constructor(..) | Type::Newtype(_)
+                ) {
+                    if let Ok(resolved) = self.builtin_method(&ty, BuiltinTrait::Display, span) {
+                        overloaded = Some(Method {
+                            ident: Intern::from_ref(BuiltinTrait::Display.method()),
+                            span,
+                            resolved: Some(resolved),
+                        });
+                    }
+                }
+                UNIT
+            }
+
+            // a panic never produces a value, so it fits anywhere
+            ExprKind::Panic(message) => {
+                self.expect_expr(message, &STRING)?;
+                self.subst.fresh_var()
+            }
+
+            ExprKind::DummyExpr => Type::Unknown,
+        };
+
+        expr.ty = Some(ty);
+
+        if let Some(method) = overloaded {
+            let kind = std::mem::replace(&mut expr.kind, ExprKind::DummyExpr);
+            expr.kind = desugar_operator(kind, method, span);
+        }
+
+        Ok(())
+    }
+
+    fn visit_var(&mut self, _var: &mut Var) -> JlyResult<()> {
+        Ok(())
+    }
+
+    fn visit_var_decl(&mut self, var_decl: &mut VarDecl) -> JlyResult<()> {
+        let mut ty = self.check_expr(&mut var_decl.value)?;
+
+        if let Some(annotation) = &var_decl.ty {
+            let annotation = self.lower_type(annotation)?;
+            ty = self.unify(&annotation, &ty, var_decl.value.span)?;
+        }
+
+        self.declarations.push((var_decl.ident_span, ty.clone()));
+        self.locals.push(ty);
+
+        Ok(())
+    }
+
+    fn visit_fn_decl(&mut self, fn_decl: &mut FnDecl) -> JlyResult<()> {
+        let n = match fn_decl.resolved {
+            Some(VarResolved::Global(n)) => n,
+            _ => unreachable!("functions are always global"),
+        };
+
+        let scheme = self.globals[n].scheme.clone();
+        let (params, return_ty) = match &scheme.ty {
+            Type::Function(params, return_ty) => (params.clone(), (**return_ty).clone()),
+            _ => unreachable!(),
+        };
+
+        for (param, ty) in fn_decl.sig.params.iter().zip(&params) {
+            self.declarations.push((param.span, ty.clone()));
+        }
+
+        // functions can't see the locals of the top level
+        let outer_locals = std::mem::replace(&mut self.locals, params);
+        self.scope = self.globals[n].scope.clone();
+        self.return_ty = Some(return_ty.clone());
+
+        self.visit_block(&mut fn_decl.body)?;
+
+        if !block_returns(&fn_decl.body) && self.subst.unify_types(&return_ty, &UNIT).is_err() {
+            self.errors.push(Error::MissingReturn {
+                expected: self.subst.resolve(&return_ty),
+                span: fn_decl.sig.span,
+            });
+        }
+
+        self.return_ty = None;
+        self.locals = outer_locals;
+        self.scope = Scope::default();
+
+        self.globals[n].checked = true;
+        let env = self.env_vars();
+        self.check_constraints(&env);
+        self.globals[n].scheme = self.subst.generalise(&scheme, &env);
+
+        let ty = self.globals[n].scheme.ty.clone();
+        self.declarations.push((fn_decl.sig.ident_span(), ty));
+
+        Ok(())
+    }
+
+    fn visit_struct_decl(&mut self, struct_decl: &mut StructDecl) -> JlyResult<()> {
+        self.scope.type_params = struct_decl
+            .generics
+            .iter()
+            .map(|generic| generic.ident)
+            .zip(self.structs[&struct_decl.ident].params.iter().copied())
+            .collect();
+
+        let mut fields: Vec<(Intern<String>, Type)> = vec![];
+        for field in &struct_decl.fields {
+            if fields.iter().any(|(ident, _)| *ident == field.ident) {
+                return Err(Error::DuplicateField(field.ident, field.span));
+            }
+            if fields.len() == 0x100 {
+                return Err(Error::TooManyFields(field.span));
+            }
+            fields.push((field.ident, self.lower_type(&field.ty)?));
+        }
+
+        self.structs.get_mut(&struct_decl.ident).unwrap().fields = fields;
+        self.scope = Scope::default();
+
+        Ok(())
+    }
+
+    fn visit_enum_decl(&mut self, enum_decl: &mut EnumDecl) -> JlyResult<()> {
+        self.scope.type_params = enum_decl
+            .generics
+            .iter()
+            .map(|generic| generic.ident)
+            .zip(self.enums[&enum_decl.ident].params.iter().copied())
+            .collect();
+
+        let payloads = enum_decl
+            .variants
+            .iter()
+            .map(|variant| match &variant.payload {
+                Some(payload) => self.lower_type(payload).map(Some),
+                None => Ok(None),
+            })
+            .collect::<JlyResult<Vec<_>>>()?;
+
+        let def = self.enums.get_mut(&enum_decl.ident).unwrap();
+        for ((_, payload), ty) in def.variants.iter_mut().zip(payloads) {
+            *payload = ty;
+        }
+        self.scope = Scope::default();
+
+        Ok(())
+    }
+
+    fn visit_newtype_decl(&mut self, newtype_decl: &mut NewtypeDecl) -> JlyResult<()> {
+        let base = self.lower_type(&newtype_decl.base)?;
+        self.newtypes.insert(newtype_decl.ident, base);
+        Ok(())
+    }
+
+    fn visit_trait_decl(&mut self, trait_decl: &mut TraitDecl) -> JlyResult<()> {
+        let index = self.trait_indices[&trait_decl.ident];
+        let self_param = self.traits[index].self_param;
+
+        let mut methods: Vec<(Intern<String>, Scheme)> = vec![];
+        for sig in &trait_decl.methods {
+            if methods.iter().any(|(ident, _)| *ident == sig.ident) {
+                return Err(Error::DuplicateDefinition(sig.ident, sig.span));
+            }
+
+            self.scope = Scope {
+                type_params: HashMap::new(),
+                self_ty: Some(Type::Param(self_param)),
+            };
+            let scheme = self.declare_sig(sig, true)?;
+
+            // there's no body to infer anything from
+            if let (Type::Function(_, ret), None) = (&scheme.ty, &sig.return_ty) {
+                self.unify(ret, &UNIT, sig.span)?;
+            }
+            let mut vars = vec![];
+            self.subst.free_vars(&scheme.ty, &mut vars);
+            if !vars.is_empty() {
+                return Err(Error::TypeAnnotationNeeded(sig.span));
+            }
+
+            let ty = self.subst.resolve(&scheme.ty);
+            methods.push((sig.ident, Scheme { ty, ..scheme }));
+        }
+
+        self.scope = Scope::default();
+        self.traits[index].methods = methods;
+
+        Ok(())
+    }
+
+    fn visit_impl_decl(&mut self, impl_decl: &mut ImplDecl) -> JlyResult<()> {
+        for method in &mut impl_decl.methods {
+            let result = self.visit_fn_decl(method);
+            self.recover(result);
+        }
+        Ok(())
+    }
+
+    fn visit_if_statement(&mut self, if_statement: &mut IfStatement) -> JlyResult<()> {
+        self.expect_expr(&mut if_statement.condition, &BOOL)?;
+        self.visit_block(&mut if_statement.then)?;
+        if let Some(else_) = &mut if_statement.else_ {
+            self.visit_statement(else_)?;
+        }
+        Ok(())
+    }
+
+    fn visit_while_loop(&mut self, while_loop: &mut WhileLoop) -> JlyResult<()> {
+        self.expect_expr(&mut while_loop.condition, &BOOL)?;
+        self.visit_block(&mut while_loop.body)
+    }
+
+    fn visit_match(&mut self, match_statement: &mut MatchStatement) -> JlyResult<()> {
+        let ty = self.check_expr(&mut match_statement.scrutinee)?;
+        let span = match_statement.scrutinee.span;
+
+        let (name, args) = match self.subst.resolve(&ty) {
+            Type::Constructor(name, args) if self.enums.contains_key(&name) => (name, args),
+            Type::Unknown => return Ok(()),
+
+            // the patterns say which enum it must be
+            Type::Var(_) => {
+                let name = match_statement
+                    .arms
+                    .iter()
+                    .find_map(|arm| match &arm.pattern.kind {
+                        PatternKind::Variant { ident, .. } => self.variants.get(ident),
+                        PatternKind::Wildcard => None,
+                    })
+                    .map(|(name, _)| *name)
+                    .ok_or(Error::TypeAnnotationNeeded(span))?;
+
+                let (args, _) = self.instantiate_enum(name);
+                self.unify(&Type::Constructor(name, args.clone()), &ty, span)?;
+                (name, args)
+            }
+
+            ty => return Err(Error::CannotMatch { ty, span }),
+        };
+
+        let def = &self.enums[&name];
+        let mapping: HashMap<_, _> = def.params.iter().copied().zip(args.clone()).collect();
+        let variants = def.variants.clone();
+
+        let mut covered = vec![false; variants.len()];
+        self.locals.push(ty);
+
+        for arm in &mut match_statement.arms {
+            let num_locals = self.locals.len();
+
+            if let PatternKind::Variant {
+                ident,
+                binding,
+                tag: resolved,
+            } = &mut arm.pattern.kind
+            {
+                let tag = match variants.iter().position(|(variant, _)| variant == ident) {
+                    Some(tag) => tag,
+                    None => {
+                        return Err(Error::UnknownVariant {
+                            ty: Type::Constructor(name, args),
+                            variant: *ident,
+                            span: arm.pattern.span,
+                        })
+                    }
+                };
+                covered[tag] = true;
+                *resolved = Some(tag);
+
+                match (binding, &variants[tag].1) {
+                    (Some((_, span)), Some(payload)) => {
+                        let ty = payload.replace_params(&mapping);
+                        self.declarations.push((*span, ty.clone()));
+                        self.locals.push(ty);
+                    }
+                    (Some(_), None) => {
+                        return Err(Error::WrongArgCount {
+                            expected: 0,
+                            found: 1,
+                            span: arm.pattern.span,
+                        })
+                    }
+                    (None, _) => {}
+                }
+            } else {
+                covered.fill(true);
+            }
+
+            self.visit_block(&mut arm.body)?;
+            self.locals.truncate(num_locals);
+        }
+
+        self.locals.pop();
+
+        match variants
+            .iter()
+            .zip(&covered)
+            .find(|(_, covered)| !**covered)
+        {
+            Some(((missing, _), _)) => Err(Error::NonExhaustiveMatch {
+                missing: *missing,
+                span: match_statement.span,
+            }),
+            None => Ok(()),
+        }
+    }
+
+    fn visit_try_statement(&mut self, try_statement: &mut TryStatement) -> JlyResult<()> {
+        self.visit_block(&mut try_statement.body)?;
+
+        let num_locals = self.locals.len();
+        let ty = Type::Constructor(Intern::from_ref(PANIC_STRUCT), vec![]);
+        self.declarations
+            .push((try_statement.binding_span(), ty.clone()));
+        self.locals.push(ty);
+        self.visit_block(&mut try_statement.recover)?;
+        self.locals.truncate(num_locals);
+
+        Ok(())
+    }
+
+    fn visit_return(&mut self, return_statement: &mut ReturnStatement) -> JlyResult<()> {
+        let return_ty = match &self.return_ty {
+            Some(ty) => ty.clone(),
+            None => return Err(Error::ReturnOutsideFunction(return_statement.span)),
+        };
+
+        match &mut return_statement.value {
+            Some(value) => {
+                self.expect_expr(value, &return_ty)?;
+            }
+            None => {
+                self.unify(&return_ty, &UNIT, return_statement.span)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Whether a block always returns before reaching its end.
+pub(crate) fn block_returns(block: &Block) -> bool {
+    block.statements.iter().any(statement_returns)
+}
+
+pub(crate) fn statement_returns(statement: &Statement) -> bool {
+    match statement {
+        Statement::Return(_) => true,
+        Statement::Block(block) => block_returns(block),
+        Statement::If(if_statement) => match &if_statement.else_ {
+            Some(else_) => block_returns(&if_statement.then) && statement_returns(else_),
+            None => false,
+        },
+        Statement::Try(try_statement) => {
+            block_returns(&try_statement.body) && block_returns(&try_statement.recover)
+        }
+        Statement::Match(match_statement) => {
+            !match_statement.arms.is_empty()
+                && match_statement
+                    .arms
+                    .iter()
+                    .all(|arm| block_returns(&arm.body))
+        }
+
+        // there's no `break`, so nothing gets past a loop that never stops
+        Statement::While(while_loop) => matches!(
+            constant_value(&while_loop.condition),
+            Some(Value::Bool(true))
+        ),
+        _ => false,
+    }
+}
+
+/// The name of a type as far as method lookup is concerned.
+fn type_head(ty: &Type) -> Option<Intern<String>> {
+    match ty {
+        Type::Builtin(builtin) => Some(Intern::new(builtin.to_string())),
+        Type::Constructor(name, _) | Type::Newtype(name) => Some(*name),
+        _ => None,
+    }
+}
+
+/// The name of the type an impl block is for. Generic structs must be
+/// implemented for distinct parameters, so each impl covers every
+/// instantiation and lookup only needs the name.
+fn impl_head(target: &Type, impl_params: &[TypeParam]) -> Option<Intern<String>> {
+    if let Type::Constructor(_, args) = target {
+        let mut seen = vec![];
+        for arg in args {
+            match arg {
+                Type::Param(param) if impl_params.contains(param) && !seen.contains(param) => {
+                    seen.push(*param)
+                }
+                _ => return None,
+            }
+        }
+    }
+
+    type_head(target)
+}
+
+/// The builtin trait that overloads an operator, if any.
+fn operator_trait(kind: &ExprKind) -> Option<BuiltinTrait> {
+    Some(match kind {
+        ExprKind::Add(..) => BuiltinTrait::Add,
+        ExprKind::Sub(..) => BuiltinTrait::Sub,
+        ExprKind::Mul(..) => BuiltinTrait::Mul,
+        ExprKind::Equal(..) | ExprKind::NotEqual(..) => BuiltinTrait::Eq,
+        ExprKind::LT(..) | ExprKind::GT(..) | ExprKind::LTEqual(..) | ExprKind::GTEqual(..) => {
+            BuiltinTrait::Ord
+        }
+        _ => return None,
+    })
+}
+
+/// Rewrite an overloaded operator as a call to the trait method that
+/// implements it. Comparisons compare the result of `cmp` with zero.
+fn desugar_operator(kind: ExprKind, method: Method, span: Span) -> ExprKind {
+    let call = |receiver, args| ExprKind::MethodCall(receiver, method, args);
+    let boxed = |kind, ty| {
+        Box::new(Expr {
+            kind,
+            span,
+            ty: Some(ty),
+        })
+    };
+    let zero = boxed(ExprKind::Value(Value::Integer(0)), INT);
+
+    match kind {
+        ExprKind::Add(a, b) | ExprKind::Sub(a, b) | ExprKind::Mul(a, b) | ExprKind::Equal(a, b) => {
+            call(a, vec![*b])
+        }
+        ExprKind::NotEqual(a, b) => ExprKind::LogicalNot(boxed(call(a, vec![*b]), BOOL)),
+        ExprKind::LT(a, b) => ExprKind::LT(boxed(call(a, vec![*b]), INT), zero),
+        ExprKind::GT(a, b) => ExprKind::GT(boxed(call(a, vec![*b]), INT), zero),
+        ExprKind::LTEqual(a, b) => ExprKind::LTEqual(boxed(call(a, vec![*b]), INT), zero),
+        ExprKind::GTEqual(a, b) => ExprKind::GTEqual(boxed(call(a, vec![*b]), INT), zero),
+        ExprKind::DebugPrint(a) => ExprKind::DebugPrint(boxed(call(a, vec![]), STRING)),
+        _ => unreachable!("not an overloadable operator"),
+    }
+}
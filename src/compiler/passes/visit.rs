@@ -20,9 +20,18 @@ pub trait Visitor {
         match statement {
             Statement::Expr(expr) => self.visit_expr(expr)?,
             Statement::VarDecl(var_decl) => self.visit_var_decl(var_decl)?,
+            Statement::FnDecl(fn_decl) => self.visit_fn_decl(fn_decl)?,
+            Statement::StructDecl(struct_decl) => self.visit_struct_decl(struct_decl)?,
+            Statement::EnumDecl(enum_decl) => self.visit_enum_decl(enum_decl)?,
+            Statement::NewtypeDecl(newtype_decl) => self.visit_newtype_decl(newtype_decl)?,
+            Statement::TraitDecl(trait_decl) => self.visit_trait_decl(trait_decl)?,
+            Statement::ImplDecl(impl_decl) => self.visit_impl_decl(impl_decl)?,
             Statement::Block(block) => self.visit_block(block)?,
             Statement::If(if_statement) => self.visit_if_statement(if_statement)?,
             Statement::While(while_loop) => self.visit_while_loop(while_loop)?,
+            Statement::Match(match_statement) => self.visit_match(match_statement)?,
+            Statement::Try(try_statement) => self.visit_try_statement(try_statement)?,
+            Statement::Return(return_statement) => self.visit_return(return_statement)?,
         }
         Ok(())
     }
@@ -33,7 +42,21 @@ pub trait Visitor {
 
     fn visit_var_decl(&mut self, var_decl: &mut VarDecl) -> JlyResult<()>;
 
+    fn visit_fn_decl(&mut self, fn_decl: &mut FnDecl) -> JlyResult<()>;
+
+    fn visit_struct_decl(&mut self, struct_decl: &mut StructDecl) -> JlyResult<()>;
+    fn visit_enum_decl(&mut self, enum_decl: &mut EnumDecl) -> JlyResult<()>;
+    fn visit_newtype_decl(&mut self, newtype_decl: &mut NewtypeDecl) -> JlyResult<()>;
+
+    fn visit_trait_decl(&mut self, trait_decl: &mut TraitDecl) -> JlyResult<()>;
+
+    fn visit_impl_decl(&mut self, impl_decl: &mut ImplDecl) -> JlyResult<()>;
+
     fn visit_if_statement(&mut self, if_statement: &mut IfStatement) -> JlyResult<()>;
 
     fn visit_while_loop(&mut self, while_loop: &mut WhileLoop) -> JlyResult<()>;
+
+    fn visit_match(&mut self, match_statement: &mut MatchStatement) -> JlyResult<()>;
+    fn visit_try_statement(&mut self, try_statement: &mut TryStatement) -> JlyResult<()>;
+    fn visit_return(&mut self, return_statement: &mut ReturnStatement) -> JlyResult<()>;
 }
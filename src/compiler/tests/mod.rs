@@ -0,0 +1,1281 @@
+use std::rc::Rc;
+
+use strum::EnumCount;
+
+use crate::compiler::diagnostic::{Emitter, ErrorReport, Severity, EXPLANATIONS};
+use crate::json::Json;
+use crate::runtime::chunk::{Chunk, Instr, Opcode};
+use crate::runtime::value::{Object, Value};
+use crate::runtime::verify::verify;
+use crate::source::expand_tabs;
+use crate::{
+    format_source, ColorChoice, CompiledProgram, ErrorFormat, ErrorReporter, LanguageServer, Lint,
+    LintLevel, LoadError, PassOptions, Position, Repl, ShortEmitter, Source, VM,
+};
+
+fn compiles(source: &str) -> bool {
+    let source = Source::new("test".to_string(), source.to_string());
+    let mut diagnostics = ErrorReporter::default();
+    CompiledProgram::compile(&source, &mut diagnostics).is_ok()
+}
+
+fn chunk(source: &str, options: PassOptions) -> Chunk {
+    let source = Source::new("test".to_string(), source.to_string());
+    let mut diagnostics = ErrorReporter::default();
+    match CompiledProgram::compile_with(&source, &mut diagnostics, options) {
+        Ok(program) => program.chunk,
+        Err(_) => panic!("failed to compile"),
+    }
+}
+
+/// A VM that throws away what scripts print, so it doesn't end up in the
+/// output of the tests.
+fn quiet_vm() -> VM {
+    let mut vm = VM::new();
+    vm.set_output(std::io::sink());
+    vm
+}
+
+fn runs(source: &str) -> bool {
+    let source = Source::new("test".to_string(), source.to_string());
+    let mut diagnostics = ErrorReporter::default();
+    match CompiledProgram::compile(&source, &mut diagnostics) {
+        Ok(program) => {
+            // everything the compiler generates should pass the verifier
+            assert_eq!(verify(&program.chunk), Ok(()));
+            quiet_vm().run(program).is_ok()
+        }
+        Err(_) => false,
+    }
+}
+
+#[test]
+fn generic_functions_are_instantiated_per_use() {
+    assert!(runs(
+        "fn id<T>(x: T) -> T { return x; }
+         let a: Int = id(1);
+         let b: String = id(\"b\");
+         if a != 1 || b != \"b\" { panic(\"wrong value\"); }"
+    ));
+}
+
+#[test]
+fn unannotated_functions_are_generalised() {
+    assert!(runs(
+        "fn twice(f, x) { return f(f(x)); }
+         fn inc(n: Int) -> Int { return n + 1; }
+         fn not(b: Bool) -> Bool { return !b; }
+         let a: Int = twice(inc, 1);
+         let b: Bool = twice(not, true);
+         if a != 3 || !b { panic(\"wrong value\"); }"
+    ));
+}
+
+#[test]
+fn type_parameters_are_rigid() {
+    assert!(!compiles("fn bad<T>(x: T) -> Int { return x; }"));
+    assert!(!compiles("fn bad<T, U>(x: T) -> U { return x; }"));
+}
+
+#[test]
+fn generic_structs() {
+    assert!(runs(
+        "struct Pair<A, B> { first: A, second: B }
+         fn swap<A, B>(p: Pair<A, B>) -> Pair<B, A> {
+             return Pair { second: p.first, first: p.second };
+         }
+         let p: Pair<String, Int> = swap(Pair { first: 1, second: \"a\" });
+         let n: Int = p.second;
+         if n != 1 || p.first != \"a\" { panic(\"not swapped\"); }"
+    ));
+    assert!(!compiles(
+        "struct Pair<A, B> { first: A, second: B }
+         let p: Pair<Int, Int> = Pair { first: 1, second: true };"
+    ));
+}
+
+const SHAPES: &str = "enum Shape { Circle(Int), Square(Int), Empty }
+     fn area(shape: Shape) -> Int {
+         match shape {
+             Circle(r) => { return 3 * r * r; }
+             Square(side) => { return side * side; }
+             Empty => { return 0; }
+         }
+     }
+     ";
+
+#[test]
+fn enums() {
+    assert!(runs(&format!(
+        "{}if area(Circle(2)) + area(Square(3)) + area(Empty) != 21 {{ panic(\"area\"); }}
+         enum Tree<T> {{ Leaf(T), Node(Branches<T>) }}
+         struct Branches<T> {{ left: Tree<T>, right: Tree<T> }}
+         fn sum(tree: Tree<Int>) -> Int {{
+             match tree {{
+                 Leaf(n) => {{ return n; }}
+                 Node(b) => {{ return sum(b.left) + sum(b.right); }}
+             }}
+         }}
+         let tree = Node(Branches {{ left: Leaf(1), right: Node(Branches {{ left: Leaf(2), right: Leaf(3) }}) }});
+         if sum(tree) != 6 {{ panic(\"sum\"); }}
+         impl Shape {{ fn grow(self) -> Shape {{
+             match self {{ Circle(r) => {{ return Circle(r + 1); }} _ => {{ return self; }} }}
+         }} }}
+         if area(Circle(1).grow()) != 12 {{ panic(\"grow\"); }}",
+        SHAPES
+    )));
+
+    assert!(!compiles(&format!("{}let a = area(Leaf(1));", SHAPES)));
+    assert!(!compiles(&format!(
+        "{}match Empty {{ Circle(r) => {{}} }}",
+        SHAPES
+    )));
+    assert!(!compiles("enum A<T> { X(T) } let a: A<String> = X(1);"));
+
+    // variants are written without their enum, so their names are global
+    assert!(!compiles("enum A { X, X }"));
+    assert!(!compiles("enum A { X } enum B { X }"));
+    assert!(!compiles("enum A { None }"));
+
+    // only `Option` and `Result` have a variant to unwrap
+    assert!(!compiles(
+        "enum A { X(Int), Y } fn f() -> Option<Int> { return Some(X(1)?); }"
+    ));
+}
+
+#[test]
+fn variants_are_limited_to_256() {
+    let variants: Vec<String> = (0..300).map(|i| format!("V{i}")).collect();
+    let source = format!("enum E {{ {} }}", variants.join(", "));
+    assert!(codes(&source).contains(&"J0037"));
+}
+
+#[test]
+fn recursion_within_a_group_is_monomorphic() {
+    assert!(!compiles(
+        "fn a(x) { return b(x); }
+         fn b(y) { return y + 1; }
+         a(\"not an int\");"
+    ));
+}
+
+#[test]
+fn missing_return_is_an_error() {
+    assert!(!compiles("fn f(n: Int) -> Int { if n > 0 { return 1; } }"));
+    assert!(compiles(
+        "fn f(n: Int) -> Int { if n > 0 { return 1; } else { return 0; } }"
+    ));
+    // a loop that never stops can only be left by returning
+    assert!(compiles(
+        "fn f(n: Int) -> Int { while true { n = n + 1; if n > 9 { return n; } } }"
+    ));
+    assert!(!compiles(
+        "fn f(n: Int) -> Int { while n > 0 { return n; } }"
+    ));
+}
+
+const SHOW: &str = "trait Show { fn show(self) -> String; }
+     struct Point { x: Int, y: Int }
+     impl Show for Point { fn show(self) -> String { return \"point\"; } }
+     impl Point { fn sum(self) -> Int { return self.x + self.y; } }
+     ";
+
+#[test]
+fn fields_are_limited_to_256() {
+    let fields: Vec<String> = (0..300).map(|i| format!("a{i}: Int")).collect();
+    let values: Vec<String> = (0..300).map(|i| format!("a{i}: {i}")).collect();
+    let source = format!(
+        "struct S {{ {} }}
+         let s = S {{ {} }};
+         if s.a299 != 299 {{ panic(\"wrong field\"); }}",
+        fields.join(", "),
+        values.join(", ")
+    );
+    assert!(codes(&source).contains(&"J0036"));
+}
+
+#[test]
+fn methods_on_concrete_types() {
+    assert!(runs(&format!(
+        "{}let p = Point {{ x: 1, y: 2 }};
+         let n: Int = p.sum();
+         let s: String = p.show();
+         if n != 3 || s != \"point\" {{ panic(\"wrong method\"); }}",
+        SHOW
+    )));
+    assert!(!compiles(&format!("{}let n = 1.sum();", SHOW)));
+}
+
+#[test]
+fn bounds_are_checked() {
+    let generic = "fn show_all<T: Show>(x: T) -> String { return x.show(); }";
+    assert!(runs(&format!(
+        "{}{}let s: String = show_all(Point {{ x: 1, y: 2 }});
+         if s != \"point\" {{ panic(\"wrong method\"); }}",
+        SHOW, generic
+    )));
+    assert!(!compiles(&format!("{}{}show_all(1);", SHOW, generic)));
+    assert!(!compiles(&format!(
+        "{}fn unbounded<T>(x: T) -> String {{ return x.show(); }}",
+        SHOW
+    )));
+}
+
+#[test]
+fn impls_must_match_their_trait() {
+    assert!(!compiles(
+        "trait Show { fn show(self) -> String; }
+         impl Show for Int { fn show(self) -> Int { return 1; } }"
+    ));
+    assert!(!compiles(
+        "trait Show { fn show(self) -> String; }
+         impl Show for Int { }"
+    ));
+    assert!(!compiles(
+        "trait Show { fn show(self) -> String; }
+         impl Show for Int {
+             fn show(self) -> String { return \"\"; }
+             fn extra(self) { }
+         }"
+    ));
+}
+
+#[test]
+fn traits_are_limited_to_256() {
+    let traits: String = (0..300)
+        .map(|i| format!("trait T{i} {{ fn m{i}(self) -> Int; }}\n"))
+        .collect();
+    let source = format!(
+        "{}impl T43 for Int {{ fn m43(self) -> Int {{ return 43; }} }}
+         impl T299 for Int {{ fn m299(self) -> Int {{ return 299; }} }}
+         fn g<T: T299>(x: T) -> Int {{ return x.m299(); }}
+         if g(1) != 299 {{ panic(\"wrong method\"); }}",
+        traits
+    );
+    assert!(codes(&source).contains(&"J0035"));
+}
+
+#[test]
+fn operators_are_overloaded_by_traits() {
+    let vec2 = "struct V { x: Int }
+         impl Add for V { fn add(self, o: V) -> V { return V { x: self.x + o.x }; } }
+         impl Ord for V { fn cmp(self, o: V) -> Int { return self.x - o.x; } }
+         ";
+    assert!(runs(&format!(
+        "{}let v: V = V {{ x: 1 }} + V {{ x: 2 }};
+         let b: Bool = v > V {{ x: 0 }};
+         if v.x != 3 || !b || (V {{ x: 1 }}) > v {{ panic(\"wrong operator\"); }}",
+        vec2
+    )));
+    assert!(!compiles(&format!(
+        "{}let v = V {{ x: 1 }} * V {{ x: 2 }};",
+        vec2
+    )));
+    assert!(!compiles(&format!("{}let v = V {{ x: 1 }} + 2;", vec2)));
+}
+
+#[test]
+fn builtin_types_implement_builtin_traits() {
+    assert!(runs(
+        "fn double<T: Add>(x: T) -> T { return x + x; }
+         fn same<T: Eq>(a: T, b: T) -> Bool { return a == b; }
+         let n: Int = double(2);
+         let b: Bool = same(\"a\", \"a\");
+         if n != 4 || !b || same(1, 2) { panic(\"wrong impl\"); }"
+    ));
+    assert!(!compiles(
+        "fn double<T: Add>(x: T) -> T { return x + x; } double(true);"
+    ));
+}
+
+#[test]
+fn newtypes_are_distinct_from_their_base() {
+    let ids = "type UserId = new Int; type Count = new Int;
+         fn lookup(id: UserId) -> Int { return id as Int; }
+         ";
+    assert!(runs(&format!(
+        "{}let n: Int = lookup(3 as UserId);
+         let same: Bool = 3 as UserId == 4 as UserId;
+         if n != 3 || same {{ panic(\"wrong value\"); }}",
+        ids
+    )));
+    assert!(!compiles(&format!("{}lookup(3);", ids)));
+    assert!(!compiles(&format!("{}lookup(3 as Count);", ids)));
+    assert!(!compiles(&format!("{}let c = 3 as UserId as Count;", ids)));
+    assert!(!compiles(&format!("{}let c = \"3\" as UserId;", ids)));
+
+    // casts wrap or unwrap one newtype at a time
+    assert!(runs(
+        "type Id = new Int; type Admin = new Id;
+         let admin = 3 as Id as Admin;
+         let id: Id = admin as Id;
+         let n: Int = admin as Id as Int;
+         if id as Int != 3 || n != 3 { panic(\"wrong value\"); }"
+    ));
+    assert!(!compiles(
+        "type Id = new Int; type Admin = new Id; let a = 3 as Admin;"
+    ));
+
+    // an unresolved variable isn't reported again as a bad cast
+    assert_eq!(codes("let n = missing as Int;"), ["J0003"]);
+}
+
+#[test]
+fn try_unwraps_or_returns_early() {
+    assert!(runs(
+        "fn first(a: Option<Int>, b: Option<Int>) -> Option<Int> {
+             return Some(a? + b?);
+         }
+         fn check(r: Result<Int, String>) -> Result<Bool, String> {
+             return Ok(r? == 1);
+         }
+         match first(Some(1), None) {
+             Some(n) => { panic(\"not returned early\"); }
+             None => {}
+         }
+         match first(Some(1), Some(2)) {
+             Some(n) => { if n != 3 { panic(\"wrong sum\"); } }
+             None => { panic(\"returned early\"); }
+         }
+         match check(Err(\"bad\")) {
+             Ok(b) => { panic(\"not returned early\"); }
+             Err(e) => { if e != \"bad\" { panic(\"wrong error\"); } }
+         }"
+    ));
+    assert!(!compiles("fn f(a: Option<Int>) -> Int { return a?; }"));
+    assert!(!compiles(
+        "fn f(r: Result<Int, String>) -> Result<Int, Bool> { return Ok(r?); }"
+    ));
+    assert!(!compiles("let a = Some(1)?;"));
+}
+
+#[test]
+fn matches_must_be_exhaustive() {
+    assert!(compiles(
+        "match Some(1) {
+             Some(n) => { print(n + 1); }
+             _ => {}
+         }"
+    ));
+    assert!(!compiles("match Some(1) { Some(n) => {} }"));
+    assert!(!compiles("match Some(1) { Ok(n) => {} _ => {} }"));
+    assert!(!compiles("match 1 { _ => {} }"));
+}
+
+#[test]
+fn panics_unwind_to_the_innermost_try() {
+    assert!(!runs("panic(\"uncaught\");"));
+    assert!(runs(
+        "fn divide(a: Int, b: Int) -> Int { return a / b; }
+         fn check(n: Int) -> Int {
+             if n < 0 { panic(\"negative\"); }
+             return divide(10, n);
+         }
+         let caught = 0;
+         let n = 1;
+         while n > -2 {
+             let kept = n;
+             try {
+                 check(n);
+             } recover err {
+                 if kept != n { panic(\"locals were lost\"); }
+                 caught = caught + 1;
+             }
+             n = n - 1;
+         }
+         if caught != 2 { panic(\"not caught\"); }"
+    ));
+
+    // returning out of a `try` removes its handler
+    assert!(!runs(
+        "fn f() -> Int {
+             try { return 1; } recover err { return 2; }
+         }
+         let a = f();
+         panic(\"after\");"
+    ));
+}
+
+#[test]
+fn deep_recursion_overflows_the_stack() {
+    let forever = "fn f(n: Int) -> Int { return f(n + 1) + 1; }\n";
+    assert_eq!(codes(&format!("{}let _a = f(0);", forever)), ["J1009"]);
+    assert!(runs(&format!(
+        "{}try {{ let _a = f(0); }} recover err {{
+             if err.message != \"stack overflow\" {{ panic(\"wrong error\"); }}
+         }}
+         fn depth(n: Int) -> Int {{
+             if n == 0 {{ return 0; }}
+             return depth(n - 1) + 1;
+         }}
+         if depth(10000) != 10000 {{ panic(\"too shallow\"); }}",
+        forever
+    )));
+}
+
+#[test]
+fn semantic_errors_are_all_reported() {
+    let source = Source::new(
+        "test".to_string(),
+        "fn f(x: Int) -> Int { return x + true; }
+         fn g() -> Int { let y = 1; }
+         fn f() {}
+         let a = undefined;
+         let b: Bool = 1;
+         print(a + zzz);
+         let c = f(1) + a;"
+            .to_string(),
+    );
+    let mut diagnostics = ErrorReporter::default();
+    assert!(CompiledProgram::compile(&source, &mut diagnostics).is_err());
+
+    let titles: Vec<_> = diagnostics
+        .reports()
+        .iter()
+        .map(|report| report.title.to_string())
+        .collect();
+    assert_eq!(
+        titles,
+        [
+            "duplicate definition",
+            "unresolved variable",
+            "unresolved variable",
+            "mismatched types",
+            "missing return",
+            "mismatched types",
+        ]
+    );
+}
+
+#[test]
+fn unresolved_variables_are_explained() {
+    let source = Source::new(
+        "test".to_string(),
+        "let counter = 1;
+         print(countr);
+         { print(later); }
+         let later = 2;
+         let x = x + 1;
+         { print(other); }
+         { let other = 1; }
+         print(z);"
+            .to_string(),
+    );
+    let mut diagnostics = ErrorReporter::default();
+    assert!(CompiledProgram::compile(&source, &mut diagnostics).is_err());
+
+    let reports = diagnostics.reports();
+    assert_eq!(reports.len(), 5);
+
+    let spans: Vec<Vec<_>> = reports
+        .iter()
+        .map(|report| {
+            report
+                .labels
+                .iter()
+                .map(|label| source.span_str(label.span.unwrap()))
+                .collect()
+        })
+        .collect();
+    assert_eq!(
+        spans,
+        [
+            vec!["countr"],
+            vec!["later", "later"],
+            vec!["x", "x"],
+            vec!["other"],
+            vec!["z"],
+        ]
+    );
+    assert_eq!(reports[0].hints, ["did you mean `counter`?"]);
+    assert!(reports[1].hints.is_empty());
+    // one letter names are all one edit apart, so `x` isn't suggested
+    assert!(reports[4].hints.is_empty());
+    assert_eq!(
+        source
+            .line_col(reports[1].labels[1].span.unwrap().start)
+            .line,
+        4
+    );
+}
+
+/// The severity and title of each report from compiling a program.
+fn lints(source: &str, options: PassOptions) -> Vec<(Severity, &'static str)> {
+    let source = Source::new("test".to_string(), source.to_string());
+    let mut diagnostics = ErrorReporter::default();
+    let result = CompiledProgram::compile_with(&source, &mut diagnostics, options);
+    assert_eq!(result.is_ok(), !diagnostics.had_errors());
+
+    diagnostics
+        .reports()
+        .iter()
+        .map(|report| (report.severity, report.title))
+        .collect()
+}
+
+#[test]
+fn lints_warn_without_stopping_compilation() {
+    let source = "fn f(x: Int, _unused: Int) -> Int {
+                      let y = 1;
+                      y = 2;
+                      y = 3;
+                      return x + y;
+                      print(1);
+                  }
+                  let a = f(1, 2);
+                  { let a = 2; print(a); }
+                  print(a);
+                  let i = 0;
+                  while i < 3 { i = i + 1; }
+                  while false {}
+                  let unused = 0;";
+    let warning = Severity::Warning;
+    assert_eq!(
+        lints(source, PassOptions::default()),
+        [
+            (warning, "unreachable code"),
+            (warning, "shadowed binding"),
+            (warning, "constant condition"),
+            (warning, "unused variable"),
+            (warning, "unused assignment"),
+        ]
+    );
+
+    let mut options = PassOptions::default();
+    options.lints.set(Lint::UnusedVariable, LintLevel::Deny);
+    options.lints.set(Lint::ShadowedBinding, LintLevel::Allow);
+    let reports = lints(source, options);
+    assert_eq!(reports.len(), 4);
+    assert!(reports.contains(&(Severity::Error, "unused variable")));
+
+    // attributes on a statement win over the ones on the module, which
+    // win over the options
+    let source = "#![deny(unused_variable)]
+                  #[allow(unused_variable)]
+                  let quiet = 0;
+                  fn f() {
+                      #[warn(unused_variable, no_such_lint)]
+                      let loud = 0;
+                  }
+                  let denied = 0;";
+    assert_eq!(
+        lints(source, options),
+        [
+            (warning, "unknown lint"),
+            (warning, "unused variable"),
+            (Severity::Error, "unused variable"),
+        ]
+    );
+    // an endless loop is fine if something in it returns or panics
+    let source = "fn f(n: Int) -> Int {
+                      while true {
+                          if n > 9 { return n; }
+                          n = n + 1;
+                      }
+                  }
+                  fn g() { while true { panic(\"stop\"); } }
+                  print(f(1));
+                  g();";
+    assert_eq!(lints(source, PassOptions::default()), []);
+}
+
+#[test]
+fn diagnostics_are_structured() {
+    let source = Source::new(
+        "test.jlf".to_string(),
+        "let unused = 1;\nprint(\"é\" == true);".to_string(),
+    );
+    let mut diagnostics = ErrorReporter::default();
+    assert!(CompiledProgram::compile(&source, &mut diagnostics).is_err());
+
+    let diagnostics = diagnostics.diagnostics(&source);
+    assert_eq!(diagnostics.len(), 1);
+    let diagnostic = &diagnostics[0];
+    assert_eq!(diagnostic.severity, Severity::Error);
+    assert_eq!(diagnostic.message, "mismatched types");
+    assert_eq!(diagnostic.file, "test.jlf");
+
+    // columns count characters, not bytes
+    let label = &diagnostic.labels[0];
+    assert_eq!(source.span_str(label.span.unwrap()), "true");
+    assert_eq!(
+        label.start,
+        Some(Position {
+            line: 2,
+            column: 14
+        })
+    );
+    assert_eq!(
+        label.end,
+        Some(Position {
+            line: 2,
+            column: 18
+        })
+    );
+
+    let json = Json::parse(&diagnostic.to_json()).unwrap();
+    assert_eq!(json.get("severity").as_str(), Some("error"));
+    assert_eq!(json.get("code").as_str(), Some("J0009"));
+    let label = &json.get("labels").as_array().unwrap()[0];
+    assert_eq!(label.get("span").get("start").as_usize(), Some(30));
+    assert_eq!(label.get("start").get("column").as_usize(), Some(14));
+    assert_eq!(
+        label.get("message").as_str(),
+        Some("expected `String` but found `Bool`")
+    );
+
+    // warnings carry the name of their lint
+    let source = Source::new("test.jlf".to_string(), "let unused = 1;".to_string());
+    let mut diagnostics = ErrorReporter::default();
+    assert!(CompiledProgram::compile(&source, &mut diagnostics).is_ok());
+    let json = Json::parse(&diagnostics.diagnostics(&source)[0].to_json()).unwrap();
+    assert_eq!(json.get("severity").as_str(), Some("warning"));
+    assert_eq!(json.get("code").as_str(), Some("unused_variable"));
+    assert_eq!(json.get("file").as_str(), Some("test.jlf"));
+}
+
+#[test]
+fn diagnostics_render_to_strings() {
+    let source = Source::new(
+        "test.jlf".to_string(),
+        "let s = \"日本\";\nlet a: Int = \"é\";\nprint(s);\n".to_string(),
+    );
+    let mut diagnostics = ErrorReporter::default();
+    assert!(CompiledProgram::compile(&source, &mut diagnostics).is_err());
+
+    // colour is only used when asked for, with no terminal to check
+    let human = diagnostics.render(&source);
+    assert!(!human.contains('\x1b'));
+    assert_eq!(
+        human,
+        "ENCOUNTERED ERROR(S) WHILE COMPILING:\n\n\
+         error[J0009]: mismatched types\n   \
+         in `test.jlf`, line 2, col 14:\n      \
+         1 | let s = \"日本\";\n      \
+         2 | let a: Int = \"é\";\n      \
+         \x20                ^^^\n      \
+         3 | print(s);\n   \
+         msg: expected `Int` but found `String`\n\n"
+    );
+
+    diagnostics.set_color(ColorChoice::Always);
+    assert!(diagnostics.render(&source).contains('\x1b'));
+
+    diagnostics.set_format(ErrorFormat::Short);
+    assert_eq!(
+        diagnostics.render(&source),
+        "test.jlf:2:14: \x1b[1;31merror[J0009]\x1b[0m: mismatched types: \
+         expected `Int` but found `String`\n"
+    );
+
+    // a host can render reports however it likes
+    struct Titles(Vec<&'static str>);
+    impl Emitter for Titles {
+        fn emit(&mut self, report: &ErrorReport, _: &Source) -> std::io::Result<()> {
+            self.0.push(report.title);
+            Ok(())
+        }
+    }
+    let mut titles = Titles(vec![]);
+    diagnostics.emit(&source, &mut titles).unwrap();
+    assert_eq!(titles.0, ["mismatched types"]);
+}
+
+#[test]
+fn columns_count_characters_and_display_width() {
+    let source = Source::new(
+        "test".to_string(),
+        "let s = \"é日本\";\n\tx\n  \tyz".to_string(),
+    );
+
+    let semicolon = source.source.find(';').unwrap();
+    assert_eq!(source.line_col(semicolon).col, 14);
+    assert_eq!(source.display_col(semicolon), 15);
+
+    // tabs go to the next tab stop
+    let x = source.source.find('x').unwrap();
+    assert_eq!(source.line_col(x).col, 2);
+    assert_eq!(source.display_col(x), 4);
+    let z = source.source.find('z').unwrap();
+    assert_eq!(source.display_col(z), 5);
+    assert_eq!(expand_tabs("  \tyz"), "    yz");
+}
+
+/// The codes reported for a program, including the code of the runtime
+/// error it fails with, if it compiles.
+fn codes(source: &str) -> Vec<&'static str> {
+    let source = Source::new("test".to_string(), source.to_string());
+    let mut diagnostics = ErrorReporter::default();
+    let result = CompiledProgram::compile(&source, &mut diagnostics);
+    let mut codes: Vec<_> = diagnostics
+        .reports()
+        .iter()
+        .filter_map(|report| report.code)
+        .collect();
+    if let Ok(program) = result {
+        if let Err(err) = quiet_vm().run(program) {
+            codes.push(err.code());
+        }
+    }
+    codes
+}
+
+#[test]
+fn explanations_examples_are_right() {
+    for explanation in EXPLANATIONS {
+        if explanation.wrong.is_empty() {
+            continue;
+        }
+
+        let code = explanation.code;
+        assert!(
+            codes(explanation.wrong).contains(&code),
+            "the wrong example of {} doesn't report it",
+            code
+        );
+        assert_eq!(
+            codes(explanation.fixed),
+            [] as [&str; 0],
+            "the fixed example of {} isn't right",
+            code
+        );
+    }
+}
+
+#[test]
+fn each_vm_has_its_own_script_args() {
+    let source = Source::new(
+        "test".to_string(),
+        "if arg_count() != 1 { panic(\"count\"); }
+         let expected = \"-x\";
+         if arg(0) != expected { panic(\"arg\"); }"
+            .to_string(),
+    );
+    let program = || {
+        CompiledProgram::compile(&source, &mut ErrorReporter::default())
+            .ok()
+            .unwrap()
+    };
+
+    let mut vm = quiet_vm();
+    vm.set_args(&["-x".to_string()]);
+    let mut other = quiet_vm();
+    other.set_args(&["-y".to_string(), "-z".to_string()]);
+
+    assert!(other.run(program()).is_err());
+    assert!(vm.run(program()).is_ok());
+}
+
+#[test]
+fn runtime_errors_point_at_their_code() {
+    let source = Source::new(
+        "test".to_string(),
+        "let n = 0;\nlet a = 1 / n;\n".to_string(),
+    );
+    let program = CompiledProgram::compile(&source, &mut ErrorReporter::default())
+        .ok()
+        .unwrap();
+
+    let mut vm = quiet_vm();
+    let err = vm.run(program).unwrap_err();
+    let span = vm.error_span().unwrap();
+    assert_eq!(source.line_col(span.start).line, 2);
+    assert_eq!(source.span_str(span), "1 / n");
+
+    let report = err.report(Some(span));
+    assert_eq!(
+        (report.title, report.code),
+        ("division by zero", Some("J1002"))
+    );
+}
+
+#[test]
+fn integer_arithmetic_is_checked() {
+    assert!(!runs("let a = 2 ^ 63;"));
+    assert!(!runs("let a = 2 ^ -1;"));
+    assert!(!runs("let a = 1 / 0;"));
+    assert!(!runs("let a = 1 % 0;"));
+    assert!(!runs("let a = 1 << 64;"));
+    assert!(!runs("let a = -(-9223372036854775807 - 1);"));
+    assert!(runs(
+        "let max = 9223372036854775807;
+         if wrapping_add(max, 1) != -max - 1 { panic(\"wrapping_add\"); }
+         if saturating_add(max, 1) != max { panic(\"saturating_add\"); }
+         if saturating_pow(-2, 65) != -max - 1 { panic(\"saturating_pow\"); }
+         if (-1) ^ max != -1 { panic(\"pow\"); }
+         try { let a = 1 / 0; } recover err {
+             if err.message != \"division by zero\" { panic(\"wrong error\"); }
+         }"
+    ));
+}
+
+#[test]
+fn bitwise_operators() {
+    assert!(runs(
+        "if (6 & 3) != 2 || (6 | 3) != 7 || (6 ~ 3) != 5 || ~0 != -1 {
+             panic(\"bitwise\");
+         }
+         if 1 << 2 + 1 != 8 || -16 >> 2 != -4 { panic(\"shifts\"); }
+         let nested: Option<Option<Int>> = Some(Some(1 >> 1));"
+    ));
+}
+
+#[test]
+fn bigints_dont_overflow() {
+    assert!(runs(
+        "let a = 1n;
+         let i = 1;
+         while i <= 30 {
+             a = a * to_big(i);
+             i = i + 1;
+         }
+         if a != 265252859812191058636308480000000n || a / a != 1n || -a >= 0n {
+             panic(\"wrong\");
+         }"
+    ));
+    assert!(!compiles("let a = 1n + 1;"));
+    assert!(!runs("let a = to_int(2n ^ 64);"));
+}
+
+#[test]
+fn constants_are_folded() {
+    let code_len = |fold_constants| {
+        let options = PassOptions {
+            fold_constants,
+            ..PassOptions::default()
+        };
+        let source = "let a = 2 * 3 + 1;
+             if false { print(a); } else if 1 < 2 { let b = a; }
+             while !true { a = 0; }";
+        chunk(source, options).code.len()
+    };
+    assert!(code_len(true) < code_len(false));
+
+    assert!(runs(
+        "let a = 2 * 3 + 1;
+         if a != 7 || 2n ^ 3 != 8n || -(4 >> 1) != -2 { panic(\"folded\"); }
+         if true { let b = a; } else { panic(\"dead\"); }
+         try { let c = 1 / 0; } recover err {}"
+    ));
+    assert_eq!(
+        codes("let _a = 9223372036854775807 + 1; let _b = 1 / 0;"),
+        ["J0033", "J0033"]
+    );
+
+    // dead branches are removed before they're folded, and branches that
+    // might not run only get a warning
+    assert!(runs("if false { let _a = 1 / 0; }"));
+    assert!(runs("while false { let _a = 1 / 0; }"));
+    assert_eq!(codes("let x = 0; if x == 1 { let _a = 1 / 0; }"), ["J0033"]);
+    assert_eq!(
+        codes("let x = 1; if x == 1 { let _a = 1 / 0; }"),
+        ["J0033", "J1002"]
+    );
+
+    // a function might only be called inside a `try`, so errors in it are
+    // warned about and left to happen at runtime
+    let shift = "fn f() -> Int { return 1 << 64; }\n";
+    assert_eq!(
+        codes(&format!(
+            "{}try {{ let _a = f(); }} recover err {{
+                 if err.message != \"shift amount out of range\" {{ panic(\"wrong error\"); }}
+             }}",
+            shift
+        )),
+        ["J0033"]
+    );
+    assert_eq!(
+        codes(&format!("{}let _a = f();", shift)),
+        ["J0033", "J1004"]
+    );
+}
+
+#[test]
+fn peephole_fuses_instructions() {
+    let source = "let i = 0;
+         let j = 10;
+         while i != 10 { i = i + 1; j = j - 1; }
+         if i != 10 || j != 0 { panic(\"loop\"); }
+         let max = 9223372036854775807;
+         try { max = max + 1; } recover err {
+             if err.line != 6 { panic(\"wrong line\"); }
+         }";
+    assert!(runs(source));
+
+    let code = chunk(source, PassOptions::default()).code;
+    let fused = |instr| code.iter().any(|op| op.instr_safe() == Some(instr));
+    assert!(fused(Instr::IncLocal) && fused(Instr::NotEqual));
+}
+
+#[test]
+fn constants_are_shared() {
+    let source = "let a = 1000 + 1000 * 1000;
+         let b = \"s\" == \"s\";
+         let c = 1 + 2 * 3;
+         let d = true;";
+    let options = PassOptions {
+        fold_constants: false,
+        ..PassOptions::default()
+    };
+    assert_eq!(chunk(source, options).constants.len(), 2);
+
+    // equal big integers in different functions are the same object
+    let source = "fn f() -> BigInt { return 7n; }
+         fn g() -> BigInt { return 7n; }";
+    let bigints: Vec<_> = chunk(source, options)
+        .constants
+        .iter()
+        .filter_map(|constant| match constant {
+            Value::Object(object) => match &**object {
+                Object::JellyFunction(function) => Some(function.chunk.constants[0].clone()),
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect();
+    match &bigints[..] {
+        [Value::Object(a), Value::Object(b)] => assert!(Rc::ptr_eq(a, b)),
+        _ => panic!("expected two big integers"),
+    }
+}
+
+#[test]
+fn statement_code_has_the_statement_line() {
+    let source = "fn f(n: Int) -> Int { return n; }
+let i = 0;
+while i < 3 {
+    f(i);
+    i = i + 1;
+}";
+    let options = PassOptions {
+        peephole: false,
+        ..PassOptions::default()
+    };
+    let chunk = chunk(source, options);
+    let source = Source::new("test".to_string(), source.to_string());
+
+    let mut lines = vec![];
+    let mut offset = 0;
+    while offset < chunk.code.len() {
+        let instr = chunk.code[offset].instr();
+        if matches!(instr, Instr::Pop | Instr::JumpU32 | Instr::JumpNotU32) {
+            let span = chunk.span_at(offset).unwrap();
+            lines.push((instr, source.line_col(span.start).line));
+        }
+        offset += 1 + instr.operand_len();
+    }
+
+    assert_eq!(
+        lines,
+        [
+            (Instr::JumpNotU32, 3),
+            (Instr::Pop, 4),
+            (Instr::Pop, 5),
+            (Instr::JumpU32, 3),
+        ]
+    );
+}
+
+#[test]
+fn bytecode_round_trips() {
+    let source = Source::new(
+        "test".to_string(),
+        "struct P { x: BigInt }
+         fn f(p: P) -> Option<BigInt> { return Some(p.x * 2n); }
+         let s = \"text\";
+         match f(P { x: 21n }) {
+             Some(n) => { if n != 42n { panic(\"wrong\"); } }
+             None => { panic(\"none\"); }
+         }"
+        .to_string(),
+    );
+    let mut diagnostics = ErrorReporter::default();
+    let program = CompiledProgram::compile(&source, &mut diagnostics)
+        .ok()
+        .unwrap();
+    let bytes = program.to_bytes();
+
+    let loaded = CompiledProgram::from_bytes(&bytes).unwrap();
+    assert_eq!(loaded.to_bytes(), bytes);
+    assert!(quiet_vm().run(loaded).is_ok());
+
+    // the source isn't stored, but where errors happen still is
+    let source = Source::new(
+        "test".to_string(),
+        "fn f(n: Int) -> Int {
+             return 10 / n;
+         }
+         try { f(0); } recover err {
+             if err.line != 2 || err.column != 21 { panic(\"wrong place\"); }
+         }"
+        .to_string(),
+    );
+    let program = CompiledProgram::compile(&source, &mut diagnostics)
+        .ok()
+        .unwrap();
+    let loaded = CompiledProgram::from_bytes(&program.to_bytes()).unwrap();
+    assert_eq!(loaded.to_bytes(), program.to_bytes());
+    assert!(quiet_vm().run(loaded).is_ok());
+
+    let mut corrupt = bytes.clone();
+    *corrupt.last_mut().unwrap() ^= 1;
+    assert_eq!(
+        CompiledProgram::from_bytes(&corrupt).err(),
+        Some(LoadError::ChecksumMismatch)
+    );
+    assert_eq!(
+        CompiledProgram::from_bytes(&bytes[..bytes.len() - 1]).err(),
+        Some(LoadError::ChecksumMismatch)
+    );
+    assert_eq!(
+        CompiledProgram::from_bytes(b"not bytecode").err(),
+        Some(LoadError::BadMagic)
+    );
+
+    // the byte just past the last instruction isn't one
+    let invalid = CompiledProgram {
+        source: None,
+        chunk: Chunk {
+            code: vec![Opcode::from(Instr::COUNT as u8)],
+            ..Chunk::default()
+        },
+        lines: None,
+    };
+    assert_eq!(
+        CompiledProgram::from_bytes(&invalid.to_bytes()).err(),
+        Some(LoadError::Malformed("invalid instruction"))
+    );
+}
+
+#[test]
+fn disassembly_shows_operands_and_source() {
+    let source = Source::new(
+        "test".to_string(),
+        "fn greet(name: String) { print(name); }
+         let i = 0;
+         while i < 3 { greet(\"hi\"); i = i + 1; }"
+            .to_string(),
+    );
+    let mut diagnostics = ErrorReporter::default();
+    let program = CompiledProgram::compile(&source, &mut diagnostics)
+        .ok()
+        .unwrap();
+    let listing = program.chunk.disassemble(program.source);
+
+    for expected in [
+        "== fn greet ==",
+        "LoadConstantU8 1 (\"hi\")",
+        "JumpNotU32 L1",
+        "IncLocal 0 +1",
+        "3 | while i < 3 { greet(\"hi\"); i = i + 1; }",
+    ] {
+        assert!(listing.contains(expected), "missing {:?}", expected);
+    }
+}
+
+#[test]
+fn repl_keeps_state_between_inputs() {
+    let mut repl = Repl::default();
+    let mut out = vec![];
+    let mut emitter = ShortEmitter::new(&mut out, false);
+    let mut eval = |input: &str| {
+        repl.eval(input, &mut emitter)
+            .map_err(|err| err.to_string())
+    };
+
+    assert_eq!(eval("let x = 40;"), Ok(None));
+    assert_eq!(
+        eval("fn add(a: Int, b: Int) -> Int { return a + b; }"),
+        Ok(None)
+    );
+    assert_eq!(eval("add(x, 2)"), Ok(Some("42".to_string())));
+
+    // inputs that fail leave nothing behind
+    assert_eq!(eval("let zero = 0;"), Ok(None));
+    assert_eq!(
+        eval("let y = x / zero;"),
+        Err("division by zero".to_string())
+    );
+    assert!(eval("y").is_err());
+    assert!(eval("let z = x + true;").is_err());
+    assert!(eval("z").is_err());
+
+    assert_eq!(eval("x = x + 1; x"), Ok(Some("41".to_string())));
+
+    // the errors are reported to the emitter, at the line of their input
+    let out = String::from_utf8(out).unwrap();
+    assert!(
+        out.contains("<repl>:5:9: error[J1002]: division by zero"),
+        "{}",
+        out
+    );
+    assert!(out.contains("error[J0003]"), "{}", out);
+
+    assert!(Repl::is_incomplete("fn f() {\n"));
+    assert!(Repl::is_incomplete("print(\"a\n"));
+    assert!(!Repl::is_incomplete("fn f() {}\n"));
+}
+
+#[test]
+fn formatting_keeps_comments_and_is_idempotent() {
+    let format = |text: &str| {
+        let source = Source::new(String::new(), text.to_string());
+        format_source(&source, &mut ErrorReporter::default()).unwrap()
+    };
+
+    let input = "# header\n\n\nstruct P { a: Int,   # the a\n  b: Int }\n\
+        fn f(x: Int) -> Int {\n  # first\n  let y = (x + 1) * 2;\n\n\n\
+        if x == 1 { return y; } else if (P { a: 1, b: 2 }).a == x { return 2; } else { return (2 ^ 3) ^ 4; }\n\
+        # end\n}\nwhile false {}\n";
+    let expected = "# header\n\nstruct P {\n    a: Int, # the a\n    b: Int,\n}\n\
+        fn f(x: Int) -> Int {\n    # first\n    let y = (x + 1) * 2;\n\n\
+        \x20   if x == 1 {\n        return y;\n    } else if (P { a: 1, b: 2 }).a == x {\n\
+        \x20       return 2;\n    } else {\n        return (2 ^ 3) ^ 4;\n    }\n    # end\n}\n\
+        while false {}\n";
+    assert_eq!(format(input), expected);
+    assert_eq!(format(expected), expected);
+
+    let enum_ = "enum Tree<T> {\n    Leaf(T),\n    Node(Branches<T>),\n    Empty,\n}\n";
+    assert_eq!(
+        format("enum Tree<T> { Leaf(T),   Node(Branches<T>), Empty }"),
+        enum_
+    );
+
+    let long = format!(
+        "g(f({}), f({}), f({}));\n",
+        "a".repeat(30),
+        "b".repeat(30),
+        "c".repeat(30)
+    );
+    let formatted = format(&long);
+    assert!(formatted.lines().all(|line| line.len() <= 100));
+    assert_eq!(format(&formatted), formatted);
+
+    // long operator chains are broken before each operator
+    let terms: Vec<_> = ('a'..='h').map(|c| c.to_string().repeat(10)).collect();
+    let chain = format!("fn f() -> Int {{ return {}; }}", terms.join(" + "));
+    let expected = format!(
+        "fn f() -> Int {{\n    return {};\n}}\n",
+        terms.join("\n        + ")
+    );
+    assert_eq!(format(&chain), expected);
+
+    let long = format!(
+        "let x = f({}, {}) + g(1, 2, 3, 4);\nif (x - 1) * 2 != {} {{}}\n",
+        "a".repeat(40),
+        "b".repeat(40),
+        "c".repeat(90)
+    );
+    let formatted = format(&long);
+    assert!(formatted.lines().all(|line| line.len() <= 100));
+    assert_eq!(format(&formatted), formatted);
+}
+
+#[test]
+fn language_server_answers_requests() {
+    let text = "fn add(a: Int, b: Int) -> Int {\n    return a + b;\n}\n\
+        let s = \"😀\"; let total = add(1, 2);\nprint(total + true);\n";
+    let uri = Json::from("file:///test.jlf");
+    let document = Json::object([("uri", uri.clone())]);
+    let at = |line: usize, character: usize| {
+        Json::object([
+            ("textDocument", document.clone()),
+            (
+                "position",
+                Json::object([("line", line.into()), ("character", character.into())]),
+            ),
+        ])
+    };
+
+    let messages = [
+        ("initialize", Some(1), Json::object([])),
+        (
+            "textDocument/didOpen",
+            None,
+            Json::object([(
+                "textDocument",
+                Json::object([("uri", uri.clone()), ("text", text.into())]),
+            )]),
+        ),
+        ("textDocument/definition", Some(2), at(4, 8)),
+        ("textDocument/references", Some(3), at(3, 20)),
+        ("textDocument/hover", Some(4), at(3, 27)),
+        ("textDocument/completion", Some(5), at(1, 11)),
+        (
+            "textDocument/documentSymbol",
+            Some(6),
+            Json::object([("textDocument", document.clone())]),
+        ),
+        ("shutdown", Some(7), Json::Null),
+        ("exit", None, Json::Null),
+    ];
+
+    let mut input = String::new();
+    for (method, id, params) in messages {
+        let mut message = vec![("jsonrpc", "2.0".into()), ("method", method.into())];
+        if let Some(id) = id {
+            message.push(("id", id.into()));
+        }
+        message.push(("params", params));
+        let body = Json::object(message).to_string();
+        input += &format!("Content-Length: {}\r\n\r\n{}", body.len(), body);
+    }
+
+    let mut output = vec![];
+    let clean = LanguageServer::default().serve(input.as_bytes(), &mut output);
+    assert!(clean.unwrap());
+
+    let output = String::from_utf8(output).unwrap();
+    let replies: Vec<Json> = output
+        .split("Content-Length: ")
+        .skip(1)
+        .map(|message| Json::parse(message.split_once("\r\n\r\n").unwrap().1).unwrap())
+        .collect();
+    let result = |id: usize| {
+        replies
+            .iter()
+            .find(|reply| reply.get("id").as_usize() == Some(id))
+            .unwrap()
+            .get("result")
+    };
+    let line_col = |range: &Json| {
+        let start = range.get("start");
+        (
+            start.get("line").as_usize(),
+            start.get("character").as_usize(),
+        )
+    };
+
+    let diagnostics = replies[1].get("params").get("diagnostics");
+    let diagnostics = diagnostics.as_array().unwrap();
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(line_col(diagnostics[0].get("range")), (Some(4), Some(14)));
+
+    // after a character that's two UTF-16 code units
+    assert_eq!(line_col(result(2).get("range")), (Some(3), Some(18)));
+
+    let references = result(3).as_array().unwrap();
+    assert_eq!(references.len(), 2);
+    assert_eq!(line_col(references[1].get("range")), (Some(4), Some(6)));
+
+    let hover = result(4).get("contents").get("value").as_str().unwrap();
+    assert!(hover.contains("add: fn(Int, Int) -> Int"), "{}", hover);
+
+    let completions: Vec<_> = result(5)
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|item| item.get("label").as_str().unwrap())
+        .collect();
+    assert!(completions.starts_with(&["b", "a", "add"]));
+    assert!(completions.contains(&"while"));
+    assert!(!completions.contains(&"total"));
+
+    let symbols: Vec<_> = result(6)
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|symbol| symbol.get("name").as_str().unwrap())
+        .collect();
+    assert_eq!(symbols, ["add", "s", "total"]);
+}
@@ -1,9 +1,21 @@
-pub use self::compiler::diagnostic::ErrorReporter;
-pub use self::runtime::vm::VM;
+pub use self::compiler::diagnostic::{
+    ColorChoice, Diagnostic, DiagnosticLabel, Emitter, ErrorFormat, ErrorReporter, Explanation,
+    HumanEmitter, JsonEmitter, Position, Severity, ShortEmitter, EXPLANATIONS,
+};
+pub use self::compiler::passes::lint::{Lint, LintLevel};
+pub use self::compiler::passes::PassOptions;
+pub use self::compiler::{dump_ast, dump_tokens, format_source};
+pub use self::lsp::LanguageServer;
+pub use self::repl::{Repl, ReplError};
+pub use self::runtime::serialize::{LoadError, MAGIC as BYTECODE_MAGIC};
+pub use self::runtime::vm::{RuntimeError, VM};
 pub use self::runtime::CompiledProgram;
 pub use self::source::Source;
 
 mod compiler;
 mod fmt;
+mod json;
+mod lsp;
+mod repl;
 mod runtime;
 mod source;
@@ -0,0 +1,547 @@
+use internment::Intern;
+
+use crate::compiler::ast::*;
+use crate::compiler::diagnostic::{ErrorReport, ErrorReporter};
+use crate::compiler::lexer::Lexer;
+use crate::compiler::parser::Parser;
+use crate::compiler::passes::codegen::CodeGenerator;
+use crate::compiler::passes::fold::ConstantFolder;
+use crate::compiler::passes::lint::{lint, LintLevels};
+use crate::compiler::passes::resolve::{Bindings, DefinitionKind, Resolver};
+use crate::compiler::passes::typecheck::TypeChecker;
+use crate::compiler::passes::visit::Visitor;
+use crate::runtime::builtins::BUILTIN_FNS;
+use crate::source::{Source, Span};
+
+const KEYWORDS: &[&str] = &[
+    "as", "else", "enum", "false", "fn", "for", "if", "impl", "let", "match", "new", "panic",
+    "print", "recover", "return", "struct", "trait", "true", "try", "type", "while",
+];
+
+/// What an editor needs to know about a document, found by running the
+/// compiler's passes over it. Offsets are in bytes.
+pub struct Analysis {
+    pub source: Source,
+    statements: Vec<Statement>,
+    bindings: Bindings,
+    typechecker: TypeChecker,
+    diagnostics: ErrorReporter,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolKind {
+    Function,
+    Method,
+    Struct,
+    Field,
+    Enum,
+    Variant,
+    Newtype,
+    Trait,
+    Impl,
+    Variable,
+}
+
+/// A declaration, for an outline of the document.
+pub struct Symbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    pub detail: Option<String>,
+
+    /// The whole declaration, and the part of it that's the name.
+    pub span: Span,
+    pub name_span: Span,
+    pub children: Vec<Symbol>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompletionKind {
+    Function,
+    Variable,
+    Keyword,
+}
+
+impl Analysis {
+    pub fn new(source: Source) -> Self {
+        let mut diagnostics = ErrorReporter::default();
+
+        let lexer = Lexer::new(source.cursor());
+        let mut module = Parser::new(lexer, &mut diagnostics).parse();
+        let parsed = diagnostics.assert_ok().is_ok();
+
+        // a module with syntax errors is still resolved for the names in
+        // it, but its semantic errors would mostly be noise
+        let mut resolver = Resolver::new();
+        let mut typechecker = TypeChecker::new();
+        let mut errors = vec![];
+        errors.extend(resolver.visit_module(&mut module).err());
+        errors.append(&mut resolver.take_errors());
+
+        if parsed {
+            errors.extend(typechecker.visit_module(&mut module).err());
+            errors.append(&mut typechecker.take_errors());
+        }
+
+        let clean = parsed && errors.is_empty();
+        if clean {
+            // folding rewrites the tree, so the rest of the passes run on a
+            // copy to keep the spans and types of what was written
+            let mut copy = Module {
+                statements: module.statements.clone(),
+                comments: vec![],
+                attributes: vec![],
+            };
+            let result = ConstantFolder::default()
+                .visit_module(&mut copy)
+                .and_then(|_| CodeGenerator::default().visit_module(&mut copy));
+            errors.extend(result.err());
+        }
+
+        if parsed {
+            for err in errors {
+                diagnostics.report(err.report());
+            }
+        }
+
+        if clean {
+            for report in lint(
+                &mut module,
+                resolver.bindings(),
+                LintLevels::default(),
+                false,
+            ) {
+                diagnostics.report(report);
+            }
+        }
+
+        Self {
+            source,
+            statements: module.statements,
+            bindings: resolver.bindings().clone(),
+            typechecker,
+            diagnostics,
+        }
+    }
+
+    pub fn diagnostics(&self) -> &[ErrorReport] {
+        self.diagnostics.reports()
+    }
+
+    /// The index of the definition of the name at an offset, which can be
+    /// a use of it or its declaration.
+    fn definition_at(&self, offset: usize) -> Option<usize> {
+        self.bindings
+            .references
+            .iter()
+            .find(|reference| contains(reference.span, offset))
+            .map(|reference| reference.definition)
+            .or_else(|| {
+                self.bindings
+                    .definitions
+                    .iter()
+                    .position(|definition| contains(definition.span, offset))
+            })
+    }
+
+    /// Where the name at an offset is declared.
+    pub fn definition(&self, offset: usize) -> Option<Span> {
+        let definition = self.definition_at(offset)?;
+        Some(self.bindings.definitions[definition].span)
+    }
+
+    /// Every use of the name at an offset, in order.
+    pub fn references(&self, offset: usize, include_declaration: bool) -> Vec<Span> {
+        let definition = match self.definition_at(offset) {
+            Some(definition) => definition,
+            None => return vec![],
+        };
+
+        let mut spans: Vec<Span> = self
+            .bindings
+            .references
+            .iter()
+            .filter(|reference| reference.definition == definition)
+            .map(|reference| reference.span)
+            .collect();
+
+        if include_declaration {
+            spans.push(self.bindings.definitions[definition].span);
+        }
+
+        spans.sort_by_key(|span| span.start);
+        spans
+    }
+
+    /// The type of the name or expression at an offset, and its span.
+    pub fn hover(&self, offset: usize) -> Option<(String, Span)> {
+        let expr = self.innermost_expr(offset);
+
+        // a use shows the type it's used at, which for a generic function
+        // is an instance of it
+        if let Some(Expr {
+            kind: ExprKind::Var(var),
+            ty: Some(ty),
+            span,
+        }) = expr
+        {
+            let ty = self.typechecker.resolve_type(ty);
+            return Some((format!("{}: {}", var.ident, ty), *span));
+        }
+
+        if let Some(definition) = self.definition_at(offset) {
+            let definition = &self.bindings.definitions[definition];
+            if contains(definition.span, offset) || expr.is_none() {
+                let ty = self.typechecker.declared_type(definition.span)?;
+                return Some((format!("{}: {}", definition.ident, ty), definition.span));
+            }
+        }
+
+        let expr = expr?;
+        let ty = self.typechecker.resolve_type(expr.ty.as_ref()?);
+        Some((ty.to_string(), expr.span))
+    }
+
+    fn innermost_expr(&self, offset: usize) -> Option<&Expr> {
+        let mut innermost: Option<&Expr> = None;
+        for statement in &self.statements {
+            for_each_expr(statement, &mut |expr| {
+                if contains(expr.span, offset)
+                    && innermost.is_none_or(|found| expr.span.len() <= found.span.len())
+                {
+                    innermost = Some(expr);
+                }
+            });
+        }
+        innermost
+    }
+
+    /// An outline of the declarations in the document.
+    pub fn symbols(&self) -> Vec<Symbol> {
+        self.statements
+            .iter()
+            .filter_map(|statement| self.symbol(statement))
+            .collect()
+    }
+
+    fn symbol(&self, statement: &Statement) -> Option<Symbol> {
+        let symbol = |name: Intern<String>, kind, span, name_span, children| Symbol {
+            name: name.to_string(),
+            kind,
+            detail: None,
+            span,
+            name_span,
+            children,
+        };
+
+        Some(match statement {
+            Statement::FnDecl(fn_decl) => self.fn_symbol(fn_decl, SymbolKind::Function),
+            Statement::VarDecl(var_decl) => Symbol {
+                detail: self
+                    .typechecker
+                    .declared_type(var_decl.ident_span)
+                    .map(|ty| ty.to_string()),
+                ..symbol(
+                    var_decl.ident,
+                    SymbolKind::Variable,
+                    var_decl.span,
+                    var_decl.ident_span,
+                    vec![],
+                )
+            },
+            Statement::StructDecl(struct_decl) => {
+                let fields = struct_decl
+                    .fields
+                    .iter()
+                    .map(|field| Symbol {
+                        detail: Some(self.source.span_str(field.ty.span).to_string()),
+                        ..symbol(
+                            field.ident,
+                            SymbolKind::Field,
+                            field.span,
+                            field.span,
+                            vec![],
+                        )
+                    })
+                    .collect();
+                symbol(
+                    struct_decl.ident,
+                    SymbolKind::Struct,
+                    struct_decl.span,
+                    name_span(struct_decl.span, struct_decl.ident),
+                    fields,
+                )
+            }
+            Statement::EnumDecl(enum_decl) => {
+                let variants = enum_decl
+                    .variants
+                    .iter()
+                    .map(|variant| Symbol {
+                        detail: variant
+                            .payload
+                            .as_ref()
+                            .map(|payload| self.source.span_str(payload.span).to_string()),
+                        ..symbol(
+                            variant.ident,
+                            SymbolKind::Variant,
+                            variant.span,
+                            variant.span,
+                            vec![],
+                        )
+                    })
+                    .collect();
+                symbol(
+                    enum_decl.ident,
+                    SymbolKind::Enum,
+                    enum_decl.span,
+                    name_span(enum_decl.span, enum_decl.ident),
+                    variants,
+                )
+            }
+            Statement::NewtypeDecl(newtype_decl) => Symbol {
+                detail: Some(self.source.span_str(newtype_decl.base.span).to_string()),
+                ..symbol(
+                    newtype_decl.ident,
+                    SymbolKind::Newtype,
+                    newtype_decl.span,
+                    name_span(newtype_decl.span, newtype_decl.ident),
+                    vec![],
+                )
+            },
+            Statement::TraitDecl(trait_decl) => {
+                let methods = trait_decl
+                    .methods
+                    .iter()
+                    .map(|sig| {
+                        symbol(
+                            sig.ident,
+                            SymbolKind::Method,
+                            sig.span,
+                            sig.ident_span(),
+                            vec![],
+                        )
+                    })
+                    .collect();
+                symbol(
+                    trait_decl.ident,
+                    SymbolKind::Trait,
+                    trait_decl.span,
+                    name_span(trait_decl.span, trait_decl.ident),
+                    methods,
+                )
+            }
+            Statement::ImplDecl(impl_decl) => {
+                let ty = self.source.span_str(impl_decl.ty.span);
+                let name = match impl_decl.trait_ {
+                    Some((trait_, _)) => format!("impl {} for {}", trait_, ty),
+                    None => format!("impl {}", ty),
+                };
+                let methods = impl_decl
+                    .methods
+                    .iter()
+                    .map(|method| self.fn_symbol(method, SymbolKind::Method))
+                    .collect();
+                Symbol {
+                    name,
+                    kind: SymbolKind::Impl,
+                    detail: None,
+                    span: impl_decl.span,
+                    name_span: impl_decl.ty.span,
+                    children: methods,
+                }
+            }
+            _ => return None,
+        })
+    }
+
+    fn fn_symbol(&self, fn_decl: &FnDecl, kind: SymbolKind) -> Symbol {
+        let name_span = fn_decl.sig.ident_span();
+        Symbol {
+            name: fn_decl.sig.ident.to_string(),
+            kind,
+            detail: self
+                .typechecker
+                .declared_type(name_span)
+                .map(|ty| ty.to_string()),
+            span: fn_decl.sig.span.join(fn_decl.body.span),
+            name_span,
+            children: vec![],
+        }
+    }
+
+    /// The names that can be written at an offset. Inner variables come
+    /// before the outer ones they shadow.
+    pub fn completions(&self, offset: usize) -> Vec<(String, CompletionKind)> {
+        let mut completions: Vec<(String, CompletionKind)> = vec![];
+        let mut add = |name: &str, kind| {
+            if !completions.iter().any(|(seen, _)| seen == name) {
+                completions.push((name.to_string(), kind));
+            }
+        };
+
+        // functions can't see the variables of the top level
+        let in_function = self.statements.iter().any(|statement| match statement {
+            Statement::FnDecl(fn_decl) => contains(fn_decl.body.span, offset),
+            Statement::ImplDecl(impl_decl) => impl_decl
+                .methods
+                .iter()
+                .any(|method| contains(method.body.span, offset)),
+            _ => false,
+        });
+
+        for definition in self.bindings.definitions.iter().rev() {
+            match definition.kind {
+                DefinitionKind::Function => add(&definition.ident, CompletionKind::Function),
+                DefinitionKind::Variable => {
+                    let in_scope = definition.span.end <= offset
+                        && match definition.scope {
+                            Some(scope) => contains(scope, offset),
+                            None => !in_function,
+                        };
+                    if in_scope {
+                        add(&definition.ident, CompletionKind::Variable);
+                    }
+                }
+            }
+        }
+
+        for func in BUILTIN_FNS {
+            add(func.name, CompletionKind::Function);
+        }
+
+        for keyword in KEYWORDS {
+            add(keyword, CompletionKind::Keyword);
+        }
+
+        completions
+    }
+}
+
+fn contains(span: Span, offset: usize) -> bool {
+    span.start <= offset && offset <= span.end
+}
+
+/// The span of the name that a declaration's span ends with.
+fn name_span(span: Span, ident: Intern<String>) -> Span {
+    Span {
+        start: span.end - ident.len(),
+        end: span.end,
+    }
+}
+
+fn for_each_expr<'a>(statement: &'a Statement, f: &mut impl FnMut(&'a Expr)) {
+    let block = |block: &'a Block, f: &mut _| {
+        for statement in &block.statements {
+            for_each_expr(statement, f);
+        }
+    };
+
+    match statement {
+        Statement::Expr(expr) => visit_expr(expr, f),
+        Statement::Block(body) => block(body, f),
+        Statement::VarDecl(var_decl) => visit_expr(&var_decl.value, f),
+        Statement::FnDecl(fn_decl) => block(&fn_decl.body, f),
+        Statement::ImplDecl(impl_decl) => {
+            for method in &impl_decl.methods {
+                block(&method.body, f);
+            }
+        }
+        Statement::If(if_statement) => {
+            visit_expr(&if_statement.condition, f);
+            block(&if_statement.then, f);
+            if let Some(else_) = &if_statement.else_ {
+                for_each_expr(else_, f);
+            }
+        }
+        Statement::While(while_loop) => {
+            visit_expr(&while_loop.condition, f);
+            block(&while_loop.body, f);
+        }
+        Statement::Match(match_statement) => {
+            visit_expr(&match_statement.scrutinee, f);
+            for arm in &match_statement.arms {
+                block(&arm.body, f);
+            }
+        }
+        Statement::Try(try_statement) => {
+            block(&try_statement.body, f);
+            block(&try_statement.recover, f);
+        }
+        Statement::Return(return_statement) => {
+            if let Some(value) = &return_statement.value {
+                visit_expr(value, f);
+            }
+        }
+        Statement::StructDecl(_)
+        | Statement::EnumDecl(_)
+        | Statement::NewtypeDecl(_)
+        | Statement::TraitDecl(_) => {}
+    }
+}
+
+fn visit_expr<'a>(expr: &'a Expr, f: &mut impl FnMut(&'a Expr)) {
+    f(expr);
+
+    match &expr.kind {
+        ExprKind::Var(_) | ExprKind::Value(_) | ExprKind::DummyExpr => {}
+
+        ExprKind::LogicalOr(lhs, rhs)
+        | ExprKind::LogicalAnd(lhs, rhs)
+        | ExprKind::Equal(lhs, rhs)
+        | ExprKind::NotEqual(lhs, rhs)
+        | ExprKind::LT(lhs, rhs)
+        | ExprKind::GT(lhs, rhs)
+        | ExprKind::LTEqual(lhs, rhs)
+        | ExprKind::GTEqual(lhs, rhs)
+        | ExprKind::Add(lhs, rhs)
+        | ExprKind::Sub(lhs, rhs)
+        | ExprKind::Mul(lhs, rhs)
+        | ExprKind::Div(lhs, rhs)
+        | ExprKind::Mod(lhs, rhs)
+        | ExprKind::Pow(lhs, rhs)
+        | ExprKind::BitAnd(lhs, rhs)
+        | ExprKind::BitOr(lhs, rhs)
+        | ExprKind::BitXor(lhs, rhs)
+        | ExprKind::Shl(lhs, rhs)
+        | ExprKind::Shr(lhs, rhs) => {
+            visit_expr(lhs, f);
+            visit_expr(rhs, f);
+        }
+
+        ExprKind::LogicalNot(expr)
+        | ExprKind::Neg(expr)
+        | ExprKind::BitNot(expr)
+        | ExprKind::Field(expr, _)
+        | ExprKind::Cast(expr, _)
+        | ExprKind::Try(expr)
+        | ExprKind::DebugPrint(expr)
+        | ExprKind::Panic(expr) => visit_expr(expr, f),
+
+        ExprKind::Assignment(_, rhs) => visit_expr(rhs, f),
+
+        ExprKind::Call(callee, args) => {
+            visit_expr(callee, f);
+            for arg in args {
+                visit_expr(arg, f);
+            }
+        }
+
+        ExprKind::MethodCall(receiver, _, args) => {
+            visit_expr(receiver, f);
+            for arg in args {
+                visit_expr(arg, f);
+            }
+        }
+
+        ExprKind::Variant(_, args) => {
+            for arg in args {
+                visit_expr(arg, f);
+            }
+        }
+
+        ExprKind::StructLiteral(literal) => {
+            for field in &literal.fields {
+                visit_expr(&field.value, f);
+            }
+        }
+    }
+}
@@ -0,0 +1,415 @@
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+
+use crate::compiler::diagnostic::{ErrorReport, Severity};
+use crate::json::Json;
+use crate::source::{Source, Span};
+
+use self::analysis::{Analysis, CompletionKind, Symbol, SymbolKind};
+
+mod analysis;
+
+// error codes from the JSON-RPC spec
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+/// A language server, talking the language server protocol over a pair of
+/// streams. Documents are kept in full and reanalysed on every change.
+#[derive(Default)]
+pub struct LanguageServer {
+    documents: HashMap<String, Analysis>,
+    shut_down: bool,
+}
+
+impl LanguageServer {
+    /// Handle messages until the client says to exit. Returns whether the
+    /// client asked the server to shut down first, as it should.
+    pub fn serve(&mut self, mut input: impl BufRead, mut output: impl Write) -> io::Result<bool> {
+        while let Some(body) = read_message(&mut input)? {
+            let message = match Json::parse(&body) {
+                Ok(message) => message,
+                Err(err) => {
+                    write_message(&mut output, &error_response(Json::Null, PARSE_ERROR, err))?;
+                    continue;
+                }
+            };
+
+            if message.get("method").as_str() == Some("exit") {
+                return Ok(self.shut_down);
+            }
+
+            for reply in self.handle(&message) {
+                write_message(&mut output, &reply)?;
+            }
+        }
+
+        Ok(self.shut_down)
+    }
+
+    /// The messages to send back for a request or notification.
+    fn handle(&mut self, message: &Json) -> Vec<Json> {
+        let method = message.get("method").as_str().unwrap_or_default();
+        let params = message.get("params");
+        let id = message.get("id");
+
+        // notifications have no id, and get no response
+        if id.is_null() {
+            return self.notification(method, params);
+        }
+
+        if self.shut_down {
+            let err = "the server has been shut down".to_string();
+            return vec![error_response(id.clone(), INVALID_REQUEST, err)];
+        }
+
+        let result = match method {
+            "initialize" => Ok(capabilities()),
+            "shutdown" => {
+                self.shut_down = true;
+                Ok(Json::Null)
+            }
+            "textDocument/definition" => self.definition(params),
+            "textDocument/references" => self.references(params),
+            "textDocument/hover" => self.hover(params),
+            "textDocument/documentSymbol" => self.symbols(params),
+            "textDocument/completion" => self.completion(params),
+            _ => Err((METHOD_NOT_FOUND, format!("unknown method `{}`", method))),
+        };
+
+        vec![match result {
+            Ok(result) => Json::object([
+                ("jsonrpc", "2.0".into()),
+                ("id", id.clone()),
+                ("result", result),
+            ]),
+            Err((code, message)) => error_response(id.clone(), code, message),
+        }]
+    }
+
+    fn notification(&mut self, method: &str, params: &Json) -> Vec<Json> {
+        let document = params.get("textDocument");
+        let uri = match document.get("uri").as_str() {
+            Some(uri) => uri.to_string(),
+            None => return vec![],
+        };
+
+        let text = match method {
+            "textDocument/didOpen" => document.get("text").as_str(),
+
+            // only full syncing is supported, so the last change has it all
+            "textDocument/didChange" => params
+                .get("contentChanges")
+                .as_array()
+                .and_then(|changes| changes.last())
+                .and_then(|change| change.get("text").as_str()),
+
+            "textDocument/didClose" => {
+                self.documents.remove(&uri);
+                return vec![publish_diagnostics(&uri, vec![])];
+            }
+            _ => None,
+        };
+
+        let text = match text {
+            Some(text) => text.to_string(),
+            None => return vec![],
+        };
+
+        let name = uri.strip_prefix("file://").unwrap_or(&uri).to_string();
+        let analysis = Analysis::new(Source::new(name, text));
+        let diagnostics = analysis
+            .diagnostics()
+            .iter()
+            .map(|report| diagnostic(&analysis.source, report))
+            .collect();
+        self.documents.insert(uri.clone(), analysis);
+
+        vec![publish_diagnostics(&uri, diagnostics)]
+    }
+
+    /// The document and offset that a request is about.
+    fn position<'a>(
+        &'a self,
+        params: &'a Json,
+    ) -> Result<(&'a str, &'a Analysis, usize), (i64, String)> {
+        let uri = params.get("textDocument").get("uri").as_str();
+        let uri = uri.ok_or((INVALID_PARAMS, "missing document".to_string()))?;
+        let analysis = self
+            .documents
+            .get(uri)
+            .ok_or_else(|| (INVALID_PARAMS, format!("`{}` isn't open", uri)))?;
+        let offset = offset(&analysis.source, params.get("position"))
+            .ok_or((INVALID_PARAMS, "invalid position".to_string()))?;
+        Ok((uri, analysis, offset))
+    }
+
+    fn definition(&self, params: &Json) -> Result<Json, (i64, String)> {
+        let (uri, analysis, offset) = self.position(params)?;
+        Ok(analysis
+            .definition(offset)
+            .map(|span| location(uri, &analysis.source, span))
+            .into())
+    }
+
+    fn references(&self, params: &Json) -> Result<Json, (i64, String)> {
+        let (uri, analysis, offset) = self.position(params)?;
+        let include_declaration = params
+            .get("context")
+            .get("includeDeclaration")
+            .as_bool()
+            .unwrap_or(true);
+
+        Ok(analysis
+            .references(offset, include_declaration)
+            .into_iter()
+            .map(|span| location(uri, &analysis.source, span))
+            .collect::<Vec<_>>()
+            .into())
+    }
+
+    fn hover(&self, params: &Json) -> Result<Json, (i64, String)> {
+        let (_, analysis, offset) = self.position(params)?;
+        Ok(match analysis.hover(offset) {
+            Some((text, span)) => Json::object([
+                (
+                    "contents",
+                    Json::object([
+                        ("kind", "markdown".into()),
+                        ("value", format!("```jellyfish\n{}\n```", text).into()),
+                    ]),
+                ),
+                ("range", range(&analysis.source, span)),
+            ]),
+            None => Json::Null,
+        })
+    }
+
+    fn symbols(&self, params: &Json) -> Result<Json, (i64, String)> {
+        let uri = params.get("textDocument").get("uri").as_str();
+        let analysis = uri
+            .and_then(|uri| self.documents.get(uri))
+            .ok_or((INVALID_PARAMS, "the document isn't open".to_string()))?;
+
+        Ok(analysis
+            .symbols()
+            .iter()
+            .map(|symbol| document_symbol(&analysis.source, symbol))
+            .collect::<Vec<_>>()
+            .into())
+    }
+
+    fn completion(&self, params: &Json) -> Result<Json, (i64, String)> {
+        let (_, analysis, offset) = self.position(params)?;
+        Ok(analysis
+            .completions(offset)
+            .into_iter()
+            .map(|(label, kind)| {
+                let kind = match kind {
+                    CompletionKind::Function => 3,
+                    CompletionKind::Variable => 6,
+                    CompletionKind::Keyword => 14,
+                };
+                Json::object([("label", label.into()), ("kind", kind.into())])
+            })
+            .collect::<Vec<_>>()
+            .into())
+    }
+}
+
+fn capabilities() -> Json {
+    Json::object([
+        (
+            "capabilities",
+            Json::object([
+                // full document syncing
+                ("textDocumentSync", 1.into()),
+                ("definitionProvider", true.into()),
+                ("referencesProvider", true.into()),
+                ("hoverProvider", true.into()),
+                ("documentSymbolProvider", true.into()),
+                ("completionProvider", Json::object([])),
+            ]),
+        ),
+        (
+            "serverInfo",
+            Json::object([
+                ("name", "jellyfish".into()),
+                ("version", env!("CARGO_PKG_VERSION").into()),
+            ]),
+        ),
+    ])
+}
+
+fn error_response(id: Json, code: i64, message: String) -> Json {
+    Json::object([
+        ("jsonrpc", "2.0".into()),
+        ("id", id),
+        (
+            "error",
+            Json::object([
+                ("code", Json::Number(code as f64)),
+                ("message", message.into()),
+            ]),
+        ),
+    ])
+}
+
+fn publish_diagnostics(uri: &str, diagnostics: Vec<Json>) -> Json {
+    Json::object([
+        ("jsonrpc", "2.0".into()),
+        ("method", "textDocument/publishDiagnostics".into()),
+        (
+            "params",
+            Json::object([("uri", uri.into()), ("diagnostics", diagnostics.into())]),
+        ),
+    ])
+}
+
+fn diagnostic(source: &Source, report: &ErrorReport) -> Json {
+    let span = report
+        .labels
+        .iter()
+        .find_map(|label| label.span)
+        .unwrap_or_default();
+
+    let mut message = report.title.to_string();
+    for label in &report.labels {
+        message += &format!("\n{}", label.msg);
+    }
+    for note in &report.notes {
+        message += &format!("\nnote: {}", note);
+    }
+    for hint in &report.hints {
+        message += &format!("\nhint: {}", hint);
+    }
+
+    let severity = match report.severity {
+        Severity::Error => 1,
+        Severity::Warning => 2,
+        Severity::Note => 3,
+    };
+
+    Json::object([
+        ("range", range(source, span)),
+        ("severity", severity.into()),
+        ("code", report.code.into()),
+        ("source", "jellyfish".into()),
+        ("message", message.into()),
+    ])
+}
+
+fn document_symbol(source: &Source, symbol: &Symbol) -> Json {
+    let kind = match symbol.kind {
+        SymbolKind::Impl => 3,
+        SymbolKind::Method => 6,
+        SymbolKind::Field => 8,
+        SymbolKind::Enum => 10,
+        SymbolKind::Trait => 11,
+        SymbolKind::Function => 12,
+        SymbolKind::Variable => 13,
+        SymbolKind::Variant => 22,
+        SymbolKind::Struct | SymbolKind::Newtype => 23,
+    };
+
+    let children: Vec<Json> = symbol
+        .children
+        .iter()
+        .map(|child| document_symbol(source, child))
+        .collect();
+
+    Json::object([
+        ("name", symbol.name.as_str().into()),
+        ("detail", symbol.detail.clone().into()),
+        ("kind", kind.into()),
+        ("range", range(source, symbol.span)),
+        ("selectionRange", range(source, symbol.name_span)),
+        ("children", children.into()),
+    ])
+}
+
+fn location(uri: &str, source: &Source, span: Span) -> Json {
+    Json::object([("uri", uri.into()), ("range", range(source, span))])
+}
+
+fn range(source: &Source, span: Span) -> Json {
+    Json::object([
+        ("start", position(source, span.start)),
+        ("end", position(source, span.end)),
+    ])
+}
+
+/// The protocol's position for an offset, which counts lines from zero and
+/// characters in UTF-16 code units.
+fn position(source: &Source, offset: usize) -> Json {
+    let line_span = source.line_span(offset);
+    let character: usize = source.source[line_span.start..offset]
+        .chars()
+        .map(char::len_utf16)
+        .sum();
+
+    Json::object([
+        ("line", source.line_index(offset).into()),
+        ("character", character.into()),
+    ])
+}
+
+/// The offset of a position, clamped to the end of its line.
+fn offset(source: &Source, position: &Json) -> Option<usize> {
+    let start = source.line_start(position.get("line").as_usize()?)?;
+    let line = source.source[start..]
+        .split('\n')
+        .next()
+        .unwrap_or_default();
+
+    let mut remaining = position.get("character").as_usize()?;
+    let mut offset = start;
+    for c in line.chars() {
+        if remaining < c.len_utf16() {
+            break;
+        }
+        remaining -= c.len_utf16();
+        offset += c.len_utf8();
+    }
+
+    Some(offset)
+}
+
+fn read_message(input: &mut impl BufRead) -> io::Result<Option<String>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse().ok();
+            }
+        }
+    }
+
+    let length = length.ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidData, "missing Content-Length header")
+    })?;
+
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+
+    String::from_utf8(body)
+        .map(Some)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+fn write_message(output: &mut impl Write, message: &Json) -> io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    output.flush()
+}
@@ -1,53 +1,580 @@
-use jellyfish_lang::{CompiledProgram, ErrorReporter, Source, VM};
+use std::io::{IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 
-fn main() {
-    let cmd = std::env::args().next().unwrap();
+use jellyfish_lang::{
+    dump_ast, dump_tokens, format_source, ColorChoice, CompiledProgram, ErrorFormat, ErrorReporter,
+    Explanation, LanguageServer, Lint, LintLevel, PassOptions, Repl, ReplError, RuntimeError,
+    Source, BYTECODE_MAGIC, VM,
+};
 
-    let path = match std::env::args().nth(1) {
-        Some(path) => path,
-        None => {
-            eprintln!("USAGE: {} <input_file>", cmd);
-            return;
-        }
+const USAGE: &str = "\
+USAGE:
+    jellyfish <command> [options] <file> [args...]
+    jellyfish <file> [args...]
+    jellyfish explain <code>
+
+COMMANDS:
+    run       compile and run a program
+    check     report errors without running
+    disasm    print the compiled bytecode
+    tokens    print the tokens of a source file
+    ast       print the syntax tree of a source file
+    fmt       format source files in place
+    repl      start an interactive session
+    lsp       start a language server on standard input and output
+    test      run the programs in the given files or directories
+    explain   describe an error code, such as `J0009`, or a lint
+
+OPTIONS:
+    --color=<when>      colour diagnostics: auto (the default), always or never
+    --no-color          the same as `--color=never`
+    --error-format=json print diagnostics as JSON, one object per line
+    --error-format=short
+                        print diagnostics on one line each, as
+                        `file:line:col: error: message`
+    --emit=bytecode     write the compiled program instead of running it
+    -o <file>           where to write the compiled program
+    --no-fold           don't fold constant expressions
+    --no-peephole       don't optimise the bytecode
+    --check             with `fmt`, list the files that aren't formatted
+    -A, --allow <lint>  don't report a lint
+    -W, --warn <lint>   report a lint as a warning
+    -D, --deny <lint>   report a lint as an error
+
+LINTS:
+    unused_variable, unused_assignment, shadowed_binding, unreachable_code,
+    constant_condition
+
+Lints can also be set in the source, with a comment such as
+`#[allow(unused_variable)]` before a statement, or `#![deny(...)]` for the
+whole file.
+
+Use `-` as the file to read from standard input.";
+
+/// Exit codes, following `sysexits.h` where one fits.
+mod exit {
+    pub const COMPILE_ERROR: u8 = 1;
+    pub const RUNTIME_ERROR: u8 = 2;
+
+    /// The language server was told to exit before it was shut down, which
+    /// the protocol treats as a crash.
+    pub const LSP_NO_SHUTDOWN: u8 = 1;
+
+    pub const USAGE: u8 = 64;
+    pub const IO_ERROR: u8 = 74;
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Command {
+    Run,
+    Check,
+    Disasm,
+    Tokens,
+    Ast,
+    Fmt,
+    Repl,
+    Lsp,
+    Test,
+    Explain,
+}
+
+impl Command {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "run" => Self::Run,
+            "check" => Self::Check,
+            "disasm" => Self::Disasm,
+            "tokens" => Self::Tokens,
+            "ast" => Self::Ast,
+            "fmt" => Self::Fmt,
+            "repl" => Self::Repl,
+            "lsp" => Self::Lsp,
+            "test" => Self::Test,
+            "explain" => Self::Explain,
+            _ => return None,
+        })
+    }
+}
+
+struct Options {
+    passes: PassOptions,
+    color: ColorChoice,
+    error_format: ErrorFormat,
+    emit_bytecode: bool,
+    output: Option<String>,
+    check: bool,
+}
+
+/// A failed command, carrying the exit code.
+struct Failure(u8);
+
+type CliResult = Result<(), Failure>;
+
+fn main() -> ExitCode {
+    match run_cli(std::env::args().skip(1).collect()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(Failure(code)) => ExitCode::from(code),
+    }
+}
+
+fn run_cli(args: Vec<String>) -> CliResult {
+    let mut args = args.into_iter();
+
+    let mut options = Options {
+        passes: PassOptions::default(),
+        color: ColorChoice::Auto,
+        error_format: ErrorFormat::Human,
+        emit_bytecode: false,
+        output: None,
+        check: false,
     };
+    let mut command = None;
+    let mut paths = vec![];
 
-    let source = match std::fs::read_to_string(path.clone()) {
-        Ok(source) => Source::new(path, source),
-        Err(_) => {
-            eprintln!("ERROR: couldn't open file");
-            return;
+    while let Some(arg) = args.next() {
+        // everything after the program belongs to it, even if it looks
+        // like an option
+        if command == Some(Command::Run) && !paths.is_empty() {
+            paths.push(arg);
+            paths.extend(args.by_ref());
+            break;
+        }
+
+        match arg.as_str() {
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return Ok(());
+            }
+            "--no-color" | "--color=never" => options.color = ColorChoice::Never,
+            "--color=auto" => options.color = ColorChoice::Auto,
+            "--color=always" => options.color = ColorChoice::Always,
+            "--error-format=human" => options.error_format = ErrorFormat::Human,
+            "--error-format=json" => options.error_format = ErrorFormat::Json,
+            "--error-format=short" => options.error_format = ErrorFormat::Short,
+            "--no-fold" => options.passes.fold_constants = false,
+            "--no-peephole" => options.passes.peephole = false,
+            "--emit=bytecode" => options.emit_bytecode = true,
+            "--check" => options.check = true,
+            "-o" => match args.next() {
+                Some(output) => options.output = Some(output),
+                None => return usage_error("`-o` needs a file"),
+            },
+            "-A" | "--allow" | "-W" | "--warn" | "-D" | "--deny" => {
+                let level = match arg.as_str() {
+                    "-A" | "--allow" => LintLevel::Allow,
+                    "-W" | "--warn" => LintLevel::Warn,
+                    _ => LintLevel::Deny,
+                };
+                match args.next().as_deref().map(Lint::from_name) {
+                    Some(Some(lint)) => options.passes.lints.set(lint, level),
+                    Some(None) => return usage_error(&format!("unknown lint after `{}`", arg)),
+                    None => return usage_error(&format!("`{}` needs a lint", arg)),
+                }
+            }
+            flag if flag.starts_with("--color=") => {
+                let when = &flag["--color=".len()..];
+                return usage_error(&format!("unknown colour choice `{}`", when));
+            }
+            flag if flag.starts_with("--error-format=") => {
+                let format = &flag["--error-format=".len()..];
+                return usage_error(&format!("unknown error format `{}`", format));
+            }
+            flag if flag.starts_with("--emit=") => {
+                return usage_error(&format!("can't emit `{}`", &flag["--emit=".len()..]));
+            }
+            flag if flag.starts_with('-') && flag != "-" => {
+                return usage_error(&format!("unknown option `{}`", flag));
+            }
+            _ if command.is_none() && paths.is_empty() => match Command::from_name(&arg) {
+                Some(cmd) => command = Some(cmd),
+                None => {
+                    command = Some(Command::Run);
+                    paths.push(arg);
+                }
+            },
+            _ => paths.push(arg),
         }
+    }
+
+    let command = match command {
+        Some(command) => command,
+        None => return usage_error("no command given"),
+    };
+
+    match command {
+        Command::Test => return test(&paths, &options),
+        Command::Fmt => return fmt(&paths, &options),
+        Command::Repl => return repl(&options),
+        Command::Lsp => return lsp(),
+        Command::Explain => return explain(&paths),
+        _ => {}
+    }
+
+    let (path, script_args) = match paths.split_first() {
+        Some((path, script_args)) => (path, script_args),
+        None => return usage_error("no file given"),
     };
 
+    let input = read_input(path)?;
+    match command {
+        Command::Run if options.emit_bytecode => emit(path, &input, &options),
+        Command::Run => run(path, &input, script_args, &options),
+        Command::Check => compile(path, &input, &options).map(|_| ()),
+        Command::Disasm => disasm(path, &input, &options),
+        Command::Tokens => {
+            let source = source(path, input)?;
+            print!("{}", dump_tokens(&source));
+            Ok(())
+        }
+        Command::Ast => {
+            let source = source(path, input)?;
+            let mut diagnostics = reporter(&options);
+            let result = dump_ast(&source, &mut diagnostics);
+            diagnostics.print(&source);
+            match result {
+                Ok(ast) => {
+                    print!("{}", ast);
+                    Ok(())
+                }
+                Err(()) => Err(Failure(exit::COMPILE_ERROR)),
+            }
+        }
+        Command::Fmt | Command::Repl | Command::Lsp | Command::Test | Command::Explain => {
+            unreachable!()
+        }
+    }
+}
+
+fn usage_error(message: &str) -> CliResult {
+    eprintln!("error: {}\n\n{}", message, USAGE);
+    Err(Failure(exit::USAGE))
+}
+
+fn reporter(options: &Options) -> ErrorReporter {
     let mut diagnostics = ErrorReporter::default();
+    diagnostics.set_color(options.color);
+    diagnostics.set_format(options.error_format);
+    diagnostics
+}
 
-    let compile_result = CompiledProgram::compile(&source, &mut diagnostics);
-    diagnostics.print(&source);
+/// The contents of a file, or of standard input for `-`.
+fn read_input(path: &str) -> Result<Vec<u8>, Failure> {
+    let result = match path {
+        "-" => {
+            let mut bytes = vec![];
+            std::io::stdin().read_to_end(&mut bytes).map(|_| bytes)
+        }
+        _ => std::fs::read(path),
+    };
 
-    let program = match compile_result {
-        Ok(module) => module,
+    result.map_err(|err| {
+        eprintln!("error: couldn't read `{}`: {}", path, err);
+        Failure(exit::IO_ERROR)
+    })
+}
+
+fn is_bytecode(path: &str, input: &[u8]) -> bool {
+    path.ends_with(".jlc") || input.starts_with(BYTECODE_MAGIC)
+}
+
+fn source(path: &str, input: Vec<u8>) -> Result<Source, Failure> {
+    let name = match path {
+        "-" => "<stdin>".to_string(),
+        _ => path.to_string(),
+    };
+
+    match String::from_utf8(input) {
+        Ok(text) => Ok(Source::new(name, text)),
         Err(_) => {
-            eprintln!("exiting with errors");
-            return;
+            eprintln!("error: `{}` isn't valid UTF-8", name);
+            Err(Failure(exit::IO_ERROR))
+        }
+    }
+}
+
+/// Compile a source file or load a bytecode file, then hand the program
+/// to `f`.
+fn with_program<T>(
+    path: &str,
+    input: &[u8],
+    options: &Options,
+    f: impl FnOnce(CompiledProgram) -> Result<T, Failure>,
+) -> Result<T, Failure> {
+    if is_bytecode(path, input) {
+        return match CompiledProgram::from_bytes(input) {
+            Ok(program) => f(program),
+            Err(err) => {
+                eprintln!("error: couldn't load `{}`: {}", path, err);
+                Err(Failure(exit::COMPILE_ERROR))
+            }
+        };
+    }
+
+    let source = source(path, input.to_vec())?;
+    let mut diagnostics = reporter(options);
+    let result = CompiledProgram::compile_with(&source, &mut diagnostics, options.passes);
+    diagnostics.print(&source);
+
+    match result {
+        Ok(program) => f(program),
+        Err(()) => Err(Failure(exit::COMPILE_ERROR)),
+    }
+}
+
+fn compile(path: &str, input: &[u8], options: &Options) -> Result<Vec<u8>, Failure> {
+    with_program(path, input, options, |program| Ok(program.to_bytes()))
+}
+
+fn run(path: &str, input: &[u8], script_args: &[String], options: &Options) -> CliResult {
+    with_program(path, input, options, |program| {
+        let source = program.source;
+        let lines = program.lines.clone();
+        let mut vm = VM::new();
+        vm.set_args(script_args);
+
+        let err = match vm.run(program) {
+            Ok(()) => return Ok(()),
+            // whatever was reading the output has stopped, so there's no
+            // one to tell
+            Err(RuntimeError::OutputClosed) => return Ok(()),
+            Err(err) => err,
+        };
+
+        // bytecode has no source, so the error can only name the file, and
+        // the line and column from its line table
+        let span = source.and(vm.error_span());
+        let mut report = err.report(span);
+        let line_col = vm
+            .error_span()
+            .zip(lines)
+            .and_then(|(span, lines)| lines.line_col(span.start));
+        if let Some(line_col) = line_col {
+            report = report.with_note(format!(
+                "raised at line {}, column {} of the source",
+                line_col.line, line_col.col
+            ));
+        }
+        let no_source;
+        let source = match source {
+            Some(source) => source,
+            None => {
+                no_source = Source::new(path.to_string(), String::new());
+                &no_source
+            }
+        };
+
+        let stderr = std::io::stderr();
+        let color = options.color.enabled(&stderr);
+        let mut emitter = reporter(options).emitter(stderr.lock(), color);
+        let _ = emitter.emit(&report, source);
+        Err(Failure(exit::RUNTIME_ERROR))
+    })
+}
+
+fn disasm(path: &str, input: &[u8], options: &Options) -> CliResult {
+    with_program(path, input, options, |program| {
+        print!("{}", program.chunk.disassemble(program.source));
+        Ok(())
+    })
+}
+
+fn emit(path: &str, input: &[u8], options: &Options) -> CliResult {
+    let bytes = compile(path, input, options)?;
+
+    let output = match (&options.output, path) {
+        (Some(output), _) => output.clone(),
+        (None, "-") => "-".to_string(),
+        (None, path) => Path::new(path)
+            .with_extension("jlc")
+            .to_string_lossy()
+            .into_owned(),
+    };
+
+    let result = match output.as_str() {
+        "-" => std::io::stdout().write_all(&bytes),
+        _ => std::fs::write(&output, bytes),
+    };
+    result.map_err(|err| {
+        eprintln!("error: couldn't write `{}`: {}", output, err);
+        Failure(exit::IO_ERROR)
+    })
+}
+
+/// Format each file in place, or with `--check`, list the files that
+/// aren't formatted. Standard input is formatted to standard output.
+fn fmt(paths: &[String], options: &Options) -> CliResult {
+    if paths.is_empty() {
+        return usage_error("no file given");
+    }
+
+    let mut failed = false;
+    let mut unformatted = false;
+
+    for path in paths {
+        let source = source(path, read_input(path)?)?;
+        let mut diagnostics = reporter(options);
+        let result = format_source(&source, &mut diagnostics);
+        diagnostics.print(&source);
+
+        let formatted = match result {
+            Ok(formatted) => formatted,
+            Err(()) => {
+                failed = true;
+                continue;
+            }
+        };
+
+        if options.check {
+            if formatted != source.source {
+                println!("{} isn't formatted", path);
+                unformatted = true;
+            }
+        } else if path == "-" {
+            print!("{}", formatted);
+        } else if formatted != source.source {
+            if let Err(err) = std::fs::write(path, formatted) {
+                eprintln!("error: couldn't write `{}`: {}", path, err);
+                return Err(Failure(exit::IO_ERROR));
+            }
+        }
+    }
+
+    match failed || unformatted {
+        true => Err(Failure(exit::COMPILE_ERROR)),
+        false => Ok(()),
+    }
+}
+
+/// Read inputs from standard input and run them one by one, until it
+/// ends.
+fn repl(options: &Options) -> CliResult {
+    let mut repl = Repl::new(options.passes);
+    let stderr = std::io::stderr();
+    let color = options.color.enabled(&stderr);
+    let mut emitter = reporter(options).emitter(stderr, color);
+
+    let interactive = std::io::stdin().is_terminal();
+    let prompt = |prompt: &str| {
+        if interactive {
+            print!("{}", prompt);
+            let _ = std::io::stdout().flush();
         }
     };
 
-    println!("CHUNK\n=====");
-    for opcode in &program.chunk.code {
-        println!(
-            "{}\t{}",
-            opcode.byte(),
-            opcode
-                .instr_safe()
-                .map(|s| format!("{:?}", s))
-                .unwrap_or_else(|| "--".to_string())
-        );
+    let mut input = String::new();
+    let mut lines = std::io::stdin().lines();
+    loop {
+        prompt(if input.is_empty() { ">> " } else { ".. " });
+
+        let line = match lines.next() {
+            Some(Ok(line)) => line,
+            Some(Err(err)) => {
+                eprintln!("error: couldn't read input: {}", err);
+                return Err(Failure(exit::IO_ERROR));
+            }
+            None => return Ok(()),
+        };
+
+        input += &line;
+        input.push('\n');
+        if Repl::is_incomplete(&input) {
+            continue;
+        }
+
+        match repl.eval(&input, &mut *emitter) {
+            Ok(Some(value)) => println!("{}", value),
+            Ok(None) | Err(ReplError::Compile | ReplError::Runtime(_)) => {}
+            Err(ReplError::Io(err)) => {
+                eprintln!("error: couldn't report diagnostics: {}", err);
+                return Err(Failure(exit::IO_ERROR));
+            }
+        }
+        input.clear();
+    }
+}
+
+/// Serve an editor until it says to exit. It should ask the server to shut
+/// down first, or the exit is treated as a crash.
+fn lsp() -> CliResult {
+    let stdin = std::io::stdin();
+    match LanguageServer::default().serve(stdin.lock(), std::io::stdout().lock()) {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(Failure(exit::LSP_NO_SHUTDOWN)),
+        Err(err) => {
+            eprintln!("error: {}", err);
+            Err(Failure(exit::IO_ERROR))
+        }
+    }
+}
+
+/// Print the description of an error code or lint.
+fn explain(codes: &[String]) -> CliResult {
+    let code = match codes {
+        [code] => code,
+        [] => return usage_error("no code given"),
+        _ => return usage_error("only one code can be explained at a time"),
+    };
+
+    match Explanation::find(code) {
+        Some(explanation) => {
+            print!("{}", explanation.render());
+            Ok(())
+        }
+        None => usage_error(&format!("`{}` isn't an error code or lint", code)),
     }
-    println!();
+}
+
+/// Run every program in the given files and directories, or in `tests/`
+/// if none are given. A program passes if it compiles and runs without
+/// an unhandled panic.
+fn test(paths: &[String], options: &Options) -> CliResult {
+    let mut files = vec![];
+    let roots = match paths {
+        [] => vec![PathBuf::from("tests")],
+        paths => paths.iter().map(PathBuf::from).collect(),
+    };
+    for root in roots {
+        collect_tests(&root, &mut files)?;
+    }
+    files.sort();
 
-    let mut vm = VM::new();
-    if let Err(err) = vm.run(program) {
-        // TODO: print runtime errors nicely
-        eprintln!("RUNTIME ERROR: {:#?}", err);
+    let mut failed = 0;
+    for file in &files {
+        let path = file.to_string_lossy();
+        let result = read_input(&path).and_then(|input| run(&path, &input, &[], options));
+        match result {
+            Ok(()) => println!("test {} ... ok", path),
+            Err(_) => {
+                println!("test {} ... FAILED", path);
+                failed += 1;
+            }
+        }
+    }
+
+    println!("\n{} passed, {} failed", files.len() - failed, failed);
+    match failed {
+        0 => Ok(()),
+        _ => Err(Failure(exit::COMPILE_ERROR)),
+    }
+}
+
+fn collect_tests(path: &Path, files: &mut Vec<PathBuf>) -> CliResult {
+    let io_error = |err: std::io::Error| {
+        eprintln!("error: couldn't read `{}`: {}", path.display(), err);
+        Failure(exit::IO_ERROR)
+    };
+
+    if !path.is_dir() {
+        files.push(path.to_path_buf());
+        return Ok(());
+    }
+
+    for entry in std::fs::read_dir(path).map_err(io_error)? {
+        let entry = entry.map_err(io_error)?.path();
+        if entry.is_dir() || entry.extension().is_some_and(|ext| ext == "jlf") {
+            collect_tests(&entry, files)?;
+        }
     }
+    Ok(())
 }
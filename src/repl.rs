@@ -0,0 +1,141 @@
+use std::fmt::{Display, Formatter};
+use std::io;
+
+use crate::compiler::diagnostic::{Emitter, ErrorReporter};
+use crate::compiler::lexer::token::{punct, Punctuation, TokenKind};
+use crate::compiler::lexer::Lexer;
+use crate::compiler::parser::Parser;
+use crate::compiler::passes::{Incremental, PassOptions};
+use crate::runtime::value::Value;
+use crate::runtime::vm::{RuntimeError, VM};
+use crate::runtime::CompiledProgram;
+use crate::source::Source;
+
+/// Why an input failed.
+#[derive(Debug)]
+pub enum ReplError {
+    /// It didn't compile. The errors have been emitted.
+    Compile,
+
+    /// It failed while running. The error has been emitted too.
+    Runtime(RuntimeError),
+
+    /// The reports couldn't be emitted.
+    Io(io::Error),
+}
+
+impl Display for ReplError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Compile => write!(f, "the input didn't compile"),
+            Self::Runtime(err) => write!(f, "{}", err),
+            Self::Io(err) => write!(f, "couldn't emit diagnostics: {}", err),
+        }
+    }
+}
+
+impl From<io::Error> for ReplError {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+/// An interactive session, where each input can use the variables,
+/// functions and types of the ones before it.
+pub struct Repl {
+    passes: Incremental,
+    vm: VM,
+    options: PassOptions,
+
+    /// The inputs that have run so far. Each input is compiled as a
+    /// continuation of them, so the spans in earlier functions stay valid.
+    history: String,
+}
+
+impl Default for Repl {
+    fn default() -> Self {
+        Self::new(PassOptions::default())
+    }
+}
+
+impl Repl {
+    pub fn new(options: PassOptions) -> Self {
+        Self {
+            passes: Incremental::default(),
+            vm: VM::new(),
+            options,
+            history: String::new(),
+        }
+    }
+
+    /// Whether the input so far has unclosed brackets or strings, so more
+    /// lines are needed before it can be compiled.
+    pub fn is_incomplete(input: &str) -> bool {
+        let source = Source::new(String::new(), input.to_string());
+        let mut depth = 0;
+
+        for token in Lexer::new(source.cursor()) {
+            match token.kind {
+                punct!(LParen) | punct!(LBrace) => depth += 1,
+                punct!(RParen) | punct!(RBrace) => depth -= 1,
+                TokenKind::Error("Unterminated string.") => return true,
+                TokenKind::Eof => break,
+                _ => {}
+            }
+        }
+
+        depth > 0
+    }
+
+    /// Compile and run an input, emitting its warnings and errors. If the
+    /// input ends with an expression, its value is given back to be echoed.
+    ///
+    /// An input that fails leaves the session as it was.
+    pub fn eval(
+        &mut self,
+        input: &str,
+        emitter: &mut dyn Emitter,
+    ) -> Result<Option<String>, ReplError> {
+        let mut text = format!("{}{}", self.history, input);
+        if !text.ends_with('\n') {
+            text.push('\n');
+        }
+        let source = Source::new("<repl>".to_string(), text);
+
+        let mut diagnostics = ErrorReporter::default();
+
+        let lexer = Lexer::new(source.cursor_at(self.history.len()));
+        let mut module = Parser::new(lexer, &mut diagnostics)
+            .allow_trailing_expr()
+            .parse();
+        let mut passes = self.passes.clone();
+        let result = match diagnostics.assert_ok() {
+            Ok(()) => passes.run_passes(&mut module, self.options, &mut diagnostics),
+            Err(()) => Err(()),
+        };
+
+        // the warnings too, if it compiled
+        diagnostics.emit(&source, emitter)?;
+        let chunk = result.map_err(|()| ReplError::Compile)?;
+
+        let program = CompiledProgram {
+            source: Some(&source),
+            chunk,
+            lines: None,
+        };
+        match self.vm.eval(program) {
+            Ok(value) => {
+                self.passes = passes;
+                self.history = source.source;
+                Ok(match value {
+                    Value::Unit => None,
+                    value => Some(value.repr()),
+                })
+            }
+            Err(err) => {
+                emitter.emit(&err.report(self.vm.error_span()), &source)?;
+                Err(ReplError::Runtime(err))
+            }
+        }
+    }
+}
@@ -0,0 +1,316 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use internment::Intern;
+
+use super::chunk::{Chunk, Instr, Opcode};
+use super::value::bigint::BigInt;
+use super::value::{JellyFunction, Object, StructInstance, StructLayout, Value};
+use super::vm::RuntimeError;
+
+/// The traits built into the language, which give meaning to operators and
+/// `print`. They come first, so user traits are numbered after them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuiltinTrait {
+    Add,
+    Sub,
+    Mul,
+    Eq,
+    Ord,
+    Display,
+}
+
+impl BuiltinTrait {
+    pub const ALL: [Self; 6] = [
+        Self::Add,
+        Self::Sub,
+        Self::Mul,
+        Self::Eq,
+        Self::Ord,
+        Self::Display,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Add => "Add",
+            Self::Sub => "Sub",
+            Self::Mul => "Mul",
+            Self::Eq => "Eq",
+            Self::Ord => "Ord",
+            Self::Display => "Display",
+        }
+    }
+
+    /// The trait's only method.
+    pub fn method(self) -> &'static str {
+        match self {
+            Self::Add => "add",
+            Self::Sub => "sub",
+            Self::Mul => "mul",
+            Self::Eq => "eq",
+            Self::Ord => "cmp",
+            Self::Display => "display",
+        }
+    }
+}
+
+/// A sum type built into the language.
+pub struct BuiltinEnum {
+    pub name: &'static str,
+    pub params: &'static [&'static str],
+
+    /// Each variant's name and the index of the type parameter it holds,
+    /// if any. The first variant is the one `?` unwraps.
+    pub variants: &'static [(&'static str, Option<usize>)],
+}
+
+pub const BUILTIN_ENUMS: &[BuiltinEnum] = &[
+    BuiltinEnum {
+        name: "Option",
+        params: &["T"],
+        variants: &[("Some", Some(0)), ("None", None)],
+    },
+    BuiltinEnum {
+        name: "Result",
+        params: &["T", "E"],
+        variants: &[("Ok", Some(0)), ("Err", Some(1))],
+    },
+];
+
+/// Find a builtin variant by name, as `(enum, tag)`.
+pub fn builtin_variant(name: &str) -> Option<(&'static BuiltinEnum, usize)> {
+    BUILTIN_ENUMS.iter().find_map(|def| {
+        def.variants
+            .iter()
+            .position(|(variant, _)| *variant == name)
+            .map(|tag| (def, tag))
+    })
+}
+
+/// What a builtin function can see of the VM calling it.
+pub struct NativeContext<'a> {
+    /// The arguments passed to the script on the command line.
+    pub script_args: &'a [Intern<String>],
+}
+
+/// A function built into the language, available as a global.
+#[derive(Debug)]
+pub struct BuiltinFn {
+    pub name: &'static str,
+    pub params: &'static [&'static str],
+    pub ret: &'static str,
+    pub func: fn(&NativeContext, &[Value]) -> Result<Value, RuntimeError>,
+}
+
+macro_rules! int_fn {
+    ($name:literal, |$a:ident| $body:expr) => {
+        BuiltinFn {
+            name: $name,
+            params: &["Int"],
+            ret: "Int",
+            func: |_, args| {
+                let $a = args[0].integer()?;
+                Ok(Value::Integer($body))
+            },
+        }
+    };
+    ($name:literal, |$a:ident, $b:ident| $body:expr) => {
+        BuiltinFn {
+            name: $name,
+            params: &["Int", "Int"],
+            ret: "Int",
+            func: |_, args| {
+                let $a = args[0].integer()?;
+                let $b = args[1].integer()?;
+                Ok(Value::Integer($body))
+            },
+        }
+    };
+}
+
+/// The builtin functions, which take up the first globals.
+pub const BUILTIN_FNS: &[BuiltinFn] = &[
+    int_fn!("wrapping_add", |a, b| a.wrapping_add(b)),
+    int_fn!("wrapping_sub", |a, b| a.wrapping_sub(b)),
+    int_fn!("wrapping_mul", |a, b| a.wrapping_mul(b)),
+    int_fn!("wrapping_pow", |a, b| pow_int(a, b, |x, y| Some(
+        x.wrapping_mul(y)
+    ))?),
+    int_fn!("wrapping_neg", |a| a.wrapping_neg()),
+    int_fn!("saturating_add", |a, b| a.saturating_add(b)),
+    int_fn!("saturating_sub", |a, b| a.saturating_sub(b)),
+    int_fn!("saturating_mul", |a, b| a.saturating_mul(b)),
+    int_fn!("saturating_pow", |a, b| pow_int(a, b, |x, y| Some(
+        x.saturating_mul(y)
+    ))?),
+    int_fn!("saturating_neg", |a| a.saturating_neg()),
+    BuiltinFn {
+        name: "to_big",
+        params: &["Int"],
+        ret: "BigInt",
+        func: |_, args| Ok(Value::from(BigInt::from(args[0].integer()?))),
+    },
+    BuiltinFn {
+        name: "to_int",
+        params: &["BigInt"],
+        ret: "Int",
+        func: |_, args| {
+            let n = args[0].bigint()?.to_i64();
+            Ok(Value::Integer(n.ok_or(RuntimeError::IntegerOverflow)?))
+        },
+    },
+    BuiltinFn {
+        name: "parse_big",
+        params: &["String"],
+        ret: "BigInt",
+        func: |_, args| match &args[0] {
+            Value::String(s) => match BigInt::parse(s.trim(), 10) {
+                Some(n) => Ok(Value::from(n)),
+                None => Err(RuntimeError::Panic(format!("invalid integer `{}`", s))),
+            },
+            other => Err(RuntimeError::TypeError {
+                expected: super::value::Type::String,
+                found: other.ty(),
+            }),
+        },
+    },
+    BuiltinFn {
+        name: "arg_count",
+        params: &[],
+        ret: "Int",
+        func: |context, _| Ok(Value::Integer(context.script_args.len() as i64)),
+    },
+    BuiltinFn {
+        name: "arg",
+        params: &["Int"],
+        ret: "String",
+        func: |context, args| {
+            let n = args[0].integer()?;
+            let arg = usize::try_from(n)
+                .ok()
+                .and_then(|n| context.script_args.get(n).copied());
+            match arg {
+                Some(arg) => Ok(Value::String(arg)),
+                None => Err(RuntimeError::Panic(format!("no argument {}", n))),
+            }
+        },
+    },
+];
+
+/// Raise `base` to `exp` by squaring, where `mul` decides what happens
+/// when a step overflows.
+pub fn pow_int(
+    mut base: i64,
+    exp: i64,
+    mul: fn(i64, i64) -> Option<i64>,
+) -> Result<i64, RuntimeError> {
+    let mut exp = u64::try_from(exp).map_err(|_| RuntimeError::NegativeExponent)?;
+    let mut acc: i64 = 1;
+
+    while exp > 0 {
+        if exp & 1 == 1 {
+            acc = mul(acc, base).ok_or(RuntimeError::IntegerOverflow)?;
+        }
+        exp >>= 1;
+
+        // any higher bit needs at least this power, so if it overflows
+        // the result would too
+        if exp > 0 {
+            base = mul(base, base).ok_or(RuntimeError::IntegerOverflow)?;
+        }
+    }
+
+    Ok(acc)
+}
+
+/// Check that an integer can be used to shift by.
+pub fn shift_amount(n: i64) -> Result<u32, RuntimeError> {
+    match n {
+        0..=63 => Ok(n as u32),
+        _ => Err(RuntimeError::InvalidShift),
+    }
+}
+
+/// The struct a `recover` block receives when it catches a panic.
+pub const PANIC_STRUCT: &str = "Panic";
+
+/// The fields of [`PANIC_STRUCT`] and their types.
+pub const PANIC_FIELDS: &[(&str, &str)] =
+    &[("message", "String"), ("line", "Int"), ("column", "Int")];
+
+/// Build the value a `recover` block receives.
+pub fn panic_value(message: String, line: usize, column: usize) -> Value {
+    let layout = StructLayout {
+        name: Intern::from_ref(PANIC_STRUCT),
+        fields: PANIC_FIELDS
+            .iter()
+            .map(|(field, _)| Intern::from_ref(*field))
+            .collect(),
+    };
+
+    Value::Object(Rc::new(Object::Struct(StructInstance {
+        layout: Rc::new(layout),
+        fields: vec![
+            Value::String(Intern::new(message)),
+            Value::Integer(line as i64),
+            Value::Integer(column as i64),
+        ],
+    })))
+}
+
+/// The builtin types that implement builtin traits, and how.
+const NATIVE_IMPLS: &[(BuiltinTrait, &str, &[Instr])] = &[
+    (BuiltinTrait::Add, "Int", &[Instr::AddInt]),
+    (BuiltinTrait::Sub, "Int", &[Instr::SubInt]),
+    (BuiltinTrait::Mul, "Int", &[Instr::MulInt]),
+    (BuiltinTrait::Eq, "Int", &[Instr::Equal]),
+    (BuiltinTrait::Ord, "Int", &[Instr::CmpInt]),
+    (BuiltinTrait::Display, "Int", &[Instr::ToString]),
+    (BuiltinTrait::Add, "BigInt", &[Instr::AddInt]),
+    (BuiltinTrait::Sub, "BigInt", &[Instr::SubInt]),
+    (BuiltinTrait::Mul, "BigInt", &[Instr::MulInt]),
+    (BuiltinTrait::Eq, "BigInt", &[Instr::Equal]),
+    (BuiltinTrait::Ord, "BigInt", &[Instr::CmpInt]),
+    (BuiltinTrait::Display, "BigInt", &[Instr::ToString]),
+    (BuiltinTrait::Eq, "String", &[Instr::Equal]),
+    (BuiltinTrait::Display, "String", &[Instr::ToString]),
+    (BuiltinTrait::Eq, "Bool", &[Instr::Equal]),
+    (BuiltinTrait::Display, "Bool", &[Instr::ToString]),
+];
+
+/// The builtin traits implemented by builtin types, as `(trait, type)`.
+pub fn native_impls() -> impl Iterator<Item = (BuiltinTrait, &'static str)> {
+    NATIVE_IMPLS.iter().map(|(trait_, ty, _)| (*trait_, *ty))
+}
+
+/// The vtables of the native impls, keyed like the VM's.
+pub fn native_vtables() -> HashMap<(u8, Intern<String>), Vec<Value>> {
+    NATIVE_IMPLS
+        .iter()
+        .map(|(trait_, ty, instrs)| {
+            let arity = match trait_ {
+                BuiltinTrait::Display => 1,
+                _ => 2,
+            };
+
+            let mut chunk = Chunk::default();
+            for local in 0..arity {
+                chunk.code.push(Instr::LoadLocal.into());
+                chunk.code.push(Opcode::from(local));
+            }
+            chunk
+                .code
+                .extend(instrs.iter().map(|instr| Opcode::from(*instr)));
+            chunk.code.push(Instr::Return.into());
+
+            let function = JellyFunction {
+                name: Intern::from_ref(trait_.method()),
+                chunk: Rc::new(chunk),
+                arity: arity as usize,
+            };
+            let method = Value::Object(Rc::new(Object::JellyFunction(Box::new(function))));
+
+            ((*trait_ as u8, Intern::from_ref(*ty)), vec![method])
+        })
+        .collect()
+}
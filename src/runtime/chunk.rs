@@ -2,18 +2,24 @@ use strum::EnumCount;
 
 use std::fmt::{Debug, Formatter};
 
+use crate::source::Span;
+
 use super::value::Value;
 
 #[repr(u8)]
-#[derive(Debug, Clone, Copy, strum_macros::EnumCount)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, strum_macros::EnumCount)]
 pub enum Instr {
     OrBool,
     AndBool,
     NotBool,
 
     Equal,
+    NotEqual,
+
+    // the integer instructions work on `BigInt`s too
     LT,
     LTEqual,
+    CmpInt,
 
     AddInt,
     SubInt,
@@ -23,22 +29,123 @@ pub enum Instr {
     PowInt,
     NegInt,
 
+    BitAndInt,
+    BitOrInt,
+    BitXorInt,
+    ShlInt,
+    ShrInt,
+    BitNotInt,
+
     LoadConstantU8,
     LoadConstantU32,
+
+    /// Load an `Int` from a signed byte operand.
+    LoadSmallInt,
+    LoadTrue,
+    LoadFalse,
     LoadUnit,
     LoadLocal,
     StoreLocal,
+    StoreLocalPop,
+
+    /// Add a signed byte to an `Int` local, in place.
+    IncLocal,
+
+    LoadGlobal,
+    DefineGlobal,
+
+    MakeStruct,
+    GetField,
+    MakeVariant,
+    IsVariant,
+    GetPayload,
 
     Pop,
 
     JumpU32,
     JumpNotU32,
 
+    DefineImpl,
+
+    Call,
+    Invoke,
     Return,
+    Try,
 
+    PushHandlerU32,
+    PopHandler,
+    Panic,
+
+    ToString,
     DebugPrint,
 }
 
+impl Instr {
+    /// The number of operand bytes following the instruction.
+    pub fn operand_len(&self) -> usize {
+        match self {
+            Self::LoadConstantU8
+            | Self::LoadSmallInt
+            | Self::LoadLocal
+            | Self::StoreLocal
+            | Self::StoreLocalPop
+            | Self::LoadGlobal
+            | Self::GetField
+            | Self::MakeVariant
+            | Self::IsVariant
+            | Self::Call => 1,
+
+            Self::IncLocal | Self::DefineImpl => 2,
+            Self::Invoke => 3,
+
+            Self::LoadConstantU32 | Self::JumpU32 | Self::JumpNotU32 | Self::PushHandlerU32 => 4,
+
+            Self::OrBool
+            | Self::AndBool
+            | Self::NotBool
+            | Self::Equal
+            | Self::NotEqual
+            | Self::LT
+            | Self::LTEqual
+            | Self::CmpInt
+            | Self::AddInt
+            | Self::SubInt
+            | Self::MulInt
+            | Self::DivInt
+            | Self::ModInt
+            | Self::PowInt
+            | Self::NegInt
+            | Self::BitAndInt
+            | Self::BitOrInt
+            | Self::BitXorInt
+            | Self::ShlInt
+            | Self::ShrInt
+            | Self::BitNotInt
+            | Self::LoadTrue
+            | Self::LoadFalse
+            | Self::LoadUnit
+            | Self::DefineGlobal
+            | Self::MakeStruct
+            | Self::GetPayload
+            | Self::Pop
+            | Self::Return
+            | Self::Try
+            | Self::PopHandler
+            | Self::Panic
+            | Self::ToString
+            | Self::DebugPrint => 0,
+        }
+    }
+
+    /// Whether the instruction's operand is the offset of other code.
+    pub fn is_jump(&self) -> bool {
+        matches!(
+            self,
+            Self::JumpU32 | Self::JumpNotU32 | Self::PushHandlerU32
+        )
+    }
+}
+
 #[derive(Clone, Copy)]
 pub union Opcode {
     instr: Instr,
@@ -86,7 +193,7 @@ impl Opcode {
     }
 
     fn could_be_instr(&self) -> bool {
-        self.byte() <= Instr::COUNT as u8
+        self.byte() < Instr::COUNT as u8
     }
 }
 
@@ -104,4 +211,16 @@ impl Debug for Opcode {
 pub struct Chunk {
     pub code: Vec<Opcode>,
     pub constants: Vec<Value>,
+
+    /// The offsets at which the source of the code changes, and the span
+    /// of the expression the code from there on belongs to.
+    pub spans: Vec<(usize, Span)>,
+}
+
+impl Chunk {
+    /// The span of the expression that the code at `offset` belongs to.
+    pub fn span_at(&self, offset: usize) -> Option<Span> {
+        let index = self.spans.partition_point(|(start, _)| *start <= offset);
+        index.checked_sub(1).map(|index| self.spans[index].1)
+    }
 }
@@ -0,0 +1,147 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use crate::runtime::builtins::BUILTIN_FNS;
+use crate::runtime::chunk::{Chunk, Instr};
+use crate::runtime::value::{Object, Value};
+use crate::source::Source;
+
+impl Chunk {
+    /// A readable listing of the chunk and the functions it defines, with
+    /// the source line each run of instructions came from if the source is
+    /// given.
+    ///
+    /// Malformed code is listed as far as it can be rather than panicking,
+    /// so this can be used to inspect bytecode the verifier rejects.
+    pub fn disassemble(&self, source: Option<&Source>) -> String {
+        let mut out = String::new();
+        self.disassemble_into(&mut out, "<script>", source);
+        out
+    }
+
+    fn disassemble_into(&self, out: &mut String, name: &str, source: Option<&Source>) {
+        writeln!(out, "== {} ==", name).unwrap();
+
+        let labels = self.jump_labels();
+        let mut line = None;
+        let mut offset = 0;
+
+        while offset < self.code.len() {
+            if let Some((source, span)) = source.zip(self.span_at(offset)) {
+                let line_span = source.line_span(span.start);
+                let line_num = source.line_col(span.start).line;
+                if line != Some(line_num) {
+                    line = Some(line_num);
+                    let text = source.span_str(line_span).trim();
+                    writeln!(out, "{:>8} | {}", line_num, text).unwrap();
+                }
+            }
+
+            if let Some(label) = labels.get(&offset) {
+                writeln!(out, "L{}:", label).unwrap();
+            }
+
+            let instr = match self.code[offset].instr_safe() {
+                Some(instr) => instr,
+                None => {
+                    writeln!(
+                        out,
+                        "{:04}    <invalid {}>",
+                        offset,
+                        self.code[offset].byte()
+                    )
+                    .unwrap();
+                    offset += 1;
+                    continue;
+                }
+            };
+
+            let end = offset + 1 + instr.operand_len();
+            let operands: Vec<u8> = match self.code.get(offset + 1..end) {
+                Some(operands) => operands.iter().map(|opcode| opcode.byte()).collect(),
+                None => {
+                    writeln!(out, "{:04}    {:?} <truncated>", offset, instr).unwrap();
+                    break;
+                }
+            };
+
+            let operands = self.describe_operands(instr, &operands, &labels);
+            writeln!(out, "{:04}    {:?}{}", offset, instr, operands).unwrap();
+            offset = end;
+        }
+
+        for constant in &self.constants {
+            if let Value::Object(obj) = constant {
+                if let Object::JellyFunction(function) = &**obj {
+                    writeln!(out).unwrap();
+                    let name = format!("fn {}", function.name);
+                    function.chunk.disassemble_into(out, &name, source);
+                }
+            }
+        }
+    }
+
+    /// Number each offset jumped to, in order.
+    fn jump_labels(&self) -> BTreeMap<usize, usize> {
+        let mut targets = vec![];
+
+        let mut offset = 0;
+        while let Some(instr) = self.code.get(offset).and_then(|opcode| opcode.instr_safe()) {
+            if instr.is_jump() {
+                if let Some(target) = self.u32_at(offset + 1) {
+                    targets.push(target);
+                }
+            }
+            offset += 1 + instr.operand_len();
+        }
+
+        targets.sort_unstable();
+        targets.dedup();
+        targets
+            .into_iter()
+            .enumerate()
+            .map(|(i, t)| (t, i))
+            .collect()
+    }
+
+    fn u32_at(&self, offset: usize) -> Option<usize> {
+        let bytes = self.code.get(offset..offset + 4)?;
+        Some(
+            bytes
+                .iter()
+                .fold(0, |n, opcode| (n << 8) | opcode.byte() as usize),
+        )
+    }
+
+    fn describe_operands(
+        &self,
+        instr: Instr,
+        operands: &[u8],
+        labels: &BTreeMap<usize, usize>,
+    ) -> String {
+        let u32 = || operands.iter().fold(0, |n, byte| (n << 8) | *byte as usize);
+        let constant = |index: usize| match self.constants.get(index) {
+            Some(Value::String(s)) => format!(" {} ({:?})", index, s.as_str()),
+            Some(value) => format!(" {} ({})", index, value.repr()),
+            None => format!(" {} (out of range)", index),
+        };
+
+        match instr {
+            Instr::LoadConstantU8 => constant(operands[0] as usize),
+            Instr::LoadConstantU32 => constant(u32()),
+            Instr::LoadSmallInt => format!(" {}", operands[0] as i8),
+            Instr::IncLocal => format!(" {} {:+}", operands[0], operands[1] as i8),
+            Instr::LoadGlobal => match BUILTIN_FNS.get(operands[0] as usize) {
+                Some(func) => format!(" {} ({})", operands[0], func.name),
+                None => format!(" {}", operands[0]),
+            },
+            Instr::JumpU32 | Instr::JumpNotU32 | Instr::PushHandlerU32 => {
+                match labels.get(&u32()) {
+                    Some(label) => format!(" L{}", label),
+                    None => format!(" {}", u32()),
+                }
+            }
+            _ => operands.iter().map(|byte| format!(" {}", byte)).collect(),
+        }
+    }
+}
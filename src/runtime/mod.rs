@@ -1,15 +1,36 @@
+pub mod builtins;
 pub mod chunk;
+mod disassemble;
+pub mod serialize;
 pub mod value;
+pub mod verify;
 pub mod vm;
 
 #[cfg(test)]
 pub mod tests;
 
-use crate::source::Source;
+use crate::source::{LineCol, LineTable, Source};
 
 use self::chunk::Chunk;
 
 pub struct CompiledProgram<'sess> {
-    pub source: &'sess Source,
+    /// The source the program was compiled from, which programs loaded
+    /// from bytecode don't have.
+    pub source: Option<&'sess Source>,
     pub chunk: Chunk,
+
+    /// Where the spans of the code are in the source, which is all programs
+    /// loaded from bytecode know about it.
+    pub lines: Option<LineTable>,
+}
+
+impl CompiledProgram<'_> {
+    /// The line and column of an offset in the source, if it's known.
+    pub fn line_col(&self, byte_pos: usize) -> Option<LineCol> {
+        match (self.source, &self.lines) {
+            (Some(source), _) => Some(source.line_col(byte_pos)),
+            (None, Some(lines)) => lines.line_col(byte_pos),
+            (None, None) => None,
+        }
+    }
 }
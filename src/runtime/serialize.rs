@@ -0,0 +1,448 @@
+//! The `.jlc` format, for storing compiled programs.
+//!
+//! A file is the magic number, the format version, a checksum of the rest
+//! of the file, the top-level chunk, and then the line table. Integers are
+//! little-endian, and lengths are `u32`s.
+//!
+//! The source isn't stored, so the line table records the line and column
+//! of the start of each span the chunks have, which is enough to say where
+//! a runtime error happened.
+
+use std::fmt::{Display, Formatter};
+use std::rc::Rc;
+
+use internment::Intern;
+
+use crate::runtime::chunk::{Chunk, Instr, Opcode};
+use crate::runtime::value::bigint::BigInt;
+use crate::runtime::value::{EnumLayout, JellyFunction, Object, StructLayout, Value};
+use crate::runtime::verify::{verify, VerifyError};
+use crate::runtime::CompiledProgram;
+use crate::source::{LineCol, LineTable, Span};
+
+pub const MAGIC: &[u8; 4] = b"JLYC";
+
+/// Bumped whenever the encoding or the instruction set changes.
+pub const FORMAT_VERSION: u16 = 2;
+
+/// Function chunks nest inside the chunks that define them. Anything
+/// deeper than this is rejected rather than risking the stack.
+const MAX_DEPTH: usize = 16;
+
+const HEADER_LEN: usize = MAGIC.len() + 2 + 4;
+
+mod tag {
+    pub const UNIT: u8 = 0;
+    pub const BOOL: u8 = 1;
+    pub const INTEGER: u8 = 2;
+    pub const FLOAT: u8 = 3;
+    pub const STRING: u8 = 4;
+    pub const BIGINT: u8 = 5;
+    pub const FUNCTION: u8 = 6;
+    pub const STRUCT_LAYOUT: u8 = 7;
+    pub const ENUM_LAYOUT: u8 = 8;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LoadError {
+    BadMagic,
+    UnsupportedVersion(u16),
+    ChecksumMismatch,
+    Truncated,
+    TrailingBytes,
+    Malformed(&'static str),
+
+    /// The file is well-formed, but its code isn't safe to run.
+    Invalid(VerifyError),
+}
+
+impl Display for LoadError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::BadMagic => write!(f, "not a compiled jellyfish program"),
+            Self::UnsupportedVersion(version) => write!(
+                f,
+                "compiled for format version {}, but only version {} is supported",
+                version, FORMAT_VERSION
+            ),
+            Self::ChecksumMismatch => write!(f, "the file is corrupt (checksum mismatch)"),
+            Self::Truncated => write!(f, "the file ends unexpectedly"),
+            Self::TrailingBytes => write!(f, "the file has data past its end"),
+            Self::Malformed(what) => write!(f, "the file is malformed: {}", what),
+            Self::Invalid(err) => write!(f, "the code is invalid: {}", err),
+        }
+    }
+}
+
+impl CompiledProgram<'_> {
+    /// Encode the program in the `.jlc` format. The source isn't included,
+    /// only the lines and columns its spans start at.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut payload = vec![];
+        write_chunk(&mut payload, &self.chunk);
+
+        let lines = match (self.source, &self.lines) {
+            (Some(source), _) => {
+                let mut offsets = vec![];
+                span_starts(&self.chunk, &mut offsets);
+                LineTable::new(source, offsets)
+            }
+            (None, Some(lines)) => lines.clone(),
+            (None, None) => LineTable::default(),
+        };
+        write_lines(&mut payload, &lines);
+
+        let mut bytes = Vec::with_capacity(HEADER_LEN + payload.len());
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&checksum(&payload).to_le_bytes());
+        bytes.extend_from_slice(&payload);
+        bytes
+    }
+
+    /// Decode a program written by [`CompiledProgram::to_bytes`], checking
+    /// that it's safe to run.
+    pub fn from_bytes(bytes: &[u8]) -> Result<CompiledProgram<'static>, LoadError> {
+        if bytes.len() < MAGIC.len() || &bytes[..MAGIC.len()] != MAGIC {
+            return Err(LoadError::BadMagic);
+        }
+
+        let mut reader = Reader {
+            bytes: &bytes[MAGIC.len()..],
+            depth: 0,
+        };
+
+        let version = u16::from_le_bytes(reader.array()?);
+        if version != FORMAT_VERSION {
+            return Err(LoadError::UnsupportedVersion(version));
+        }
+
+        let expected = u32::from_le_bytes(reader.array()?);
+        if checksum(reader.bytes) != expected {
+            return Err(LoadError::ChecksumMismatch);
+        }
+
+        let chunk = reader.chunk()?;
+        let lines = reader.lines()?;
+        if !reader.bytes.is_empty() {
+            return Err(LoadError::TrailingBytes);
+        }
+
+        verify(&chunk).map_err(LoadError::Invalid)?;
+
+        Ok(CompiledProgram {
+            source: None,
+            chunk,
+            lines: Some(lines),
+        })
+    }
+}
+
+/// Check that a chunk's code decodes into whole instructions, and that
+/// their constants and jump targets exist, so it can't be misread as
+/// something else.
+fn check_code(chunk: &Chunk) -> Result<(), LoadError> {
+    let operand = |offset: usize, len: usize| {
+        chunk.code[offset + 1..offset + 1 + len]
+            .iter()
+            .fold(0, |n, opcode| n << 8 | opcode.byte() as usize)
+    };
+
+    let mut starts = vec![false; chunk.code.len()];
+    let mut jumps = vec![];
+
+    let mut offset = 0;
+    while offset < chunk.code.len() {
+        let instr = match chunk.code[offset].instr_safe() {
+            Some(instr) => instr,
+            None => return Err(LoadError::Malformed("invalid instruction")),
+        };
+        if offset + 1 + instr.operand_len() > chunk.code.len() {
+            return Err(LoadError::Malformed("missing operand"));
+        }
+
+        let constant = match instr {
+            Instr::LoadConstantU8 => Some(operand(offset, 1)),
+            Instr::LoadConstantU32 => Some(operand(offset, 4)),
+            _ => None,
+        };
+        if constant.is_some_and(|index| index >= chunk.constants.len()) {
+            return Err(LoadError::Malformed("constant out of range"));
+        }
+        if instr.is_jump() {
+            jumps.push(operand(offset, 4));
+        }
+
+        starts[offset] = true;
+        offset += 1 + instr.operand_len();
+    }
+
+    match jumps
+        .into_iter()
+        .all(|target| starts.get(target) == Some(&true))
+    {
+        true => Ok(()),
+        false => Err(LoadError::Malformed(
+            "jump into the middle of an instruction",
+        )),
+    }
+}
+
+/// 32-bit FNV-1a, which is plenty to catch accidental corruption.
+fn checksum(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0x811c9dc5, |hash, byte| {
+        (hash ^ *byte as u32).wrapping_mul(0x01000193)
+    })
+}
+
+fn write_u32(out: &mut Vec<u8>, n: usize) {
+    out.extend_from_slice(&(n as u32).to_le_bytes());
+}
+
+fn write_str(out: &mut Vec<u8>, s: &str) {
+    write_u32(out, s.len());
+    out.extend_from_slice(s.as_bytes());
+}
+
+fn write_names(out: &mut Vec<u8>, names: &[Intern<String>]) {
+    write_u32(out, names.len());
+    for name in names {
+        write_str(out, name);
+    }
+}
+
+fn write_chunk(out: &mut Vec<u8>, chunk: &Chunk) {
+    write_u32(out, chunk.code.len());
+    out.extend(chunk.code.iter().map(Opcode::byte));
+
+    write_u32(out, chunk.constants.len());
+    for constant in &chunk.constants {
+        write_value(out, constant);
+    }
+
+    write_u32(out, chunk.spans.len());
+    for (offset, span) in &chunk.spans {
+        write_u32(out, *offset);
+        write_u32(out, span.start);
+        write_u32(out, span.end);
+    }
+}
+
+fn write_lines(out: &mut Vec<u8>, lines: &LineTable) {
+    write_u32(out, lines.positions.len());
+    for (offset, line_col) in &lines.positions {
+        write_u32(out, *offset);
+        write_u32(out, line_col.line);
+        write_u32(out, line_col.col);
+    }
+}
+
+/// The offsets the spans of a chunk and the functions in it start at.
+fn span_starts(chunk: &Chunk, offsets: &mut Vec<usize>) {
+    offsets.extend(chunk.spans.iter().map(|(_, span)| span.start));
+    for constant in &chunk.constants {
+        if let Value::Object(obj) = constant {
+            if let Object::JellyFunction(function) = &**obj {
+                span_starts(&function.chunk, offsets);
+            }
+        }
+    }
+}
+
+fn write_value(out: &mut Vec<u8>, value: &Value) {
+    match value {
+        Value::Unit => out.push(tag::UNIT),
+        Value::Bool(b) => out.extend_from_slice(&[tag::BOOL, *b as u8]),
+        Value::Integer(n) => {
+            out.push(tag::INTEGER);
+            out.extend_from_slice(&n.to_le_bytes());
+        }
+        Value::Float(bits) => {
+            out.push(tag::FLOAT);
+            out.extend_from_slice(&bits.to_le_bytes());
+        }
+        Value::String(s) => {
+            out.push(tag::STRING);
+            write_str(out, s);
+        }
+        Value::Object(obj) => match &**obj {
+            Object::BigInt(n) => {
+                out.push(tag::BIGINT);
+                write_str(out, &n.to_string());
+            }
+            Object::JellyFunction(function) => {
+                out.push(tag::FUNCTION);
+                write_str(out, &function.name);
+                write_u32(out, function.arity);
+                write_chunk(out, &function.chunk);
+            }
+            Object::StructLayout(layout) => {
+                out.push(tag::STRUCT_LAYOUT);
+                write_str(out, &layout.name);
+                write_names(out, &layout.fields);
+            }
+            Object::EnumLayout(layout) => {
+                out.push(tag::ENUM_LAYOUT);
+                write_str(out, &layout.name);
+                write_names(out, &layout.variants);
+            }
+            Object::NativeFunction(_) | Object::Struct(_) | Object::Variant(_) => {
+                unreachable!("only created at runtime")
+            }
+        },
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    depth: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], LoadError> {
+        if len > self.bytes.len() {
+            return Err(LoadError::Truncated);
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], LoadError> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    fn u8(&mut self) -> Result<u8, LoadError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<usize, LoadError> {
+        Ok(u32::from_le_bytes(self.array()?) as usize)
+    }
+
+    /// A length, checked against what's left so a corrupt length can't
+    /// make us allocate more than the file could hold.
+    fn len(&mut self, item_size: usize) -> Result<usize, LoadError> {
+        let len = self.u32()?;
+        match len.checked_mul(item_size) {
+            Some(size) if size <= self.bytes.len() => Ok(len),
+            _ => Err(LoadError::Truncated),
+        }
+    }
+
+    fn str(&mut self) -> Result<&'a str, LoadError> {
+        let len = self.len(1)?;
+        std::str::from_utf8(self.take(len)?).map_err(|_| LoadError::Malformed("invalid UTF-8"))
+    }
+
+    fn names(&mut self) -> Result<Vec<Intern<String>>, LoadError> {
+        let len = self.len(4)?;
+        (0..len)
+            .map(|_| Ok(Intern::from_ref(self.str()?)))
+            .collect()
+    }
+
+    fn chunk(&mut self) -> Result<Chunk, LoadError> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return Err(LoadError::Malformed("functions are nested too deeply"));
+        }
+
+        let len = self.len(1)?;
+        let code = self
+            .take(len)?
+            .iter()
+            .map(|byte| Opcode::from(*byte))
+            .collect();
+
+        let len = self.len(1)?;
+        let constants = (0..len).map(|_| self.value()).collect::<Result<_, _>>()?;
+
+        let len = self.len(12)?;
+        let mut spans: Vec<(usize, Span)> = Vec::with_capacity(len);
+        for _ in 0..len {
+            let offset = self.u32()?;
+            let span = Span {
+                start: self.u32()?,
+                end: self.u32()?,
+            };
+            if span.start > span.end {
+                return Err(LoadError::Malformed("span ends before it starts"));
+            }
+            if spans.last().is_some_and(|(last, _)| *last >= offset) {
+                return Err(LoadError::Malformed("spans are out of order"));
+            }
+            spans.push((offset, span));
+        }
+
+        self.depth -= 1;
+        let chunk = Chunk {
+            code,
+            constants,
+            spans,
+        };
+        check_code(&chunk)?;
+        Ok(chunk)
+    }
+
+    fn lines(&mut self) -> Result<LineTable, LoadError> {
+        let len = self.len(12)?;
+        let mut positions: Vec<(usize, LineCol)> = Vec::with_capacity(len);
+        for _ in 0..len {
+            let offset = self.u32()?;
+            let line_col = LineCol {
+                line: self.u32()?,
+                col: self.u32()?,
+            };
+            if line_col.line == 0 || line_col.col == 0 {
+                return Err(LoadError::Malformed("lines and columns count from 1"));
+            }
+            if positions.last().is_some_and(|(last, _)| *last >= offset) {
+                return Err(LoadError::Malformed("the line table is out of order"));
+            }
+            positions.push((offset, line_col));
+        }
+        Ok(LineTable { positions })
+    }
+
+    fn value(&mut self) -> Result<Value, LoadError> {
+        let object = |obj| Value::Object(Rc::new(obj));
+
+        Ok(match self.u8()? {
+            tag::UNIT => Value::Unit,
+            tag::BOOL => match self.u8()? {
+                0 => Value::Bool(false),
+                1 => Value::Bool(true),
+                _ => return Err(LoadError::Malformed("invalid boolean")),
+            },
+            tag::INTEGER => Value::Integer(i64::from_le_bytes(self.array()?)),
+            tag::FLOAT => Value::Float(u64::from_le_bytes(self.array()?)),
+            tag::STRING => Value::String(Intern::from_ref(self.str()?)),
+            tag::BIGINT => match BigInt::parse(self.str()?, 10) {
+                Some(n) => object(Object::BigInt(n)),
+                None => return Err(LoadError::Malformed("invalid big integer")),
+            },
+            tag::FUNCTION => {
+                let name = Intern::from_ref(self.str()?);
+                let arity = self.u32()?;
+                let chunk = Rc::new(self.chunk()?);
+                object(Object::JellyFunction(Box::new(JellyFunction {
+                    name,
+                    chunk,
+                    arity,
+                })))
+            }
+            tag::STRUCT_LAYOUT => {
+                let name = Intern::from_ref(self.str()?);
+                let fields = self.names()?;
+                object(Object::StructLayout(Rc::new(StructLayout { name, fields })))
+            }
+            tag::ENUM_LAYOUT => {
+                let name = Intern::from_ref(self.str()?);
+                let variants = self.names()?;
+                object(Object::EnumLayout(Rc::new(EnumLayout { name, variants })))
+            }
+            _ => return Err(LoadError::Malformed("unknown constant kind")),
+        })
+    }
+}
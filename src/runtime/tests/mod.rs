@@ -18,3 +18,290 @@ fn opcode_is_small() {
 
     assert!(size_of::<Opcode>() == 1)
 }
+
+#[test]
+fn bigint_matches_i128() {
+    use super::value::bigint::BigInt;
+
+    let samples: [i128; 8] = [
+        0,
+        1,
+        -7,
+        i64::MAX as i128,
+        i64::MIN as i128,
+        (i64::MAX as i128) * 3 + 11,
+        -(1 << 100),
+        123_456_789_012_345_678_901_234_567,
+    ];
+    let big = |n: i128| BigInt::parse(&n.to_string(), 10).unwrap();
+
+    for a in samples {
+        assert_eq!(big(a).to_string(), a.to_string());
+        for b in samples {
+            if let Some(sum) = a.checked_add(b) {
+                assert_eq!(big(a).checked_add(&big(b)), Some(big(sum)));
+            }
+            if let Some(product) = a.checked_mul(b) {
+                assert_eq!(big(a).checked_mul(&big(b)), Some(big(product)));
+            }
+            if b != 0 {
+                assert_eq!(big(a).checked_div(&big(b)), Some(big(a / b)));
+                assert_eq!(big(a).checked_rem(&big(b)), Some(big(a % b)));
+            }
+            assert_eq!(big(a).cmp(&big(b)), a.cmp(&b));
+        }
+    }
+}
+
+#[test]
+fn bigint_powers_are_exact_and_limited() {
+    use super::value::bigint::BigInt;
+    use super::vm::RuntimeError;
+
+    let big = |n: i128| BigInt::parse(&n.to_string(), 10).unwrap();
+
+    assert_eq!(big(3).pow(4).ok(), Some(big(81)));
+    assert_eq!(big(-2).pow(3).ok(), Some(big(-8)));
+    assert_eq!(big(2).pow(100).ok(), Some(big(1 << 100)));
+    assert_eq!(big(7).pow(0).ok(), Some(big(1)));
+    assert_eq!(big(0).pow(0).ok(), Some(big(1)));
+    assert!(matches!(
+        big(2).pow(-1),
+        Err(RuntimeError::NegativeExponent)
+    ));
+
+    // 0, 1 and -1 never grow
+    assert_eq!(big(0).pow(i64::MAX).ok(), Some(big(0)));
+    assert_eq!(big(1).pow(i64::MAX).ok(), Some(big(1)));
+    assert_eq!(big(-1).pow(i64::MAX).ok(), Some(big(-1)));
+
+    // single-digit bases are limited too, before any work is done
+    assert!(matches!(
+        big(3).pow(200_000_000),
+        Err(RuntimeError::IntegerOverflow)
+    ));
+    assert!(matches!(
+        big(65536).pow(2_000_000),
+        Err(RuntimeError::IntegerOverflow)
+    ));
+    assert!(big(2).pow(10_000).is_ok());
+}
+
+#[test]
+fn verifier_rejects_unsafe_code() {
+    use super::chunk::{Chunk, Instr};
+    use super::verify::{verify, VerifyError};
+
+    let chunk = |code: &[u8]| Chunk {
+        code: code.iter().map(|byte| Opcode::from(*byte)).collect(),
+        constants: vec![Value::Integer(1)],
+        spans: vec![],
+    };
+    let (load, pop, ret) = (
+        Instr::LoadConstantU8 as u8,
+        Instr::Pop as u8,
+        Instr::Return as u8,
+    );
+    let jump = Instr::JumpNotU32 as u8;
+
+    assert_eq!(verify(&chunk(&[load, 0, ret])), Ok(()));
+    assert_eq!(
+        verify(&chunk(&[u8::MAX])),
+        Err(VerifyError::InvalidInstruction {
+            offset: 0,
+            byte: u8::MAX
+        })
+    );
+    assert_eq!(
+        verify(&chunk(&[load])),
+        Err(VerifyError::MissingOperand { offset: 0 })
+    );
+    assert_eq!(
+        verify(&chunk(&[load, 1, ret])),
+        Err(VerifyError::ConstantOutOfRange {
+            offset: 0,
+            index: 1
+        })
+    );
+    assert_eq!(
+        verify(&chunk(&[pop, ret])),
+        Err(VerifyError::StackUnderflow { offset: 0 })
+    );
+    assert_eq!(
+        verify(&chunk(&[load, 0, pop])),
+        Err(VerifyError::FallsOffEnd)
+    );
+
+    // into the middle of an instruction
+    assert_eq!(
+        verify(&chunk(&[load, 0, jump, 0, 0, 0, 1, ret])),
+        Err(VerifyError::InvalidJump {
+            offset: 2,
+            target: 1
+        })
+    );
+
+    // one way round the loop leaves an extra value on the stack
+    assert_eq!(
+        verify(&chunk(&[
+            load,
+            0,
+            load,
+            0,
+            Instr::JumpU32 as u8,
+            0,
+            0,
+            0,
+            2
+        ])),
+        Err(VerifyError::InconsistentStack { offset: 2 })
+    );
+}
+
+#[test]
+fn nested_values_are_printed_without_display_impls() {
+    use std::rc::Rc;
+
+    use internment::Intern;
+
+    use super::value::{EnumLayout, Object, StructInstance, StructLayout, VariantInstance};
+
+    let v = Value::Object(Rc::new(Object::Struct(StructInstance {
+        layout: Rc::new(StructLayout {
+            name: Intern::from_ref("V"),
+            fields: vec![Intern::from_ref("x")],
+        }),
+        fields: vec![Value::Integer(1)],
+    })));
+    let some = Value::Object(Rc::new(Object::Variant(VariantInstance {
+        layout: Rc::new(EnumLayout {
+            name: Intern::from_ref("Option"),
+            variants: vec![Intern::from_ref("Some"), Intern::from_ref("None")],
+        }),
+        tag: 0,
+        value: v,
+    })));
+
+    // even if `V` has a `Display` impl, the runtime can't know about it
+    assert_eq!(some.repr(), "Some(V { x: 1 })");
+}
+
+#[test]
+fn verified_code_fails_without_panicking() {
+    use std::rc::Rc;
+
+    use internment::Intern;
+
+    use super::chunk::{Chunk, Instr};
+    use super::value::{JellyFunction, Object, StructLayout};
+    use super::verify::{verify, VerifyError};
+    use super::vm::{RuntimeError, VM};
+    use crate::CompiledProgram;
+
+    let chunk = |code: &[u8], constants: Vec<Value>| Chunk {
+        code: code.iter().map(|byte| Opcode::from(*byte)).collect(),
+        constants,
+        spans: vec![],
+    };
+    let run = |chunk: Chunk| {
+        assert_eq!(verify(&chunk), Ok(()));
+        let program = CompiledProgram {
+            source: None,
+            chunk,
+            lines: None,
+        };
+        VM::new().run(program)
+    };
+    let empty_struct = || {
+        Value::Object(Rc::new(Object::StructLayout(Rc::new(StructLayout {
+            name: Intern::from_ref("Empty"),
+            fields: vec![],
+        }))))
+    };
+    let ret = Instr::Return as u8;
+
+    // a method no impl defines
+    let code = [Instr::LoadTrue as u8, Instr::Invoke as u8, 200, 0, 1, ret];
+    assert!(matches!(
+        run(chunk(&code, vec![])),
+        Err(RuntimeError::InvalidBytecode("no such method"))
+    ));
+
+    // `wrapping_add` without its arguments
+    let code = [Instr::LoadGlobal as u8, 0, Instr::Call as u8, 0, ret];
+    assert!(matches!(
+        run(chunk(&code, vec![])),
+        Err(RuntimeError::InvalidBytecode("wrong number of arguments"))
+    ));
+
+    // a function of two parameters without its arguments
+    let function = JellyFunction {
+        name: Intern::from_ref("f"),
+        chunk: Rc::new(chunk(&[Instr::LoadUnit as u8, ret], vec![])),
+        arity: 2,
+    };
+    let function = Value::Object(Rc::new(Object::JellyFunction(Box::new(function))));
+    let code = [Instr::LoadConstantU8 as u8, 0, Instr::Call as u8, 0, ret];
+    assert!(matches!(
+        run(chunk(&code, vec![function])),
+        Err(RuntimeError::InvalidBytecode("wrong number of arguments"))
+    ));
+
+    // `?` on something other than a variant
+    let code = [Instr::LoadTrue as u8, Instr::Try as u8, ret];
+    assert!(matches!(
+        run(chunk(&code, vec![])),
+        Err(RuntimeError::TypeError { .. })
+    ));
+
+    // a field of a struct without any
+    let code = [
+        Instr::LoadConstantU8 as u8,
+        0,
+        Instr::MakeStruct as u8,
+        Instr::GetField as u8,
+        3,
+        ret,
+    ];
+    assert!(matches!(
+        run(chunk(&code, vec![empty_struct()])),
+        Err(RuntimeError::InvalidBytecode("no such field"))
+    ));
+
+    // a global the code reads before defining it
+    let code = [
+        Instr::LoadGlobal as u8,
+        super::builtins::BUILTIN_FNS.len() as u8,
+        Instr::LoadUnit as u8,
+        Instr::DefineGlobal as u8,
+        ret,
+    ];
+    assert!(matches!(
+        run(chunk(&code, vec![])),
+        Err(RuntimeError::InvalidBytecode("undefined global"))
+    ));
+
+    // jumping over the layout to the instruction that uses it
+    let code = [
+        Instr::LoadTrue as u8,
+        Instr::JumpNotU32 as u8,
+        0,
+        0,
+        0,
+        12,
+        Instr::LoadUnit as u8,
+        Instr::JumpU32 as u8,
+        0,
+        0,
+        0,
+        14,
+        Instr::LoadConstantU8 as u8,
+        0,
+        Instr::MakeStruct as u8,
+        ret,
+    ];
+    assert_eq!(
+        verify(&chunk(&code, vec![empty_struct()])),
+        Err(VerifyError::MissingLayout { offset: 14 })
+    );
+}
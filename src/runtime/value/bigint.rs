@@ -0,0 +1,315 @@
+use std::cmp::Ordering;
+use std::fmt::{Display, Formatter};
+
+use crate::runtime::vm::RuntimeError;
+
+/// The most bits a power may have, so that a script can't exhaust memory
+/// with a single `^`.
+const MAX_POW_BITS: u64 = 1 << 24;
+
+/// An arbitrary-precision integer, stored as a sign and a magnitude.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct BigInt {
+    negative: bool,
+
+    /// Base 2^32 digits, least significant first, without leading zeroes,
+    /// so zero has no digits.
+    digits: Vec<u32>,
+}
+
+impl BigInt {
+    fn new(negative: bool, mut digits: Vec<u32>) -> Self {
+        while digits.last() == Some(&0) {
+            digits.pop();
+        }
+        let negative = negative && !digits.is_empty();
+        Self { negative, digits }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.digits.is_empty()
+    }
+
+    /// The number of bits in the magnitude.
+    fn bit_len(&self) -> u64 {
+        match self.digits.last() {
+            Some(top) => self.digits.len() as u64 * 32 - top.leading_zeros() as u64,
+            None => 0,
+        }
+    }
+
+    /// Parse an optionally signed integer in the given radix.
+    pub fn parse(s: &str, radix: u32) -> Option<Self> {
+        let (negative, s) = match s.strip_prefix('-') {
+            Some(s) => (true, s),
+            None => (false, s.strip_prefix('+').unwrap_or(s)),
+        };
+
+        if s.is_empty() {
+            return None;
+        }
+
+        let mut digits = vec![];
+        for c in s.chars() {
+            let digit = c.to_digit(radix)?;
+            mul_small(&mut digits, radix, digit);
+        }
+
+        Some(Self::new(negative, digits))
+    }
+
+    pub fn to_i64(&self) -> Option<i64> {
+        if self.digits.len() > 2 {
+            return None;
+        }
+
+        let magnitude = self
+            .digits
+            .iter()
+            .rev()
+            .fold(0u64, |acc, digit| (acc << 32) | *digit as u64);
+
+        if self.negative {
+            // `i64::MIN` has no positive counterpart
+            match magnitude <= 1 << 63 {
+                true => Some((magnitude as i64).wrapping_neg()),
+                false => None,
+            }
+        } else {
+            i64::try_from(magnitude).ok()
+        }
+    }
+
+    pub fn neg(&self) -> Self {
+        Self::new(!self.negative, self.digits.clone())
+    }
+
+    pub fn checked_add(&self, other: &Self) -> Option<Self> {
+        if self.negative == other.negative {
+            return Some(Self::new(
+                self.negative,
+                add_mag(&self.digits, &other.digits),
+            ));
+        }
+
+        // the signs differ, so the larger magnitude decides the sign
+        Some(match cmp_mag(&self.digits, &other.digits) {
+            Ordering::Less => Self::new(other.negative, sub_mag(&other.digits, &self.digits)),
+            _ => Self::new(self.negative, sub_mag(&self.digits, &other.digits)),
+        })
+    }
+
+    pub fn checked_sub(&self, other: &Self) -> Option<Self> {
+        self.checked_add(&other.neg())
+    }
+
+    pub fn checked_mul(&self, other: &Self) -> Option<Self> {
+        let negative = self.negative != other.negative;
+        Some(Self::new(negative, mul_mag(&self.digits, &other.digits)))
+    }
+
+    /// Division rounding towards zero, like `i64`'s.
+    pub fn checked_div(&self, other: &Self) -> Option<Self> {
+        let (quotient, _) = self.div_rem(other)?;
+        Some(quotient)
+    }
+
+    /// The remainder of division rounding towards zero, like `i64`'s.
+    pub fn checked_rem(&self, other: &Self) -> Option<Self> {
+        let (_, remainder) = self.div_rem(other)?;
+        Some(remainder)
+    }
+
+    fn div_rem(&self, other: &Self) -> Option<(Self, Self)> {
+        if other.is_zero() {
+            return None;
+        }
+
+        let (quotient, remainder) = div_rem_mag(&self.digits, &other.digits);
+        Some((
+            Self::new(self.negative != other.negative, quotient),
+            Self::new(self.negative, remainder),
+        ))
+    }
+
+    pub fn pow(&self, exp: i64) -> Result<Self, RuntimeError> {
+        let exp = u64::try_from(exp).map_err(|_| RuntimeError::NegativeExponent)?;
+
+        // powers of 0, 1 and -1 stay small however large the exponent
+        let trivial = self.digits.len() == 1 && self.digits[0] == 1;
+        if !self.is_zero() && !trivial && self.bit_len().saturating_mul(exp) > MAX_POW_BITS {
+            return Err(RuntimeError::IntegerOverflow);
+        }
+
+        let mut base = self.clone();
+        let mut acc = Self::from(1);
+        let mut exp = exp;
+
+        while exp > 0 {
+            if exp & 1 == 1 {
+                acc = acc.checked_mul(&base).unwrap();
+            }
+            exp >>= 1;
+            if exp > 0 {
+                base = base.checked_mul(&base).unwrap();
+            }
+        }
+
+        Ok(acc)
+    }
+}
+
+impl From<i64> for BigInt {
+    fn from(n: i64) -> Self {
+        let magnitude = n.unsigned_abs();
+        Self::new(n < 0, vec![magnitude as u32, (magnitude >> 32) as u32])
+    }
+}
+
+impl Ord for BigInt {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self.negative, other.negative) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (false, false) => cmp_mag(&self.digits, &other.digits),
+            (true, true) => cmp_mag(&other.digits, &self.digits),
+        }
+    }
+}
+
+impl PartialOrd for BigInt {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Display for BigInt {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.is_zero() {
+            return write!(f, "0");
+        }
+
+        // peel off nine decimal digits at a time
+        let mut digits = self.digits.clone();
+        let mut chunks = vec![];
+        while !digits.is_empty() {
+            chunks.push(div_small(&mut digits, 1_000_000_000));
+        }
+
+        if self.negative {
+            write!(f, "-")?;
+        }
+        write!(f, "{}", chunks.pop().unwrap())?;
+        for chunk in chunks.iter().rev() {
+            write!(f, "{:09}", chunk)?;
+        }
+        Ok(())
+    }
+}
+
+fn cmp_mag(a: &[u32], b: &[u32]) -> Ordering {
+    a.len()
+        .cmp(&b.len())
+        .then_with(|| a.iter().rev().cmp(b.iter().rev()))
+}
+
+fn add_mag(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut result = Vec::with_capacity(a.len().max(b.len()) + 1);
+    let mut carry = 0u64;
+
+    for i in 0..a.len().max(b.len()) {
+        let sum = *a.get(i).unwrap_or(&0) as u64 + *b.get(i).unwrap_or(&0) as u64 + carry;
+        result.push(sum as u32);
+        carry = sum >> 32;
+    }
+    result.push(carry as u32);
+
+    result
+}
+
+/// `a - b`, where `a` is at least `b`.
+fn sub_mag(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut result = Vec::with_capacity(a.len());
+    let mut borrow = 0i64;
+
+    for (i, digit) in a.iter().enumerate() {
+        let mut diff = *digit as i64 - *b.get(i).unwrap_or(&0) as i64 - borrow;
+        borrow = (diff < 0) as i64;
+        if diff < 0 {
+            diff += 1 << 32;
+        }
+        result.push(diff as u32);
+    }
+
+    result
+}
+
+fn mul_mag(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut result = vec![0u32; a.len() + b.len()];
+
+    for (i, x) in a.iter().enumerate() {
+        let mut carry = 0u64;
+        for (j, y) in b.iter().enumerate() {
+            let product = *x as u64 * *y as u64 + result[i + j] as u64 + carry;
+            result[i + j] = product as u32;
+            carry = product >> 32;
+        }
+        result[i + b.len()] = carry as u32;
+    }
+
+    result
+}
+
+/// Long division, one bit at a time.
+fn div_rem_mag(a: &[u32], b: &[u32]) -> (Vec<u32>, Vec<u32>) {
+    if let [divisor] = b {
+        let mut quotient = a.to_vec();
+        let remainder = div_small(&mut quotient, *divisor);
+        return (quotient, vec![remainder]);
+    }
+
+    let mut quotient = vec![0u32; a.len()];
+    let mut remainder: Vec<u32> = vec![];
+
+    for bit in (0..a.len() * 32).rev() {
+        // remainder = remainder * 2 + the next bit of `a`
+        mul_small(&mut remainder, 2, (a[bit / 32] >> (bit % 32)) & 1);
+
+        if cmp_mag(&remainder, b) != Ordering::Less {
+            remainder = sub_mag(&remainder, b);
+            while remainder.last() == Some(&0) {
+                remainder.pop();
+            }
+            quotient[bit / 32] |= 1 << (bit % 32);
+        }
+    }
+
+    (quotient, remainder)
+}
+
+/// `digits = digits * factor + addend`, in place.
+fn mul_small(digits: &mut Vec<u32>, factor: u32, addend: u32) {
+    let mut carry = addend as u64;
+    for digit in digits.iter_mut() {
+        let product = *digit as u64 * factor as u64 + carry;
+        *digit = product as u32;
+        carry = product >> 32;
+    }
+    if carry > 0 {
+        digits.push(carry as u32);
+    }
+}
+
+/// Divide in place, returning the remainder.
+fn div_small(digits: &mut Vec<u32>, divisor: u32) -> u32 {
+    let mut remainder = 0u64;
+    for digit in digits.iter_mut().rev() {
+        let current = (remainder << 32) | *digit as u64;
+        *digit = (current / divisor as u64) as u32;
+        remainder = current % divisor as u64;
+    }
+    while digits.last() == Some(&0) {
+        digits.pop();
+    }
+    remainder as u32
+}
@@ -1,3 +1,4 @@
+pub mod bigint;
 pub mod repr;
 
 use internment::Intern;
@@ -7,6 +8,9 @@ use std::rc::Rc;
 
 use crate::runtime::vm::RuntimeError;
 
+use self::bigint::BigInt;
+
+use super::builtins::BuiltinFn;
 use super::chunk::Chunk;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -32,7 +36,10 @@ pub enum Value {
 impl PartialEq for Value {
     fn eq(&self, other: &Self) -> bool {
         match (self, other) {
-            (Self::Object(a), Self::Object(b)) => Rc::ptr_eq(a, b),
+            (Self::Object(a), Self::Object(b)) => match (&**a, &**b) {
+                (Object::BigInt(a), Object::BigInt(b)) => a == b,
+                _ => Rc::ptr_eq(a, b),
+            },
             (Self::String(a), Self::String(b)) => a == b,
             (Self::Integer(a), Self::Integer(b)) => a == b,
             (Self::Float(a), Self::Float(b)) => a == b,
@@ -56,6 +63,26 @@ impl Value {
         }
     }
 
+    /// The name of the value's type, used to find trait impls at runtime.
+    pub fn type_name(&self) -> Intern<String> {
+        Intern::from_ref(match self {
+            Self::Object(obj) => match &**obj {
+                Object::Struct(instance) => return instance.layout.name,
+                Object::Variant(variant) => return variant.layout.name,
+                Object::BigInt(_) => "BigInt",
+                Object::JellyFunction(_)
+                | Object::NativeFunction(_)
+                | Object::StructLayout(_)
+                | Object::EnumLayout(_) => "fn",
+            },
+            Self::String(_) => "String",
+            Self::Integer(_) => "Int",
+            Self::Float(_) => "Float",
+            Self::Bool(_) => "Bool",
+            Self::Unit => "()",
+        })
+    }
+
     pub fn integer(&self) -> Result<i64, RuntimeError> {
         match self {
             Self::Integer(n) => Ok(*n),
@@ -66,6 +93,22 @@ impl Value {
         }
     }
 
+    pub fn bigint(&self) -> Result<&BigInt, RuntimeError> {
+        match self {
+            Self::Object(obj) => match &**obj {
+                Object::BigInt(n) => Ok(n),
+                _ => Err(RuntimeError::TypeError {
+                    expected: Type::Object,
+                    found: Type::Object,
+                }),
+            },
+            other => Err(RuntimeError::TypeError {
+                expected: Type::Object,
+                found: other.ty(),
+            }),
+        }
+    }
+
     pub fn bool(&self) -> Result<bool, RuntimeError> {
         match self {
             Self::Bool(b) => Ok(*b),
@@ -77,14 +120,55 @@ impl Value {
     }
 }
 
+impl From<BigInt> for Value {
+    fn from(n: BigInt) -> Self {
+        Self::Object(Rc::new(Object::BigInt(n)))
+    }
+}
+
 #[derive(Debug)]
 pub enum Object {
     JellyFunction(Box<JellyFunction>),
+    NativeFunction(&'static BuiltinFn),
+    StructLayout(Rc<StructLayout>),
+    Struct(StructInstance),
+    EnumLayout(Rc<EnumLayout>),
+    Variant(VariantInstance),
+    BigInt(BigInt),
 }
 
 #[derive(Debug)]
 pub struct JellyFunction {
-    pub chunk: Chunk,
+    pub name: Intern<String>,
+    pub chunk: Rc<Chunk>,
     pub arity: usize,
-    pub return_type: Type,
+}
+
+/// The name and field names of a struct, shared between its instances.
+#[derive(Debug)]
+pub struct StructLayout {
+    pub name: Intern<String>,
+    pub fields: Vec<Intern<String>>,
+}
+
+#[derive(Debug)]
+pub struct StructInstance {
+    pub layout: Rc<StructLayout>,
+    pub fields: Vec<Value>,
+}
+
+/// The name and variant names of a sum type, shared between its values.
+#[derive(Debug)]
+pub struct EnumLayout {
+    pub name: Intern<String>,
+    pub variants: Vec<Intern<String>>,
+}
+
+#[derive(Debug)]
+pub struct VariantInstance {
+    pub layout: Rc<EnumLayout>,
+    pub tag: usize,
+
+    /// The value the variant holds, or `()` if it holds nothing.
+    pub value: Value,
 }
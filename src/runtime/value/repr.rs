@@ -1,6 +1,10 @@
 use super::*;
 
 impl Value {
+    /// How `print` shows a value that has no `Display` impl. The type
+    /// checker picks the impls, and the runtime doesn't know them, so the
+    /// values inside this one are always shown like this, even if their
+    /// types have impls.
     pub fn repr(&self) -> String {
         match self {
             Self::Object(obj) => obj.repr(),
@@ -17,12 +21,42 @@ impl Object {
     pub fn repr(&self) -> String {
         match self {
             Self::JellyFunction(func) => func.repr(),
+            Self::NativeFunction(func) => format!("{{builtin fn {}}}", func.name),
+            Self::StructLayout(layout) => format!("{{struct {}}}", layout.name),
+            Self::Struct(instance) => instance.repr(),
+            Self::EnumLayout(layout) => format!("{{enum {}}}", layout.name),
+            Self::Variant(variant) => variant.repr(),
+            Self::BigInt(n) => n.to_string(),
         }
     }
 }
 
 impl JellyFunction {
     pub fn repr(&self) -> String {
-        "{func}".to_string()
+        format!("{{fn {}}}", self.name)
+    }
+}
+
+impl StructInstance {
+    pub fn repr(&self) -> String {
+        let fields = self
+            .layout
+            .fields
+            .iter()
+            .zip(&self.fields)
+            .map(|(name, value)| format!("{}: {}", name, value.repr()))
+            .collect::<Vec<_>>()
+            .join(", ");
+        format!("{} {{ {} }}", self.layout.name, fields)
+    }
+}
+
+impl VariantInstance {
+    pub fn repr(&self) -> String {
+        let name = self.layout.variants[self.tag];
+        match self.value {
+            Value::Unit => name.to_string(),
+            ref value => format!("{}({})", name, value.repr()),
+        }
     }
 }
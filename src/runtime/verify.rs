@@ -0,0 +1,379 @@
+//! Checks that a chunk can be run safely, for bytecode that didn't come
+//! straight from the compiler.
+
+use std::fmt::{Display, Formatter};
+
+use crate::runtime::builtins::BUILTIN_FNS;
+use crate::runtime::chunk::{Chunk, Instr};
+use crate::runtime::value::{Object, Value};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VerifyError {
+    InvalidInstruction {
+        offset: usize,
+        byte: u8,
+    },
+    MissingOperand {
+        offset: usize,
+    },
+    ConstantOutOfRange {
+        offset: usize,
+        index: usize,
+    },
+    LocalOutOfRange {
+        offset: usize,
+        slot: usize,
+    },
+    GlobalOutOfRange {
+        offset: usize,
+        index: usize,
+    },
+    InvalidJump {
+        offset: usize,
+        target: usize,
+    },
+
+    /// An instruction that needs a layout isn't always given one.
+    MissingLayout {
+        offset: usize,
+    },
+    StackUnderflow {
+        offset: usize,
+    },
+
+    /// Two paths reach an instruction with different numbers of values
+    /// on the stack.
+    InconsistentStack {
+        offset: usize,
+    },
+    FallsOffEnd,
+}
+
+impl Display for VerifyError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidInstruction { offset, byte } => {
+                write!(f, "invalid instruction {} at offset {}", byte, offset)
+            }
+            Self::MissingOperand { offset } => {
+                write!(
+                    f,
+                    "missing operand for the instruction at offset {}",
+                    offset
+                )
+            }
+            Self::ConstantOutOfRange { offset, index } => {
+                write!(f, "no constant {} for offset {}", index, offset)
+            }
+            Self::LocalOutOfRange { offset, slot } => {
+                write!(f, "no local {} at offset {}", slot, offset)
+            }
+            Self::GlobalOutOfRange { offset, index } => {
+                write!(f, "no global {} at offset {}", index, offset)
+            }
+            Self::InvalidJump { offset, target } => write!(
+                f,
+                "the jump at offset {} lands on {}, which isn't an instruction",
+                offset, target
+            ),
+            Self::MissingLayout { offset } => {
+                write!(f, "the instruction at offset {} has no layout", offset)
+            }
+            Self::StackUnderflow { offset } => {
+                write!(f, "the stack underflows at offset {}", offset)
+            }
+            Self::InconsistentStack { offset } => write!(
+                f,
+                "the stack depth at offset {} depends on how it's reached",
+                offset
+            ),
+            Self::FallsOffEnd => write!(f, "the code runs past its end"),
+        }
+    }
+}
+
+struct Decoded {
+    offset: usize,
+    instr: Instr,
+    operands: Vec<u8>,
+}
+
+impl Decoded {
+    fn u8(&self) -> usize {
+        self.operands[0] as usize
+    }
+
+    fn u32(&self) -> usize {
+        u32::from_be_bytes(self.operands[..].try_into().unwrap()) as usize
+    }
+
+    /// The constant it loads, if it loads one.
+    fn constant<'a>(&self, chunk: &'a Chunk) -> Option<&'a Value> {
+        match self.instr {
+            Instr::LoadConstantU8 => chunk.constants.get(self.u8()),
+            Instr::LoadConstantU32 => chunk.constants.get(self.u32()),
+            _ => None,
+        }
+    }
+
+    /// The number of fields or variants of the layout it loads, if it
+    /// loads one.
+    fn layout(&self, chunk: &Chunk) -> Option<usize> {
+        match self.constant(chunk)? {
+            Value::Object(obj) => match &**obj {
+                Object::StructLayout(layout) => Some(layout.fields.len()),
+                Object::EnumLayout(layout) => Some(layout.variants.len()),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+/// What's known about the stack on reaching an instruction.
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct State {
+    depth: usize,
+
+    /// The size of the layout on top of the stack, if there is one there
+    /// on every path to the instruction.
+    layout: Option<usize>,
+}
+
+/// Verify a top-level chunk, and the chunks of the functions it defines.
+pub fn verify(chunk: &Chunk) -> Result<(), VerifyError> {
+    let defined = decode(chunk)?
+        .iter()
+        .filter(|decoded| decoded.instr == Instr::DefineGlobal)
+        .count();
+
+    verify_chunk(chunk, 0, BUILTIN_FNS.len() + defined)
+}
+
+fn verify_chunk(chunk: &Chunk, arity: usize, globals: usize) -> Result<(), VerifyError> {
+    let code = decode(chunk)?;
+
+    let mut index_of = vec![None; chunk.code.len()];
+    for (i, decoded) in code.iter().enumerate() {
+        index_of[decoded.offset] = Some(i);
+    }
+
+    let start = State {
+        depth: arity,
+        layout: None,
+    };
+    let mut states: Vec<Option<State>> = vec![None; code.len()];
+    let mut worklist = vec![(0, start)];
+
+    while let Some((i, state)) = worklist.pop() {
+        let decoded = match code.get(i) {
+            Some(decoded) => decoded,
+            None => return Err(VerifyError::FallsOffEnd),
+        };
+        let offset = decoded.offset;
+        let depth = state.depth;
+
+        let state = match states[i] {
+            None => state,
+            Some(seen) if seen == state => continue,
+            Some(seen) if seen.depth != depth => {
+                return Err(VerifyError::InconsistentStack { offset })
+            }
+
+            // the paths disagree about the layout, so there's none to use
+            Some(seen) if seen.layout.is_none() => continue,
+            Some(_) => State {
+                layout: None,
+                ..state
+            },
+        };
+        states[i] = Some(state);
+
+        let (pops, pushes) = stack_effect(decoded, state.layout)?;
+        if depth < pops {
+            return Err(VerifyError::StackUnderflow { offset });
+        }
+        let after = State {
+            depth: depth - pops + pushes,
+            layout: decoded.layout(chunk),
+        };
+
+        let local = |slot: usize, limit: usize| match slot < limit {
+            true => Ok(()),
+            false => Err(VerifyError::LocalOutOfRange { offset, slot }),
+        };
+        let jump = |target: usize| match index_of.get(target) {
+            Some(Some(index)) => Ok(*index),
+            _ => Err(VerifyError::InvalidJump { offset, target }),
+        };
+
+        match decoded.instr {
+            Instr::LoadLocal | Instr::StoreLocal | Instr::IncLocal => local(decoded.u8(), depth)?,
+            Instr::StoreLocalPop => local(decoded.u8(), depth - 1)?,
+
+            Instr::LoadGlobal if decoded.u8() >= globals => {
+                return Err(VerifyError::GlobalOutOfRange {
+                    offset,
+                    index: decoded.u8(),
+                })
+            }
+
+            Instr::LoadConstantU8 | Instr::LoadConstantU32 => {
+                let index = match decoded.instr {
+                    Instr::LoadConstantU8 => decoded.u8(),
+                    _ => decoded.u32(),
+                };
+                match decoded.constant(chunk) {
+                    Some(Value::Object(obj)) => {
+                        if let Object::JellyFunction(function) = &**obj {
+                            verify_chunk(&function.chunk, function.arity, globals)?;
+                        }
+                    }
+                    Some(_) => {}
+                    None => return Err(VerifyError::ConstantOutOfRange { offset, index }),
+                }
+            }
+
+            Instr::JumpU32 => {
+                worklist.push((jump(decoded.u32())?, after));
+                continue;
+            }
+            Instr::JumpNotU32 => worklist.push((jump(decoded.u32())?, after)),
+
+            // the handler is reached with the panic pushed
+            Instr::PushHandlerU32 => {
+                let handler = State {
+                    depth: depth + 1,
+                    layout: None,
+                };
+                worklist.push((jump(decoded.u32())?, handler))
+            }
+
+            Instr::Return => continue,
+
+            _ => {}
+        }
+
+        worklist.push((i + 1, after));
+    }
+
+    Ok(())
+}
+
+fn decode(chunk: &Chunk) -> Result<Vec<Decoded>, VerifyError> {
+    let mut code = vec![];
+
+    let mut offset = 0;
+    while offset < chunk.code.len() {
+        let byte = chunk.code[offset].byte();
+        let instr = match chunk.code[offset].instr_safe() {
+            Some(instr) => instr,
+            None => return Err(VerifyError::InvalidInstruction { offset, byte }),
+        };
+
+        let end = offset + 1 + instr.operand_len();
+        let operands = match chunk.code.get(offset + 1..end) {
+            Some(operands) => operands.iter().map(|opcode| opcode.byte()).collect(),
+            None => return Err(VerifyError::MissingOperand { offset }),
+        };
+
+        code.push(Decoded {
+            offset,
+            instr,
+            operands,
+        });
+        offset = end;
+    }
+
+    Ok(code)
+}
+
+/// How many values an instruction pops, and then how many it pushes, given
+/// the size of the layout on top of the stack.
+fn stack_effect(decoded: &Decoded, layout: Option<usize>) -> Result<(usize, usize), VerifyError> {
+    let layout = || {
+        layout.ok_or(VerifyError::MissingLayout {
+            offset: decoded.offset,
+        })
+    };
+
+    Ok(match decoded.instr {
+        Instr::LoadConstantU8
+        | Instr::LoadConstantU32
+        | Instr::LoadSmallInt
+        | Instr::LoadTrue
+        | Instr::LoadFalse
+        | Instr::LoadUnit
+        | Instr::LoadLocal
+        | Instr::LoadGlobal => (0, 1),
+
+        Instr::NotBool
+        | Instr::NegInt
+        | Instr::BitNotInt
+        | Instr::GetField
+        | Instr::IsVariant
+        | Instr::GetPayload
+        | Instr::Try
+        | Instr::Panic
+        | Instr::ToString => (1, 1),
+
+        Instr::OrBool
+        | Instr::AndBool
+        | Instr::Equal
+        | Instr::NotEqual
+        | Instr::LT
+        | Instr::LTEqual
+        | Instr::CmpInt
+        | Instr::AddInt
+        | Instr::SubInt
+        | Instr::MulInt
+        | Instr::DivInt
+        | Instr::ModInt
+        | Instr::PowInt
+        | Instr::BitAndInt
+        | Instr::BitOrInt
+        | Instr::BitXorInt
+        | Instr::ShlInt
+        | Instr::ShrInt => (2, 1),
+
+        Instr::StoreLocal
+        | Instr::IncLocal
+        | Instr::JumpU32
+        | Instr::PushHandlerU32
+        | Instr::PopHandler => (0, 0),
+
+        Instr::StoreLocalPop
+        | Instr::DefineGlobal
+        | Instr::Pop
+        | Instr::JumpNotU32
+        | Instr::Return
+        | Instr::DebugPrint => (1, 0),
+
+        // the layout, then its fields
+        Instr::MakeStruct => (layout()? + 1, 1),
+        Instr::MakeVariant => {
+            if decoded.u8() >= layout()? {
+                return Err(VerifyError::MissingLayout {
+                    offset: decoded.offset,
+                });
+            }
+            (2, 1)
+        }
+
+        // the type name, then the methods
+        Instr::DefineImpl => (decoded.operands[1] as usize + 1, 0),
+
+        // the callee, then the arguments
+        Instr::Call => (decoded.u8() + 1, 1),
+
+        // the receiver is the first argument, so there's at least one
+        Instr::Invoke => match decoded.operands[2] {
+            0 => {
+                return Err(VerifyError::StackUnderflow {
+                    offset: decoded.offset,
+                })
+            }
+            argc => (argc as usize, 1),
+        },
+    })
+}
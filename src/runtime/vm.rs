@@ -1,31 +1,175 @@
+use std::collections::HashMap;
+use std::io::{ErrorKind, Write};
+use std::rc::Rc;
+
+use internment::Intern;
+
+use crate::compiler::diagnostic::ErrorReport;
 use crate::runtime::value::Type;
+use crate::source::Span;
 use crate::CompiledProgram;
 
+use super::builtins::{
+    native_vtables, panic_value, pow_int, shift_amount, NativeContext, BUILTIN_FNS,
+};
 use super::chunk::{Chunk, Instr};
-use super::value::Value;
+use super::value::{Object, StructInstance, Value, VariantInstance};
 
 #[derive(Debug, Clone)]
 pub enum RuntimeError {
     IntegerOverflow,
     DivisionByZero,
-    TypeError { expected: Type, found: Type },
+    NegativeExponent,
+    InvalidShift,
+    TypeError {
+        expected: Type,
+        found: Type,
+    },
+    Panic(String),
+
+    /// Code that passed the verifier did something only the type checker
+    /// rules out, like calling a function with the wrong number of
+    /// arguments.
+    InvalidBytecode(&'static str),
+
+    /// Standard output was closed, as by a pipe to a program that stopped
+    /// reading. Nothing can recover from it, since nothing can be printed.
+    OutputClosed,
+
+    /// Functions called each other more than `MAX_FRAMES` deep, as by a
+    /// recursion that never stops.
+    StackOverflow,
 }
 
+impl RuntimeError {
+    /// The stable code of the error, which `jellyfish explain` describes.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::IntegerOverflow => "J1001",
+            Self::DivisionByZero => "J1002",
+            Self::NegativeExponent => "J1003",
+            Self::InvalidShift => "J1004",
+            Self::TypeError { .. } => "J1005",
+            Self::Panic(_) => "J1006",
+            Self::InvalidBytecode(_) => "J1007",
+            Self::OutputClosed => "J1008",
+            Self::StackOverflow => "J1009",
+        }
+    }
+
+    fn title(&self) -> &'static str {
+        match self {
+            Self::IntegerOverflow => "integer overflow",
+            Self::DivisionByZero => "division by zero",
+            Self::NegativeExponent => "negative exponent",
+            Self::InvalidShift => "invalid shift",
+            Self::TypeError { .. } => "type error",
+            Self::Panic(_) => "panic",
+            Self::InvalidBytecode(_) => "invalid bytecode",
+            Self::OutputClosed => "output closed",
+            Self::StackOverflow => "stack overflow",
+        }
+    }
+
+    /// What the error's report says about the code that raised it.
+    fn label(&self) -> String {
+        match self {
+            Self::IntegerOverflow => "the result is too big".to_string(),
+            Self::DivisionByZero => "the divisor is zero".to_string(),
+            Self::NegativeExponent => "the exponent is negative".to_string(),
+            Self::InvalidShift => "the shift amount isn't between 0 and 63".to_string(),
+            Self::InvalidBytecode(problem) => problem.to_string(),
+            Self::StackOverflow => format!("more than {} calls are running", MAX_FRAMES),
+            Self::TypeError { .. } | Self::Panic(_) | Self::OutputClosed => self.to_string(),
+        }
+    }
+
+    /// Report the error, pointing at the code that raised it if that's
+    /// known.
+    pub fn report(&self, span: Option<Span>) -> ErrorReport {
+        let report = ErrorReport::new(self.title()).with_code(self.code());
+        match span {
+            Some(span) => report.with_labelled_source(self.label(), span),
+            None => report.with_label(self.label()),
+        }
+    }
+}
+
+impl std::fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::IntegerOverflow => write!(f, "integer overflow"),
+            Self::DivisionByZero => write!(f, "division by zero"),
+            Self::NegativeExponent => write!(f, "negative exponent"),
+            Self::InvalidShift => write!(f, "shift amount out of range"),
+            Self::TypeError { expected, found } => {
+                write!(f, "expected {:?} but found {:?}", expected, found)
+            }
+            Self::Panic(message) => write!(f, "{}", message),
+            Self::InvalidBytecode(problem) => write!(f, "invalid bytecode: {}", problem),
+            Self::OutputClosed => write!(f, "standard output was closed"),
+            Self::StackOverflow => write!(f, "stack overflow"),
+        }
+    }
+}
+
+/// The error for an object of the wrong kind, which the type checker rules
+/// out.
+fn wrong_object() -> RuntimeError {
+    RuntimeError::TypeError {
+        expected: Type::Object,
+        found: Type::Object,
+    }
+}
+
+/// How many calls can be running at once.
+pub const MAX_FRAMES: usize = 1 << 16;
+
 pub struct CallFrame {
-    chunk: Chunk,
+    chunk: Rc<Chunk>,
     ip: usize,
+
+    /// The index of the frame's first local in the value stack.
+    base: usize,
 }
 
 impl CallFrame {
-    pub fn new(chunk: Chunk) -> Self {
-        Self { chunk, ip: 0 }
+    pub fn new(chunk: Rc<Chunk>, base: usize) -> Self {
+        Self { chunk, ip: 0, base }
     }
 }
 
-#[derive(Default)]
+/// Where to carry on when a panic is caught by a `try` statement.
+struct Handler {
+    /// The number of frames below the one that installed the handler.
+    depth: usize,
+    stack_len: usize,
+    dest: usize,
+}
+
 pub struct VM {
     call_stack: Vec<CallFrame>,
     value_stack: Vec<Value>,
+    globals: Vec<Value>,
+    handlers: Vec<Handler>,
+
+    /// The methods of each trait impl, by trait index and type name.
+    vtables: HashMap<(u8, Intern<String>), Vec<Value>>,
+
+    /// The arguments the script can read with `arg`.
+    script_args: Vec<Intern<String>>,
+
+    /// Where the last error `eval` gave back was raised.
+    error_span: Option<Span>,
+
+    /// Where `print` writes to.
+    output: Box<dyn Write>,
+}
+
+impl Default for VM {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl VM {
@@ -33,12 +177,114 @@ impl VM {
         Self {
             call_stack: vec![],
             value_stack: vec![],
+            globals: BUILTIN_FNS
+                .iter()
+                .map(|func| Value::Object(Rc::new(Object::NativeFunction(func))))
+                .collect(),
+            handlers: vec![],
+            vtables: native_vtables(),
+            script_args: vec![],
+            error_span: None,
+            output: Box::new(std::io::stdout()),
         }
     }
 
+    /// Send what the script prints somewhere other than standard output.
+    pub fn set_output(&mut self, output: impl Write + 'static) {
+        self.output = Box::new(output);
+    }
+
+    /// Set the command-line arguments the script can read with `arg`.
+    pub fn set_args(&mut self, args: &[String]) {
+        self.script_args = args.iter().map(Intern::from_ref).collect();
+    }
+
+    /// Where the last error the VM gave back was raised, if the program
+    /// has spans.
+    pub fn error_span(&self) -> Option<Span> {
+        self.error_span
+    }
+
     pub fn run(&mut self, module: CompiledProgram) -> Result<(), RuntimeError> {
-        let mut frame = CallFrame::new(module.chunk);
+        self.eval(module).map(|_| ())
+    }
+
+    /// Run a program on top of the ones run before, whose top-level
+    /// variables and functions it can use, and return the value of the top
+    /// level.
+    ///
+    /// If the program fails, everything it defined is dropped again.
+    pub fn eval(&mut self, mut module: CompiledProgram) -> Result<Value, RuntimeError> {
+        let stack_len = self.value_stack.len();
+        let num_globals = self.globals.len();
+
+        let chunk = std::mem::take(&mut module.chunk);
+        let mut frame = CallFrame::new(Rc::new(chunk), 0);
+        self.handlers.clear();
+
+        loop {
+            let err = match self.execute(&mut frame) {
+                Ok(value) => return Ok(value),
+                Err(err) => err,
+            };
+
+            if let Err(err) = self.recover(&mut frame, err, &module) {
+                self.error_span = self.current_span(&frame);
+                self.call_stack.clear();
+                self.handlers.clear();
+                self.value_stack.truncate(stack_len);
+                self.globals.truncate(num_globals);
+                return Err(err);
+            }
+        }
+    }
+
+    /// Unwind to the innermost handler and pass it the error, or give the
+    /// error back if nothing handles it.
+    fn recover(
+        &mut self,
+        frame: &mut CallFrame,
+        err: RuntimeError,
+        module: &CompiledProgram,
+    ) -> Result<(), RuntimeError> {
+        if matches!(err, RuntimeError::OutputClosed) {
+            return Err(err);
+        }
+        let handler = match self.handlers.pop() {
+            Some(handler) => handler,
+            None => return Err(err),
+        };
 
+        let span = self.current_span(frame);
+        let (line, column) = match span.and_then(|span| module.line_col(span.start)) {
+            Some(line_col) => (line_col.line, line_col.col),
+            None => (0, 0),
+        };
+
+        if self.call_stack.len() > handler.depth {
+            self.call_stack.truncate(handler.depth + 1);
+            *frame = self.call_stack.pop().unwrap();
+        }
+
+        self.value_stack.truncate(handler.stack_len);
+        self.value_stack
+            .push(panic_value(err.to_string(), line, column));
+        frame.ip = handler.dest;
+
+        Ok(())
+    }
+
+    /// The span of the code that's running.
+    fn current_span(&self, frame: &CallFrame) -> Option<Span> {
+        // native methods have no spans, so look through their callers too
+        std::iter::once(frame)
+            .chain(self.call_stack.iter().rev())
+            .find_map(|frame| frame.chunk.span_at(frame.ip.saturating_sub(1)))
+    }
+
+    /// Run until the outermost frame returns, giving its value, or an error
+    /// is raised.
+    fn execute(&mut self, frame: &mut CallFrame) -> Result<Value, RuntimeError> {
         macro_rules! read {
             () => {{
                 let opcode = frame.chunk.code[frame.ip];
@@ -79,6 +325,15 @@ impl VM {
             () => {{
                 self.value_stack.pop().unwrap()
             }};
+            (Object) => {{
+                match self.value_stack.pop().unwrap() {
+                    Value::Object(obj) => Ok(obj),
+                    other => Err(RuntimeError::TypeError {
+                        expected: Type::Object,
+                        found: other.ty(),
+                    }),
+                }
+            }};
             ($ty:ident) => {{
                 match self.value_stack.pop().unwrap() {
                     Value::$ty(n) => Ok(n),
@@ -98,13 +353,119 @@ impl VM {
             }};
         }
 
+        // `BigInt`s have methods named like `i64`'s, so both are handled
+        // by the same instructions
         macro_rules! integer_op {
             ($op:ident, $err:ident) => {{
-                let b = pop!(Integer)?;
-                let a = pop!(Integer)?;
+                let b = pop!();
+                let a = pop!();
 
-                let c = a.$op(b).ok_or(RuntimeError::$err)?;
-                push!(Value::Integer(c));
+                let c = match (&a, &b) {
+                    (Value::Integer(a), Value::Integer(b)) => {
+                        Value::Integer(a.$op(*b).ok_or(RuntimeError::$err)?)
+                    }
+                    _ => Value::from(a.bigint()?.$op(b.bigint()?).ok_or(RuntimeError::$err)?),
+                };
+                push!(c);
+            }};
+        }
+
+        macro_rules! compare {
+            () => {{
+                let b = pop!();
+                let a = pop!();
+
+                match (&a, &b) {
+                    (Value::Integer(a), Value::Integer(b)) => a.cmp(b),
+                    _ => a.bigint()?.cmp(b.bigint()?),
+                }
+            }};
+        }
+
+        // the divisor is left on the stack for the operation itself
+        macro_rules! check_divisor {
+            () => {{
+                let is_zero = match peek!() {
+                    Value::Integer(n) => *n == 0,
+                    divisor => divisor.bigint()?.is_zero(),
+                };
+                if is_zero {
+                    return Err(RuntimeError::DivisionByZero);
+                }
+            }};
+        }
+
+        macro_rules! call {
+            ($argc:expr) => {{
+                let argc = $argc;
+                let base = self.value_stack.len() - argc;
+
+                let native = match &self.value_stack[base - 1] {
+                    Value::Object(obj) => match &**obj {
+                        Object::NativeFunction(func) => Some(*func),
+                        _ => None,
+                    },
+                    _ => None,
+                };
+
+                // builtins run straight away, without a frame of their own
+                if let Some(func) = native {
+                    if func.params.len() != argc {
+                        return Err(RuntimeError::InvalidBytecode("wrong number of arguments"));
+                    }
+                    let args = self.value_stack.split_off(base);
+                    self.value_stack.pop();
+                    let context = NativeContext {
+                        script_args: &self.script_args,
+                    };
+                    push!((func.func)(&context, &args)?);
+                    continue;
+                }
+
+                let chunk = match &self.value_stack[base - 1] {
+                    Value::Object(obj) => match &**obj {
+                        Object::JellyFunction(func) if func.arity == argc => func.chunk.clone(),
+                        Object::JellyFunction(_) => {
+                            return Err(RuntimeError::InvalidBytecode("wrong number of arguments"))
+                        }
+                        _ => return Err(wrong_object()),
+                    },
+                    other => {
+                        return Err(RuntimeError::TypeError {
+                            expected: Type::Object,
+                            found: other.ty(),
+                        })
+                    }
+                };
+
+                if self.call_stack.len() == MAX_FRAMES {
+                    return Err(RuntimeError::StackOverflow);
+                }
+                let callee = CallFrame::new(chunk, base);
+                self.call_stack.push(std::mem::replace(frame, callee));
+            }};
+        }
+
+        macro_rules! return_value {
+            ($result:expr) => {{
+                let result = $result;
+                match self.call_stack.pop() {
+                    Some(caller) => {
+                        // pop the callee's locals and the callee itself
+                        self.value_stack.truncate(frame.base - 1);
+                        push!(result);
+                        *frame = caller;
+
+                        // drop the handlers of `try` statements returned out of
+                        while matches!(
+                            self.handlers.last(),
+                            Some(handler) if handler.depth > self.call_stack.len()
+                        ) {
+                            self.handlers.pop();
+                        }
+                    }
+                    None => return Ok(result),
+                }
             }};
         }
 
@@ -124,23 +485,74 @@ impl VM {
                     push!(Value::Bool(!a))
                 }
 
-                Instr::Equal => binary_op!(==, Integer -> Bool),
-                Instr::LT => binary_op!(<, Integer -> Bool),
-                Instr::LTEqual => binary_op!(<=, Integer -> Bool),
+                Instr::Equal => {
+                    let b = pop!();
+                    let a = pop!();
+                    push!(Value::Bool(a == b));
+                }
+                Instr::NotEqual => {
+                    let b = pop!();
+                    let a = pop!();
+                    push!(Value::Bool(a != b));
+                }
+                Instr::LT => {
+                    let ordering = compare!();
+                    push!(Value::Bool(ordering.is_lt()));
+                }
+                Instr::LTEqual => {
+                    let ordering = compare!();
+                    push!(Value::Bool(ordering.is_le()));
+                }
+                Instr::CmpInt => {
+                    let ordering = compare!();
+                    push!(Value::Integer(ordering as i64));
+                }
 
                 Instr::AddInt => integer_op!(checked_add, IntegerOverflow),
                 Instr::SubInt => integer_op!(checked_sub, IntegerOverflow),
                 Instr::MulInt => integer_op!(checked_mul, IntegerOverflow),
-                Instr::DivInt => integer_op!(checked_div, IntegerOverflow),
-                Instr::ModInt => integer_op!(checked_rem, IntegerOverflow),
+                Instr::DivInt => {
+                    check_divisor!();
+                    integer_op!(checked_div, IntegerOverflow)
+                }
+                Instr::ModInt => {
+                    check_divisor!();
+                    integer_op!(checked_rem, IntegerOverflow)
+                }
                 Instr::PowInt => {
-                    let b = pop!(Integer)? as u32;
-                    let a = pop!(Integer)?;
-                    push!(Value::Integer(a.pow(b)));
+                    let b = pop!(Integer)?;
+                    let c = match pop!() {
+                        Value::Integer(a) => Value::Integer(pow_int(a, b, i64::checked_mul)?),
+                        a => Value::from(a.bigint()?.pow(b)?),
+                    };
+                    push!(c);
                 }
                 Instr::NegInt => {
+                    let b = match pop!() {
+                        Value::Integer(a) => {
+                            Value::Integer(a.checked_neg().ok_or(RuntimeError::IntegerOverflow)?)
+                        }
+                        a => Value::from(a.bigint()?.neg()),
+                    };
+                    push!(b)
+                }
+
+                Instr::BitAndInt => binary_op!(&, Integer -> Integer),
+                Instr::BitOrInt => binary_op!(|, Integer -> Integer),
+                Instr::BitXorInt => binary_op!(^, Integer -> Integer),
+                Instr::ShlInt => {
+                    let b = shift_amount(pop!(Integer)?)?;
                     let a = pop!(Integer)?;
-                    push!(Value::Integer(-a))
+                    push!(Value::Integer(a << b));
+                }
+                Instr::ShrInt => {
+                    let b = shift_amount(pop!(Integer)?)?;
+                    let a = pop!(Integer)?;
+                    push!(Value::Integer(a >> b));
+                }
+                Instr::BitNotInt => {
+                    let a = pop!(Integer)?;
+                    push!(Value::Integer(!a))
                 }
 
                 Instr::LoadConstantU8 => {
@@ -148,19 +560,104 @@ impl VM {
                     push!(constant);
                 }
                 Instr::LoadConstantU32 => {
-                    let constant = frame.chunk.constants[read_u32!() as usize].clone();
+                    let constant = frame.chunk.constants[read_u32!()].clone();
                     push!(constant);
                 }
 
+                Instr::LoadSmallInt => {
+                    push!(Value::Integer(read_u8!() as i8 as i64))
+                }
+                Instr::LoadTrue => {
+                    push!(Value::Bool(true))
+                }
+                Instr::LoadFalse => {
+                    push!(Value::Bool(false))
+                }
                 Instr::LoadUnit => {
                     push!(Value::Unit)
                 }
 
                 Instr::LoadLocal => {
-                    push!(self.value_stack[read_u8!() as usize].clone())
+                    push!(self.value_stack[frame.base + read_u8!() as usize].clone())
                 }
                 Instr::StoreLocal => {
-                    self.value_stack[read_u8!() as usize] = peek!().clone();
+                    self.value_stack[frame.base + read_u8!() as usize] = peek!().clone();
+                }
+                Instr::StoreLocalPop => {
+                    let slot = frame.base + read_u8!() as usize;
+                    self.value_stack[slot] = pop!();
+                }
+                Instr::IncLocal => {
+                    let slot = frame.base + read_u8!() as usize;
+                    let delta = read_u8!() as i8 as i64;
+                    let n = self.value_stack[slot].integer()?;
+                    self.value_stack[slot] =
+                        Value::Integer(n.checked_add(delta).ok_or(RuntimeError::IntegerOverflow)?);
+                }
+                Instr::LoadGlobal => {
+                    let global = match self.globals.get(read_u8!() as usize) {
+                        Some(global) => global.clone(),
+                        None => return Err(RuntimeError::InvalidBytecode("undefined global")),
+                    };
+                    push!(global)
+                }
+                Instr::DefineGlobal => {
+                    let value = pop!();
+                    self.globals.push(value);
+                }
+
+                Instr::MakeStruct => {
+                    let layout = match &*pop!(Object)? {
+                        Object::StructLayout(layout) => layout.clone(),
+                        _ => return Err(wrong_object()),
+                    };
+                    let fields = self
+                        .value_stack
+                        .split_off(self.value_stack.len() - layout.fields.len());
+                    push!(Value::Object(Rc::new(Object::Struct(StructInstance {
+                        layout,
+                        fields
+                    }))));
+                }
+                Instr::GetField => {
+                    let index = read_u8!() as usize;
+                    let field = match &*pop!(Object)? {
+                        Object::Struct(instance) => match instance.fields.get(index) {
+                            Some(field) => field.clone(),
+                            None => return Err(RuntimeError::InvalidBytecode("no such field")),
+                        },
+                        _ => return Err(wrong_object()),
+                    };
+                    push!(field);
+                }
+
+                Instr::MakeVariant => {
+                    let tag = read_u8!() as usize;
+                    let layout = match &*pop!(Object)? {
+                        Object::EnumLayout(layout) => layout.clone(),
+                        _ => return Err(wrong_object()),
+                    };
+                    let value = pop!();
+                    push!(Value::Object(Rc::new(Object::Variant(VariantInstance {
+                        layout,
+                        tag,
+                        value
+                    }))));
+                }
+                Instr::IsVariant => {
+                    let tag = read_u8!() as usize;
+                    let is_variant = match &*pop!(Object)? {
+                        Object::Variant(variant) => variant.tag == tag,
+                        _ => return Err(wrong_object()),
+                    };
+                    push!(Value::Bool(is_variant));
+                }
+                Instr::GetPayload => {
+                    let value = match &*pop!(Object)? {
+                        Object::Variant(variant) => variant.value.clone(),
+                        _ => return Err(wrong_object()),
+                    };
+                    push!(value);
                 }
 
                 Instr::Pop => drop(pop!()),
@@ -175,12 +672,91 @@ impl VM {
                     }
                 }
 
-                Instr::Return => break,
+                Instr::DefineImpl => {
+                    let trait_index = read_u8!();
+                    let count = read_u8!() as usize;
 
-                Instr::DebugPrint => println!("{}", pop!().repr()),
+                    let methods = self.value_stack.split_off(self.value_stack.len() - count);
+                    let ty = pop!(String)?;
+                    self.vtables.insert((trait_index, ty), methods);
+                }
+
+                Instr::Call => call!(read_u8!() as usize),
+                Instr::Invoke => {
+                    let trait_index = read_u8!();
+                    let method = read_u8!() as usize;
+                    let argc = read_u8!() as usize;
+
+                    // the receiver is the first argument
+                    let receiver = self.value_stack.len() - argc;
+                    let ty = self.value_stack[receiver].type_name();
+                    let func = match self
+                        .vtables
+                        .get(&(trait_index, ty))
+                        .and_then(|methods| methods.get(method))
+                    {
+                        Some(func) => func.clone(),
+                        None => return Err(RuntimeError::InvalidBytecode("no such method")),
+                    };
+
+                    self.value_stack.insert(receiver, func);
+                    call!(argc)
+                }
+                Instr::Return => return_value!(pop!()),
+                Instr::Try => {
+                    let value = pop!();
+                    let variant = match &value {
+                        Value::Object(obj) => match &**obj {
+                            Object::Variant(variant) => variant,
+                            _ => return Err(wrong_object()),
+                        },
+                        other => {
+                            return Err(RuntimeError::TypeError {
+                                expected: Type::Object,
+                                found: other.ty(),
+                            })
+                        }
+                    };
+
+                    // `Some` and `Ok` are unwrapped, `None` and `Err` returned
+                    if variant.tag == 0 {
+                        push!(variant.value.clone());
+                    } else {
+                        return_value!(value);
+                    }
+                }
+
+                Instr::PushHandlerU32 => {
+                    let dest = read_u32!();
+                    self.handlers.push(Handler {
+                        depth: self.call_stack.len(),
+                        stack_len: self.value_stack.len(),
+                        dest,
+                    });
+                }
+                Instr::PopHandler => drop(self.handlers.pop()),
+                Instr::Panic => {
+                    let message = pop!(String)?;
+                    return Err(RuntimeError::Panic(message.to_string()));
+                }
+
+                Instr::ToString => {
+                    let s = pop!().repr();
+                    push!(Value::String(Intern::new(s)));
+                }
+                Instr::DebugPrint => {
+                    let text = pop!().repr();
+                    match writeln!(self.output, "{}", text) {
+                        Ok(()) => {}
+                        Err(err) if err.kind() == ErrorKind::BrokenPipe => {
+                            return Err(RuntimeError::OutputClosed)
+                        }
+                        Err(err) => {
+                            return Err(RuntimeError::Panic(format!("couldn't print: {}", err)))
+                        }
+                    }
+                }
             }
         }
-
-        Ok(())
     }
 }
@@ -74,10 +74,19 @@ impl Source {
         }
     }
 
-    pub fn cursor(&self) -> Cursor {
+    pub fn cursor(&self) -> Cursor<'_> {
         Cursor::new(self)
     }
 
+    /// A cursor starting part way through the source, so the spans of what
+    /// it reads are still relative to the whole source.
+    pub fn cursor_at(&self, offset: usize) -> Cursor<'_> {
+        let mut cursor = Cursor::new(self);
+        cursor.chars = self.source[offset..].chars();
+        cursor.start_span();
+        cursor
+    }
+
     pub fn span_str(&self, span: Span) -> &str {
         let span = span.overlap(self.file_span());
         &self.source[span.range()]
@@ -90,6 +99,11 @@ impl Source {
         }
     }
 
+    /// The offset a line starts at, counting lines from zero.
+    pub fn line_start(&self, line_index: usize) -> Option<usize> {
+        self.line_offsets.get(line_index).copied()
+    }
+
     pub fn line_span(&self, byte_pos: usize) -> Span {
         let line_index = self.line_index(byte_pos);
 
@@ -103,16 +117,25 @@ impl Source {
         Span { start, end }.overlap(self.file_span())
     }
 
+    /// The line and column of an offset, counting lines and characters
+    /// from 1.
     pub fn line_col(&self, byte_pos: usize) -> LineCol {
         let line_index = self.line_index(byte_pos);
-        let col_index = byte_pos - self.line_offsets[line_index];
+        let line_start = self.line_offsets[line_index];
 
         LineCol {
             line: line_index + 1,
-            col: col_index + 1,
+            col: self.source[line_start..byte_pos].chars().count() + 1,
         }
     }
 
+    /// The column an offset is shown at in a terminal, counting from zero,
+    /// with tabs expanded and wide characters taking two columns.
+    pub fn display_col(&self, byte_pos: usize) -> usize {
+        let line_start = self.line_span(byte_pos).start;
+        display_width(&self.source[line_start..byte_pos])
+    }
+
     pub fn file_span(&self) -> Span {
         Span {
             start: 0,
@@ -127,6 +150,105 @@ impl Source {
     }
 }
 
+/// The lines and columns of some offsets in a source, for when the source
+/// itself isn't kept.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct LineTable {
+    /// Sorted by offset, with no offset twice.
+    pub(crate) positions: Vec<(usize, LineCol)>,
+}
+
+impl LineTable {
+    pub fn new(source: &Source, offsets: impl IntoIterator<Item = usize>) -> Self {
+        let mut offsets: Vec<_> = offsets.into_iter().collect();
+        offsets.sort_unstable();
+        offsets.dedup();
+
+        let positions = offsets
+            .into_iter()
+            .map(|offset| (offset, source.line_col(offset)))
+            .collect();
+        LineTable { positions }
+    }
+
+    /// The line and column of an offset, if it's in the table.
+    pub fn line_col(&self, byte_pos: usize) -> Option<LineCol> {
+        let index = self
+            .positions
+            .binary_search_by_key(&byte_pos, |(offset, _)| *offset)
+            .ok()?;
+        Some(self.positions[index].1)
+    }
+}
+
+/// How many columns apart tab stops are.
+pub const TAB_WIDTH: usize = 4;
+
+/// How many columns the start of a line takes up in a terminal.
+pub fn display_width(text: &str) -> usize {
+    text.chars().fold(0, |width, c| match c {
+        '\t' => width + TAB_WIDTH - width % TAB_WIDTH,
+        c => width + char_width(c),
+    })
+}
+
+/// A line with its tabs replaced by spaces up to the next tab stop, so it
+/// lines up with `display_width`.
+pub fn expand_tabs(line: &str) -> String {
+    let mut expanded = String::with_capacity(line.len());
+    let mut width = 0;
+    for c in line.chars() {
+        match c {
+            '\t' => {
+                let spaces = TAB_WIDTH - width % TAB_WIDTH;
+                expanded.push_str(&" ".repeat(spaces));
+                width += spaces;
+            }
+            c => {
+                expanded.push(c);
+                width += char_width(c);
+            }
+        }
+    }
+    expanded
+}
+
+/// The number of columns a character takes up in a terminal. This covers
+/// the common zero-width and wide characters rather than all of Unicode.
+fn char_width(c: char) -> usize {
+    match c as u32 {
+        // control characters, combining marks, zero-width spaces and joiners,
+        // and variation selectors
+        0x00..=0x1f
+        | 0x7f..=0x9f
+        | 0x0300..=0x036f
+        | 0x1ab0..=0x1aff
+        | 0x1dc0..=0x1dff
+        | 0x200b..=0x200f
+        | 0x20d0..=0x20ff
+        | 0xfe00..=0xfe0f
+        | 0xfe20..=0xfe2f => 0,
+
+        // East Asian wide and fullwidth characters, and emoji
+        0x1100..=0x115f
+        | 0x2e80..=0x303e
+        | 0x3041..=0x33ff
+        | 0x3400..=0x4dbf
+        | 0x4e00..=0x9fff
+        | 0xa000..=0xa4cf
+        | 0xac00..=0xd7a3
+        | 0xf900..=0xfaff
+        | 0xfe30..=0xfe4f
+        | 0xff00..=0xff60
+        | 0xffe0..=0xffe6
+        | 0x1f300..=0x1f64f
+        | 0x1f900..=0x1f9ff
+        | 0x20000..=0x3fffd => 2,
+
+        _ => 1,
+    }
+}
+
 pub struct Cursor<'sess> {
     source: &'sess Source,
     chars: Chars<'sess>,